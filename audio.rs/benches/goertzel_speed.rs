@@ -21,6 +21,18 @@ fn bench_goertzel(c: &mut Criterion) {
 			black_box(analyzer.analyze(&sample));
 		});
 	});
+
+	let sample: Vec<f32> = (0..1024).map(|_| rng.gen_range(-1.0..=1.0)).collect();
+	let mut analyzer = GoertzelAnalyzer::new(
+		DftCtx::new(SampleRate(44_100), 1024),
+		(10..58).collect(),
+		&HannWindow::new(),
+	);
+	c.bench_function("Goertzel analyzer (many bins)", |b| {
+		b.iter(|| {
+			black_box(analyzer.analyze(&sample));
+		});
+	});
 }
 
 criterion_group! {
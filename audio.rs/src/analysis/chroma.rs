@@ -0,0 +1,249 @@
+use crate::analysis::{dft::StftAnalyzer, window_offsets, DftCtx, WindowingFn};
+
+/// Default tuning reference (A4), in Hz.
+const DEFAULT_TUNING: f32 = 440.;
+
+/// Lowest frequency taken into account (A0), in Hz. Below this value the bins are too wide
+/// compared to the distance between semitones to be meaningful.
+const MIN_FREQUENCY: f32 = 27.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PitchClass {
+	C,
+	CSharp,
+	D,
+	DSharp,
+	E,
+	F,
+	FSharp,
+	G,
+	GSharp,
+	A,
+	ASharp,
+	B,
+}
+
+impl PitchClass {
+	pub const ALL: [PitchClass; 12] = [
+		PitchClass::C,
+		PitchClass::CSharp,
+		PitchClass::D,
+		PitchClass::DSharp,
+		PitchClass::E,
+		PitchClass::F,
+		PitchClass::FSharp,
+		PitchClass::G,
+		PitchClass::GSharp,
+		PitchClass::A,
+		PitchClass::ASharp,
+		PitchClass::B,
+	];
+
+	/// The pitch class of the note closest to `frequency`, given the frequency of A4.
+	#[must_use]
+	pub fn from_frequency(frequency: f32, tuning: f32) -> Self {
+		// MIDI note number, where A4 is 69 and C4 is 60.
+		let note = 69. + 12. * (frequency / tuning).log2();
+		#[allow(clippy::cast_possible_truncation)]
+		let idx = (note.round() as i64).rem_euclid(12);
+		#[allow(clippy::cast_sign_loss)]
+		Self::ALL[idx as usize]
+	}
+}
+
+/// The energy of a signal folded into the 12 pitch classes of the chromatic scale.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Chroma {
+	energies: [f32; 12],
+}
+
+impl Chroma {
+	/// The energy of each pitch class, starting from [`PitchClass::C`].
+	#[must_use]
+	pub fn energies(&self) -> &[f32; 12] {
+		&self.energies
+	}
+
+	#[must_use]
+	pub fn energy(&self, pitch_class: PitchClass) -> f32 {
+		self.energies[pitch_class as usize]
+	}
+
+	/// The pitch class with the highest energy.
+	#[must_use]
+	pub fn dominant(&self) -> PitchClass {
+		PitchClass::ALL
+			.into_iter()
+			.max_by(|&a, &b| self.energy(a).total_cmp(&self.energy(b)))
+			.unwrap_or(PitchClass::C)
+	}
+
+	/// A copy of this chroma scaled so that the highest energy is 1.
+	/// A silent chroma is returned as is.
+	#[must_use]
+	pub fn normalized(&self) -> Self {
+		let max = self.energies.iter().copied().fold(0., f32::max);
+		if max <= f32::EPSILON {
+			return *self;
+		}
+		Self {
+			energies: self.energies.map(|e| e / max),
+		}
+	}
+}
+
+/// Extracts the chroma (a.k.a. pitch class profile) of a signal on top of the STFT, which can be
+/// used for key and chord detection.
+#[derive(Debug, Clone)]
+pub struct ChromaAnalyzer {
+	stft_analyzer: StftAnalyzer,
+	tuning: f32,
+	bin_to_pitch_class: Vec<Option<PitchClass>>,
+}
+
+impl ChromaAnalyzer {
+	/// Create an analyzer tuned to A4 = 440Hz.
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, windowing_fn: &impl WindowingFn) -> Self {
+		Self::new_with_tuning(dft_ctx, windowing_fn, DEFAULT_TUNING)
+	}
+
+	/// Create an analyzer tuned to the given frequency of A4, in Hz.
+	#[must_use]
+	pub fn new_with_tuning(dft_ctx: DftCtx, windowing_fn: &impl WindowingFn, tuning: f32) -> Self {
+		Self {
+			stft_analyzer: StftAnalyzer::new(dft_ctx, windowing_fn),
+			tuning,
+			bin_to_pitch_class: (0..dft_ctx.n_of_bins())
+				.map(|bin| {
+					let frequency = dft_ctx.bin_to_frequency(bin);
+					(frequency >= MIN_FREQUENCY)
+						.then(|| PitchClass::from_frequency(frequency, tuning))
+				})
+				.collect(),
+		}
+	}
+
+	/// Analyze a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze(&mut self, signal: &[f32]) -> Chroma {
+		let mut chroma = Chroma::default();
+		for (harmonic, pitch_class) in self
+			.stft_analyzer
+			.analyze(signal)
+			.iter()
+			.zip(self.bin_to_pitch_class.iter())
+		{
+			if let Some(pitch_class) = pitch_class {
+				chroma.energies[*pitch_class as usize] += harmonic.power();
+			}
+		}
+		chroma
+	}
+
+	/// Analyze all the complete windows contained in a longer signal, taking a window
+	/// every `hop` samples.
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer(&mut self, signal: &[f32], hop: usize) -> Vec<Chroma> {
+		let samples_per_window = self.dft_ctx().samples_per_window();
+		window_offsets(signal.len(), samples_per_window, hop)
+			.map(|offset| self.analyze(&signal[offset..offset + samples_per_window]))
+			.collect()
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.stft_analyzer.dft_ctx()
+	}
+
+	/// The frequency of A4, in Hz.
+	#[must_use]
+	pub fn tuning(&self) -> f32 {
+		self.tuning
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use crate::{analysis::windowing_fns::HannWindow, SampleRate};
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn chord(sample_rate: SampleRate, n_of_samples: usize, frequencies: &[f32]) -> Vec<f32> {
+		(0..n_of_samples)
+			.map(|i| {
+				frequencies
+					.iter()
+					.map(|f| (TAU * f * i as f32 / sample_rate.0 as f32).sin())
+					.sum()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn pitch_classes() {
+		assert_eq!(PitchClass::from_frequency(440., 440.), PitchClass::A);
+		assert_eq!(PitchClass::from_frequency(261.63, 440.), PitchClass::C);
+		assert_eq!(PitchClass::from_frequency(55., 440.), PitchClass::A);
+		assert_eq!(PitchClass::from_frequency(30.87, 440.), PitchClass::B);
+		assert_eq!(PitchClass::from_frequency(432., 432.), PitchClass::A);
+		assert_eq!(PitchClass::from_frequency(432., 440.), PitchClass::A);
+		assert_eq!(PitchClass::from_frequency(466.16, 440.), PitchClass::ASharp);
+	}
+
+	#[test]
+	fn a_major_chord() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 8192);
+		let signal = chord(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window() * 2,
+			&[440., 554.37, 659.25],
+		);
+
+		let mut analyzer = ChromaAnalyzer::new(dft_ctx, &HannWindow);
+		let chromas = analyzer.analyze_buffer(&signal, dft_ctx.samples_per_window() / 2);
+		assert_eq!(chromas.len(), 3);
+
+		for chroma in chromas {
+			let chroma = chroma.normalized();
+			let mut ranking = PitchClass::ALL;
+			ranking.sort_by(|&a, &b| chroma.energy(b).total_cmp(&chroma.energy(a)));
+			let mut top = ranking[..3].to_vec();
+			top.sort();
+			assert_eq!(
+				top,
+				vec![PitchClass::CSharp, PitchClass::E, PitchClass::A],
+				"{chroma:?}"
+			);
+			assert!(chroma.energies().iter().all(|&e| e <= 1.));
+		}
+	}
+
+	#[test]
+	fn configurable_tuning() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 8192);
+		// A#4 with the baroque tuning (A4 = 415Hz), almost exactly A4 with the standard one.
+		let frequency = 415. * 2f32.powf(1. / 12.);
+		let signal = chord(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window(),
+			&[frequency],
+		);
+
+		let mut analyzer = ChromaAnalyzer::new_with_tuning(dft_ctx, &HannWindow, 415.);
+		assert_eq!(analyzer.analyze(&signal).dominant(), PitchClass::ASharp);
+
+		let mut analyzer = ChromaAnalyzer::new(dft_ctx, &HannWindow);
+		assert_eq!(analyzer.analyze(&signal).dominant(), PitchClass::A);
+	}
+}
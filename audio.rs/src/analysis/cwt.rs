@@ -0,0 +1,268 @@
+use std::f32::consts::TAU;
+
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::{
+	analysis::{dft::FftCache, Harmonic},
+	SampleRate,
+};
+
+/// Default non-dimensional frequency of the Morlet wavelet, which sets the trade-off
+/// between time and frequency resolution (about 6 cycles per wavelet).
+const DEFAULT_OMEGA0: f32 = 6.;
+
+/// The result of a [`CwtAnalyzer`]: one complex coefficient per sample for each analyzed frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scalogram {
+	sample_rate: SampleRate,
+	frequencies: Vec<f32>,
+	coefficients: Vec<Vec<Complex32>>,
+}
+
+impl Scalogram {
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	/// The center frequencies of the scales, in the same order used by [`Self::coefficients`].
+	#[must_use]
+	pub fn frequencies(&self) -> &[f32] {
+		&self.frequencies
+	}
+
+	/// The coefficients of the scale at `scale_idx`, one for each sample of the analyzed signal.
+	///
+	/// # Panics
+	/// - if `scale_idx` is out of bounds.
+	#[must_use]
+	pub fn coefficients(&self, scale_idx: usize) -> &[Complex32] {
+		&self.coefficients[scale_idx]
+	}
+
+	/// The amplitude and phase of the center frequency of the scale at `scale_idx` around `sample_idx`.
+	///
+	/// # Panics
+	/// - if `scale_idx` or `sample_idx` are out of bounds.
+	#[must_use]
+	pub fn harmonic(&self, scale_idx: usize, sample_idx: usize) -> Harmonic {
+		Harmonic::new(
+			self.coefficients[scale_idx][sample_idx],
+			self.frequencies[scale_idx],
+		)
+	}
+
+	#[must_use]
+	pub fn n_of_scales(&self) -> usize {
+		self.frequencies.len()
+	}
+
+	#[must_use]
+	pub fn n_of_samples(&self) -> usize {
+		self.coefficients.first().map_or(0, Vec::len)
+	}
+}
+
+/// Continuous wavelet transform based on the (analytic) Morlet wavelet.
+///
+/// Unlike the STFT, whose windows have a fixed length, each scale uses a wavelet whose length is
+/// inversely proportional to its frequency: high frequencies get a fine time resolution, which makes
+/// the CWT better suited to transient-heavy signals, while low frequencies keep a fine frequency resolution.
+///
+/// The coefficients are normalized so that a sinusoid of amplitude 1 at the center frequency of a scale
+/// yields coefficients of amplitude 1, with the same phase convention as [`Harmonic`].
+#[derive(Debug, Clone)]
+pub struct CwtAnalyzer {
+	sample_rate: SampleRate,
+	frequencies: Vec<f32>,
+	omega0: f32,
+}
+
+impl CwtAnalyzer {
+	/// Create an analyzer for the given center `frequencies`, in Hz.
+	///
+	/// # Panics
+	/// - if any of the frequencies is not in the range between 0 (excluded) and the Nyquist frequency.
+	#[must_use]
+	pub fn new(sample_rate: SampleRate, frequencies: Vec<f32>) -> Self {
+		Self::new_with_omega0(sample_rate, frequencies, DEFAULT_OMEGA0)
+	}
+
+	/// Create an analyzer for `n_of_scales` center frequencies spaced logarithmically between
+	/// `frequency_range.0` and `frequency_range.1` (both included), in Hz.
+	///
+	/// # Panics
+	/// - if `n_of_scales` is less than 2.
+	/// - if any of the frequencies is not in the range between 0 (excluded) and the Nyquist frequency.
+	#[must_use]
+	pub fn new_log_spaced(
+		sample_rate: SampleRate,
+		frequency_range: (f32, f32),
+		n_of_scales: usize,
+	) -> Self {
+		assert!(n_of_scales >= 2, "at least 2 scales are required");
+		let ratio = frequency_range.1 / frequency_range.0;
+		#[allow(clippy::cast_precision_loss)]
+		let frequencies = (0..n_of_scales)
+			.map(|i| frequency_range.0 * ratio.powf(i as f32 / (n_of_scales - 1) as f32))
+			.collect();
+		Self::new(sample_rate, frequencies)
+	}
+
+	/// Create an analyzer with a custom Morlet parameter: higher values of `omega0` improve the frequency
+	/// resolution at the expense of the time resolution.
+	///
+	/// # Panics
+	/// - if any of the frequencies is not in the range between 0 (excluded) and the Nyquist frequency.
+	/// - if `omega0` is not positive.
+	#[must_use]
+	pub fn new_with_omega0(sample_rate: SampleRate, frequencies: Vec<f32>, omega0: f32) -> Self {
+		#[allow(clippy::cast_precision_loss)]
+		let nyquist_frequency = sample_rate.0 as f32 / 2.;
+		assert!(
+			frequencies
+				.iter()
+				.all(|&f| f > 0. && f <= nyquist_frequency),
+			"frequencies must be in the range (0, {nyquist_frequency}]"
+		);
+		assert!(omega0 > 0., "omega0 must be positive");
+		Self {
+			sample_rate,
+			frequencies,
+			omega0,
+		}
+	}
+
+	/// Analyze a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// Note: the signal is zero-padded, therefore the coefficients near its edges are attenuated.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze(&self, signal: &[f32]) -> Scalogram {
+		if signal.is_empty() {
+			return Scalogram {
+				sample_rate: self.sample_rate,
+				frequencies: self.frequencies.clone(),
+				coefficients: vec![vec![]; self.frequencies.len()],
+			};
+		}
+
+		// Padding avoids the wrap-around of the circular convolution.
+		let transform_size = (signal.len() * 2).next_power_of_two();
+		let planner = FftCache::global();
+		let fft = planner.plan_fft_forward(transform_size);
+		let ifft = planner.plan_fft_inverse(transform_size);
+
+		let mut spectrum: Vec<Complex32> = signal
+			.iter()
+			.map(|&s| Complex::new(s, 0.))
+			.chain(std::iter::repeat(Complex::ZERO))
+			.take(transform_size)
+			.collect();
+		fft.process(&mut spectrum);
+
+		#[allow(clippy::cast_precision_loss)]
+		let (transform_size_f, sample_rate_f) = (transform_size as f32, self.sample_rate.0 as f32);
+
+		let mut buffer = vec![Complex::ZERO; transform_size];
+		let coefficients = self
+			.frequencies
+			.iter()
+			.map(|&frequency| {
+				// Morlet wavelet in the domain of frequency, only defined for positive frequencies (analytic).
+				// Its peak gain is 2 to compensate for the discarded negative frequencies.
+				let scale = self.omega0 / (TAU * frequency / sample_rate_f);
+				for (k, (dst, src)) in buffer.iter_mut().zip(&spectrum).enumerate() {
+					#[allow(clippy::cast_precision_loss)]
+					let omega = TAU * k as f32 / transform_size_f;
+					*dst = if k > 0 && k <= transform_size / 2 {
+						let x = scale * omega - self.omega0;
+						src * (2. * (-x * x / 2.).exp() / transform_size_f)
+					} else {
+						Complex::ZERO
+					};
+				}
+				ifft.process(&mut buffer);
+				buffer[..signal.len()].to_vec()
+			})
+			.collect();
+
+		Scalogram {
+			sample_rate: self.sample_rate,
+			frequencies: self.frequencies.clone(),
+			coefficients,
+		}
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	#[must_use]
+	pub fn frequencies(&self) -> &[f32] {
+		&self.frequencies
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn peaks_at_the_frequency_of_a_sinusoid() {
+		let sample_rate = SampleRate(8000);
+		let signal: Vec<f32> = (0..4000)
+			.map(|i| (TAU * 440. * i as f32 / 8000.).cos())
+			.collect();
+
+		let analyzer = CwtAnalyzer::new_log_spaced(sample_rate, (110., 1760.), 33);
+		let scalogram = analyzer.analyze(&signal);
+		assert_eq!(scalogram.n_of_scales(), 33);
+		assert_eq!(scalogram.n_of_samples(), signal.len());
+
+		let middle = signal.len() / 2;
+		let strongest = (0..scalogram.n_of_scales())
+			.max_by(|&a, &b| {
+				scalogram
+					.harmonic(a, middle)
+					.amplitude()
+					.total_cmp(&scalogram.harmonic(b, middle).amplitude())
+			})
+			.unwrap();
+		let harmonic = scalogram.harmonic(strongest, middle);
+		assert!((harmonic.frequency() - 440.).abs() < 1., "{harmonic:?}");
+		assert!((harmonic.amplitude() - 1.).abs() < 0.01, "{harmonic:?}");
+
+		// Same phase convention as the DFT analyzers: cos(2π440t) has phase 2π440t.
+		let expected_phase = Complex32::from_polar(1., TAU * 440. * middle as f32 / 8000.);
+		assert!((harmonic.phasor() / harmonic.amplitude() - expected_phase).norm() < 0.01);
+	}
+
+	#[test]
+	fn localizes_transients_at_high_frequencies() {
+		let sample_rate = SampleRate(8000);
+		let mut signal = vec![0.; 4000];
+		signal[2000] = 1.;
+
+		let analyzer = CwtAnalyzer::new(sample_rate, vec![100., 2000.]);
+		let scalogram = analyzer.analyze(&signal);
+
+		let spread = |scale_idx: usize| {
+			let coefficients = scalogram.coefficients(scale_idx);
+			let peak = coefficients[2000].norm();
+			// Number of samples where the response is at least half of the peak.
+			coefficients
+				.iter()
+				.filter(|c| c.norm() >= peak / 2.)
+				.count()
+		};
+
+		let high_spread = spread(1);
+		let low_spread = spread(0);
+		// Half a millisecond at 2kHz, tens of milliseconds at 100Hz.
+		assert!(high_spread < 10, "{high_spread}");
+		assert!(low_spread > 10 * high_spread, "{low_spread}");
+	}
+}
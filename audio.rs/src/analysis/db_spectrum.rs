@@ -0,0 +1,51 @@
+use crate::analysis::{DftCtx, DiscreteHarmonic, Window};
+
+/// Lowest value returned by the `analyze_db` methods of the analyzers, in dB.
+/// Silent bins would otherwise be mapped to -∞.
+pub const DB_FLOOR: f32 = -120.;
+
+/// The reference level of the `analyze_db` methods of the analyzers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DbReference {
+	/// dBFS: 0dB corresponds to a sinusoid with amplitude 1.
+	#[default]
+	FullScale,
+	/// 0dB corresponds to a sinusoid with the given amplitude, in the same unit as the samples.
+	Amplitude(f32),
+}
+
+impl DbReference {
+	fn amplitude(self) -> f32 {
+		match self {
+			DbReference::FullScale => 1.,
+			DbReference::Amplitude(amplitude) => amplitude,
+		}
+	}
+}
+
+/// Convert the output of an analyzer to (frequency, dB) pairs, compensating for the window length
+/// and the coherent gain of the window so that the peak of a sinusoid centered in a bin reads
+/// its amplitude relative to `reference`.
+pub(crate) fn to_db_spectrum(
+	transform: &[DiscreteHarmonic],
+	dft_ctx: DftCtx,
+	window: &Window,
+	reference: DbReference,
+) -> Vec<(f32, f32)> {
+	// A sinusoid with amplitude A yields a bin with amplitude A * coherent_gain * sqrt(N) / 2.
+	#[allow(clippy::cast_precision_loss)]
+	let reference_amplitude = reference.amplitude()
+		* window.coherent_gain()
+		* (dft_ctx.samples_per_window() as f32).sqrt()
+		/ 2.;
+
+	transform
+		.iter()
+		.map(|harmonic| {
+			(
+				dft_ctx.bin_to_frequency(harmonic.bin()),
+				(20. * (harmonic.amplitude() / reference_amplitude).log10()).max(DB_FLOOR),
+			)
+		})
+		.collect()
+}
@@ -0,0 +1,261 @@
+#![allow(clippy::cast_precision_loss)]
+#![allow(clippy::cast_possible_wrap)]
+
+use std::time::Duration;
+
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::{analysis::dft::FftCache, NOfFrames, SampleRate};
+
+/// Weighting applied to the cross-spectrum before computing the cross-correlation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorrelationWeighting {
+	/// Plain cross-correlation.
+	#[default]
+	None,
+	/// Phase transform (GCC-PHAT): only the phase of the cross-spectrum is kept,
+	/// which sharpens the correlation peak and makes the estimate more robust
+	/// to reverberation and to signals with an uneven spectrum.
+	Phat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayEstimate {
+	lag: isize,
+	sample_rate: SampleRate,
+	correlation: f32,
+}
+
+impl DelayEstimate {
+	/// The delay of the second signal relative to the first one, in frames.
+	///
+	/// A positive value means that the second signal lags behind the first one,
+	/// a negative value means that it's ahead of it.
+	#[must_use]
+	pub fn lag(&self) -> isize {
+		self.lag
+	}
+
+	/// The absolute value of [`Self::lag`].
+	#[must_use]
+	pub fn n_of_frames(&self) -> NOfFrames {
+		NOfFrames(self.lag.unsigned_abs())
+	}
+
+	/// The absolute value of [`Self::lag`], converted to a [`Duration`].
+	#[must_use]
+	pub fn duration(&self) -> Duration {
+		Duration::from_secs_f64(self.lag.unsigned_abs() as f64 / self.sample_rate.0 as f64)
+	}
+
+	/// The height of the correlation peak. With [`CorrelationWeighting::None`] this is the normalized
+	/// cross-correlation (1.0 for identical signals), with [`CorrelationWeighting::Phat`] it's the
+	/// fraction of the spectrum that agrees with the estimated delay. In both cases,
+	/// higher values mean a more reliable estimate.
+	#[must_use]
+	pub fn correlation(&self) -> f32 {
+		self.correlation
+	}
+}
+
+/// Estimate the delay of `b` relative to `a` by looking for the peak of their cross-correlation,
+/// considering lags up to `max_lag` frames in both directions.
+///
+/// `a` and `b` are expected to be mono signals sampled at `sample_rate`, e.g. two channels of the
+/// same capture. If either of them is empty, there is nothing to correlate and the estimate has
+/// a lag and a correlation of 0.
+#[must_use]
+pub fn estimate_delay(
+	a: &[f32],
+	b: &[f32],
+	max_lag: NOfFrames,
+	sample_rate: SampleRate,
+) -> DelayEstimate {
+	estimate_delay_with_weighting(a, b, max_lag, sample_rate, CorrelationWeighting::None)
+}
+
+/// Same as [`estimate_delay`], but with a configurable weighting of the cross-spectrum.
+#[must_use]
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn estimate_delay_with_weighting(
+	a: &[f32],
+	b: &[f32],
+	max_lag: NOfFrames,
+	sample_rate: SampleRate,
+	weighting: CorrelationWeighting,
+) -> DelayEstimate {
+	if a.is_empty() || b.is_empty() {
+		return DelayEstimate {
+			lag: 0,
+			sample_rate,
+			correlation: 0.,
+		};
+	}
+
+	// No lag can be longer than the longest signal.
+	let max_lag = max_lag.0.min(a.len().max(b.len()) - 1);
+	// Zero-padding to (at least) the longest signal plus the maximum lag turns the circular
+	// correlation computed by the FFT into a linear one for all the lags considered.
+	let transform_size = (a.len().max(b.len()) + max_lag).next_power_of_two();
+
+	let planner = FftCache::global();
+	let forward = planner.plan_fft_forward(transform_size);
+	let inverse = planner.plan_fft_inverse(transform_size);
+
+	let to_padded_complex = |signal: &[f32]| {
+		let mut out = vec![Complex32::ZERO; transform_size];
+		for (dst, &src) in out.iter_mut().zip(signal) {
+			*dst = Complex::new(src, 0.);
+		}
+		out
+	};
+
+	let mut spectrum_a = to_padded_complex(a);
+	let mut spectrum_b = to_padded_complex(b);
+	forward.process(&mut spectrum_a);
+	forward.process(&mut spectrum_b);
+
+	let mut cross_correlation: Vec<Complex32> = spectrum_a
+		.iter()
+		.zip(&spectrum_b)
+		.map(|(a, b)| {
+			let cross = a.conj() * b;
+			match weighting {
+				CorrelationWeighting::None => cross,
+				CorrelationWeighting::Phat => {
+					let norm = cross.norm();
+					if norm > f32::EPSILON {
+						cross / norm
+					} else {
+						Complex::ZERO
+					}
+				}
+			}
+		})
+		.collect();
+	inverse.process(&mut cross_correlation);
+
+	let normalization = match weighting {
+		CorrelationWeighting::None => {
+			let energy_a = a.iter().map(|s| s * s).sum::<f32>();
+			let energy_b = b.iter().map(|s| s * s).sum::<f32>();
+			transform_size as f32 * (energy_a * energy_b).sqrt()
+		}
+		CorrelationWeighting::Phat => transform_size as f32,
+	};
+
+	let max_lag = max_lag as isize;
+	let (lag, correlation) = (-max_lag..=max_lag)
+		.map(|lag| {
+			let idx = if lag < 0 {
+				transform_size - lag.unsigned_abs()
+			} else {
+				lag.unsigned_abs()
+			};
+			(lag, cross_correlation[idx].re)
+		})
+		.max_by(|(_, a), (_, b)| a.total_cmp(b))
+		.unwrap_or((0, 0.));
+
+	DelayEstimate {
+		lag,
+		sample_rate,
+		correlation: if normalization > f32::EPSILON {
+			correlation / normalization
+		} else {
+			0.
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, Rng, SeedableRng};
+
+	use super::*;
+
+	fn noise(n_of_samples: usize) -> Vec<f32> {
+		let mut rng = StdRng::seed_from_u64(42);
+		(0..n_of_samples)
+			.map(|_| rng.gen_range(-1.0..1.0))
+			.collect()
+	}
+
+	fn delayed(signal: &[f32], delay: usize) -> Vec<f32> {
+		let mut out = vec![0.; delay];
+		out.extend_from_slice(&signal[..signal.len() - delay]);
+		out
+	}
+
+	#[test]
+	fn estimates_positive_and_negative_delays() {
+		let sample_rate = SampleRate(44100);
+		let a = noise(4410);
+		let b = delayed(&a, 37);
+
+		for weighting in [CorrelationWeighting::None, CorrelationWeighting::Phat] {
+			let estimate =
+				estimate_delay_with_weighting(&a, &b, NOfFrames(100), sample_rate, weighting);
+			assert_eq!(estimate.lag(), 37);
+			assert_eq!(estimate.n_of_frames(), NOfFrames(37));
+			assert!(estimate.correlation() > 0.5, "{estimate:?}");
+
+			let estimate =
+				estimate_delay_with_weighting(&b, &a, NOfFrames(100), sample_rate, weighting);
+			assert_eq!(estimate.lag(), -37);
+			assert_eq!(estimate.n_of_frames(), NOfFrames(37));
+		}
+	}
+
+	#[test]
+	fn delay_duration() {
+		let sample_rate = SampleRate(44100);
+		let a = noise(44100);
+		let b = delayed(&a, 441);
+
+		let estimate = estimate_delay(&a, &b, NOfFrames(1000), sample_rate);
+		assert_eq!(estimate.duration(), Duration::from_millis(10));
+	}
+
+	#[test]
+	fn lags_beyond_max_lag_are_ignored() {
+		let a = noise(4410);
+		let b = delayed(&a, 200);
+
+		let estimate = estimate_delay(&a, &b, NOfFrames(100), SampleRate(44100));
+		assert!(estimate.lag().abs() <= 100);
+		assert!(estimate.correlation() < 0.5, "{estimate:?}");
+	}
+
+	#[test]
+	fn signals_of_different_lengths() {
+		let burst = noise(100);
+		let mut b = vec![0.; 6000];
+		b.extend_from_slice(&burst);
+		b.resize(7000, 0.);
+
+		let estimate = estimate_delay(&burst, &b, NOfFrames(10000), SampleRate(44100));
+		assert_eq!(estimate.lag(), 6000);
+		assert!(estimate.correlation() > 0.9, "{estimate:?}");
+
+		let estimate = estimate_delay(&b, &burst, NOfFrames(10000), SampleRate(44100));
+		assert_eq!(estimate.lag(), -6000);
+		assert!(estimate.correlation() > 0.9, "{estimate:?}");
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn empty_signals() {
+		for (a, b) in [
+			(&[][..], &[][..]),
+			(&[1.][..], &[][..]),
+			(&[][..], &[1.][..]),
+		] {
+			let estimate = estimate_delay(a, b, NOfFrames(10), SampleRate(44100));
+			assert_eq!(estimate.lag(), 0);
+			assert_eq!(estimate.correlation(), 0.);
+		}
+		let estimate = estimate_delay(&[1.], &[1.], NOfFrames(10), SampleRate(44100));
+		assert_eq!(estimate.lag(), 0);
+	}
+}
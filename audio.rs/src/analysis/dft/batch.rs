@@ -0,0 +1,58 @@
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::analysis::{window_offsets, DftCtx, DiscreteHarmonic, Spectrogram};
+
+/// Common interface of the analyzers that can be driven over multiple windows.
+pub(crate) trait WindowAnalyzer {
+	fn dft_ctx(&self) -> DftCtx;
+	fn analyze_window(&mut self, signal: &[f32]) -> &Vec<DiscreteHarmonic>;
+}
+
+pub(crate) fn analyze_buffer<A: WindowAnalyzer>(
+	analyzer: &mut A,
+	signal: &[f32],
+	hop: usize,
+) -> Spectrogram {
+	let dft_ctx = analyzer.dft_ctx();
+	let samples_per_window = dft_ctx.samples_per_window();
+	Spectrogram::new(
+		dft_ctx,
+		hop,
+		window_offsets(signal.len(), samples_per_window, hop)
+			.map(|offset| {
+				analyzer
+					.analyze_window(&signal[offset..offset + samples_per_window])
+					.clone()
+			})
+			.collect(),
+	)
+}
+
+#[cfg(feature = "rayon")]
+pub(crate) fn analyze_windows_par<A: WindowAnalyzer + Clone + Send + Sync>(
+	analyzer: &A,
+	windows: &[&[f32]],
+) -> Vec<Vec<DiscreteHarmonic>> {
+	windows
+		.par_iter()
+		.map_init(
+			|| analyzer.clone(),
+			|analyzer, window| analyzer.analyze_window(window).clone(),
+		)
+		.collect()
+}
+
+#[cfg(feature = "rayon")]
+pub(crate) fn analyze_buffer_par<A: WindowAnalyzer + Clone + Send + Sync>(
+	analyzer: &A,
+	signal: &[f32],
+	hop: usize,
+) -> Spectrogram {
+	let dft_ctx = analyzer.dft_ctx();
+	let samples_per_window = dft_ctx.samples_per_window();
+	let windows: Vec<&[f32]> = window_offsets(signal.len(), samples_per_window, hop)
+		.map(|offset| &signal[offset..offset + samples_per_window])
+		.collect();
+	Spectrogram::new(dft_ctx, hop, analyze_windows_par(analyzer, &windows))
+}
@@ -0,0 +1,119 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex, OnceLock},
+};
+
+use mutex_ext::LockExt;
+use rustfft::{Fft, FftDirection, FftPlanner};
+
+type Plans = HashMap<(usize, FftDirection), Arc<dyn Fft<f32>>>;
+
+/// A thread-safe cache of FFT plans, keyed by size and direction.
+///
+/// Planning an FFT is relatively expensive and each plan holds its own pre-computed twiddle factors:
+/// sharing plans avoids paying this cost for every analyzer (or synthesizer) of the same size.
+/// The analyzers in this module use the process-wide [`FftCache::global`] instance.
+#[derive(Default)]
+pub struct FftCache {
+	plans: Mutex<Plans>,
+}
+
+impl std::fmt::Debug for FftCache {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("FftCache")
+			.field(
+				"plans",
+				&self
+					.plans
+					.with_lock(|plans| plans.keys().copied().collect::<Vec<_>>()),
+			)
+			.finish()
+	}
+}
+
+impl FftCache {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The process-wide cache.
+	#[must_use]
+	pub fn global() -> &'static FftCache {
+		static GLOBAL: OnceLock<FftCache> = OnceLock::new();
+		GLOBAL.get_or_init(FftCache::new)
+	}
+
+	/// Get (or create and cache) a plan for the forward FFT of `len` samples.
+	#[must_use]
+	pub fn plan_fft_forward(&self, len: usize) -> Arc<dyn Fft<f32>> {
+		self.plan_fft(len, FftDirection::Forward)
+	}
+
+	/// Get (or create and cache) a plan for the inverse FFT of `len` samples.
+	#[must_use]
+	pub fn plan_fft_inverse(&self, len: usize) -> Arc<dyn Fft<f32>> {
+		self.plan_fft(len, FftDirection::Inverse)
+	}
+
+	/// Get (or create and cache) a plan for the FFT of `len` samples in the given `direction`.
+	#[must_use]
+	pub fn plan_fft(&self, len: usize, direction: FftDirection) -> Arc<dyn Fft<f32>> {
+		self.plans.with_lock_mut(|plans| {
+			plans
+				.entry((len, direction))
+				.or_insert_with(|| FftPlanner::new().plan_fft(len, direction))
+				.clone()
+		})
+	}
+
+	/// The number of cached plans.
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.plans.with_lock(HashMap::len)
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Drop all the cached plans. Plans that are still in use are kept alive by their users.
+	pub fn clear(&self) {
+		self.plans.with_lock_mut(HashMap::clear);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reuses_plans_of_the_same_size_and_direction() {
+		let cache = FftCache::new();
+		assert!(cache.is_empty());
+
+		let forward = cache.plan_fft_forward(256);
+		let inverse = cache.plan_fft_inverse(256);
+		assert!(Arc::ptr_eq(&forward, &cache.plan_fft_forward(256)));
+		assert!(!Arc::ptr_eq(&forward, &inverse));
+		assert_eq!(inverse.fft_direction(), FftDirection::Inverse);
+
+		let other_size = cache.plan_fft_forward(512);
+		assert_eq!(other_size.len(), 512);
+		assert_eq!(cache.len(), 3);
+
+		cache.clear();
+		assert!(cache.is_empty());
+		assert!(!Arc::ptr_eq(&forward, &cache.plan_fft_forward(256)));
+	}
+
+	#[test]
+	fn analyzers_share_the_global_cache() {
+		let plan = FftCache::global().plan_fft_forward(1234);
+		let other = std::thread::spawn(|| FftCache::global().plan_fft_forward(1234))
+			.join()
+			.unwrap();
+		assert!(Arc::ptr_eq(&plan, &other));
+	}
+}
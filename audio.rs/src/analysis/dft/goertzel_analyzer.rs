@@ -2,34 +2,88 @@ use std::f32::consts::TAU;
 
 use rustfft::num_complex::{Complex, Complex32};
 
-use crate::analysis::{DftCtx, DiscreteHarmonic, WindowingFn};
+use crate::analysis::{
+	db_spectrum::to_db_spectrum, DbReference, DftCtx, DiscreteHarmonic, Spectrogram, Window,
+	WindowingFn,
+};
 
-#[derive(Debug)]
+use super::batch::{self, WindowAnalyzer};
+
+/// Number of bins processed together while scanning the signal, so that the windowed
+/// signal is read once per group of bins and the (independent) recurrences can be vectorized.
+const LANES: usize = 8;
+
+/// Pre-computed coefficients for a group of [`LANES`] bins.
+///
+/// When the number of bins is not a multiple of [`LANES`], the last group
+/// is padded with zeroed coefficients, whose results are simply discarded.
+#[derive(Debug, Clone, Copy)]
+struct BinLanes {
+	cosine_terms: [f32; LANES],
+	twiddles: [Complex32; LANES],
+}
+
+#[derive(Debug, Clone)]
 pub struct GoertzelAnalyzer {
 	dft_ctx: DftCtx,
-	windowing_values: Vec<f32>,
+	window: Window,
 	cur_transform: Vec<DiscreteHarmonic>,
 	cur_signal: Vec<f32>,
-	coefficients: Vec<(f32, Complex32)>,
+	coefficients: Vec<BinLanes>,
 	normalization_factor: f32,
 }
 
 impl GoertzelAnalyzer {
-	#[allow(clippy::cast_precision_loss)]
 	pub fn new(
 		dft_ctx: DftCtx,
-		mut frequency_bins: Vec<usize>,
+		frequency_bins: Vec<usize>,
 		windowing_fn: &impl WindowingFn,
 	) -> Self {
+		Self::new_with_window(
+			dft_ctx,
+			frequency_bins,
+			&Window::new(windowing_fn, dft_ctx.samples_per_window()),
+		)
+	}
+
+	/// Create an analyzer that shares the pre-computed values of `window`.
+	///
+	/// # Panics
+	/// - if the length of `window` is different from `samples_per_window`.
+	#[allow(clippy::cast_precision_loss)]
+	#[must_use]
+	pub fn new_with_window(
+		dft_ctx: DftCtx,
+		mut frequency_bins: Vec<usize>,
+		window: &Window,
+	) -> Self {
+		assert_eq!(
+			window.len(),
+			dft_ctx.samples_per_window(),
+			"window with incompatible length received"
+		);
 		frequency_bins.sort_unstable();
 		Self {
 			dft_ctx,
 			// Pre-computing coefficients
 			coefficients: frequency_bins
-				.iter()
-				.map(|&bin| {
-					let ω = TAU * bin as f32 / dft_ctx.samples_per_window() as f32;
-					(2.0 * ω.cos(), Complex32::new(ω.cos(), ω.sin()))
+				.chunks(LANES)
+				.map(|bins| {
+					let mut lanes = BinLanes {
+						cosine_terms: [0.; LANES],
+						twiddles: [Complex32::ZERO; LANES],
+					};
+					for ((cosine_term, twiddle), &bin) in lanes
+						.cosine_terms
+						.iter_mut()
+						.zip(lanes.twiddles.iter_mut())
+						.zip(bins)
+					{
+						let ω = TAU * bin as f32 / dft_ctx.samples_per_window() as f32;
+						*cosine_term = 2.0 * ω.cos();
+						*twiddle = Complex32::new(ω.cos(), ω.sin());
+					}
+					lanes
 				})
 				.collect(),
 			cur_transform: frequency_bins
@@ -37,9 +91,7 @@ impl GoertzelAnalyzer {
 				.map(|bin| DiscreteHarmonic::new(Complex::ZERO, bin))
 				.collect(),
 			cur_signal: vec![0.; dft_ctx.samples_per_window()],
-			windowing_values: (0..dft_ctx.samples_per_window())
-				.map(|i| windowing_fn.ratio_at(i, dft_ctx.samples_per_window()))
-				.collect(),
+			window: window.clone(),
 			// Normalization also applies here.
 			// https://docs.rs/rustfft/6.2.0/rustfft/index.html#normalization
 			#[allow(clippy::cast_precision_loss)]
@@ -67,32 +119,188 @@ impl GoertzelAnalyzer {
 			.cur_signal
 			.iter_mut()
 			.zip(signal)
-			.zip(self.windowing_values.iter())
+			.zip(self.window.values())
 		{
 			*dst = sample * windowing_value;
 		}
 
-		for (coeff, bin_point) in self.coefficients.iter().zip(self.cur_transform.iter_mut()) {
-			let mut z1 = 0.0;
-			let mut z2 = 0.0;
+		Self::run_recurrences(
+			&self.coefficients,
+			self.normalization_factor,
+			&self.cur_signal,
+			&mut self.cur_transform,
+		);
 
-			for &sample in &self.cur_signal {
-				let z0 = sample + coeff.0 * z1 - z2;
-				z2 = z1;
-				z1 = z0;
-			}
+		&self.cur_transform
+	}
 
-			bin_point.phasor =
-				Complex32::new(z1 * coeff.1.re - z2, z1 * coeff.1.im) * self.normalization_factor;
-		}
+	/// Same as [`Self::analyze`], but the results are written into `output`, which is cleared first.
+	///
+	/// This lets the caller keep the results across calls while reusing its own allocations.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	pub fn analyze_into(&mut self, signal: &[f32], output: &mut Vec<DiscreteHarmonic>) {
+		output.clear();
+		output.extend_from_slice(self.analyze(signal));
+	}
 
-		&self.cur_transform
+	/// Same as [`Self::analyze`], but the results are returned as an iterator, sorted by frequency bin.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	pub fn analyze_iter(&mut self, signal: &[f32]) -> impl Iterator<Item = DiscreteHarmonic> + '_ {
+		self.analyze(signal).iter().copied()
+	}
+
+	/// Same as [`Self::analyze`], but the results are returned in a newly allocated `Vec`
+	/// that is not tied to the analyzer.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_owned(&mut self, signal: &[f32]) -> Vec<DiscreteHarmonic> {
+		self.analyze(signal).clone()
+	}
+
+	/// Analyze a signal and convert the result to (frequency, dB) pairs, sorted by frequency.
+	///
+	/// The levels are relative to `reference` and are compensated for the window, so that the peak
+	/// of a sinusoid reads its amplitude. Values are clamped to [`crate::analysis::DB_FLOOR`].
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_db(&mut self, signal: &[f32], reference: DbReference) -> Vec<(f32, f32)> {
+		let (dft_ctx, window) = (self.dft_ctx, self.window.clone());
+		to_db_spectrum(self.analyze(signal), dft_ctx, &window, reference)
+	}
+
+	/// Analyze all the complete windows contained in a longer signal, taking a window
+	/// every `hop` samples.
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer(&mut self, signal: &[f32], hop: usize) -> Spectrogram {
+		batch::analyze_buffer(self, signal, hop)
+	}
+
+	/// Analyze multiple windows in sequence, reusing the pre-computed coefficients and the internal
+	/// buffers for all of them. Useful for offline scans, where the setup of each call would
+	/// otherwise dominate.
+	///
+	/// The returned `Vec` has the same order as `windows`.
+	///
+	/// # Panics
+	/// - if any of the passed `windows` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_many(&mut self, windows: &[&[f32]]) -> Vec<Vec<DiscreteHarmonic>> {
+		windows
+			.iter()
+			.map(|window| self.analyze_owned(window))
+			.collect()
+	}
+
+	/// Same as [`Self::analyze_many`], but the windowing function is not applied, i.e. the `windows`
+	/// are expected to be already windowed (or to be analyzed with a rectangular window). This also
+	/// saves copying each window into the internal buffer.
+	///
+	/// # Panics
+	/// - if any of the passed `windows` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_many_unwindowed(&mut self, windows: &[&[f32]]) -> Vec<Vec<DiscreteHarmonic>> {
+		windows
+			.iter()
+			.map(|window| {
+				assert_eq!(
+					window.len(),
+					self.dft_ctx.samples_per_window(),
+					"signal with incompatible length received"
+				);
+				Self::run_recurrences(
+					&self.coefficients,
+					self.normalization_factor,
+					window,
+					&mut self.cur_transform,
+				);
+				self.cur_transform.clone()
+			})
+			.collect()
+	}
+
+	/// Analyze multiple windows in parallel, using a clone of this analyzer for each worker thread.
+	///
+	/// The returned `Vec` has the same order as `windows`.
+	///
+	/// # Panics
+	/// - if any of the passed `windows` is not compatible with the configured `samples_per_window`.
+	#[cfg(feature = "rayon")]
+	#[must_use]
+	pub fn analyze_windows_par(&self, windows: &[&[f32]]) -> Vec<Vec<DiscreteHarmonic>> {
+		batch::analyze_windows_par(self, windows)
+	}
+
+	/// Parallel version of [`Self::analyze_buffer`].
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[cfg(feature = "rayon")]
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer_par(&self, signal: &[f32], hop: usize) -> Spectrogram {
+		batch::analyze_buffer_par(self, signal, hop)
 	}
 
 	#[must_use]
 	pub fn dft_ctx(&self) -> DftCtx {
 		self.dft_ctx
 	}
+
+	#[must_use]
+	pub fn window(&self) -> &Window {
+		&self.window
+	}
+
+	fn run_recurrences(
+		coefficients: &[BinLanes],
+		normalization_factor: f32,
+		signal: &[f32],
+		transform: &mut [DiscreteHarmonic],
+	) {
+		for (lanes, bin_points) in coefficients.iter().zip(transform.chunks_mut(LANES)) {
+			let mut z1 = [0.0; LANES];
+			let mut z2 = [0.0; LANES];
+
+			for &sample in signal {
+				for ((z1, z2), cosine_term) in
+					z1.iter_mut().zip(z2.iter_mut()).zip(lanes.cosine_terms)
+				{
+					let z0 = sample + cosine_term * *z1 - *z2;
+					*z2 = *z1;
+					*z1 = z0;
+				}
+			}
+
+			for (((bin_point, twiddle), z1), z2) in
+				bin_points.iter_mut().zip(lanes.twiddles).zip(z1).zip(z2)
+			{
+				bin_point.phasor =
+					Complex32::new(z1 * twiddle.re - z2, z1 * twiddle.im) * normalization_factor;
+			}
+		}
+	}
+}
+
+impl WindowAnalyzer for GoertzelAnalyzer {
+	fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	fn analyze_window(&mut self, signal: &[f32]) -> &Vec<DiscreteHarmonic> {
+		self.analyze(signal)
+	}
 }
 
 #[cfg(test)]
@@ -100,7 +308,7 @@ impl GoertzelAnalyzer {
 mod tests {
 	use super::*;
 	use crate::{
-		analysis::{windowing_fns::HannWindow, Harmonic},
+		analysis::{find_strongest_peak, windowing_fns::HannWindow, Harmonic},
 		output::harmonics_to_samples,
 		SampleRate,
 	};
@@ -128,14 +336,7 @@ mod tests {
 				&[Harmonic::new(Complex32::ONE, frequency)],
 			);
 			let analysis = stft_analyzer.analyze(&signal);
-			assert_eq!(
-				analysis
-					.iter()
-					.max_by(|a, b| a.power().total_cmp(&b.power()))
-					.unwrap()
-					.bin(),
-				bin
-			);
+			assert_eq!(find_strongest_peak(analysis).unwrap().bin(), bin);
 		}
 	}
 
@@ -160,14 +361,72 @@ mod tests {
 			&[Harmonic::new(Complex32::ONE, frequency)],
 		);
 		let analysis = stft_analyzer.analyze(&signal);
-		let phase = analysis
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
-			.unwrap()
-			.phase();
+		let phase = find_strongest_peak(analysis).unwrap().phase();
 		assert!(phase.abs() < 0.001, "{phase}");
 	}
 
+	#[test]
+	fn goertzel_grouped_bins_match_single_bin_analysis() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 512);
+
+		// Not a multiple of the number of lanes, to also exercise the padded group.
+		let bins: Vec<usize> = (10..10 + LANES * 2 + 3).collect();
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window(),
+			&[
+				Harmonic::new(Complex32::ONE, dft_ctx.bin_to_frequency(12)),
+				Harmonic::new(Complex32::from_polar(0.5, 1.), 3000.),
+			],
+		);
+
+		let mut grouped = GoertzelAnalyzer::new(dft_ctx, bins.clone(), &HannWindow);
+		let grouped_analysis = grouped.analyze_owned(&signal);
+		assert_eq!(grouped_analysis.len(), bins.len());
+
+		for (bin, grouped_result) in bins.into_iter().zip(grouped_analysis) {
+			let mut single = GoertzelAnalyzer::new(dft_ctx, vec![bin], &HannWindow);
+			let single_result = single.analyze(&signal)[0];
+			assert_eq!(single_result.bin(), grouped_result.bin());
+			assert!((single_result.phasor() - grouped_result.phasor()).norm() < f32::EPSILON);
+		}
+	}
+
+	#[test]
+	fn goertzel_analyze_many_matches_single_windows() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let bins = vec![5, 6, 7, 8, 9];
+
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			1024,
+			&[Harmonic::new(Complex32::ONE, dft_ctx.bin_to_frequency(7))],
+		);
+		let windows: Vec<&[f32]> = signal.chunks_exact(dft_ctx.samples_per_window()).collect();
+
+		let mut analyzer = GoertzelAnalyzer::new(dft_ctx, bins.clone(), &HannWindow);
+		let results = analyzer.analyze_many(&windows);
+		assert_eq!(results.len(), windows.len());
+		for (window, result) in windows.iter().zip(&results) {
+			assert_eq!(result, analyzer.analyze(window));
+		}
+
+		// Windowing the signal beforehand and skipping the windowing function yields the same results.
+		let prewindowed: Vec<Vec<f32>> = windows
+			.iter()
+			.map(|window| {
+				window
+					.iter()
+					.enumerate()
+					.map(|(i, sample)| sample * HannWindow.ratio_at(i, window.len()))
+					.collect()
+			})
+			.collect();
+		let prewindowed: Vec<&[f32]> = prewindowed.iter().map(Vec::as_slice).collect();
+		let mut unwindowed = GoertzelAnalyzer::new(dft_ctx, bins, &HannWindow);
+		assert_eq!(unwindowed.analyze_many_unwindowed(&prewindowed), results);
+	}
+
 	#[test]
 	#[allow(clippy::cast_precision_loss)]
 	fn goertzel_peaks_at_frequency_bin_440() {
@@ -183,10 +442,7 @@ mod tests {
 			&[Harmonic::new(Complex32::ONE, 440.)],
 		);
 		let analysis = stft_analyzer.analyze(&signal);
-		let h = analysis
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
-			.unwrap();
+		let h = find_strongest_peak(analysis).unwrap();
 		assert_eq!(h.bin(), 1);
 		assert!(h.phase().abs() < 0.01);
 	}
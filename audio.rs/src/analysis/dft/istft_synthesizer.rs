@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use rustfft::{
+	num_complex::{Complex, Complex32},
+	Fft,
+};
+
+use crate::analysis::{DftCtx, DiscreteHarmonic, Window, WindowingFn};
+
+use super::FftCache;
+
+/// Inverse of [`super::StftAnalyzer`]: turns a sequence of DFT results, taken every `hop` samples,
+/// back into a signal in the domain of time using a windowed overlap-add.
+#[derive(Clone)]
+pub struct IstftSynthesizer {
+	dft_ctx: DftCtx,
+	hop: usize,
+	window: Window,
+	window_normalization: Vec<f32>,
+	ifft_processor: Arc<dyn Fft<f32>>,
+	complex_signal: Vec<Complex32>,
+	scratch: Vec<Complex32>,
+	overlap_buffer: Vec<f32>,
+	output: Vec<f32>,
+	normalization_factor: f32,
+}
+
+impl std::fmt::Debug for IstftSynthesizer {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("IstftSynthesizer")
+			.field("dft_ctx", &self.dft_ctx)
+			.field("hop", &self.hop)
+			.field("window", &self.window)
+			.field("window_normalization", &self.window_normalization)
+			.field("ifft_processor", &"omitted")
+			.field("complex_signal", &self.complex_signal)
+			.field("scratch", &self.scratch)
+			.field("overlap_buffer", &self.overlap_buffer)
+			.field("output", &self.output)
+			.field("normalization_factor", &self.normalization_factor)
+			.finish()
+	}
+}
+
+impl IstftSynthesizer {
+	/// Note: `windowing_fn` is applied again after the inverse transform, therefore it should be
+	/// the same function used to analyze the signal.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, hop: usize, windowing_fn: &impl WindowingFn) -> Self {
+		Self::new_with_window(
+			dft_ctx,
+			hop,
+			&Window::new(windowing_fn, dft_ctx.samples_per_window()),
+		)
+	}
+
+	/// Create a synthesizer that shares the pre-computed values of `window`.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	/// - if the length of `window` is different from `samples_per_window`.
+	#[must_use]
+	pub fn new_with_window(dft_ctx: DftCtx, hop: usize, window: &Window) -> Self {
+		let samples_per_window = dft_ctx.samples_per_window();
+		assert!(
+			hop > 0 && hop <= samples_per_window,
+			"hop ({hop}) must be in the range 1..={samples_per_window}"
+		);
+		assert_eq!(
+			window.len(),
+			samples_per_window,
+			"window with incompatible length received"
+		);
+
+		let ifft_processor = FftCache::global().plan_fft_inverse(samples_per_window);
+		let scratch_len = ifft_processor.get_inplace_scratch_len();
+
+		// Every output sample is the sum of the contributions of all the overlapping windows,
+		// each weighted twice by the windowing function (analysis and synthesis).
+		let window_normalization = (0..hop)
+			.map(|i| {
+				let weight = window
+					.values()
+					.iter()
+					.skip(i)
+					.step_by(hop)
+					.map(|w| w * w)
+					.sum::<f32>();
+				if weight > f32::EPSILON {
+					1. / weight
+				} else {
+					0.
+				}
+			})
+			.collect();
+
+		Self {
+			dft_ctx,
+			hop,
+			window: window.clone(),
+			window_normalization,
+			ifft_processor,
+			complex_signal: vec![Complex::ZERO; samples_per_window],
+			scratch: vec![Complex::ZERO; scratch_len],
+			overlap_buffer: vec![0.; samples_per_window],
+			output: vec![0.; hop],
+			// https://docs.rs/rustfft/6.2.0/rustfft/index.html#normalization
+			#[allow(clippy::cast_precision_loss)]
+			normalization_factor: 1.0 / (samples_per_window as f32).sqrt(),
+		}
+	}
+
+	/// Synthesize the next `hop` samples of the signal, given the DFT of the next window.
+	///
+	/// `transform` is expected to contain the bins between 0 and the Nyquist frequency, like
+	/// the output of [`super::StftAnalyzer::analyze`]. Missing bins are treated as silence.
+	///
+	/// Note: the first `samples_per_window - hop` samples are only partially reconstructed, because
+	/// the synthesizer has not yet received all the windows that overlap them.
+	///
+	/// # Panics
+	/// - if `transform` contains bins outside of the range supported by the configured [`DftCtx`].
+	#[must_use]
+	pub fn synthesize(&mut self, transform: &[DiscreteHarmonic]) -> &[f32] {
+		let samples_per_window = self.dft_ctx.samples_per_window();
+
+		self.complex_signal.fill(Complex::ZERO);
+		for harmonic in transform {
+			let bin = harmonic.bin();
+			assert!(
+				bin < self.dft_ctx.n_of_bins(),
+				"bin {bin} is out of the supported range"
+			);
+			self.complex_signal[bin] = harmonic.phasor();
+			if bin != 0 && bin != samples_per_window - bin {
+				self.complex_signal[samples_per_window - bin] = harmonic.phasor().conj();
+			}
+		}
+
+		self.ifft_processor
+			.process_with_scratch(&mut self.complex_signal, &mut self.scratch);
+
+		for ((dst, src), windowing_value) in self
+			.overlap_buffer
+			.iter_mut()
+			.zip(self.complex_signal.iter())
+			.zip(self.window.values())
+		{
+			*dst += src.re * self.normalization_factor * windowing_value;
+		}
+
+		for ((dst, src), normalization) in self
+			.output
+			.iter_mut()
+			.zip(self.overlap_buffer.iter())
+			.zip(self.window_normalization.iter())
+		{
+			*dst = src * normalization;
+		}
+
+		self.overlap_buffer.copy_within(self.hop.., 0);
+		self.overlap_buffer[samples_per_window - self.hop..].fill(0.);
+
+		&self.output
+	}
+
+	/// Discard the contributions of the previously synthesized windows.
+	pub fn reset(&mut self) {
+		self.overlap_buffer.fill(0.);
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	#[must_use]
+	pub fn hop(&self) -> usize {
+		self.hop
+	}
+
+	#[must_use]
+	pub fn window(&self) -> &Window {
+		&self.window
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		analysis::{dft::StftAnalyzer, windowing_fns::HannWindow},
+		SampleRate,
+	};
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn istft_reconstructs_the_analyzed_signal() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let hop = 64;
+
+		let signal: Vec<f32> = (0..4096)
+			.map(|i| (i as f32 * 0.05).sin() * 0.5 + (i as f32 * 0.31).cos() * 0.25)
+			.collect();
+
+		let mut analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let mut synthesizer = IstftSynthesizer::new(dft_ctx, hop, &HannWindow);
+
+		let mut output = vec![];
+		for offset in (0..=signal.len() - dft_ctx.samples_per_window()).step_by(hop) {
+			output.extend_from_slice(synthesizer.synthesize(
+				analyzer.analyze(&signal[offset..offset + dft_ctx.samples_per_window()]),
+			));
+		}
+
+		// Skip the samples that are not yet covered by all the overlapping windows.
+		let settled = dft_ctx.samples_per_window() - hop;
+		for (reconstructed, original) in output.iter().zip(&signal).skip(settled) {
+			assert!(
+				(reconstructed - original).abs() < 0.001,
+				"{reconstructed} != {original}"
+			);
+		}
+	}
+}
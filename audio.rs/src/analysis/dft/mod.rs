@@ -4,6 +4,14 @@ pub use stft_analyzer::*;
 mod goertzel_analyzer;
 pub use goertzel_analyzer::*;
 
+mod istft_synthesizer;
+pub use istft_synthesizer::*;
+
+mod fft_cache;
+pub use fft_cache::*;
+
+mod batch;
+
 #[cfg(test)]
 #[cfg(feature = "output")]
 mod tests {
@@ -14,6 +22,7 @@ mod tests {
 	use crate::{
 		analysis::{
 			dft::{GoertzelAnalyzer, StftAnalyzer},
+			find_strongest_peak,
 			windowing_fns::HannWindow,
 			DftCtx, Harmonic,
 		},
@@ -50,16 +59,8 @@ mod tests {
 		);
 		let mut stft = StftAnalyzer::new(dft_ctx, &HannWindow::new());
 
-		let stft_result = stft
-			.analyze(&signal)
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
-			.unwrap();
-		let goertzel_result = goertzel
-			.analyze(&signal)
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
-			.unwrap();
+		let stft_result = find_strongest_peak(stft.analyze(&signal)).unwrap();
+		let goertzel_result = find_strongest_peak(goertzel.analyze(&signal)).unwrap();
 
 		assert_eq!(
 			stft_result.bin(),
@@ -1,19 +1,28 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
 use rustfft::{
 	num_complex::{Complex, Complex32},
-	Fft, FftPlanner,
+	Fft,
 };
 
-use crate::analysis::{DftCtx, DiscreteHarmonic, WindowingFn};
+use crate::analysis::{
+	db_spectrum::to_db_spectrum, DbReference, DftCtx, DiscreteHarmonic, Spectrogram, Window,
+	WindowingFn,
+};
+
+use super::{
+	batch::{self, WindowAnalyzer},
+	FftCache,
+};
 
 #[derive(Clone)]
 pub struct StftAnalyzer {
 	dft_ctx: DftCtx,
-	windowing_values: Vec<f32>,
+	window: Window,
 	fft_processor: Arc<dyn Fft<f32>>,
 	complex_signal: Vec<Complex32>,
 	cur_transform: Vec<DiscreteHarmonic>,
+	bins: Range<usize>,
 	normalization_factor: f32,
 	scratch: Vec<Complex32>,
 }
@@ -22,11 +31,12 @@ impl std::fmt::Debug for StftAnalyzer {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("StftAnalyzer")
 			.field("dft_ctx", &self.dft_ctx)
-			.field("windowing_values", &self.windowing_values)
+			.field("window", &self.window)
 			.field("fft_processor", &"omitted")
 			.field("complex_signal", &self.complex_signal)
 			.field("scratch", &self.scratch)
 			.field("cur_transform", &self.cur_transform)
+			.field("bins", &self.bins)
 			.field("normalization_factor", &self.normalization_factor)
 			.finish()
 	}
@@ -35,20 +45,70 @@ impl std::fmt::Debug for StftAnalyzer {
 impl StftAnalyzer {
 	#[must_use]
 	pub fn new(dft_ctx: DftCtx, windowing_fn: &impl WindowingFn) -> Self {
-		let mut planner = FftPlanner::new();
-		let transform_size = dft_ctx.n_of_bins();
-		let fft_processor = planner.plan_fft_forward(dft_ctx.samples_per_window());
+		Self::new_with_window(
+			dft_ctx,
+			&Window::new(windowing_fn, dft_ctx.samples_per_window()),
+		)
+	}
+
+	/// Create an analyzer that shares the pre-computed values of `window`.
+	///
+	/// # Panics
+	/// - if the length of `window` is different from `samples_per_window`.
+	#[must_use]
+	pub fn new_with_window(dft_ctx: DftCtx, window: &Window) -> Self {
+		Self::new_with_bins(dft_ctx, window, 0..dft_ctx.n_of_bins())
+	}
+
+	/// Create an analyzer that only keeps the bins corresponding to the frequencies
+	/// between `frequency_range.0` and `frequency_range.1` (both included), in Hz.
+	///
+	/// Useful for narrowband tasks (e.g. tracking a single instrument), as it reduces
+	/// the size of the transform that has to be stored and iterated.
+	///
+	/// The range is clamped between 0 and the Nyquist frequency, the ones covered by the bins.
+	///
+	/// # Panics
+	/// - if `frequency_range.0` is greater than `frequency_range.1`.
+	#[must_use]
+	pub fn new_with_frequency_range(
+		dft_ctx: DftCtx,
+		windowing_fn: &impl WindowingFn,
+		frequency_range: (f32, f32),
+	) -> Self {
+		assert!(
+			frequency_range.0 <= frequency_range.1,
+			"the lower end of the frequency range must not be greater than the upper end"
+		);
+		#[allow(clippy::cast_precision_loss)]
+		let nyquist_frequency = dft_ctx.sample_rate().0 as f32 / 2.;
+		let first_bin = dft_ctx.frequency_to_bin(frequency_range.0.clamp(0., nyquist_frequency));
+		let last_bin = dft_ctx.frequency_to_bin(frequency_range.1.clamp(0., nyquist_frequency));
+		Self::new_with_bins(
+			dft_ctx,
+			&Window::new(windowing_fn, dft_ctx.samples_per_window()),
+			first_bin..last_bin + 1,
+		)
+	}
+
+	fn new_with_bins(dft_ctx: DftCtx, window: &Window, bins: Range<usize>) -> Self {
+		assert_eq!(
+			window.len(),
+			dft_ctx.samples_per_window(),
+			"window with incompatible length received"
+		);
+		let fft_processor = FftCache::global().plan_fft_forward(dft_ctx.samples_per_window());
 		let scratch_len = fft_processor.get_inplace_scratch_len();
 		Self {
 			dft_ctx,
-			windowing_values: (0..dft_ctx.samples_per_window())
-				.map(|i| windowing_fn.ratio_at(i, dft_ctx.samples_per_window()))
-				.collect(),
+			window: window.clone(),
 			fft_processor,
 			complex_signal: vec![Complex { re: 0., im: 0. }; dft_ctx.samples_per_window()],
-			cur_transform: (0..transform_size)
+			cur_transform: bins
+				.clone()
 				.map(|i| DiscreteHarmonic::new(Complex::ZERO, i))
 				.collect(),
+			bins,
 			scratch: vec![Complex::ZERO; scratch_len],
 			// https://docs.rs/rustfft/6.2.0/rustfft/index.html#normalization
 			#[allow(clippy::cast_precision_loss)]
@@ -58,7 +118,7 @@ impl StftAnalyzer {
 
 	/// Analyze a signal in the domain of time, sampled at the configured sample rate.
 	///
-	/// The returned `Vec` is sorted by frequency bin.
+	/// The returned `Vec` is sorted by frequency bin and contains the bins in [`Self::bins`].
 	///
 	/// Note: performance-wise, FFT works better when the signal length is a power of two.
 	///
@@ -78,7 +138,7 @@ impl StftAnalyzer {
 			.complex_signal
 			.iter_mut()
 			.zip(signal)
-			.zip(self.windowing_values.iter())
+			.zip(self.window.values())
 		{
 			*c = Complex::new(sample * windowing_value, 0.0);
 		}
@@ -86,10 +146,9 @@ impl StftAnalyzer {
 		self.fft_processor
 			.process_with_scratch(&mut self.complex_signal, &mut self.scratch);
 
-		let transform_size = self.cur_transform.len();
 		self.cur_transform
 			.iter_mut()
-			.zip(self.complex_signal.iter().take(transform_size))
+			.zip(self.complex_signal[self.bins.clone()].iter())
 			.for_each(|(dst, src)| {
 				dst.phasor = src * self.normalization_factor;
 			});
@@ -97,10 +156,108 @@ impl StftAnalyzer {
 		&self.cur_transform
 	}
 
+	/// Same as [`Self::analyze`], but the results are written into `output`, which is cleared first.
+	///
+	/// This lets the caller keep the results across calls while reusing its own allocations.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	pub fn analyze_into(&mut self, signal: &[f32], output: &mut Vec<DiscreteHarmonic>) {
+		output.clear();
+		output.extend_from_slice(self.analyze(signal));
+	}
+
+	/// Same as [`Self::analyze`], but the results are returned as an iterator, sorted by frequency bin.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	pub fn analyze_iter(&mut self, signal: &[f32]) -> impl Iterator<Item = DiscreteHarmonic> + '_ {
+		self.analyze(signal).iter().copied()
+	}
+
+	/// Same as [`Self::analyze`], but the results are returned in a newly allocated `Vec`
+	/// that is not tied to the analyzer.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_owned(&mut self, signal: &[f32]) -> Vec<DiscreteHarmonic> {
+		self.analyze(signal).clone()
+	}
+
+	/// Analyze a signal and convert the result to (frequency, dB) pairs, sorted by frequency.
+	///
+	/// The levels are relative to `reference` and are compensated for the window, so that the peak
+	/// of a sinusoid reads its amplitude. Values are clamped to [`crate::analysis::DB_FLOOR`].
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	pub fn analyze_db(&mut self, signal: &[f32], reference: DbReference) -> Vec<(f32, f32)> {
+		let (dft_ctx, window) = (self.dft_ctx, self.window.clone());
+		to_db_spectrum(self.analyze(signal), dft_ctx, &window, reference)
+	}
+
+	/// Analyze all the complete windows contained in a longer signal, taking a window
+	/// every `hop` samples.
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer(&mut self, signal: &[f32], hop: usize) -> Spectrogram {
+		batch::analyze_buffer(self, signal, hop)
+	}
+
+	/// Analyze multiple windows in parallel, using a clone of this analyzer for each worker thread.
+	///
+	/// The returned `Vec` has the same order as `windows`.
+	///
+	/// # Panics
+	/// - if any of the passed `windows` is not compatible with the configured `samples_per_window`.
+	#[cfg(feature = "rayon")]
+	#[must_use]
+	pub fn analyze_windows_par(&self, windows: &[&[f32]]) -> Vec<Vec<DiscreteHarmonic>> {
+		batch::analyze_windows_par(self, windows)
+	}
+
+	/// Parallel version of [`Self::analyze_buffer`].
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[cfg(feature = "rayon")]
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer_par(&self, signal: &[f32], hop: usize) -> Spectrogram {
+		batch::analyze_buffer_par(self, signal, hop)
+	}
+
 	#[must_use]
 	pub fn dft_ctx(&self) -> DftCtx {
 		self.dft_ctx
 	}
+
+	#[must_use]
+	pub fn window(&self) -> &Window {
+		&self.window
+	}
+
+	/// The range of bins computed by this analyzer, i.e. all the bins up to the Nyquist
+	/// frequency unless the analyzer was created with [`Self::new_with_frequency_range`].
+	#[must_use]
+	pub fn bins(&self) -> Range<usize> {
+		self.bins.clone()
+	}
+}
+
+impl WindowAnalyzer for StftAnalyzer {
+	fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	fn analyze_window(&mut self, signal: &[f32]) -> &Vec<DiscreteHarmonic> {
+		self.analyze(signal)
+	}
 }
 
 #[cfg(test)]
@@ -109,7 +266,7 @@ mod tests {
 	use math_utils::one_dimensional_mapping::MapRatio;
 
 	use crate::{
-		analysis::{windowing_fns::HannWindow, Harmonic},
+		analysis::{find_strongest_peak, windowing_fns::HannWindow, Harmonic, DB_FLOOR},
 		output::harmonics_to_samples,
 		SampleRate,
 	};
@@ -137,14 +294,7 @@ mod tests {
 				&[Harmonic::new(Complex32::ONE, frequency)],
 			);
 			let analysis = stft_analyzer.analyze(&signal);
-			assert_eq!(
-				analysis
-					.iter()
-					.max_by(|a, b| a.power().total_cmp(&b.power()))
-					.unwrap()
-					.bin(),
-				bins[10]
-			);
+			assert_eq!(find_strongest_peak(analysis).unwrap().bin(), bins[10]);
 		}
 	}
 
@@ -160,9 +310,7 @@ mod tests {
 			&[Harmonic::new(Complex32::ONE, 440.)],
 		);
 		let analysis = stft_analyzer.analyze(&signal);
-		let h = analysis[1..] // skip 0Hz
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
+		let h = find_strongest_peak(&analysis[1..]) // skip 0Hz
 			.unwrap();
 		assert_eq!(h.bin(), 1);
 		assert!(h.phase().abs() < 0.01);
@@ -185,11 +333,161 @@ mod tests {
 			&[Harmonic::new(Complex32::ONE, frequency)],
 		);
 		let analysis = stft_analyzer.analyze(&signal);
-		let phase = analysis
-			.iter()
-			.max_by(|a, b| a.power().total_cmp(&b.power()))
-			.unwrap()
-			.phase();
+		let phase = find_strongest_peak(analysis).unwrap().phase();
 		assert!(phase.abs() < 0.001, "{phase}");
 	}
+
+	#[test]
+	fn stft_frequency_range() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 4410);
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window(),
+			&[Harmonic::new(Complex32::ONE, 440.)],
+		);
+
+		let mut full_analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let mut band_analyzer =
+			StftAnalyzer::new_with_frequency_range(dft_ctx, &HannWindow, (400., 500.));
+		assert_eq!(band_analyzer.bins(), 40..51);
+
+		let band = band_analyzer.analyze_owned(&signal);
+		assert_eq!(band.len(), 11);
+		assert_eq!(band, full_analyzer.analyze(&signal)[40..51]);
+		assert_eq!(find_strongest_peak(&band).unwrap().bin(), 44);
+	}
+
+	#[test]
+	fn stft_frequency_range_is_clamped() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 4410);
+		let analyzer = StftAnalyzer::new_with_frequency_range(dft_ctx, &HannWindow, (20., 24000.));
+		assert_eq!(analyzer.bins(), 2..dft_ctx.n_of_bins());
+		let analyzer = StftAnalyzer::new_with_frequency_range(dft_ctx, &HannWindow, (-100., 10.));
+		assert_eq!(analyzer.bins(), 0..2);
+	}
+
+	#[test]
+	fn stft_db_spectrum() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 4410);
+		let frequency = dft_ctx.bin_to_frequency(100);
+		// harmonics_to_samples normalizes the amplitudes, scale the sinusoid afterwards.
+		let signal: Vec<f32> = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window(),
+			&[Harmonic::new(Complex32::ONE, frequency)],
+		)
+		.into_iter()
+		.map(|sample| sample * 0.5)
+		.collect();
+
+		let mut stft_analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let spectrum = stft_analyzer.analyze_db(&signal, DbReference::FullScale);
+		assert_eq!(spectrum.len(), dft_ctx.n_of_bins());
+
+		let (peak_frequency, peak_db) = spectrum[100];
+		assert!((peak_frequency - frequency).abs() < f32::EPSILON);
+		assert!((peak_db + 6.02).abs() < 0.1, "{peak_db}");
+
+		let spectrum = stft_analyzer.analyze_db(&signal, DbReference::Amplitude(0.5));
+		assert!(spectrum[100].1.abs() < 0.1, "{}", spectrum[100].1);
+
+		let silence = stft_analyzer.analyze_db(&vec![0.; 4410], DbReference::FullScale);
+		assert!(silence
+			.iter()
+			.all(|&(_, db)| (db - DB_FLOOR).abs() < f32::EPSILON));
+	}
+
+	#[test]
+	fn stft_shared_window() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			dft_ctx.samples_per_window(),
+			&[Harmonic::new(Complex32::ONE, 1000.)],
+		);
+
+		let window = Window::new(&HannWindow, dft_ctx.samples_per_window());
+		let mut shared = StftAnalyzer::new_with_window(dft_ctx, &window);
+		let mut other = StftAnalyzer::new_with_window(dft_ctx, &window);
+		assert_eq!(
+			shared.window().values().as_ptr(),
+			other.window().values().as_ptr()
+		);
+
+		let mut own = StftAnalyzer::new(dft_ctx, &HannWindow);
+		assert_eq!(shared.analyze(&signal), own.analyze(&signal));
+		assert_eq!(other.analyze(&signal), own.analyze(&signal));
+	}
+
+	#[test]
+	fn stft_owned_and_iterator_results() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			512,
+			&[Harmonic::new(Complex32::ONE, 1000.)],
+		);
+		let (first_window, second_window) = signal.split_at(dft_ctx.samples_per_window());
+
+		let mut stft_analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let first = stft_analyzer.analyze_owned(first_window);
+
+		let mut second = vec![DiscreteHarmonic::new(Complex32::ONE, 1000)];
+		stft_analyzer.analyze_into(second_window, &mut second);
+		assert_eq!(second.len(), dft_ctx.n_of_bins());
+		assert_eq!(&second, stft_analyzer.analyze(second_window));
+
+		// The results of the first window are still available.
+		assert_ne!(first, second);
+		assert_eq!(
+			stft_analyzer.analyze_iter(first_window).collect::<Vec<_>>(),
+			first
+		);
+	}
+
+	#[test]
+	fn stft_analyze_buffer_matches_single_windows() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let hop = 100;
+
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			1000,
+			&[Harmonic::new(Complex32::ONE, 1000.)],
+		);
+
+		let mut stft_analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let spectrogram = stft_analyzer.analyze_buffer(&signal, hop);
+		assert_eq!(spectrogram.n_of_windows(), 8);
+
+		for (i, window) in spectrogram.windows().iter().enumerate() {
+			let offset = spectrogram.window_offset(i);
+			assert_eq!(
+				window,
+				stft_analyzer.analyze(&signal[offset..offset + dft_ctx.samples_per_window()])
+			);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "rayon")]
+	fn stft_analyze_buffer_par_matches_sequential() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 256);
+		let hop = 64;
+
+		let signal = harmonics_to_samples(
+			dft_ctx.sample_rate(),
+			4096,
+			&[
+				Harmonic::new(Complex32::ONE, 1000.),
+				Harmonic::new(Complex32::ONE, 5000.),
+			],
+		);
+
+		let mut stft_analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		assert_eq!(
+			stft_analyzer.analyze_buffer_par(&signal, hop),
+			stft_analyzer.analyze_buffer(&signal, hop)
+		);
+	}
 }
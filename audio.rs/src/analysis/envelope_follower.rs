@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use crate::{metering::amplitude_to_dB, SampleRate};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EnvelopeMode {
+	/// Follows the absolute value of the signal, reacting to every transient.
+	#[default]
+	Peak,
+	/// Follows the mean square of the signal and returns its square root, which better matches the perceived loudness.
+	Rms,
+}
+
+/// Tracks the level of a signal with separate attack and release time constants, as the
+/// building block of gates, compressors and level meters.
+///
+/// When the input level rises above the current envelope, the envelope moves towards it with the
+/// attack time constant, otherwise it decays with the release time constant. A time constant
+/// is the time the envelope takes to cover about 63% of the distance to a new steady level.
+#[derive(Debug, Clone)]
+pub struct EnvelopeFollower {
+	sample_rate: SampleRate,
+	mode: EnvelopeMode,
+	attack: Duration,
+	release: Duration,
+	attack_coefficient: f32,
+	release_coefficient: f32,
+	/// The current state, squared in [`EnvelopeMode::Rms`].
+	state: f32,
+}
+
+impl EnvelopeFollower {
+	#[must_use]
+	pub fn new(
+		sample_rate: SampleRate,
+		mode: EnvelopeMode,
+		attack: Duration,
+		release: Duration,
+	) -> Self {
+		Self {
+			sample_rate,
+			mode,
+			attack,
+			release,
+			attack_coefficient: smoothing_coefficient(sample_rate, attack),
+			release_coefficient: smoothing_coefficient(sample_rate, release),
+			state: 0.,
+		}
+	}
+
+	/// Feed the next sample, returning the updated envelope.
+	pub fn process_sample(&mut self, sample: f32) -> f32 {
+		let input = match self.mode {
+			EnvelopeMode::Peak => sample.abs(),
+			EnvelopeMode::Rms => sample * sample,
+		};
+		let coefficient = if input > self.state {
+			self.attack_coefficient
+		} else {
+			self.release_coefficient
+		};
+		self.state = input + coefficient * (self.state - input);
+		self.value()
+	}
+
+	/// Feed the next chunk of the signal, writing the envelope after each sample into `output`.
+	///
+	/// # Panics
+	/// - if `output` and `signal` have different lengths.
+	pub fn process_into(&mut self, signal: &[f32], output: &mut [f32]) {
+		assert_eq!(
+			signal.len(),
+			output.len(),
+			"output must have the same length as signal"
+		);
+		for (dst, &sample) in output.iter_mut().zip(signal) {
+			*dst = self.process_sample(sample);
+		}
+	}
+
+	/// Feed the next chunk of the signal, returning the envelope after each sample.
+	#[must_use]
+	pub fn process(&mut self, signal: &[f32]) -> Vec<f32> {
+		let mut output = vec![0.; signal.len()];
+		self.process_into(signal, &mut output);
+		output
+	}
+
+	/// The current envelope, with the same unit as the samples.
+	#[must_use]
+	pub fn value(&self) -> f32 {
+		match self.mode {
+			EnvelopeMode::Peak => self.state,
+			EnvelopeMode::Rms => self.state.sqrt(),
+		}
+	}
+
+	/// The current envelope, in dBFS.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn dB(&self) -> f32 {
+		amplitude_to_dB(self.value())
+	}
+
+	/// Bring the envelope back to silence.
+	pub fn reset(&mut self) {
+		self.state = 0.;
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	#[must_use]
+	pub fn mode(&self) -> EnvelopeMode {
+		self.mode
+	}
+
+	#[must_use]
+	pub fn attack(&self) -> Duration {
+		self.attack
+	}
+
+	#[must_use]
+	pub fn release(&self) -> Duration {
+		self.release
+	}
+}
+
+/// The one-pole coefficient corresponding to `time_constant`. A zero time constant
+/// makes the envelope follow the input instantly.
+fn smoothing_coefficient(sample_rate: SampleRate, time_constant: Duration) -> f32 {
+	#[allow(clippy::cast_precision_loss)]
+	let samples = time_constant.as_secs_f64() * sample_rate.0 as f64;
+	if samples <= 0. {
+		return 0.;
+	}
+	#[allow(clippy::cast_possible_truncation)]
+	return (-1. / samples).exp() as f32;
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+
+	use super::*;
+
+	#[test]
+	fn attack_and_release_time_constants() {
+		let sample_rate = SampleRate(1000);
+		let mut follower = EnvelopeFollower::new(
+			sample_rate,
+			EnvelopeMode::Peak,
+			Duration::from_millis(10),
+			Duration::from_millis(100),
+		);
+
+		let envelope = follower.process(&[1.; 10]);
+		assert!(
+			(envelope[9] - (1. - (-1f32).exp())).abs() < 0.01,
+			"{envelope:?}"
+		);
+
+		for _ in 0..1000 {
+			follower.process_sample(1.);
+		}
+		assert!((follower.value() - 1.).abs() < 1e-3);
+
+		let envelope = follower.process(&[0.; 100]);
+		assert!(
+			(envelope[99] - (-1f32).exp()).abs() < 0.01,
+			"{}",
+			envelope[99]
+		);
+
+		follower.reset();
+		assert!(follower.value().abs() < f32::EPSILON);
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn peak_and_rms_levels_of_a_sinusoid() {
+		let sample_rate = SampleRate(48000);
+		let signal: Vec<f32> = (0..48000)
+			.map(|i| (TAU * 100. * i as f32 / 48000.).sin())
+			.collect();
+
+		let mut peak = EnvelopeFollower::new(
+			sample_rate,
+			EnvelopeMode::Peak,
+			Duration::ZERO,
+			Duration::from_secs(1),
+		);
+		let envelope = peak.process(&signal);
+		assert!((envelope[47999] - 1.).abs() < 0.02, "{}", envelope[47999]);
+
+		let mut rms = EnvelopeFollower::new(
+			sample_rate,
+			EnvelopeMode::Rms,
+			Duration::from_millis(100),
+			Duration::from_millis(100),
+		);
+		let mut output = vec![0.; signal.len()];
+		rms.process_into(&signal, &mut output);
+		assert!(
+			(rms.value() - FRAC_1_SQRT_2).abs() < 0.02,
+			"{}",
+			rms.value()
+		);
+		assert!((rms.dB() + 3.01).abs() < 0.3, "{}", rms.dB());
+	}
+}
@@ -0,0 +1,145 @@
+use std::f32::consts::TAU;
+
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::{analysis::dft::FftCache, SampleRate};
+
+/// Compute the analytic signal of `signal` using an FFT-based Hilbert transform.
+///
+/// The real part of each sample is the original signal, while the imaginary part is its
+/// Hilbert transform (i.e. the signal with all its components shifted by -90°). For example,
+/// the analytic signal of `cos(ωt)` is `e^(iωt)`.
+///
+/// Note: the transform assumes the signal is periodic, therefore the samples near the edges
+/// are less accurate unless the signal fades in and out.
+#[must_use]
+pub fn analytic_signal(signal: &[f32]) -> Vec<Complex32> {
+	let len = signal.len();
+	if len == 0 {
+		return vec![];
+	}
+
+	let planner = FftCache::global();
+	let mut buffer: Vec<Complex32> = signal.iter().map(|&s| Complex::new(s, 0.)).collect();
+	planner.plan_fft_forward(len).process(&mut buffer);
+
+	// Double the positive frequencies and remove the negative ones. DC and Nyquist are kept as they are.
+	for (k, value) in buffer.iter_mut().enumerate().skip(1) {
+		if 2 * k < len {
+			*value *= 2.;
+		} else if 2 * k > len {
+			*value = Complex::ZERO;
+		}
+	}
+
+	planner.plan_fft_inverse(len).process(&mut buffer);
+	#[allow(clippy::cast_precision_loss)]
+	let normalization_factor = 1. / len as f32;
+	for value in &mut buffer {
+		*value *= normalization_factor;
+	}
+	buffer
+}
+
+/// Instantaneous amplitude and frequency of a signal, sample by sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+	sample_rate: SampleRate,
+	amplitudes: Vec<f32>,
+	frequencies: Vec<f32>,
+}
+
+impl Envelope {
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	/// The instantaneous amplitude (a.k.a. envelope) of each sample, i.e. the AM demodulated signal.
+	#[must_use]
+	pub fn amplitudes(&self) -> &[f32] {
+		&self.amplitudes
+	}
+
+	/// The instantaneous frequency of each sample in Hz, i.e. the FM demodulated signal.
+	#[must_use]
+	pub fn frequencies(&self) -> &[f32] {
+		&self.frequencies
+	}
+}
+
+/// Extract the instantaneous amplitude and frequency of `signal`, using its [`analytic_signal`].
+///
+/// The instantaneous frequency is only meaningful for signals that contain a single component
+/// (or a narrow band of frequencies) at any given time.
+#[must_use]
+pub fn envelope(signal: &[f32], sample_rate: SampleRate) -> Envelope {
+	let analytic = analytic_signal(signal);
+	#[allow(clippy::cast_precision_loss)]
+	let sample_rate_f = sample_rate.0 as f32;
+
+	let mut frequencies: Vec<f32> = analytic
+		.windows(2)
+		.map(|pair| (pair[1] * pair[0].conj()).arg() * sample_rate_f / TAU)
+		.collect();
+	// The last sample has no successor, reuse the previous estimate.
+	if let Some(&last) = frequencies.last() {
+		frequencies.push(last);
+	} else if !analytic.is_empty() {
+		frequencies.push(0.);
+	}
+
+	Envelope {
+		sample_rate,
+		amplitudes: analytic.iter().map(Complex32::norm).collect(),
+		frequencies,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn analytic_signal_of_a_cosine() {
+		// An integer number of periods, so that the signal is periodic in the transform.
+		let signal: Vec<f32> = (0..1000)
+			.map(|i| (TAU * 10. * i as f32 / 1000.).cos())
+			.collect();
+
+		let analytic = analytic_signal(&signal);
+		assert_eq!(analytic.len(), signal.len());
+		for (i, value) in analytic.iter().enumerate() {
+			let expected = Complex32::from_polar(1., TAU * 10. * i as f32 / 1000.);
+			assert!(
+				(value - expected).norm() < 1e-3,
+				"{value:?} != {expected:?}"
+			);
+		}
+
+		assert!(analytic_signal(&[]).is_empty());
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn demodulates_amplitude_and_frequency() {
+		let sample_rate = SampleRate(8000);
+		// 440Hz carrier, amplitude modulated at 5Hz.
+		let modulation = |i: usize| 1. + 0.5 * (TAU * 5. * i as f32 / 8000.).cos();
+		let signal: Vec<f32> = (0..8000)
+			.map(|i| modulation(i) * (TAU * 440. * i as f32 / 8000.).cos())
+			.collect();
+
+		let envelope = envelope(&signal, sample_rate);
+		assert_eq!(envelope.amplitudes().len(), signal.len());
+		assert_eq!(envelope.frequencies().len(), signal.len());
+
+		for i in (100..7900).step_by(50) {
+			let amplitude = envelope.amplitudes()[i];
+			assert!((amplitude - modulation(i)).abs() < 0.01, "{i}: {amplitude}");
+			let frequency = envelope.frequencies()[i];
+			assert!((frequency - 440.).abs() < 1., "{i}: {frequency}");
+		}
+	}
+}
@@ -0,0 +1,295 @@
+use crate::analysis::{
+	dft::{IstftSynthesizer, StftAnalyzer},
+	DftCtx, DiscreteHarmonic, Spectrogram, Window, WindowingFn,
+};
+
+/// Default length of the median filters, in windows (harmonic) and bins (percussive).
+const DEFAULT_KERNEL_SIZE: usize = 17;
+
+/// The two components of a signal separated by [`HarmonicPercussiveSeparator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarmonicPercussiveSignals {
+	harmonic: Vec<f32>,
+	percussive: Vec<f32>,
+}
+
+impl HarmonicPercussiveSignals {
+	/// The stationary, tonal part of the signal (e.g. sustained notes).
+	#[must_use]
+	pub fn harmonic(&self) -> &[f32] {
+		&self.harmonic
+	}
+
+	/// The transient, broadband part of the signal (e.g. drum hits).
+	#[must_use]
+	pub fn percussive(&self) -> &[f32] {
+		&self.percussive
+	}
+
+	#[must_use]
+	pub fn into_signals(self) -> (Vec<f32>, Vec<f32>) {
+		(self.harmonic, self.percussive)
+	}
+}
+
+/// Splits a signal into its harmonic and percussive components using median filtering (Fitzgerald, 2010).
+///
+/// In a spectrogram, harmonic sounds appear as horizontal lines (stable over time) and percussive sounds
+/// as vertical lines (spread over all the frequencies). Median filtering the magnitudes across time enhances
+/// the former, while median filtering across frequency enhances the latter. The two enhanced spectrograms
+/// are then used to build soft masks, so that the two components always add up to the original signal.
+#[derive(Debug, Clone)]
+pub struct HarmonicPercussiveSeparator {
+	hop: usize,
+	harmonic_kernel_size: usize,
+	percussive_kernel_size: usize,
+	analyzer: StftAnalyzer,
+	harmonic_synthesizer: IstftSynthesizer,
+	percussive_synthesizer: IstftSynthesizer,
+}
+
+impl HarmonicPercussiveSeparator {
+	/// Create a separator that uses median filters of 17 windows and 17 bins.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, hop: usize, windowing_fn: &impl WindowingFn) -> Self {
+		Self::new_with_kernel_sizes(
+			dft_ctx,
+			hop,
+			windowing_fn,
+			DEFAULT_KERNEL_SIZE,
+			DEFAULT_KERNEL_SIZE,
+		)
+	}
+
+	/// Create a separator with custom median filter lengths: `harmonic_kernel_size` is the number of windows
+	/// considered when filtering across time, `percussive_kernel_size` the number of bins considered when
+	/// filtering across frequency.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	/// - if any of the kernel sizes is 0.
+	#[must_use]
+	pub fn new_with_kernel_sizes(
+		dft_ctx: DftCtx,
+		hop: usize,
+		windowing_fn: &impl WindowingFn,
+		harmonic_kernel_size: usize,
+		percussive_kernel_size: usize,
+	) -> Self {
+		assert!(
+			harmonic_kernel_size > 0 && percussive_kernel_size > 0,
+			"kernel sizes must be greater than 0"
+		);
+		let window = Window::new(windowing_fn, dft_ctx.samples_per_window());
+		Self {
+			hop,
+			harmonic_kernel_size,
+			percussive_kernel_size,
+			analyzer: StftAnalyzer::new_with_window(dft_ctx, &window),
+			harmonic_synthesizer: IstftSynthesizer::new_with_window(dft_ctx, hop, &window),
+			percussive_synthesizer: IstftSynthesizer::new_with_window(dft_ctx, hop, &window),
+		}
+	}
+
+	/// Separate a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// The returned signals have the same length as `signal`.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn separate(&mut self, signal: &[f32]) -> HarmonicPercussiveSignals {
+		let samples_per_window = self.dft_ctx().samples_per_window();
+
+		// Pad both ends so that every sample of the signal is covered by all its overlapping windows.
+		let lead = samples_per_window - self.hop;
+		let mut padded = vec![0.; lead + signal.len() + samples_per_window];
+		padded[lead..lead + signal.len()].copy_from_slice(signal);
+
+		let spectrogram = self.analyzer.analyze_buffer(&padded, self.hop);
+		let (harmonic, percussive) = self.separate_spectrogram(&spectrogram);
+
+		self.harmonic_synthesizer.reset();
+		self.percussive_synthesizer.reset();
+		let mut harmonic_signal = Vec::with_capacity(padded.len());
+		let mut percussive_signal = Vec::with_capacity(padded.len());
+		for (harmonic_window, percussive_window) in
+			harmonic.windows().iter().zip(percussive.windows())
+		{
+			harmonic_signal
+				.extend_from_slice(self.harmonic_synthesizer.synthesize(harmonic_window));
+			percussive_signal
+				.extend_from_slice(self.percussive_synthesizer.synthesize(percussive_window));
+		}
+
+		HarmonicPercussiveSignals {
+			harmonic: harmonic_signal[lead..lead + signal.len()].to_vec(),
+			percussive: percussive_signal[lead..lead + signal.len()].to_vec(),
+		}
+	}
+
+	/// Separate a spectrogram into its harmonic and percussive components, returned in this order.
+	///
+	/// The bins of the two resulting spectrograms always add up to the bins of `spectrogram`.
+	#[must_use]
+	pub fn separate_spectrogram(&self, spectrogram: &Spectrogram) -> (Spectrogram, Spectrogram) {
+		let magnitudes: Vec<Vec<f32>> = spectrogram
+			.windows()
+			.iter()
+			.map(|window| window.iter().map(DiscreteHarmonic::amplitude).collect())
+			.collect();
+
+		let mut harmonic_windows = Vec::with_capacity(magnitudes.len());
+		let mut percussive_windows = Vec::with_capacity(magnitudes.len());
+		let mut neighbors =
+			Vec::with_capacity(self.harmonic_kernel_size.max(self.percussive_kernel_size));
+
+		for (window_idx, window) in spectrogram.windows().iter().enumerate() {
+			let mut harmonic_window = Vec::with_capacity(window.len());
+			let mut percussive_window = Vec::with_capacity(window.len());
+
+			for (bin_idx, bin) in window.iter().enumerate() {
+				neighbors.clear();
+				neighbors.extend(
+					kernel_range(window_idx, self.harmonic_kernel_size, magnitudes.len())
+						.map(|i| magnitudes[i][bin_idx]),
+				);
+				let harmonic_enhanced = median(&mut neighbors);
+
+				neighbors.clear();
+				neighbors.extend(
+					kernel_range(bin_idx, self.percussive_kernel_size, window.len())
+						.map(|i| magnitudes[window_idx][i]),
+				);
+				let percussive_enhanced = median(&mut neighbors);
+
+				// Wiener-like soft mask.
+				let harmonic_power = harmonic_enhanced * harmonic_enhanced;
+				let percussive_power = percussive_enhanced * percussive_enhanced;
+				let total_power = harmonic_power + percussive_power;
+				let harmonic_mask = if total_power > f32::EPSILON {
+					harmonic_power / total_power
+				} else {
+					0.5
+				};
+
+				let phasor = bin.phasor();
+				harmonic_window.push(DiscreteHarmonic::new(phasor * harmonic_mask, bin.bin()));
+				percussive_window.push(DiscreteHarmonic::new(
+					phasor * (1. - harmonic_mask),
+					bin.bin(),
+				));
+			}
+
+			harmonic_windows.push(harmonic_window);
+			percussive_windows.push(percussive_window);
+		}
+
+		(
+			Spectrogram::new(spectrogram.dft_ctx(), spectrogram.hop(), harmonic_windows),
+			Spectrogram::new(spectrogram.dft_ctx(), spectrogram.hop(), percussive_windows),
+		)
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.analyzer.dft_ctx()
+	}
+
+	#[must_use]
+	pub fn hop(&self) -> usize {
+		self.hop
+	}
+}
+
+/// The indices covered by a kernel of `kernel_size` elements centered in `center`, clamped to `0..len`.
+fn kernel_range(center: usize, kernel_size: usize, len: usize) -> std::ops::Range<usize> {
+	let half = kernel_size / 2;
+	center.saturating_sub(half)..(center + kernel_size - half).min(len)
+}
+
+fn median(values: &mut [f32]) -> f32 {
+	let mid = values.len() / 2;
+	let (_, &mut median, _) = values.select_nth_unstable_by(mid, f32::total_cmp);
+	median
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use crate::{analysis::windowing_fns::HannWindow, SampleRate};
+
+	use super::*;
+
+	/// A steady tone with a click every `click_period` samples.
+	#[allow(clippy::cast_precision_loss)]
+	fn tone_with_clicks(
+		sample_rate: SampleRate,
+		n_of_samples: usize,
+		click_period: usize,
+	) -> Vec<f32> {
+		(0..n_of_samples)
+			.map(|i| {
+				let tone = 0.5 * (TAU * 440. * i as f32 / sample_rate.0 as f32).sin();
+				let click = if i % click_period == click_period / 2 {
+					1.
+				} else {
+					0.
+				};
+				tone + click
+			})
+			.collect()
+	}
+
+	fn energy(signal: &[f32]) -> f32 {
+		signal.iter().map(|s| s * s).sum()
+	}
+
+	#[test]
+	fn components_add_up_to_the_original_signal() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 256);
+		let signal = tone_with_clicks(dft_ctx.sample_rate(), 4000, 1000);
+
+		let mut separator = HarmonicPercussiveSeparator::new(dft_ctx, 64, &HannWindow);
+		let separated = separator.separate(&signal);
+		assert_eq!(separated.harmonic().len(), signal.len());
+		assert_eq!(separated.percussive().len(), signal.len());
+
+		for ((h, p), s) in separated
+			.harmonic()
+			.iter()
+			.zip(separated.percussive())
+			.zip(&signal)
+		{
+			assert!((h + p - s).abs() < 1e-3, "{h} + {p} != {s}");
+		}
+	}
+
+	#[test]
+	fn separates_tones_from_clicks() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 256);
+		let click_period = 1000;
+		let signal = tone_with_clicks(dft_ctx.sample_rate(), 8000, click_period);
+
+		let mut separator = HarmonicPercussiveSeparator::new(dft_ctx, 64, &HannWindow);
+		let (harmonic, percussive) = separator.separate(&signal).into_signals();
+
+		// Halfway between two clicks (at 500 and 1500), the percussive component is almost silent.
+		let quiet_zone = 800..1200;
+		assert!(
+			energy(&percussive[quiet_zone.clone()]) < 0.05 * energy(&harmonic[quiet_zone]),
+			"the tone leaked into the percussive component"
+		);
+
+		// Around a click, the percussive component dominates the sample of the click.
+		let click = click_period + click_period / 2;
+		assert!(
+			percussive[click].abs() > harmonic[click].abs() + 0.5,
+			"{} vs {}",
+			percussive[click],
+			harmonic[click]
+		);
+	}
+}
@@ -0,0 +1,273 @@
+#![allow(clippy::cast_precision_loss)]
+
+use std::time::Duration;
+
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::{
+	analysis::{dft::FftCache, Harmonic},
+	SampleRate,
+};
+
+/// Regularization of the spectral division used by [`ImpulseResponse::from_sweep`], relative to the
+/// peak power of the excitation spectrum. It prevents the amplification of noise at the frequencies
+/// that the excitation doesn't cover.
+const DECONVOLUTION_REGULARIZATION: f32 = 1e-4;
+
+/// Decay range (in dB, relative to the total energy) used to estimate the reverberation time (T20).
+const RT60_FIT_RANGE: (f32, f32) = (-5., -25.);
+
+/// The response of a linear system (e.g. a speaker in a room) to a unit impulse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpulseResponse {
+	sample_rate: SampleRate,
+	samples: Vec<f32>,
+}
+
+impl ImpulseResponse {
+	#[must_use]
+	pub fn new(sample_rate: SampleRate, samples: Vec<f32>) -> Self {
+		Self {
+			sample_rate,
+			samples,
+		}
+	}
+
+	/// Compute the impulse response by deconvolving the `recording` of the response of a system
+	/// with the `excitation` signal that was played, e.g. one generated by `output::exponential_sine_sweep`.
+	///
+	/// The recording should start together with the excitation and last longer than it, so that the tail
+	/// of the response is captured as well. The resulting impulse response has the same length as the
+	/// recording.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn from_sweep(sample_rate: SampleRate, excitation: &[f32], recording: &[f32]) -> Self {
+		// Zero-padding to (at least) the sum of the lengths turns the circular convolution
+		// computed by the FFT into a linear one, so that the non-causal artifacts (e.g. the harmonic distortion
+		// products of an ESS) end up after the portion of the result that is kept.
+		let transform_size = (excitation.len() + recording.len())
+			.max(1)
+			.next_power_of_two();
+
+		let planner = FftCache::global();
+		let forward = planner.plan_fft_forward(transform_size);
+		let inverse = planner.plan_fft_inverse(transform_size);
+
+		let to_padded_complex = |signal: &[f32]| {
+			let mut out = vec![Complex32::ZERO; transform_size];
+			for (dst, &src) in out.iter_mut().zip(signal) {
+				*dst = Complex::new(src, 0.);
+			}
+			out
+		};
+
+		let mut excitation_spectrum = to_padded_complex(excitation);
+		let mut spectrum = to_padded_complex(recording);
+		forward.process(&mut excitation_spectrum);
+		forward.process(&mut spectrum);
+
+		let regularization = excitation_spectrum
+			.iter()
+			.map(Complex32::norm_sqr)
+			.fold(0., f32::max)
+			* DECONVOLUTION_REGULARIZATION;
+
+		for (y, x) in spectrum.iter_mut().zip(&excitation_spectrum) {
+			let denominator = x.norm_sqr() + regularization;
+			*y = if denominator > f32::EPSILON {
+				*y * x.conj() / denominator
+			} else {
+				Complex::ZERO
+			};
+		}
+		inverse.process(&mut spectrum);
+
+		// https://docs.rs/rustfft/6.2.0/rustfft/index.html#normalization
+		let normalization_factor = 1. / transform_size as f32;
+		Self::new(
+			sample_rate,
+			spectrum
+				.iter()
+				.take(recording.len())
+				.map(|c| c.re * normalization_factor)
+				.collect(),
+		)
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	#[must_use]
+	pub fn samples(&self) -> &[f32] {
+		&self.samples
+	}
+
+	#[must_use]
+	pub fn into_samples(self) -> Vec<f32> {
+		self.samples
+	}
+
+	#[must_use]
+	pub fn duration(&self) -> Duration {
+		Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate.0 as f64)
+	}
+
+	/// The energy decay curve (Schroeder backward integration), in dB relative to the total energy
+	/// of the response. The curve starts at 0dB and decreases monotonically.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn energy_decay_curve_dB(&self) -> Vec<f32> {
+		let mut remaining_energy = self.samples.iter().map(|s| s * s).sum::<f32>();
+		if remaining_energy <= f32::EPSILON {
+			return vec![f32::NEG_INFINITY; self.samples.len()];
+		}
+		let total_energy = remaining_energy;
+		self.samples
+			.iter()
+			.map(|s| {
+				let level = 10. * (remaining_energy.max(0.) / total_energy).log10();
+				remaining_energy -= s * s;
+				level
+			})
+			.collect()
+	}
+
+	/// Estimate the reverberation time, i.e. the time it takes for the energy of the response to decay
+	/// by 60dB, by extrapolating the slope of the energy decay curve between -5dB and -25dB (T20).
+	///
+	/// Returns `None` if the response is silent or too short to be measured.
+	#[must_use]
+	pub fn rt60(&self) -> Option<Duration> {
+		let (upper, lower) = RT60_FIT_RANGE;
+		let points: Vec<(f32, f32)> = self
+			.energy_decay_curve_dB()
+			.into_iter()
+			.enumerate()
+			.skip_while(|&(_, level)| level > upper)
+			.take_while(|&(_, level)| level >= lower)
+			.map(|(i, level)| (i as f32 / self.sample_rate.0 as f32, level))
+			.collect();
+
+		if points.len() < 2 {
+			return None;
+		}
+
+		// Least squares linear regression.
+		let n = points.len() as f32;
+		let mean_t = points.iter().map(|(t, _)| t).sum::<f32>() / n;
+		let mean_level = points.iter().map(|(_, level)| level).sum::<f32>() / n;
+		let covariance = points
+			.iter()
+			.map(|(t, level)| (t - mean_t) * (level - mean_level))
+			.sum::<f32>();
+		let variance = points
+			.iter()
+			.map(|(t, _)| (t - mean_t).powi(2))
+			.sum::<f32>();
+		let slope = covariance / variance;
+
+		if slope >= 0. {
+			return None;
+		}
+		Some(Duration::from_secs_f32(-60. / slope))
+	}
+
+	/// The frequency response of the system, i.e. the DFT of the impulse response, from 0Hz
+	/// to the Nyquist frequency.
+	#[must_use]
+	pub fn frequency_response(&self) -> Vec<Harmonic> {
+		let transform_size = self.samples.len();
+		if transform_size == 0 {
+			return vec![];
+		}
+
+		let planner = FftCache::global();
+		let forward = planner.plan_fft_forward(transform_size);
+		let mut spectrum: Vec<Complex32> =
+			self.samples.iter().map(|&s| Complex::new(s, 0.)).collect();
+		forward.process(&mut spectrum);
+
+		let frequency_gap = self.sample_rate.0 as f32 / transform_size as f32;
+		spectrum
+			.into_iter()
+			.take(transform_size / 2 + 1)
+			.enumerate()
+			.map(|(bin, phasor)| Harmonic::new(phasor, bin as f32 * frequency_gap))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, Rng, SeedableRng};
+
+	use super::*;
+
+	#[test]
+	#[cfg(feature = "output")]
+	fn deconvolves_a_sweep() {
+		use crate::output::exponential_sine_sweep;
+
+		let sample_rate = SampleRate(48000);
+		let excitation = exponential_sine_sweep(sample_rate, 20., 23000., Duration::from_secs(1));
+		let mut recording = vec![0.; excitation.len() + 4800];
+		for (i, &s) in excitation.iter().enumerate() {
+			recording[i + 100] += 0.5 * s;
+			recording[i + 300] += 0.25 * s;
+		}
+
+		let ir = ImpulseResponse::from_sweep(sample_rate, &excitation, &recording);
+		assert_eq!(ir.samples().len(), recording.len());
+
+		let samples = ir.samples();
+		let (peak_idx, _) = samples
+			.iter()
+			.enumerate()
+			.max_by(|(_, a), (_, b)| a.total_cmp(b))
+			.unwrap();
+		assert_eq!(peak_idx, 100);
+		let ratio = samples[300] / samples[100];
+		assert!((ratio - 0.5).abs() < 0.05, "{ratio}");
+
+		let response = ir.frequency_response();
+		let gain_at_1khz = response
+			.iter()
+			.min_by(|a, b| {
+				(a.frequency() - 1000.)
+					.abs()
+					.total_cmp(&(b.frequency() - 1000.).abs())
+			})
+			.unwrap()
+			.amplitude();
+		// |0.5 + 0.25 * e^(-jω200)| lies between 0.25 and 0.75
+		assert!((0.2..0.8).contains(&gain_at_1khz), "{gain_at_1khz}");
+	}
+
+	#[test]
+	fn estimates_rt60() {
+		let sample_rate = SampleRate(48000);
+		let rt60 = 0.5;
+		let mut rng = StdRng::seed_from_u64(42);
+		let samples = (0..sample_rate.0)
+			.map(|i| {
+				let t = i as f32 / sample_rate.0 as f32;
+				// -60dB of energy after rt60 seconds
+				rng.gen_range(-1.0f32..1.0) * 10f32.powf(-3. * t / rt60)
+			})
+			.collect();
+
+		let estimate = ImpulseResponse::new(sample_rate, samples)
+			.rt60()
+			.unwrap()
+			.as_secs_f32();
+		assert!((estimate - rt60).abs() < rt60 * 0.05, "{estimate}");
+	}
+
+	#[test]
+	fn no_rt60_for_silence() {
+		let ir = ImpulseResponse::new(SampleRate(48000), vec![0.; 4800]);
+		assert_eq!(ir.rt60(), None);
+	}
+}
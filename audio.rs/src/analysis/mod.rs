@@ -3,6 +3,9 @@ pub mod dft;
 mod windowing_fn;
 pub use windowing_fn::*;
 
+mod window;
+pub use window::*;
+
 pub mod windowing_fns;
 
 mod harmonic;
@@ -14,6 +17,60 @@ pub use discrete_harmonic::*;
 mod dft_ctx;
 pub use dft_ctx::*;
 
+mod chroma;
+pub use chroma::*;
+
+mod cwt;
+pub use cwt::*;
+
+mod db_spectrum;
+pub use db_spectrum::*;
+
+mod delay_estimation;
+pub use delay_estimation::*;
+
+mod envelope_follower;
+pub use envelope_follower::*;
+
+mod hilbert;
+pub use hilbert::*;
+
+mod hpss;
+pub use hpss::*;
+
+mod impulse_response;
+pub use impulse_response::*;
+
+mod multi_resolution;
+pub use multi_resolution::*;
+
+mod noise_floor;
+pub use noise_floor::*;
+
+mod octave_bands;
+pub use octave_bands::*;
+
+mod peaks;
+pub use peaks::*;
+
+mod reassignment;
+pub use reassignment::*;
+
+mod spectral_filter;
+pub use spectral_filter::*;
+
+mod spectrogram;
+pub use spectrogram::*;
+
+mod thd;
+pub use thd::*;
+
+mod time_stretcher;
+pub use time_stretcher::*;
+
+mod transfer_function;
+pub use transfer_function::*;
+
 impl DiscreteHarmonic {
 	#[must_use]
 	pub fn to_harmonic(&self, dft_ctx: DftCtx) -> Harmonic {
@@ -0,0 +1,275 @@
+use crate::{
+	analysis::{dft::StftAnalyzer, window_offsets, DftCtx, Harmonic, WindowingFn},
+	SampleRate,
+};
+
+/// A frequency band analyzed with a specific window length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResolutionBand {
+	samples_per_window: usize,
+	upper_frequency: u32,
+}
+
+impl ResolutionBand {
+	/// A band that extends from the upper frequency of the previous band (or 0Hz) up to `upper_frequency` (excluded), in Hz.
+	#[must_use]
+	pub const fn new(upper_frequency: u32, samples_per_window: usize) -> Self {
+		Self {
+			samples_per_window,
+			upper_frequency,
+		}
+	}
+
+	#[must_use]
+	pub const fn upper_frequency(&self) -> u32 {
+		self.upper_frequency
+	}
+
+	#[must_use]
+	pub const fn samples_per_window(&self) -> usize {
+		self.samples_per_window
+	}
+}
+
+#[derive(Debug, Clone)]
+struct BandAnalyzer {
+	analyzer: StftAnalyzer,
+	/// Bins of `analyzer` that fall inside the band.
+	bins: std::ops::Range<usize>,
+	/// Offset of the window of this band in the longest window.
+	offset: usize,
+	/// Makes the amplitudes of this band comparable with the ones obtained with the longest window.
+	scale: f32,
+}
+
+/// Analyzes each frequency band with a different window length and merges the results into a single
+/// set of harmonics, sorted by frequency.
+///
+/// A single STFT has to trade frequency resolution (long windows) for time resolution (short windows).
+/// Low frequencies usually need the former to tell apart close notes, while high frequencies benefit
+/// from the latter to capture transients: using long windows for the lows and short windows
+/// for the highs gives a better compromise than any single window length.
+///
+/// All the windows are centered on the same instant, i.e. the shorter windows are taken from the middle of the longest one.
+/// The amplitudes of each band are rescaled so that a sinusoid yields the same amplitude regardless of the window length.
+#[derive(Debug, Clone)]
+pub struct MultiResolutionAnalyzer {
+	sample_rate: SampleRate,
+	samples_per_window: usize,
+	bands: Vec<BandAnalyzer>,
+	cur_transform: Vec<Harmonic>,
+}
+
+impl MultiResolutionAnalyzer {
+	/// Create an analyzer for the given `bands`. Frequencies above the upper frequency of the last band
+	/// (or the Nyquist frequency) are not analyzed.
+	///
+	/// # Panics
+	/// - if `bands` is empty.
+	/// - if `bands` is not sorted by increasing upper frequency.
+	#[must_use]
+	pub fn new(
+		sample_rate: SampleRate,
+		bands: &[ResolutionBand],
+		windowing_fn: &impl WindowingFn,
+	) -> Self {
+		assert!(!bands.is_empty(), "at least one band is required");
+		assert!(
+			bands
+				.windows(2)
+				.all(|pair| pair[0].upper_frequency < pair[1].upper_frequency),
+			"bands must be sorted by increasing upper frequency"
+		);
+
+		let samples_per_window = bands
+			.iter()
+			.map(ResolutionBand::samples_per_window)
+			.max()
+			.unwrap_or_default();
+
+		let mut lower_frequency = 0.;
+		let bands: Vec<BandAnalyzer> = bands
+			.iter()
+			.map(|band| {
+				let dft_ctx = DftCtx::new(sample_rate, band.samples_per_window);
+				#[allow(clippy::cast_precision_loss)]
+				let upper_frequency = band.upper_frequency as f32;
+				let bins = (0..dft_ctx.n_of_bins())
+					.filter(|&bin| {
+						let frequency = dft_ctx.bin_to_frequency(bin);
+						lower_frequency <= frequency && frequency < upper_frequency
+					})
+					.fold(None, |range: Option<std::ops::Range<usize>>, bin| {
+						Some(range.map_or(bin..bin + 1, |range| range.start..bin + 1))
+					})
+					.unwrap_or(0..0);
+				lower_frequency = upper_frequency;
+
+				#[allow(clippy::cast_precision_loss)]
+				let scale = (samples_per_window as f32 / band.samples_per_window as f32).sqrt();
+
+				BandAnalyzer {
+					analyzer: StftAnalyzer::new(dft_ctx, windowing_fn),
+					bins,
+					offset: (samples_per_window - band.samples_per_window) / 2,
+					scale,
+				}
+			})
+			.collect();
+
+		let n_of_harmonics = bands.iter().map(|band| band.bins.len()).sum();
+
+		Self {
+			sample_rate,
+			samples_per_window,
+			bands,
+			cur_transform: Vec::with_capacity(n_of_harmonics),
+		}
+	}
+
+	/// Analyze a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// The returned `Vec` is sorted by frequency.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with [`Self::samples_per_window`].
+	#[must_use]
+	pub fn analyze(&mut self, signal: &[f32]) -> &Vec<Harmonic> {
+		assert_eq!(
+			signal.len(),
+			self.samples_per_window,
+			"signal with incompatible length received"
+		);
+
+		self.cur_transform.clear();
+		for band in &mut self.bands {
+			let window =
+				&signal[band.offset..band.offset + band.analyzer.dft_ctx().samples_per_window()];
+			let dft_ctx = band.analyzer.dft_ctx();
+			let transform = band.analyzer.analyze(window);
+			self.cur_transform
+				.extend(transform[band.bins.clone()].iter().map(|harmonic| {
+					Harmonic::new(
+						harmonic.phasor() * band.scale,
+						dft_ctx.bin_to_frequency(harmonic.bin()),
+					)
+				}));
+		}
+
+		&self.cur_transform
+	}
+
+	/// Analyze all the complete windows contained in a longer signal, taking a window
+	/// every `hop` samples.
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze_buffer(&mut self, signal: &[f32], hop: usize) -> Vec<Vec<Harmonic>> {
+		window_offsets(signal.len(), self.samples_per_window, hop)
+			.map(|offset| {
+				self.analyze(&signal[offset..offset + self.samples_per_window])
+					.clone()
+			})
+			.collect()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	/// The length of the longest window, i.e. the number of samples expected by [`Self::analyze`].
+	#[must_use]
+	pub fn samples_per_window(&self) -> usize {
+		self.samples_per_window
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use crate::analysis::windowing_fns::HannWindow;
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn sines(sample_rate: SampleRate, n_of_samples: usize, frequencies: &[f32]) -> Vec<f32> {
+		(0..n_of_samples)
+			.map(|i| {
+				frequencies
+					.iter()
+					.map(|f| (TAU * f * i as f32 / sample_rate.0 as f32).sin())
+					.sum()
+			})
+			.collect()
+	}
+
+	fn amplitude_at(transform: &[Harmonic], frequency: f32) -> f32 {
+		transform
+			.iter()
+			.min_by(|a, b| {
+				(a.frequency() - frequency)
+					.abs()
+					.total_cmp(&(b.frequency() - frequency).abs())
+			})
+			.unwrap()
+			.amplitude()
+	}
+
+	fn analyzer() -> MultiResolutionAnalyzer {
+		MultiResolutionAnalyzer::new(
+			SampleRate(8000),
+			&[
+				ResolutionBand::new(500, 4000),
+				ResolutionBand::new(2000, 1000),
+				ResolutionBand::new(4000, 250),
+			],
+			&HannWindow,
+		)
+	}
+
+	#[test]
+	fn merges_the_bands_by_frequency() {
+		let mut analyzer = analyzer();
+		assert_eq!(analyzer.samples_per_window(), 4000);
+
+		let transform = analyzer.analyze(&vec![0.; 4000]);
+		// 0..500Hz every 2Hz, 500..2000Hz every 8Hz, 2000..4000Hz every 32Hz.
+		assert_eq!(transform.len(), 250 + 187 + 62);
+		assert!(transform
+			.windows(2)
+			.all(|pair| pair[0].frequency() < pair[1].frequency()));
+		assert!(transform.last().unwrap().frequency() < 4000.);
+	}
+
+	#[test]
+	fn resolves_close_low_notes_and_keeps_levels_consistent() {
+		let mut analyzer = analyzer();
+		let signal = sines(SampleRate(8000), 4000, &[100., 110., 1000., 3008.]);
+		let transform = analyzer.analyze(&signal);
+
+		// The long window tells apart two sines only 10Hz apart.
+		let peak = amplitude_at(transform, 100.);
+		assert!(amplitude_at(transform, 105.) < peak / 10.);
+		assert!((amplitude_at(transform, 110.) - peak).abs() < peak / 10.);
+
+		// The same sinusoid has the same amplitude in all the bands.
+		for frequency in [1000., 3008.] {
+			let amplitude = amplitude_at(transform, frequency);
+			assert!(
+				(amplitude - peak).abs() < peak / 10.,
+				"{frequency}Hz: {amplitude} vs {peak}"
+			);
+		}
+	}
+
+	#[test]
+	fn analyzes_longer_buffers() {
+		let mut analyzer = analyzer();
+		let spectra = analyzer.analyze_buffer(&vec![0.; 10000], 2000);
+		assert_eq!(spectra.len(), 4);
+	}
+}
@@ -0,0 +1,132 @@
+use crate::analysis::DiscreteHarmonic;
+
+/// An estimate of the power of the background noise of a spectrum.
+///
+/// Because the harmonics of interest usually occupy a small fraction of the bins, a low percentile
+/// (e.g. the median) of the powers of all the bins is a robust estimate of the noise, and can be used to
+/// build thresholds that adapt to the input level instead of relying on absolute power values.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct NoiseFloor {
+	power: f32,
+}
+
+impl NoiseFloor {
+	/// Estimate the noise floor as the power at the given `percentile` (from 0. to 1.) of the powers of `bins`.
+	///
+	/// # Panics
+	/// - if `percentile` is not in the range `0.0..=1.0`.
+	#[must_use]
+	pub fn estimate(bins: &[DiscreteHarmonic], percentile: f32) -> Self {
+		assert!(
+			(0.0..=1.0).contains(&percentile),
+			"percentile must be in the range 0.0..=1.0"
+		);
+		if bins.is_empty() {
+			return Self::default();
+		}
+
+		let mut powers: Vec<f32> = bins.iter().map(DiscreteHarmonic::power).collect();
+		#[allow(clippy::cast_precision_loss)]
+		#[allow(clippy::cast_sign_loss)]
+		let idx = (percentile * (powers.len() - 1) as f32).round() as usize;
+		let (_, &mut power, _) = powers.select_nth_unstable_by(idx, f32::total_cmp);
+		Self { power }
+	}
+
+	/// Estimate the noise floor as the median of the powers of `bins`.
+	#[must_use]
+	pub fn median(bins: &[DiscreteHarmonic]) -> Self {
+		Self::estimate(bins, 0.5)
+	}
+
+	/// The power of the noise floor, with the same unit as [`DiscreteHarmonic::power`].
+	#[must_use]
+	pub fn power(&self) -> f32 {
+		self.power
+	}
+
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn dB(&self) -> f32 {
+		10. * self.power.log10()
+	}
+
+	/// The signal to noise ratio of `harmonic`, in dB.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn snr_dB(&self, harmonic: &DiscreteHarmonic) -> f32 {
+		10. * (harmonic.power() / self.power).log10()
+	}
+
+	/// Whether `harmonic` stands out from the noise floor by at least `threshold` dB.
+	#[must_use]
+	pub fn is_above(&self, harmonic: &DiscreteHarmonic, threshold: f32) -> bool {
+		self.snr_dB(harmonic) >= threshold
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, Rng, SeedableRng};
+	use rustfft::num_complex::Complex32;
+
+	use super::*;
+
+	fn noisy_spectrum(
+		noise_amplitude: f32,
+		peak_bin: usize,
+		peak_amplitude: f32,
+	) -> Vec<DiscreteHarmonic> {
+		let mut rng = StdRng::seed_from_u64(42);
+		(0..512)
+			.map(|bin| {
+				let amplitude = if bin == peak_bin {
+					peak_amplitude
+				} else {
+					noise_amplitude * rng.gen_range(0.5f32..1.5)
+				};
+				DiscreteHarmonic::new(
+					Complex32::from_polar(amplitude, rng.gen_range(0.0..1.0)),
+					bin,
+				)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn estimates_the_noise_floor_and_snr() {
+		let bins = noisy_spectrum(0.01, 100, 1.);
+
+		let noise_floor = NoiseFloor::median(&bins);
+		assert!((noise_floor.dB() + 40.).abs() < 3., "{noise_floor:?}");
+
+		let snr = noise_floor.snr_dB(&bins[100]);
+		assert!((snr - 40.).abs() < 3., "{snr}");
+		assert!(noise_floor.is_above(&bins[100], 30.));
+		assert!(!noise_floor.is_above(&bins[99], 30.));
+	}
+
+	#[test]
+	fn threshold_adapts_to_the_input_level() {
+		let quiet = noisy_spectrum(0.001, 100, 0.1);
+		let loud = noisy_spectrum(0.1, 100, 10.);
+
+		let quiet_snr = NoiseFloor::median(&quiet).snr_dB(&quiet[100]);
+		let loud_snr = NoiseFloor::median(&loud).snr_dB(&loud[100]);
+		assert!((quiet_snr - loud_snr).abs() < 0.01);
+	}
+
+	#[test]
+	fn percentiles() {
+		let bins: Vec<DiscreteHarmonic> = (1..=5)
+			.map(|i| {
+				#[allow(clippy::cast_precision_loss)]
+				DiscreteHarmonic::new(Complex32::new((i as f32).sqrt(), 0.), i)
+			})
+			.collect();
+		assert!((NoiseFloor::estimate(&bins, 0.).power() - 1.).abs() < 1e-5);
+		assert!((NoiseFloor::median(&bins).power() - 3.).abs() < 1e-5);
+		assert!((NoiseFloor::estimate(&bins, 1.).power() - 5.).abs() < 1e-5);
+		assert_eq!(NoiseFloor::median(&[]), NoiseFloor::default());
+	}
+}
@@ -0,0 +1,249 @@
+use crate::analysis::{DftCtx, DiscreteHarmonic};
+
+/// Nominal center frequencies of the one-third octave bands, as defined by ANSI S1.11 / IEC 61260.
+/// Every third entry (starting from 16Hz) is also the nominal center frequency of an octave band.
+const NOMINAL_THIRD_OCTAVE_CENTERS: [f32; 31] = [
+	16., 20., 25., 31.5, 40., 50., 63., 80., 100., 125., 160., 200., 250., 315., 400., 500., 630.,
+	800., 1000., 1250., 1600., 2000., 2500., 3150., 4000., 5000., 6300., 8000., 10000., 12500.,
+	16000.,
+];
+
+/// Index of 1kHz, the reference frequency, in [`NOMINAL_THIRD_OCTAVE_CENTERS`].
+const REFERENCE_IDX: usize = 18;
+
+/// Octave ratio (base 10), as recommended by IEC 61260.
+const OCTAVE_RATIO: f32 = 1.995_262_3; // 10^(3/10)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BandResolution {
+	Octave,
+	ThirdOctave,
+}
+
+impl BandResolution {
+	const fn bands_per_octave(self) -> usize {
+		match self {
+			BandResolution::Octave => 1,
+			BandResolution::ThirdOctave => 3,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+	nominal_center: f32,
+	center: f32,
+	lower_edge: f32,
+	upper_edge: f32,
+}
+
+impl Band {
+	/// The conventional frequency used to label the band (e.g. 31.5Hz).
+	#[must_use]
+	pub fn nominal_center_frequency(&self) -> f32 {
+		self.nominal_center
+	}
+
+	/// The exact (geometric) center frequency of the band.
+	#[must_use]
+	pub fn center_frequency(&self) -> f32 {
+		self.center
+	}
+
+	/// The frequency range covered by the band, lower edge included and upper edge excluded.
+	#[must_use]
+	pub fn frequency_range(&self) -> (f32, f32) {
+		(self.lower_edge, self.upper_edge)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandEnergy {
+	band: Band,
+	power: f32,
+}
+
+impl BandEnergy {
+	#[must_use]
+	pub fn band(&self) -> Band {
+		self.band
+	}
+
+	/// The sum of the powers of the bins that fall in the band, with the same unit as [`DiscreteHarmonic::power`].
+	#[must_use]
+	pub fn power(&self) -> f32 {
+		self.power
+	}
+
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn dB(&self) -> f32 {
+		10. * self.power.log10()
+	}
+}
+
+/// Aggregates the bins of a DFT into the standard octave or one-third octave bands, like the ones
+/// shown by spectrum analyzers and sound level meters.
+///
+/// Only the bands whose center frequency is below the Nyquist frequency are taken into account.
+///
+/// Note: at low frequencies, bands can be narrower than the frequency gap between two bins, in which case
+/// they won't contain any bin and their power will be 0. Use longer windows to increase the resolution.
+#[derive(Debug, Clone)]
+pub struct OctaveBands {
+	dft_ctx: DftCtx,
+	bands: Vec<Band>,
+	bin_to_band: Vec<Option<usize>>,
+}
+
+impl OctaveBands {
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, resolution: BandResolution) -> Self {
+		let bands_per_octave = resolution.bands_per_octave();
+		#[allow(clippy::cast_precision_loss)]
+		let nyquist_frequency = dft_ctx.sample_rate().0 as f32 / 2.;
+		#[allow(clippy::cast_precision_loss)]
+		let half_band_ratio = OCTAVE_RATIO.powf(1. / (2. * bands_per_octave as f32));
+
+		let bands: Vec<Band> = NOMINAL_THIRD_OCTAVE_CENTERS
+			.iter()
+			.enumerate()
+			.filter(|(idx, _)| idx % 3 == REFERENCE_IDX % 3 || bands_per_octave == 3)
+			.map(|(idx, &nominal_center_frequency)| {
+				#[allow(clippy::cast_precision_loss)]
+				#[allow(clippy::cast_possible_wrap)]
+				let center_frequency =
+					1000. * OCTAVE_RATIO.powf((idx as isize - REFERENCE_IDX as isize) as f32 / 3.);
+				Band {
+					nominal_center: nominal_center_frequency,
+					center: center_frequency,
+					lower_edge: center_frequency / half_band_ratio,
+					upper_edge: center_frequency * half_band_ratio,
+				}
+			})
+			.filter(|band| band.center < nyquist_frequency)
+			.collect();
+
+		let bin_to_band = (0..dft_ctx.n_of_bins())
+			.map(|bin| {
+				let frequency = dft_ctx.bin_to_frequency(bin);
+				bands
+					.iter()
+					.position(|band| band.lower_edge <= frequency && frequency < band.upper_edge)
+			})
+			.collect();
+
+		Self {
+			dft_ctx,
+			bands,
+			bin_to_band,
+		}
+	}
+
+	/// The bands, sorted by frequency.
+	#[must_use]
+	pub fn bands(&self) -> &[Band] {
+		&self.bands
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	/// Compute the energy of each band, given the output of an analyzer configured with the same [`DftCtx`].
+	///
+	/// The result has the same length and order as [`Self::bands`]. Bins that are not part of `transform`
+	/// (e.g. the ones that were not requested to a [`super::dft::GoertzelAnalyzer`]) are treated as silence.
+	///
+	/// # Panics
+	/// - if `transform` contains bins outside of the range supported by the configured [`DftCtx`].
+	#[must_use]
+	pub fn energies(&self, transform: &[DiscreteHarmonic]) -> Vec<BandEnergy> {
+		let mut energies: Vec<BandEnergy> = self
+			.bands
+			.iter()
+			.map(|&band| BandEnergy { band, power: 0. })
+			.collect();
+
+		for harmonic in transform {
+			if let Some(band_idx) = self.bin_to_band[harmonic.bin()] {
+				energies[band_idx].power += harmonic.power();
+			}
+		}
+
+		energies
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rustfft::num_complex::Complex32;
+
+	use crate::SampleRate;
+
+	use super::*;
+
+	#[test]
+	fn standard_bands() {
+		let dft_ctx = DftCtx::new(SampleRate(48000), 4800);
+
+		let octaves = OctaveBands::new(dft_ctx, BandResolution::Octave);
+		let nominal: Vec<f32> = octaves
+			.bands()
+			.iter()
+			.map(Band::nominal_center_frequency)
+			.collect();
+		assert_eq!(
+			nominal,
+			vec![16., 31.5, 63., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.]
+		);
+
+		let thirds = OctaveBands::new(dft_ctx, BandResolution::ThirdOctave);
+		assert_eq!(thirds.bands().len(), 31);
+
+		// Adjacent bands share their edges.
+		for pair in thirds.bands().windows(2) {
+			assert!((pair[0].frequency_range().1 - pair[1].frequency_range().0).abs() < 1e-2);
+		}
+
+		let band_1k = thirds.bands()[18];
+		assert!((band_1k.center_frequency() - 1000.).abs() < 1e-3);
+		let (lower, upper) = band_1k.frequency_range();
+		assert!((lower - 891.25).abs() < 0.1, "{lower}");
+		assert!((upper - 1122.02).abs() < 0.1, "{upper}");
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn bands_above_nyquist_are_excluded() {
+		let dft_ctx = DftCtx::new(SampleRate(6000), 600);
+		let octaves = OctaveBands::new(dft_ctx, BandResolution::Octave);
+		assert_eq!(
+			octaves.bands().last().unwrap().nominal_center_frequency(),
+			2000.
+		);
+	}
+
+	#[test]
+	fn aggregates_bin_powers() {
+		let dft_ctx = DftCtx::new(SampleRate(48000), 4800);
+		let octaves = OctaveBands::new(dft_ctx, BandResolution::Octave);
+
+		let transform: Vec<DiscreteHarmonic> = [1000., 1100., 4000.]
+			.iter()
+			.map(|&f| DiscreteHarmonic::new(Complex32::ONE, dft_ctx.frequency_to_bin(f)))
+			.collect();
+
+		let energies = octaves.energies(&transform);
+		assert_eq!(energies.len(), octaves.bands().len());
+		for energy in energies {
+			let expected = match energy.band().nominal_center_frequency() {
+				1000. => 2.,
+				4000. => 1.,
+				_ => 0.,
+			};
+			assert!((energy.power() - expected).abs() < 1e-6, "{energy:?}");
+		}
+	}
+}
@@ -0,0 +1,130 @@
+use crate::analysis::DiscreteHarmonic;
+
+/// Find the local maxima of the power spectrum described by `bins`, returning at most the
+/// `max_peaks` strongest ones, sorted by decreasing power.
+///
+/// A bin is considered a peak if its power is greater than the power of its neighbors.
+/// Neighbors are determined by the position in the slice, so `bins` is expected to be
+/// sorted by frequency bin, like the output of the analyzers in [`super::dft`]. The first
+/// and the last bin only have to be greater than their only neighbor.
+///
+/// Peaks whose prominence is less than `min_prominence` (in dB) are discarded. The prominence of a peak
+/// is the ratio between its power and the highest power from which the peak can be reached
+/// without passing through a higher bin, i.e. how much the peak stands out from its surroundings.
+/// Passing `0.` keeps all the local maxima.
+#[must_use]
+pub fn find_peaks(
+	bins: &[DiscreteHarmonic],
+	min_prominence: f32,
+	max_peaks: usize,
+) -> Vec<DiscreteHarmonic> {
+	let powers: Vec<f32> = bins.iter().map(DiscreteHarmonic::power).collect();
+
+	let mut peaks: Vec<DiscreteHarmonic> = (0..powers.len())
+		.filter(|&i| {
+			let power = powers[i];
+			power > 0.
+				&& (i == 0 || power > powers[i - 1])
+				&& (i == powers.len() - 1 || power >= powers[i + 1])
+		})
+		.filter(|&i| prominence_dB(&powers, i) >= min_prominence)
+		.map(|i| bins[i])
+		.collect();
+
+	peaks.sort_by(|a, b| b.power().total_cmp(&a.power()));
+	peaks.truncate(max_peaks);
+	peaks
+}
+
+/// Find the strongest bin, ignoring the ones that are not local maxima.
+///
+/// Equivalent to `find_peaks(bins, 0., 1).first().copied()`.
+#[must_use]
+pub fn find_strongest_peak(bins: &[DiscreteHarmonic]) -> Option<DiscreteHarmonic> {
+	find_peaks(bins, 0., 1).first().copied()
+}
+
+#[allow(non_snake_case)]
+fn prominence_dB(powers: &[f32], peak_idx: usize) -> f32 {
+	let peak = powers[peak_idx];
+
+	// Lowest point between the peak and the closest higher bin (or the end of the spectrum) on each side.
+	let left_base = powers[..peak_idx]
+		.iter()
+		.rev()
+		.take_while(|&&p| p <= peak)
+		.fold(peak, |min, &p| min.min(p));
+	let right_base = powers[peak_idx + 1..]
+		.iter()
+		.take_while(|&&p| p <= peak)
+		.fold(peak, |min, &p| min.min(p));
+
+	let base = left_base.max(right_base);
+	if base <= 0. {
+		return f32::INFINITY;
+	}
+	10. * (peak / base).log10()
+}
+
+#[cfg(test)]
+mod tests {
+	use rustfft::num_complex::Complex32;
+
+	use super::*;
+
+	fn bins_from_amplitudes(amplitudes: &[f32]) -> Vec<DiscreteHarmonic> {
+		amplitudes
+			.iter()
+			.enumerate()
+			.map(|(bin, &amplitude)| DiscreteHarmonic::new(Complex32::new(amplitude, 0.), bin))
+			.collect()
+	}
+
+	#[test]
+	fn finds_the_strongest_peaks() {
+		let bins = bins_from_amplitudes(&[0.1, 0.5, 0.1, 0.2, 1.0, 0.3, 0.1, 0.4, 0.1]);
+
+		let peaks: Vec<usize> = find_peaks(&bins, 0., 10)
+			.iter()
+			.map(DiscreteHarmonic::bin)
+			.collect();
+		assert_eq!(peaks, vec![4, 1, 7]);
+
+		let peaks: Vec<usize> = find_peaks(&bins, 0., 2)
+			.iter()
+			.map(DiscreteHarmonic::bin)
+			.collect();
+		assert_eq!(peaks, vec![4, 1]);
+
+		assert_eq!(find_strongest_peak(&bins).map(|h| h.bin()), Some(4));
+		assert_eq!(find_strongest_peak(&[]), None);
+	}
+
+	#[test]
+	fn filters_by_prominence() {
+		// The bump at bin 3 barely stands out from its surroundings.
+		let bins = bins_from_amplitudes(&[0.01, 0.5, 0.4, 0.41, 0.3, 1.0, 0.01]);
+
+		let peaks: Vec<usize> = find_peaks(&bins, 0., 10)
+			.iter()
+			.map(DiscreteHarmonic::bin)
+			.collect();
+		assert_eq!(peaks, vec![5, 1, 3]);
+
+		let peaks: Vec<usize> = find_peaks(&bins, 3., 10)
+			.iter()
+			.map(DiscreteHarmonic::bin)
+			.collect();
+		assert_eq!(peaks, vec![5, 1]);
+	}
+
+	#[test]
+	fn edges_can_be_peaks() {
+		let bins = bins_from_amplitudes(&[1.0, 0.5, 0.2, 0.8]);
+		let peaks: Vec<usize> = find_peaks(&bins, 0., 10)
+			.iter()
+			.map(DiscreteHarmonic::bin)
+			.collect();
+		assert_eq!(peaks, vec![0, 3]);
+	}
+}
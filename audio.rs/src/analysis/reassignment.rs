@@ -0,0 +1,212 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::analysis::{DftCtx, Spectrogram};
+
+/// A sharper version of a [`Spectrogram`], obtained with time-frequency reassignment.
+///
+/// The power of each bin of the original spectrogram is moved to the time and frequency where
+/// it is actually concentrated, which are estimated from the phases of the STFT:
+/// - the instantaneous frequency is the rate at which the phase of the bin advances between two consecutive windows;
+/// - the group delay (the time of the event within the window) is the rate at which the phase changes across adjacent bins.
+///
+/// This compensates the smearing introduced by the window: sinusoids collapse into thin lines and
+/// transients into sharp vertical strokes, without having to choose a shorter or longer window.
+///
+/// Note: frequency estimates are only unambiguous when `hop` is small compared to `samples_per_window`
+/// (e.g. a quarter of it or less).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReassignedSpectrogram {
+	dft_ctx: DftCtx,
+	hop: usize,
+	powers: Vec<Vec<f32>>,
+}
+
+impl ReassignedSpectrogram {
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	/// The number of samples between the beginning of two consecutive windows.
+	#[must_use]
+	pub fn hop(&self) -> usize {
+		self.hop
+	}
+
+	/// The reassigned powers, indexed by window and then by bin, with the same shape
+	/// as the windows of the original [`Spectrogram`].
+	#[must_use]
+	pub fn powers(&self) -> &[Vec<f32>] {
+		&self.powers
+	}
+
+	#[must_use]
+	pub fn n_of_windows(&self) -> usize {
+		self.powers.len()
+	}
+}
+
+impl Spectrogram {
+	/// Compute the reassigned version of this spectrogram.
+	///
+	/// The windows are expected to contain all the bins between 0 and the Nyquist frequency, like
+	/// the output of [`super::dft::StftAnalyzer::analyze_buffer`].
+	///
+	/// # Panics
+	/// - if a window doesn't contain all the bins supported by the configured [`DftCtx`].
+	#[must_use]
+	pub fn reassigned(&self) -> ReassignedSpectrogram {
+		let dft_ctx = self.dft_ctx();
+		let samples_per_window = dft_ctx.samples_per_window();
+		let n_of_bins = dft_ctx.n_of_bins();
+		let n_of_windows = self.n_of_windows();
+		let windows = self.windows();
+		#[allow(clippy::cast_precision_loss)]
+		let (samples_per_window_f, hop_f) = (samples_per_window as f32, self.hop() as f32);
+
+		let mut powers = vec![vec![0.; n_of_bins]; n_of_windows];
+		for (window_idx, window) in windows.iter().enumerate() {
+			assert_eq!(
+				window.len(),
+				n_of_bins,
+				"window with an incompatible number of bins received"
+			);
+
+			// Compare each window with the previous one, or with the next one for the first window.
+			let neighbor = match window_idx {
+				0 if n_of_windows > 1 => Some((&windows[1], 1.)),
+				0 => None,
+				_ => Some((&windows[window_idx - 1], -1.)),
+			};
+
+			for (bin, harmonic) in window.iter().enumerate() {
+				let power = harmonic.power();
+				if power <= 0. {
+					continue;
+				}
+
+				#[allow(clippy::cast_precision_loss)]
+				let bin_f = bin as f32;
+				let reassigned_bin = neighbor.map_or(bin_f, |(neighbor, direction)| {
+					let expected_advance = TAU * bin_f * hop_f / samples_per_window_f;
+					let advance = direction * (harmonic.phase() - neighbor[bin].phase());
+					let deviation = wrap_phase(advance - expected_advance);
+					bin_f + deviation * samples_per_window_f / (TAU * hop_f)
+				});
+
+				// A pulse at the center of the window makes the phase decrease by π at every bin.
+				let adjacent_bin = if bin + 1 < n_of_bins {
+					bin + 1
+				} else {
+					bin - 1
+				};
+				#[allow(clippy::cast_precision_loss)]
+				let bin_distance = adjacent_bin as f32 - bin_f;
+				let phase_slope = wrap_phase(
+					(window[adjacent_bin].phase() - harmonic.phase()) / bin_distance + PI,
+				);
+				let time_offset = -phase_slope * samples_per_window_f / TAU;
+				#[allow(clippy::cast_precision_loss)]
+				let reassigned_window = window_idx as f32 + time_offset / hop_f;
+
+				#[allow(clippy::cast_possible_truncation)]
+				#[allow(clippy::cast_sign_loss)]
+				#[allow(clippy::cast_precision_loss)]
+				let (target_window, target_bin) = (
+					reassigned_window
+						.round()
+						.clamp(0., (n_of_windows - 1) as f32) as usize,
+					reassigned_bin.round().clamp(0., (n_of_bins - 1) as f32) as usize,
+				);
+				powers[target_window][target_bin] += power;
+			}
+		}
+
+		ReassignedSpectrogram {
+			dft_ctx,
+			hop: self.hop(),
+			powers,
+		}
+	}
+}
+
+/// Wrap a phase into the range `-π..=π`.
+fn wrap_phase(phase: f32) -> f32 {
+	phase - TAU * (phase / TAU).round()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		analysis::{dft::StftAnalyzer, windowing_fns::HannWindow, DiscreteHarmonic},
+		SampleRate,
+	};
+
+	use super::*;
+
+	fn fraction_of_power_in(powers: &[f32], idx: usize, radius: usize) -> f32 {
+		let total: f32 = powers.iter().sum();
+		let near: f32 = powers[idx.saturating_sub(radius)..=(idx + radius).min(powers.len() - 1)]
+			.iter()
+			.sum();
+		near / total
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn sharpens_sinusoids_in_frequency() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 256);
+		// Almost halfway between bins 32 and 33, where the leakage of the plain STFT is at its worst.
+		let frequency = dft_ctx.bin_to_frequency(32) + dft_ctx.frequency_gap() / 2. - 1.;
+		let signal: Vec<f32> = (0..4096)
+			.map(|i| (TAU * frequency * i as f32 / 8000.).sin())
+			.collect();
+
+		let spectrogram = StftAnalyzer::new(dft_ctx, &HannWindow).analyze_buffer(&signal, 32);
+		let reassigned = spectrogram.reassigned();
+		assert_eq!(reassigned.n_of_windows(), spectrogram.n_of_windows());
+
+		let window_idx = spectrogram.n_of_windows() / 2;
+		let plain: Vec<f32> = spectrogram.windows()[window_idx]
+			.iter()
+			.map(DiscreteHarmonic::power)
+			.collect();
+		let sharp = &reassigned.powers()[window_idx];
+
+		let plain_fraction = fraction_of_power_in(&plain, 32, 0);
+		let sharp_fraction = fraction_of_power_in(sharp, 32, 0);
+		assert!(sharp_fraction > 0.9, "{sharp_fraction}");
+		assert!(
+			sharp_fraction > plain_fraction + 0.3,
+			"{sharp_fraction} vs {plain_fraction}"
+		);
+	}
+
+	#[test]
+	fn sharpens_transients_in_time() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 256);
+		let hop = 32;
+		let mut signal = vec![0.; 2048];
+		// The click is at the center of the window that starts at 1024.
+		signal[1024 + 128] = 1.;
+
+		let spectrogram = StftAnalyzer::new(dft_ctx, &HannWindow).analyze_buffer(&signal, hop);
+		let reassigned = spectrogram.reassigned();
+
+		let plain: Vec<f32> = spectrogram
+			.windows()
+			.iter()
+			.map(|w| w.iter().map(DiscreteHarmonic::power).sum())
+			.collect();
+		let sharp: Vec<f32> = reassigned.powers().iter().map(|w| w.iter().sum()).collect();
+
+		let click_window = 1024 / hop;
+		let plain_fraction = fraction_of_power_in(&plain, click_window, 0);
+		let sharp_fraction = fraction_of_power_in(&sharp, click_window, 0);
+		assert!(sharp_fraction > 0.9, "{sharp_fraction}");
+		assert!(
+			sharp_fraction > plain_fraction + 0.3,
+			"{sharp_fraction} vs {plain_fraction}"
+		);
+	}
+}
@@ -0,0 +1,190 @@
+use crate::analysis::{
+	dft::{IstftSynthesizer, StftAnalyzer},
+	window_offsets, DftCtx, DiscreteHarmonic, Window, WindowingFn,
+};
+
+/// Filters a signal in the domain of frequency: each STFT window is multiplied by a gain curve and
+/// the result is resynthesized with a windowed overlap-add.
+///
+/// This makes it easy to build filters with arbitrary responses (brickwall band-pass, graphic EQs, ...)
+/// without designing IIR or FIR filters. The gains are real, therefore the filter doesn't alter the phase.
+///
+/// Note: the transition between a passband and a stopband can't be sharper than the frequency gap between
+/// two bins, and very steep gain curves may cause some ringing.
+#[derive(Debug, Clone)]
+pub struct SpectralFilter {
+	hop: usize,
+	gains: Vec<f32>,
+	analyzer: StftAnalyzer,
+	synthesizer: IstftSynthesizer,
+	filtered: Vec<DiscreteHarmonic>,
+}
+
+impl SpectralFilter {
+	/// Create a filter whose gain (as a linear factor, not in dB) at each frequency, in Hz, is given by `gain_curve`.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	#[must_use]
+	pub fn new(
+		dft_ctx: DftCtx,
+		hop: usize,
+		windowing_fn: &impl WindowingFn,
+		gain_curve: impl Fn(f32) -> f32,
+	) -> Self {
+		let window = Window::new(windowing_fn, dft_ctx.samples_per_window());
+		let mut filter = Self {
+			hop,
+			gains: vec![1.; dft_ctx.n_of_bins()],
+			analyzer: StftAnalyzer::new_with_window(dft_ctx, &window),
+			synthesizer: IstftSynthesizer::new_with_window(dft_ctx, hop, &window),
+			filtered: Vec::with_capacity(dft_ctx.n_of_bins()),
+		};
+		filter.set_gain_curve(gain_curve);
+		filter
+	}
+
+	/// Create a brickwall filter that keeps the frequencies between `frequency_range.0`
+	/// and `frequency_range.1` (both included), in Hz, and removes all the others.
+	///
+	/// # Panics
+	/// - if `hop` is 0 or greater than `samples_per_window`.
+	#[must_use]
+	pub fn band_pass(
+		dft_ctx: DftCtx,
+		hop: usize,
+		windowing_fn: &impl WindowingFn,
+		frequency_range: (f32, f32),
+	) -> Self {
+		Self::new(dft_ctx, hop, windowing_fn, |frequency| {
+			if (frequency_range.0..=frequency_range.1).contains(&frequency) {
+				1.
+			} else {
+				0.
+			}
+		})
+	}
+
+	/// Replace the gain curve. The new curve is applied starting from the next call to [`Self::filter`].
+	pub fn set_gain_curve(&mut self, gain_curve: impl Fn(f32) -> f32) {
+		let dft_ctx = self.dft_ctx();
+		for (bin, gain) in self.gains.iter_mut().enumerate() {
+			*gain = gain_curve(dft_ctx.bin_to_frequency(bin));
+		}
+	}
+
+	/// The gain applied to each bin, sorted by frequency bin.
+	#[must_use]
+	pub fn gains(&self) -> &[f32] {
+		&self.gains
+	}
+
+	/// Filter a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// The returned signal has the same length as `signal`.
+	#[must_use]
+	pub fn filter(&mut self, signal: &[f32]) -> Vec<f32> {
+		let samples_per_window = self.dft_ctx().samples_per_window();
+
+		// Pad both ends so that every sample of the signal is covered by all its overlapping windows.
+		let lead = samples_per_window - self.hop;
+		let mut padded = vec![0.; lead + signal.len() + samples_per_window];
+		padded[lead..lead + signal.len()].copy_from_slice(signal);
+
+		self.synthesizer.reset();
+		let mut output = Vec::with_capacity(padded.len());
+		for offset in window_offsets(padded.len(), samples_per_window, self.hop) {
+			self.filtered.clear();
+			self.filtered.extend(
+				self.analyzer
+					.analyze(&padded[offset..offset + samples_per_window])
+					.iter()
+					.zip(&self.gains)
+					.map(|(harmonic, gain)| {
+						DiscreteHarmonic::new(harmonic.phasor() * gain, harmonic.bin())
+					}),
+			);
+			output.extend_from_slice(self.synthesizer.synthesize(&self.filtered));
+		}
+
+		output.drain(..lead);
+		output.truncate(signal.len());
+		output
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.analyzer.dft_ctx()
+	}
+
+	#[must_use]
+	pub fn hop(&self) -> usize {
+		self.hop
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use crate::{analysis::windowing_fns::HannWindow, SampleRate};
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn sine(frequency: f32, n_of_samples: usize) -> Vec<f32> {
+		(0..n_of_samples)
+			.map(|i| (TAU * frequency * i as f32 / 8000.).sin())
+			.collect()
+	}
+
+	fn rms(signal: &[f32]) -> f32 {
+		#[allow(clippy::cast_precision_loss)]
+		return (signal.iter().map(|s| s * s).sum::<f32>() / signal.len() as f32).sqrt();
+	}
+
+	#[test]
+	fn unity_gain_is_transparent() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 512);
+		let signal = sine(440., 4000);
+
+		let mut filter = SpectralFilter::new(dft_ctx, 128, &HannWindow, |_| 1.);
+		let output = filter.filter(&signal);
+		assert_eq!(output.len(), signal.len());
+		for (filtered, original) in output.iter().zip(&signal) {
+			assert!(
+				(filtered - original).abs() < 1e-3,
+				"{filtered} != {original}"
+			);
+		}
+	}
+
+	#[test]
+	fn band_pass_removes_out_of_band_components() {
+		let dft_ctx = DftCtx::new(SampleRate(8000), 512);
+		let low = sine(200., 8000);
+		let mid = sine(1000., 8000);
+		let high = sine(3000., 8000);
+		let signal: Vec<f32> = low
+			.iter()
+			.zip(&mid)
+			.zip(&high)
+			.map(|((l, m), h)| l + m + h)
+			.collect();
+
+		let mut filter = SpectralFilter::band_pass(dft_ctx, 128, &HannWindow, (500., 2000.));
+		let output = filter.filter(&signal);
+
+		let residual: Vec<f32> = output.iter().zip(&mid).map(|(o, m)| o - m).collect();
+		assert!(rms(&residual) < 0.01 * rms(&mid), "{}", rms(&residual));
+
+		// Changing the curve on the fly.
+		filter.set_gain_curve(|frequency| if frequency < 500. { 0.5 } else { 0. });
+		let output = filter.filter(&signal);
+		assert!(
+			(rms(&output) - 0.5 * rms(&low)).abs() < 0.01,
+			"{}",
+			rms(&output)
+		);
+	}
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use crate::analysis::{DftCtx, DiscreteHarmonic};
+
+/// A sequence of DFT results, one for each window of a longer signal.
+///
+/// Consecutive windows start `hop` samples apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+	dft_ctx: DftCtx,
+	hop: usize,
+	windows: Vec<Vec<DiscreteHarmonic>>,
+}
+
+impl Spectrogram {
+	/// # Panics
+	/// - if `hop` is 0.
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, hop: usize, windows: Vec<Vec<DiscreteHarmonic>>) -> Self {
+		assert!(hop > 0, "hop must be greater than 0");
+		Self {
+			dft_ctx,
+			hop,
+			windows,
+		}
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	/// The number of samples between the beginning of two consecutive windows.
+	#[must_use]
+	pub fn hop(&self) -> usize {
+		self.hop
+	}
+
+	/// The analysis results, ordered by time.
+	#[must_use]
+	pub fn windows(&self) -> &[Vec<DiscreteHarmonic>] {
+		&self.windows
+	}
+
+	#[must_use]
+	pub fn n_of_windows(&self) -> usize {
+		self.windows.len()
+	}
+
+	/// The index of the first sample of the window at `window_idx`.
+	#[must_use]
+	pub fn window_offset(&self, window_idx: usize) -> usize {
+		window_idx * self.hop
+	}
+
+	/// The time at which the window at `window_idx` begins, relative to the beginning of the signal.
+	#[must_use]
+	#[allow(clippy::cast_precision_loss)]
+	pub fn window_time(&self, window_idx: usize) -> Duration {
+		Duration::from_secs_f64(
+			self.window_offset(window_idx) as f64 / self.dft_ctx.sample_rate().0 as f64,
+		)
+	}
+
+	#[must_use]
+	pub fn into_windows(self) -> Vec<Vec<DiscreteHarmonic>> {
+		self.windows
+	}
+}
+
+/// The offsets of all the complete windows of `samples_per_window` samples that fit
+/// in a signal of `signal_len` samples, spaced `hop` samples apart.
+pub(crate) fn window_offsets(
+	signal_len: usize,
+	samples_per_window: usize,
+	hop: usize,
+) -> impl Iterator<Item = usize> {
+	assert!(hop > 0, "hop must be greater than 0");
+	(0..=signal_len.saturating_sub(samples_per_window))
+		.step_by(hop)
+		.take_while(move |&offset| offset + samples_per_window <= signal_len)
+}
@@ -0,0 +1,249 @@
+use crate::analysis::{dft::StftAnalyzer, DftCtx, DiscreteHarmonic, WindowingFn};
+
+/// Number of bins on each side of a harmonic that are attributed to it,
+/// to account for the spectral leakage caused by the windowing function.
+const LOBE_HALF_WIDTH: usize = 3;
+
+const DEFAULT_N_OF_HARMONICS: usize = 9;
+
+/// Result of a [`ThdAnalyzer`] measurement.
+///
+/// All the fields are powers, with the same unit as [`DiscreteHarmonic::power`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThdMeasurement {
+	fundamental: f32,
+	harmonics: f32,
+	total: f32,
+}
+
+impl ThdMeasurement {
+	/// Total harmonic distortion, expressed as the ratio between the RMS amplitude
+	/// of the harmonics and the RMS amplitude of the fundamental.
+	#[must_use]
+	pub fn thd(&self) -> f32 {
+		ratio(self.harmonics, self.fundamental)
+	}
+
+	#[must_use]
+	pub fn thd_percent(&self) -> f32 {
+		self.thd() * 100.
+	}
+
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn thd_dB(&self) -> f32 {
+		20. * self.thd().log10()
+	}
+
+	/// Total harmonic distortion plus noise, expressed as the ratio between the RMS amplitude
+	/// of everything but the fundamental (DC excluded) and the RMS amplitude of the fundamental.
+	#[must_use]
+	pub fn thd_n(&self) -> f32 {
+		ratio(self.noise_and_distortion_power(), self.fundamental)
+	}
+
+	#[must_use]
+	pub fn thd_n_percent(&self) -> f32 {
+		self.thd_n() * 100.
+	}
+
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn thd_n_dB(&self) -> f32 {
+		20. * self.thd_n().log10()
+	}
+
+	/// Signal to noise and distortion ratio, in dB.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn sinad_dB(&self) -> f32 {
+		10. * (self.total / self.noise_and_distortion_power()).log10()
+	}
+
+	/// The power of the fundamental, with the same unit as [`DiscreteHarmonic::power`].
+	#[must_use]
+	pub fn fundamental_power(&self) -> f32 {
+		self.fundamental
+	}
+
+	fn noise_and_distortion_power(&self) -> f32 {
+		(self.total - self.fundamental).max(0.)
+	}
+}
+
+fn ratio(power: f32, reference_power: f32) -> f32 {
+	if reference_power <= f32::EPSILON {
+		return 0.;
+	}
+	(power / reference_power).sqrt()
+}
+
+/// Measures the total harmonic distortion of a signal containing a sine wave of a known frequency
+/// (e.g. the recording of a test tone played through a device).
+///
+/// Note: the power of each harmonic is collected from the bins adjacent to it, so the
+/// windowing function should have a narrow main lobe and low side lobes (e.g. Hann or Blackman).
+#[derive(Debug, Clone)]
+pub struct ThdAnalyzer {
+	stft_analyzer: StftAnalyzer,
+	fundamental_frequency: f32,
+	n_of_harmonics: usize,
+}
+
+impl ThdAnalyzer {
+	/// Create an analyzer that takes into account the first 9 harmonics
+	/// above the fundamental (i.e. up to 10 times the fundamental frequency).
+	#[must_use]
+	pub fn new(
+		dft_ctx: DftCtx,
+		windowing_fn: &impl WindowingFn,
+		fundamental_frequency: f32,
+	) -> Self {
+		Self::new_with_n_of_harmonics(
+			dft_ctx,
+			windowing_fn,
+			fundamental_frequency,
+			DEFAULT_N_OF_HARMONICS,
+		)
+	}
+
+	/// Create an analyzer that takes into account the first `n_of_harmonics` harmonics
+	/// above the fundamental. Harmonics above the Nyquist frequency are ignored.
+	#[must_use]
+	pub fn new_with_n_of_harmonics(
+		dft_ctx: DftCtx,
+		windowing_fn: &impl WindowingFn,
+		fundamental_frequency: f32,
+		n_of_harmonics: usize,
+	) -> Self {
+		Self {
+			stft_analyzer: StftAnalyzer::new(dft_ctx, windowing_fn),
+			fundamental_frequency,
+			n_of_harmonics,
+		}
+	}
+
+	/// Measure the distortion of a signal in the domain of time, sampled at the configured sample rate.
+	///
+	/// # Panics
+	/// - if the passed `signal` is not compatible with the configured `samples_per_window`.
+	#[must_use]
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn analyze(&mut self, signal: &[f32]) -> ThdMeasurement {
+		let dft_ctx = self.dft_ctx();
+		let transform = self.stft_analyzer.analyze(signal);
+		let n_of_bins = transform.len();
+
+		let lobe_power = |bin: usize| {
+			transform
+				[bin.saturating_sub(LOBE_HALF_WIDTH)..(bin + LOBE_HALF_WIDTH + 1).min(n_of_bins)]
+				.iter()
+				.map(DiscreteHarmonic::power)
+				.sum::<f32>()
+		};
+
+		let fundamental_bin = dft_ctx.frequency_to_bin(self.fundamental_frequency);
+		let fundamental_power = lobe_power(fundamental_bin);
+
+		#[allow(clippy::cast_precision_loss)]
+		let harmonics_power = (2..=self.n_of_harmonics + 1)
+			.map(|k| dft_ctx.frequency_to_bin(self.fundamental_frequency * k as f32))
+			.take_while(|&bin| bin < n_of_bins - 1)
+			.map(lobe_power)
+			.sum::<f32>();
+
+		// DC and its leakage are neither signal nor noise.
+		let total_power = transform
+			.iter()
+			.skip(LOBE_HALF_WIDTH + 1)
+			.map(DiscreteHarmonic::power)
+			.sum::<f32>();
+
+		ThdMeasurement {
+			fundamental: fundamental_power,
+			harmonics: harmonics_power,
+			total: total_power,
+		}
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.stft_analyzer.dft_ctx()
+	}
+
+	#[must_use]
+	pub fn fundamental_frequency(&self) -> f32 {
+		self.fundamental_frequency
+	}
+
+	#[must_use]
+	pub fn n_of_harmonics(&self) -> usize {
+		self.n_of_harmonics
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use rand::{rngs::StdRng, Rng, SeedableRng};
+
+	use crate::{analysis::windowing_fns::HannWindow, SampleRate};
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn distorted_sine(dft_ctx: DftCtx, frequency: f32, harmonics: &[(usize, f32)]) -> Vec<f32> {
+		let sample_rate = dft_ctx.sample_rate().0 as f32;
+		(0..dft_ctx.samples_per_window())
+			.map(|i| {
+				let t = i as f32 / sample_rate;
+				(TAU * frequency * t).sin()
+					+ harmonics
+						.iter()
+						.map(|&(k, amplitude)| amplitude * (TAU * frequency * k as f32 * t).sin())
+						.sum::<f32>()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn measures_harmonic_distortion() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 4410);
+		let signal = distorted_sine(dft_ctx, 1000., &[(2, 0.01), (3, 0.005)]);
+
+		let mut analyzer = ThdAnalyzer::new(dft_ctx, &HannWindow, 1000.);
+		let measurement = analyzer.analyze(&signal);
+
+		let expected = (0.01f32.powi(2) + 0.005f32.powi(2)).sqrt();
+		assert!(
+			(measurement.thd() - expected).abs() < 1e-4,
+			"{} != {expected}",
+			measurement.thd()
+		);
+		assert!((measurement.thd_percent() - expected * 100.).abs() < 1e-2);
+		assert!((measurement.thd_dB() - 20. * expected.log10()).abs() < 0.1);
+		// Without noise, THD+N and THD coincide.
+		assert!((measurement.thd_n() - measurement.thd()).abs() < 1e-4);
+		assert!((measurement.sinad_dB() + 20. * expected.log10()).abs() < 0.1);
+	}
+
+	#[test]
+	fn noise_only_affects_thd_n() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 4410);
+		let mut rng = StdRng::seed_from_u64(42);
+		let signal: Vec<f32> = distorted_sine(dft_ctx, 1000., &[(2, 0.01)])
+			.into_iter()
+			.map(|s| s + rng.gen_range(-0.01f32..0.01))
+			.collect();
+
+		let mut analyzer = ThdAnalyzer::new(dft_ctx, &HannWindow, 1000.);
+		let measurement = analyzer.analyze(&signal);
+
+		assert!((measurement.thd() - 0.01).abs() < 1e-3, "{measurement:?}");
+		assert!(
+			measurement.thd_n() > measurement.thd() * 1.1,
+			"{measurement:?}"
+		);
+	}
+}
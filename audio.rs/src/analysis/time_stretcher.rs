@@ -0,0 +1,354 @@
+#![allow(clippy::cast_precision_loss)]
+#![allow(clippy::cast_possible_truncation)]
+#![allow(clippy::cast_sign_loss)]
+
+use std::{
+	borrow::Borrow,
+	f32::consts::{PI, TAU},
+};
+
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::{
+	analysis::{
+		dft::{IstftSynthesizer, StftAnalyzer},
+		windowing_fns::HannWindow,
+		DftCtx, DiscreteHarmonic, Window,
+	},
+	buffers::InterleavedAudioBuffer,
+	SamplingCtx,
+};
+
+/// Changes the duration of a signal without changing its pitch, using a phase vocoder.
+///
+/// Signals can be passed in chunks of any size, the stretched output is returned
+/// as soon as enough input frames are available to complete the analysis windows.
+#[derive(Debug, Clone)]
+pub struct TimeStretcher {
+	sampling_ctx: SamplingCtx,
+	dft_ctx: DftCtx,
+	ratio: f32,
+	synthesis_hop: usize,
+	channels: Vec<ChannelState>,
+}
+
+#[derive(Debug, Clone)]
+struct ChannelState {
+	analyzer: StftAnalyzer,
+	synthesizer: IstftSynthesizer,
+	pending: Vec<f32>,
+	/// Fractional position of the next analysis window in `pending`.
+	read_position: f64,
+	/// Position of the previous analysis window, relative to the current `pending` buffer.
+	prev_position: Option<f64>,
+	prev_phases: Vec<f32>,
+	synthesis_phases: Vec<f32>,
+	peaks: Vec<usize>,
+	transform: Vec<DiscreteHarmonic>,
+}
+
+impl TimeStretcher {
+	/// Create a new [`TimeStretcher`].
+	///
+	/// `ratio` is the factor by which the duration of the signal is multiplied, e.g. 2.0 makes the signal
+	/// last twice as long while 0.5 halves its duration.
+	///
+	/// `samples_per_window` controls the frequency resolution of the vocoder: longer windows preserve
+	/// tonal content better, while shorter windows smear transients less.
+	///
+	/// # Panics
+	/// - if `ratio` is not a positive finite number.
+	/// - if `samples_per_window` is less than 4.
+	#[must_use]
+	pub fn new(sampling_ctx: SamplingCtx, samples_per_window: usize, ratio: f32) -> Self {
+		assert!(
+			samples_per_window >= 4,
+			"samples_per_window must be at least 4"
+		);
+		let dft_ctx = DftCtx::new(sampling_ctx.sample_rate(), samples_per_window);
+		let synthesis_hop = samples_per_window / 4;
+
+		let window = Window::new(&HannWindow, samples_per_window);
+
+		let mut stretcher = Self {
+			sampling_ctx,
+			dft_ctx,
+			ratio: 1.,
+			synthesis_hop,
+			channels: (0..sampling_ctx.n_ch())
+				.map(|_| ChannelState {
+					analyzer: StftAnalyzer::new_with_window(dft_ctx, &window),
+					synthesizer: IstftSynthesizer::new_with_window(dft_ctx, synthesis_hop, &window),
+					pending: Vec::with_capacity(samples_per_window * 2),
+					read_position: 0.,
+					prev_position: None,
+					prev_phases: vec![0.; dft_ctx.n_of_bins()],
+					synthesis_phases: vec![0.; dft_ctx.n_of_bins()],
+					peaks: Vec::with_capacity(dft_ctx.n_of_bins()),
+					transform: (0..dft_ctx.n_of_bins())
+						.map(|bin| DiscreteHarmonic::new(Complex::ZERO, bin))
+						.collect(),
+				})
+				.collect(),
+		};
+		stretcher.set_ratio(ratio);
+		stretcher
+	}
+
+	/// Change the stretching ratio. The new value is applied starting from the next analysis window.
+	///
+	/// # Panics
+	/// - if `ratio` is not a positive finite number.
+	pub fn set_ratio(&mut self, ratio: f32) {
+		assert!(
+			ratio.is_finite() && ratio > 0.,
+			"ratio must be a positive finite number"
+		);
+		self.ratio = ratio;
+	}
+
+	#[must_use]
+	pub fn ratio(&self) -> f32 {
+		self.ratio
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.dft_ctx
+	}
+
+	/// Feed the next chunk of the signal, returning the stretched frames that are ready.
+	///
+	/// # Panics
+	/// - if the sampling context of the chunk is different from the configured one.
+	#[must_use]
+	pub fn process<Buffer: Borrow<[f32]>>(
+		&mut self,
+		chunk: &InterleavedAudioBuffer<Buffer>,
+	) -> InterleavedAudioBuffer<Vec<f32>> {
+		assert_eq!(
+			chunk.sampling_ctx(),
+			self.sampling_ctx,
+			"chunk with incompatible sampling context received"
+		);
+
+		let n_ch = self.sampling_ctx.n_ch();
+		for (ch, channel) in self.channels.iter_mut().enumerate() {
+			channel
+				.pending
+				.extend(chunk.raw_buffer().borrow().iter().skip(ch).step_by(n_ch));
+		}
+
+		self.drain()
+	}
+
+	/// Push enough silence to flush the frames that are still buffered inside the vocoder.
+	#[must_use]
+	pub fn flush(&mut self) -> InterleavedAudioBuffer<Vec<f32>> {
+		for channel in &mut self.channels {
+			let padding = channel.pending.len() + self.dft_ctx.samples_per_window();
+			channel.pending.resize(padding, 0.);
+		}
+		let output = self.drain();
+		self.reset();
+		output
+	}
+
+	/// Discard all the buffered frames and the phase history.
+	pub fn reset(&mut self) {
+		for channel in &mut self.channels {
+			channel.pending.clear();
+			channel.read_position = 0.;
+			channel.prev_position = None;
+			channel.synthesizer.reset();
+		}
+	}
+
+	fn drain(&mut self) -> InterleavedAudioBuffer<Vec<f32>> {
+		let analysis_hop = f64::from(self.synthesis_hop as f32 / self.ratio);
+		let samples_per_window = self.dft_ctx.samples_per_window();
+
+		let outputs: Vec<Vec<f32>> = self
+			.channels
+			.iter_mut()
+			.map(|channel| {
+				let mut output = vec![];
+				while (channel.read_position as usize) + samples_per_window <= channel.pending.len()
+				{
+					let offset = channel.read_position as usize;
+					channel.vocode(offset, self.synthesis_hop);
+					output.extend_from_slice(channel.synthesizer.synthesize(&channel.transform));
+					channel.prev_position = Some(offset as f64);
+					channel.read_position += analysis_hop;
+				}
+
+				// Drop the samples that won't be read by future windows.
+				let consumed = channel.read_position as usize;
+				let consumed = consumed.min(channel.pending.len());
+				channel.pending.drain(..consumed);
+				channel.read_position -= consumed as f64;
+				channel.prev_position = channel.prev_position.map(|p| p - consumed as f64);
+
+				output
+			})
+			.collect();
+
+		let n_of_frames = outputs.first().map_or(0, Vec::len);
+		let mut interleaved = vec![0.; n_of_frames * self.sampling_ctx.n_ch()];
+		for (ch, output) in outputs.iter().enumerate() {
+			for (dst, src) in interleaved
+				.iter_mut()
+				.skip(ch)
+				.step_by(self.sampling_ctx.n_ch())
+				.zip(output)
+			{
+				*dst = *src;
+			}
+		}
+		InterleavedAudioBuffer::new(self.sampling_ctx, interleaved)
+	}
+}
+
+impl ChannelState {
+	fn vocode(&mut self, offset: usize, synthesis_hop: usize) {
+		let samples_per_window = self.analyzer.dft_ctx().samples_per_window();
+		let analysis = self
+			.analyzer
+			.analyze(&self.pending[offset..offset + samples_per_window]);
+
+		let analysis_hop = self.prev_position.map(|prev| (offset as f64 - prev) as f32);
+
+		// Phases are only propagated at the spectral peaks, while the bins surrounding
+		// each peak keep their original phase offset relative to it (identity phase locking).
+		// This preserves the coherence of the bins belonging to the same partial.
+		self.peaks.clear();
+		self.peaks.extend((0..analysis.len()).filter(|&bin| {
+			let power = analysis[bin].power();
+			(bin == 0 || power > analysis[bin - 1].power())
+				&& analysis
+					.get(bin + 1)
+					.is_none_or(|next| power >= next.power())
+		}));
+
+		for &peak in &self.peaks {
+			let phase = analysis[peak].phase();
+			let bin_frequency = TAU * peak as f32 / samples_per_window as f32;
+			self.synthesis_phases[peak] = match analysis_hop {
+				Some(analysis_hop) if analysis_hop > 0. => {
+					let deviation =
+						wrap_phase(phase - self.prev_phases[peak] - bin_frequency * analysis_hop);
+					let true_frequency = bin_frequency + deviation / analysis_hop;
+					wrap_phase(self.synthesis_phases[peak] + true_frequency * synthesis_hop as f32)
+				}
+				// The window hasn't moved, keep the phase advancing at the bin frequency.
+				Some(_) => {
+					wrap_phase(self.synthesis_phases[peak] + bin_frequency * synthesis_hop as f32)
+				}
+				None => phase,
+			};
+		}
+
+		let mut nearest = 0;
+		for (bin, (harmonic, dst)) in analysis.iter().zip(self.transform.iter_mut()).enumerate() {
+			while nearest + 1 < self.peaks.len()
+				&& self.peaks[nearest + 1].abs_diff(bin) < self.peaks[nearest].abs_diff(bin)
+			{
+				nearest += 1;
+			}
+			if let Some(&peak) = self.peaks.get(nearest) {
+				if peak != bin {
+					self.synthesis_phases[bin] =
+						self.synthesis_phases[peak] + harmonic.phase() - analysis[peak].phase();
+				}
+			}
+			dst.phasor = Complex32::from_polar(harmonic.amplitude(), self.synthesis_phases[bin]);
+		}
+
+		for (prev_phase, harmonic) in self.prev_phases.iter_mut().zip(analysis) {
+			*prev_phase = harmonic.phase();
+		}
+	}
+}
+
+fn wrap_phase(phase: f32) -> f32 {
+	(phase + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+#[cfg(feature = "output")]
+mod tests {
+	use crate::{
+		analysis::{find_strongest_peak, Harmonic},
+		output::harmonics_to_samples,
+		SampleRate,
+	};
+
+	use super::*;
+
+	fn dominant_frequency(signal: &[f32], dft_ctx: DftCtx) -> f32 {
+		let mut analyzer = StftAnalyzer::new(dft_ctx, &HannWindow);
+		let bin = find_strongest_peak(analyzer.analyze(&signal[..dft_ctx.samples_per_window()]))
+			.unwrap()
+			.bin();
+		dft_ctx.bin_to_frequency(bin)
+	}
+
+	#[test]
+	fn time_stretcher_preserves_pitch() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(44100), 2);
+		let frequency = 440.;
+
+		let mono = harmonics_to_samples(
+			sampling_ctx.sample_rate(),
+			44100,
+			&[Harmonic::new(Complex32::ONE, frequency)],
+		);
+		let signal = InterleavedAudioBuffer::new(
+			sampling_ctx,
+			mono.iter().flat_map(|&s| [s, s]).collect::<Vec<f32>>(),
+		);
+
+		for ratio in [0.5, 1.5, 2.] {
+			let mut stretcher = TimeStretcher::new(sampling_ctx, 2048, ratio);
+
+			// Feed the signal in irregular chunks to exercise the streaming path.
+			let mut output = InterleavedAudioBuffer::new(sampling_ctx, vec![]);
+			for chunk in signal.raw_buffer().chunks(2 * 1000) {
+				output.extend(
+					stretcher
+						.process(&InterleavedAudioBuffer::new(sampling_ctx, chunk))
+						.iter(),
+				);
+			}
+			output.extend(stretcher.flush().iter());
+
+			let expected_frames = signal.n_of_frames().0 as f32 * ratio;
+			let actual_frames = output.n_of_frames().0 as f32;
+			assert!(
+				(actual_frames - expected_frames).abs() < 2. * 2048.,
+				"ratio {ratio}: expected ~{expected_frames} frames, got {actual_frames}"
+			);
+
+			let left: Vec<f32> = output.iter().map(|frame| frame[0]).collect();
+			let peak = left[4096..left.len() - 4096]
+				.iter()
+				.fold(0_f32, |peak, sample| peak.max(sample.abs()));
+			assert!(
+				(peak - 1.).abs() < 0.05,
+				"ratio {ratio}: amplitude not preserved ({peak})"
+			);
+
+			let dft_ctx = DftCtx::new(sampling_ctx.sample_rate(), 8192);
+			let measured = dominant_frequency(&left[4096..], dft_ctx);
+			assert!(
+				(measured - frequency).abs() <= dft_ctx.frequency_gap(),
+				"ratio {ratio}: expected {frequency}Hz, got {measured}Hz"
+			);
+		}
+	}
+}
@@ -0,0 +1,247 @@
+use rustfft::num_complex::{Complex, Complex32};
+
+use crate::analysis::{
+	dft::StftAnalyzer, window_offsets, DftCtx, DiscreteHarmonic, Window, WindowingFn,
+};
+
+/// The estimated response of a system at a specific DFT bin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferFunctionBin {
+	response: DiscreteHarmonic,
+	coherence: f32,
+}
+
+impl TransferFunctionBin {
+	/// The complex gain of the system: its amplitude is the ratio between the amplitude
+	/// of the measurement and the amplitude of the reference, its phase is the phase shift
+	/// introduced by the system.
+	#[must_use]
+	pub fn response(&self) -> DiscreteHarmonic {
+		self.response
+	}
+
+	#[must_use]
+	pub fn bin(&self) -> usize {
+		self.response.bin()
+	}
+
+	#[must_use]
+	pub fn gain(&self) -> f32 {
+		self.response.amplitude()
+	}
+
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn gain_dB(&self) -> f32 {
+		self.response.dB()
+	}
+
+	#[must_use]
+	pub fn phase(&self) -> f32 {
+		self.response.phase()
+	}
+
+	/// A value between 0 and 1 that describes how much of the measurement is linearly explained by
+	/// the reference at this bin. Values close to 1 mean that the response is reliable, lower
+	/// values hint at noise, non-linearities or an insufficient amount of energy in the reference.
+	#[must_use]
+	pub fn coherence(&self) -> f32 {
+		self.coherence
+	}
+}
+
+/// Dual-channel analyzer that estimates the transfer function `H(f)` of a system
+/// (e.g. a speaker in a room) given a reference signal (what was played) and a measurement
+/// signal (what was recorded), captured simultaneously.
+///
+/// The spectra of multiple windows are averaged (Welch's method), and the
+/// transfer function is computed as the ratio between the cross-spectrum and the
+/// auto-spectrum of the reference.
+#[derive(Debug, Clone)]
+pub struct TransferFunctionAnalyzer {
+	reference_analyzer: StftAnalyzer,
+	measurement_analyzer: StftAnalyzer,
+	cross_spectrum: Vec<Complex32>,
+	reference_power: Vec<f32>,
+	measurement_power: Vec<f32>,
+	n_of_windows: usize,
+}
+
+impl TransferFunctionAnalyzer {
+	#[must_use]
+	pub fn new(dft_ctx: DftCtx, windowing_fn: &impl WindowingFn) -> Self {
+		let n_of_bins = dft_ctx.n_of_bins();
+		let window = Window::new(windowing_fn, dft_ctx.samples_per_window());
+		Self {
+			reference_analyzer: StftAnalyzer::new_with_window(dft_ctx, &window),
+			measurement_analyzer: StftAnalyzer::new_with_window(dft_ctx, &window),
+			cross_spectrum: vec![Complex::ZERO; n_of_bins],
+			reference_power: vec![0.; n_of_bins],
+			measurement_power: vec![0.; n_of_bins],
+			n_of_windows: 0,
+		}
+	}
+
+	/// Add a pair of simultaneous windows to the average.
+	///
+	/// # Panics
+	/// - if either `reference` or `measurement` is not compatible with the configured `samples_per_window`.
+	pub fn accumulate(&mut self, reference: &[f32], measurement: &[f32]) {
+		let reference_transform = self.reference_analyzer.analyze(reference);
+		let measurement_transform = self.measurement_analyzer.analyze(measurement);
+
+		for (((cross, reference_power), measurement_power), (x, y)) in self
+			.cross_spectrum
+			.iter_mut()
+			.zip(self.reference_power.iter_mut())
+			.zip(self.measurement_power.iter_mut())
+			.zip(reference_transform.iter().zip(measurement_transform))
+		{
+			*cross += x.phasor().conj() * y.phasor();
+			*reference_power += x.power();
+			*measurement_power += y.power();
+		}
+
+		self.n_of_windows += 1;
+	}
+
+	/// Add all the complete windows contained in two longer simultaneous signals to the average,
+	/// taking a window every `hop` samples.
+	///
+	/// # Panics
+	/// - if `hop` is 0.
+	/// - if `reference` and `measurement` have different lengths.
+	#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+	pub fn accumulate_buffer(&mut self, reference: &[f32], measurement: &[f32], hop: usize) {
+		assert_eq!(
+			reference.len(),
+			measurement.len(),
+			"reference and measurement must have the same length"
+		);
+		let samples_per_window = self.dft_ctx().samples_per_window();
+		for offset in window_offsets(reference.len(), samples_per_window, hop) {
+			self.accumulate(
+				&reference[offset..offset + samples_per_window],
+				&measurement[offset..offset + samples_per_window],
+			);
+		}
+	}
+
+	/// The transfer function estimated from all the windows accumulated so far, sorted by frequency bin.
+	///
+	/// Bins in which the reference carries no energy have a null response and coherence.
+	#[must_use]
+	pub fn frequency_response(&self) -> Vec<TransferFunctionBin> {
+		self.cross_spectrum
+			.iter()
+			.zip(self.reference_power.iter())
+			.zip(self.measurement_power.iter())
+			.enumerate()
+			.map(|(bin, ((cross, &reference_power), &measurement_power))| {
+				if reference_power <= f32::EPSILON {
+					return TransferFunctionBin {
+						response: DiscreteHarmonic::new(Complex::ZERO, bin),
+						coherence: 0.,
+					};
+				}
+				TransferFunctionBin {
+					response: DiscreteHarmonic::new(cross / reference_power, bin),
+					coherence: if measurement_power <= f32::EPSILON {
+						0.
+					} else {
+						(cross.norm_sqr() / (reference_power * measurement_power)).min(1.)
+					},
+				}
+			})
+			.collect()
+	}
+
+	/// The number of windows accumulated since the creation of the analyzer or the last [`Self::reset`].
+	#[must_use]
+	pub fn n_of_windows(&self) -> usize {
+		self.n_of_windows
+	}
+
+	#[must_use]
+	pub fn dft_ctx(&self) -> DftCtx {
+		self.reference_analyzer.dft_ctx()
+	}
+
+	/// Discard all the accumulated windows.
+	pub fn reset(&mut self) {
+		self.cross_spectrum.fill(Complex::ZERO);
+		self.reference_power.fill(0.);
+		self.measurement_power.fill(0.);
+		self.n_of_windows = 0;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, Rng, SeedableRng};
+
+	use crate::{analysis::windowing_fns::HannWindow, SampleRate};
+
+	use super::*;
+
+	fn noise(seed: u64, n_of_samples: usize) -> Vec<f32> {
+		let mut rng = StdRng::seed_from_u64(seed);
+		(0..n_of_samples)
+			.map(|_| rng.gen_range(-1.0..1.0))
+			.collect()
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn measures_the_response_of_a_filter() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 1024);
+		let reference = noise(1, 44100);
+		// y[n] = 0.5 * x[n] + 0.25 * x[n - 1]
+		let measurement: Vec<f32> = (0..reference.len())
+			.map(|i| 0.5 * reference[i] + if i > 0 { 0.25 * reference[i - 1] } else { 0. })
+			.collect();
+
+		let mut analyzer = TransferFunctionAnalyzer::new(dft_ctx, &HannWindow);
+		analyzer.accumulate_buffer(&reference, &measurement, 512);
+		assert!(analyzer.n_of_windows() > 80);
+
+		let response = analyzer.frequency_response();
+		assert_eq!(response.len(), dft_ctx.n_of_bins());
+		for point in response.iter().skip(1).step_by(37) {
+			let omega = 2. * std::f32::consts::PI * point.bin() as f32
+				/ dft_ctx.samples_per_window() as f32;
+			let expected = Complex32::new(0.5, 0.) + Complex32::from_polar(0.25, -omega);
+			assert!(
+				(point.response().phasor() - expected).norm() < 0.02,
+				"{point:?} != {expected:?}"
+			);
+			assert!(point.coherence() > 0.99, "{point:?}");
+		}
+	}
+
+	#[test]
+	fn uncorrelated_signals_have_low_coherence() {
+		let dft_ctx = DftCtx::new(SampleRate(44100), 1024);
+		let reference = noise(1, 44100);
+		let measurement = noise(2, 44100);
+
+		let mut analyzer = TransferFunctionAnalyzer::new(dft_ctx, &HannWindow);
+		analyzer.accumulate_buffer(&reference, &measurement, 512);
+
+		let response = analyzer.frequency_response();
+		#[allow(clippy::cast_precision_loss)]
+		let average_coherence = response
+			.iter()
+			.map(TransferFunctionBin::coherence)
+			.sum::<f32>()
+			/ response.len() as f32;
+		assert!(average_coherence < 0.1, "{average_coherence}");
+
+		analyzer.reset();
+		assert_eq!(analyzer.n_of_windows(), 0);
+		assert!(analyzer
+			.frequency_response()
+			.iter()
+			.all(|point| point.gain() == 0.));
+	}
+}
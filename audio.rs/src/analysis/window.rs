@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use crate::analysis::WindowingFn;
+
+/// The values of a [`WindowingFn`] pre-computed for a specific number of samples.
+///
+/// Cloning a [`Window`] is cheap, as the values are shared: analyzers and synthesizers
+/// with the same `samples_per_window` can be created from the same instance
+/// (e.g. with [`super::dft::StftAnalyzer::new_with_window`]) instead of each computing
+/// and storing its own copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+	values: Arc<[f32]>,
+	coherent_gain: f32,
+	energy_gain: f32,
+}
+
+impl Window {
+	#[must_use]
+	pub fn new(windowing_fn: &impl WindowingFn, n_of_samples: usize) -> Self {
+		let values: Arc<[f32]> = (0..n_of_samples)
+			.map(|i| windowing_fn.ratio_at(i, n_of_samples))
+			.collect();
+
+		#[allow(clippy::cast_precision_loss)]
+		let n_of_samples_f = n_of_samples.max(1) as f32;
+		let coherent_gain = values.iter().sum::<f32>() / n_of_samples_f;
+		let energy_gain = values.iter().map(|v| v * v).sum::<f32>() / n_of_samples_f;
+
+		Self {
+			values,
+			coherent_gain,
+			energy_gain,
+		}
+	}
+
+	#[must_use]
+	pub fn values(&self) -> &[f32] {
+		&self.values
+	}
+
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.values.len()
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.values.is_empty()
+	}
+
+	/// The mean of the window values, i.e. how much the window attenuates the amplitude of a sinusoid
+	/// centered in a bin (0.5 for the Hann window).
+	#[must_use]
+	pub fn coherent_gain(&self) -> f32 {
+		self.coherent_gain
+	}
+
+	/// The factor that restores the amplitude of sinusoids after windowing.
+	#[must_use]
+	pub fn amplitude_correction(&self) -> f32 {
+		1. / self.coherent_gain
+	}
+
+	/// The factor that restores the energy (e.g. of broadband noise) after windowing.
+	#[must_use]
+	pub fn energy_correction(&self) -> f32 {
+		1. / self.energy_gain.sqrt()
+	}
+
+	/// The equivalent noise bandwidth of the window, in bins (1.5 for the Hann window).
+	#[must_use]
+	pub fn equivalent_noise_bandwidth(&self) -> f32 {
+		self.energy_gain / (self.coherent_gain * self.coherent_gain)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::analysis::windowing_fns::{HannWindow, IdentityWindow};
+
+	use super::*;
+
+	#[test]
+	fn correction_factors() {
+		let rectangle = Window::new(&IdentityWindow, 1024);
+		assert_eq!(rectangle.len(), 1024);
+		assert!((rectangle.coherent_gain() - 1.).abs() < 1e-6);
+		assert!((rectangle.energy_correction() - 1.).abs() < 1e-6);
+		assert!((rectangle.equivalent_noise_bandwidth() - 1.).abs() < 1e-6);
+
+		let hann = Window::new(&HannWindow, 1024);
+		assert!((hann.coherent_gain() - 0.5).abs() < 1e-3);
+		assert!((hann.amplitude_correction() - 2.).abs() < 1e-2);
+		assert!((hann.energy_correction() - (8f32 / 3.).sqrt()).abs() < 1e-2);
+		assert!((hann.equivalent_noise_bandwidth() - 1.5).abs() < 1e-2);
+	}
+
+	#[test]
+	fn clones_share_the_values() {
+		let window = Window::new(&HannWindow, 256);
+		let clone = window.clone();
+		assert_eq!(window, clone);
+		assert_eq!(window.values().as_ptr(), clone.values().as_ptr());
+		assert!(Window::new(&HannWindow, 0).is_empty());
+	}
+}
@@ -0,0 +1,248 @@
+//! Detection of the operations that are not realtime-safe (heap allocations and contended locks)
+//! inside the audio callbacks of the input and output streams, including the `on_data` and
+//! data producer closures passed to them.
+//!
+//! Allocations are only observed when [`AuditAllocator`] is installed as the global allocator:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: audio::audit::AuditAllocator = audio::audit::AuditAllocator::new(std::alloc::System);
+//! ```
+//!
+//! The streams never wait for their own locks inside the callbacks. Neither do the players,
+//! oscillators, mixers, synths and metronomes of [`crate::output`], which play silence
+//! for the chunks generated while their state is being changed, and hand the signals
+//! they are done with (and the callbacks reporting it) over to a worker thread rather
+//! than dropping (or calling) them inside the callbacks. The locks taken by the `on_data`
+//! and data producer closures can be checked with [`check_lock`].
+//!
+//! Offline output streams (see [`crate::output::OutputStream::new_offline`]) run the same checks
+//! while rendering, so that the data producers can be audited without an audio device.
+//!
+//! The scratch buffers of the streams are preallocated for chunks of up to 4096 frames
+//! (or the requested buffer size, if larger), so only larger chunks make them grow.
+//! File captures (e.g. [`crate::output::OutputStream::start_capture`]) reuse the buffers
+//! of the written chunks, so they only allocate during the first callbacks.
+
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	cell::Cell,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+#[cfg(any(feature = "input", feature = "output"))]
+use std::{
+	sync::{Mutex, TryLockError},
+	thread,
+};
+
+/// How the violations detected inside an audio callback are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AuditMode {
+	/// Only count them, see [`callback_audit_stats`].
+	#[default]
+	Count,
+	/// Count them and panic at the end of the callback that caused them.
+	Panic,
+}
+
+/// Counters collected since the start of the program or the last [`reset_callback_audit_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CallbackAuditStats {
+	/// The number of audio callbacks run.
+	pub callbacks: usize,
+	/// The number of calls to the allocator (allocations, reallocations and deallocations)
+	/// made inside the audio callbacks.
+	pub allocations: usize,
+	/// The number of times an audio callback found a lock already held by another thread.
+	pub contended_locks: usize,
+	/// The number of audio callbacks in which at least a violation has been detected.
+	pub flagged_callbacks: usize,
+}
+
+static PANIC_ON_VIOLATION: AtomicBool = AtomicBool::new(false);
+static CALLBACKS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static CONTENDED_LOCKS: AtomicUsize = AtomicUsize::new(0);
+static FLAGGED_CALLBACKS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+	// Const-initialized and without destructors, so that they can be used from the allocator.
+	static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+	static VIOLATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Set how the violations are reported, [`AuditMode::Count`] by default.
+pub fn set_audit_mode(mode: AuditMode) {
+	PANIC_ON_VIOLATION.store(mode == AuditMode::Panic, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn audit_mode() -> AuditMode {
+	if PANIC_ON_VIOLATION.load(Ordering::Relaxed) {
+		AuditMode::Panic
+	} else {
+		AuditMode::Count
+	}
+}
+
+#[must_use]
+pub fn callback_audit_stats() -> CallbackAuditStats {
+	CallbackAuditStats {
+		callbacks: CALLBACKS.load(Ordering::Relaxed),
+		allocations: ALLOCATIONS.load(Ordering::Relaxed),
+		contended_locks: CONTENDED_LOCKS.load(Ordering::Relaxed),
+		flagged_callbacks: FLAGGED_CALLBACKS.load(Ordering::Relaxed),
+	}
+}
+
+pub fn reset_callback_audit_stats() {
+	for counter in [
+		&CALLBACKS,
+		&ALLOCATIONS,
+		&CONTENDED_LOCKS,
+		&FLAGGED_CALLBACKS,
+	] {
+		counter.store(0, Ordering::Relaxed);
+	}
+}
+
+fn record_violation(counter: &AtomicUsize) {
+	if IN_CALLBACK.try_with(Cell::get).unwrap_or(false) {
+		counter.fetch_add(1, Ordering::Relaxed);
+		let _ = VIOLATIONS.try_with(|violations| violations.set(violations.get() + 1));
+	}
+}
+
+/// A global allocator that forwards to `A`, counting the calls made inside the audio callbacks,
+/// see the [module documentation](self).
+#[derive(Debug, Default)]
+pub struct AuditAllocator<A = System> {
+	inner: A,
+}
+
+impl<A> AuditAllocator<A> {
+	pub const fn new(inner: A) -> Self {
+		Self { inner }
+	}
+}
+
+// SAFETY: every call is forwarded to the inner allocator, the bookkeeping doesn't allocate.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for AuditAllocator<A> {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		record_violation(&ALLOCATIONS);
+		self.inner.alloc(layout)
+	}
+
+	unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+		record_violation(&ALLOCATIONS);
+		self.inner.alloc_zeroed(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		record_violation(&ALLOCATIONS);
+		self.inner.dealloc(ptr, layout);
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		record_violation(&ALLOCATIONS);
+		self.inner.realloc(ptr, layout, new_size)
+	}
+}
+
+/// Marks the current thread as running an audio callback until dropped.
+#[cfg(any(feature = "input", feature = "output"))]
+pub(crate) struct CallbackGuard;
+
+#[cfg(any(feature = "input", feature = "output"))]
+impl CallbackGuard {
+	pub(crate) fn enter() -> Self {
+		CALLBACKS.fetch_add(1, Ordering::Relaxed);
+		VIOLATIONS.set(0);
+		IN_CALLBACK.set(true);
+		Self
+	}
+}
+
+#[cfg(any(feature = "input", feature = "output"))]
+impl Drop for CallbackGuard {
+	fn drop(&mut self) {
+		IN_CALLBACK.set(false);
+		let violations = VIOLATIONS.get();
+		if violations > 0 {
+			FLAGGED_CALLBACKS.fetch_add(1, Ordering::Relaxed);
+			assert!(
+				!PANIC_ON_VIOLATION.load(Ordering::Relaxed) || thread::panicking(),
+				"{violations} allocations or contended locks inside an audio callback"
+			);
+		}
+	}
+}
+
+/// Record a violation if `mutex` is held by another thread. Meant to be called right before
+/// locking it from an audio callback, e.g. in a data producer:
+///
+/// ```ignore
+/// audio::audit::check_lock(&state);
+/// state.lock().unwrap().fill(chunk);
+/// ```
+#[cfg(any(feature = "input", feature = "output"))]
+pub fn check_lock<T>(mutex: &Mutex<T>) {
+	if let Err(TryLockError::WouldBlock) = mutex.try_lock() {
+		record_violation(&CONTENDED_LOCKS);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The counters are global, so everything is checked in a single test to avoid interference.
+	// Allocations are checked by the `audit` integration test, which installs the allocator.
+	#[test]
+	#[cfg(any(feature = "input", feature = "output"))]
+	fn violations_are_counted() {
+		set_audit_mode(AuditMode::Count);
+		reset_callback_audit_stats();
+
+		let mutex = Mutex::new(());
+		{
+			let _guard = CallbackGuard::enter();
+			// Free, so nothing is recorded.
+			check_lock(&mutex);
+		}
+		// Outside of a callback nothing is recorded.
+		let held = mutex.lock().unwrap();
+		check_lock(&mutex);
+		assert_eq!(callback_audit_stats().contended_locks, 0);
+		{
+			let _guard = CallbackGuard::enter();
+			check_lock(&mutex);
+		}
+		drop(held);
+
+		assert_eq!(
+			callback_audit_stats(),
+			CallbackAuditStats {
+				callbacks: 2,
+				allocations: 0,
+				contended_locks: 1,
+				flagged_callbacks: 1,
+			}
+		);
+
+		set_audit_mode(AuditMode::Panic);
+		assert_eq!(audit_mode(), AuditMode::Panic);
+		let held = mutex.lock().unwrap();
+		let result = thread::scope(|scope| {
+			scope
+				.spawn(|| {
+					let _guard = CallbackGuard::enter();
+					check_lock(&mutex);
+				})
+				.join()
+		});
+		drop(held);
+		assert!(result.is_err());
+		set_audit_mode(AuditMode::Count);
+	}
+}
@@ -0,0 +1,532 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex, Weak},
+	time::Duration,
+};
+
+use cpal::{
+	traits::{DeviceTrait, StreamTrait},
+	BuildStreamError, Device, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig,
+	StreamError, StreamInstant, SupportedStreamConfig,
+};
+use mutex_ext::LockExt;
+
+pub use cpal::{InputStreamTimestamp, OutputStreamTimestamp};
+
+use crate::{
+	buffers::InterleavedAudioBuffer, callback_capacity, stream_config, AudioStreamError, HostError,
+	IOMode, NOfFrames, SampleRate, SamplingCtx, StreamOptions,
+};
+
+/// Receives the interleaved samples captured by an [`AudioDevice`].
+pub type DeviceInputCallback = dyn FnMut(&[f32], InputStreamTimestamp) + Send + 'static;
+
+/// Fills the interleaved samples played by an [`AudioDevice`].
+pub type DeviceOutputCallback = dyn FnMut(&mut [f32], OutputStreamTimestamp) + Send + 'static;
+
+/// Receives the errors of a stream of an [`AudioDevice`], e.g. when it gets disconnected.
+pub type DeviceErrorCallback = dyn FnMut(AudioStreamError) + Send + 'static;
+
+/// A stream started by an [`AudioDevice`], which keeps running until it's dropped.
+///
+/// The streams are held, and dropped, by the thread that built them, so they don't need to be `Send`.
+pub trait DeviceStream {}
+
+impl DeviceStream for Stream {}
+
+/// The device layer [`crate::input::InputStream`] and [`crate::output::OutputStream`] are built over.
+///
+/// Besides the devices of the audio hosts, it can be implemented to feed the streams
+/// from other sources, e.g. with a [`MockDevice`] in tests.
+pub trait AudioDevice: Send + Sync + 'static {
+	/// The name reported in the errors and in the reconnection events.
+	fn name(&self) -> String;
+
+	/// Start passing the chunks captured at `sampling_ctx` to `on_data`,
+	/// until the returned stream is dropped.
+	///
+	/// # Errors
+	/// [`AudioStreamError::BuildFailed`] or [`AudioStreamError::StartFailed`].
+	fn build_input_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		on_data: Box<DeviceInputCallback>,
+		on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError>;
+
+	/// Start playing the chunks filled at `sampling_ctx` by `fill`, until the returned
+	/// stream is dropped. The chunks are zeroed before being passed to `fill`.
+	///
+	/// # Errors
+	/// [`AudioStreamError::BuildFailed`] or [`AudioStreamError::StartFailed`].
+	fn build_output_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		fill: Box<DeviceOutputCallback>,
+		on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError>;
+}
+
+/// A device of an audio host, opened with the configuration chosen by `device_provider`.
+pub(crate) struct CpalDevice {
+	device: Device,
+	config: SupportedStreamConfig,
+	options: StreamOptions,
+}
+
+impl CpalDevice {
+	pub(crate) fn new(
+		device: Device,
+		config: SupportedStreamConfig,
+		options: StreamOptions,
+	) -> Self {
+		Self {
+			device,
+			config,
+			options,
+		}
+	}
+
+	/// The sampling context of the configuration, which the streams are built with.
+	pub(crate) fn sampling_ctx(&self) -> SamplingCtx {
+		SamplingCtx::new(
+			SampleRate(self.config.sample_rate().0 as usize),
+			self.config.channels() as usize,
+		)
+	}
+
+	fn start(
+		&self,
+		stream: Result<Stream, BuildStreamError>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		let device_name = self.name();
+		let stream = stream.map_err(|err| AudioStreamError::BuildFailed {
+			device_name: device_name.clone(),
+			source: HostError::new(err),
+		})?;
+		stream.play().map_err(|err| AudioStreamError::StartFailed {
+			device_name,
+			source: HostError::new(err),
+		})?;
+		Ok(Box::new(stream))
+	}
+}
+
+impl AudioDevice for CpalDevice {
+	fn name(&self) -> String {
+		self.device.name().unwrap_or_default()
+	}
+
+	fn build_input_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		mut on_data: Box<DeviceInputCallback>,
+		mut on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		debug_assert_eq!(sampling_ctx, self.sampling_ctx());
+		self.start(self.device.build_input_stream(
+			&stream_config(&self.config, self.options.buffer_size),
+			move |data: &[f32], info| on_data(data, info.timestamp()),
+			move |err: StreamError| on_error(err.into()),
+			None,
+		))
+	}
+
+	fn build_output_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		mut fill: Box<DeviceOutputCallback>,
+		mut on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		debug_assert_eq!(sampling_ctx, self.sampling_ctx());
+		let stream_config = stream_config(&self.config, self.options.buffer_size);
+		let capacity = callback_capacity(self.options.buffer_size);
+		let fill = move |output: &mut [f32], info: &OutputCallbackInfo| {
+			// The f32 buffers come straight from the host, with whatever they held before.
+			output.fill(0.);
+			fill(output, info.timestamp());
+		};
+		let on_error = move |err: StreamError| on_error(err.into());
+		self.start(match self.config.sample_format() {
+			SampleFormat::I16 => build_converted_stream::<i16>(
+				&self.device,
+				&stream_config,
+				capacity,
+				fill,
+				on_error,
+			),
+			SampleFormat::U16 => build_converted_stream::<u16>(
+				&self.device,
+				&stream_config,
+				capacity,
+				fill,
+				on_error,
+			),
+			_ => self
+				.device
+				.build_output_stream(&stream_config, fill, on_error, None),
+		})
+	}
+}
+
+/// Build a stream for a device with an integer sample format, whose samples are generated
+/// by `fill` as f32 and converted in the callback, see [`IntegerSample`].
+///
+/// The conversion buffer is preallocated for chunks of up to `capacity` frames.
+fn build_converted_stream<T: IntegerSample>(
+	device: &Device,
+	stream_config: &StreamConfig,
+	capacity: NOfFrames,
+	mut fill: impl FnMut(&mut [f32], &OutputCallbackInfo) + Send + 'static,
+	on_stream_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<Stream, BuildStreamError> {
+	let mut buffer = Vec::with_capacity(capacity.0 * usize::from(stream_config.channels));
+	device.build_output_stream(
+		stream_config,
+		move |output: &mut [T], info: &OutputCallbackInfo| {
+			buffer.clear();
+			buffer.resize(output.len(), 0.);
+			fill(&mut buffer, info);
+			for (output, &sample) in output.iter_mut().zip(&buffer) {
+				*output = T::from_f32(sample);
+			}
+		},
+		on_stream_error,
+		None,
+	)
+}
+
+/// The integer sample formats supported by output devices, besides f32.
+trait IntegerSample: SizedSample {
+	/// Convert a sample, clipping it to the range [-1, 1].
+	fn from_f32(sample: f32) -> Self;
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // REASON: the values are clamped before the cast
+impl IntegerSample for i16 {
+	fn from_f32(sample: f32) -> Self {
+		(sample.clamp(-1., 1.) * f32::from(i16::MAX)).round() as i16
+	}
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // REASON: the values are clamped before the cast
+impl IntegerSample for u16 {
+	fn from_f32(sample: f32) -> Self {
+		((sample.clamp(-1., 1.) + 1.) * f32::from(u16::MAX) / 2.).round() as u16
+	}
+}
+
+/// The reason a [`MockDevice`] refuses to build a stream.
+#[derive(thiserror::Error, Debug)]
+#[error("the device only supports {0}")]
+struct UnsupportedSamplingCtx(SamplingCtx);
+
+/// An [`AudioDevice`] without audio hardware, driven by hand, e.g. to test what is built
+/// on top of the streams deterministically.
+///
+/// Nothing happens until [`Self::process`] is called: every call runs one callback
+/// of each stream built on the device, passing the next frames of the input
+/// scripted with [`Self::push_input`] to the input streams, and recording what the output
+/// streams fill, to be read back with [`Self::take_output`].
+///
+/// The timestamps follow a virtual clock that starts at 0 and advances by the length
+/// of each chunk, with no input nor output latency.
+///
+/// Clones share the same device.
+#[derive(Clone)]
+pub struct MockDevice {
+	name: String,
+	sampling_ctx: SamplingCtx,
+	state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+	/// See [`MockDevice::push_input`].
+	input: VecDeque<f32>,
+	/// See [`MockDevice::take_output`].
+	output: Vec<f32>,
+	streams: Vec<MockStreamCallbacks>,
+	next_id: usize,
+	elapsed: Duration,
+}
+
+struct MockStreamCallbacks {
+	id: usize,
+	callback: MockCallback,
+	on_error: Box<DeviceErrorCallback>,
+}
+
+enum MockCallback {
+	Input(Box<DeviceInputCallback>),
+	Output(Box<DeviceOutputCallback>),
+}
+
+/// Removes its callbacks from the [`MockDevice`] when dropped.
+struct MockStream {
+	id: usize,
+	state: Weak<Mutex<MockState>>,
+}
+
+impl DeviceStream for MockStream {}
+
+impl Drop for MockStream {
+	fn drop(&mut self) {
+		if let Some(state) = self.state.upgrade() {
+			state.with_lock_mut(|state| state.streams.retain(|stream| stream.id != self.id));
+		}
+	}
+}
+
+impl MockDevice {
+	/// A device that only supports streams at `sampling_ctx`.
+	#[must_use]
+	pub fn new(name: &str, sampling_ctx: SamplingCtx) -> Self {
+		Self {
+			name: name.to_owned(),
+			sampling_ctx,
+			state: Arc::new(Mutex::new(MockState::default())),
+		}
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	/// Queue interleaved samples to be captured by the input streams. Once the queue
+	/// is empty, they capture silence.
+	///
+	/// # Panics
+	/// - if the number of samples is not a multiple of the number of channels.
+	/// - if the mutex guarding the device is poisoned.
+	pub fn push_input(&self, samples: &[f32]) {
+		assert!(
+			samples.len().is_multiple_of(self.sampling_ctx.n_ch()),
+			"the number of samples must be a multiple of the number of channels"
+		);
+		self.state
+			.with_lock_mut(|state| state.input.extend(samples));
+	}
+
+	/// Run one callback of `n_of_frames` of every stream built on the device.
+	///
+	/// # Panics
+	/// - if the mutex guarding the device is poisoned.
+	pub fn process(&self, n_of_frames: NOfFrames) {
+		let n_of_samples = self.sampling_ctx.frames_to_samples(n_of_frames);
+		let duration = self.sampling_ctx.frames_to_duration(n_of_frames);
+		self.state.with_lock_mut(|state| {
+			let available = n_of_samples.min(state.input.len());
+			let mut input: Vec<f32> = state.input.drain(..available).collect();
+			input.resize(n_of_samples, 0.);
+			let mut output = vec![0.; n_of_samples];
+
+			let now = virtual_instant(state.elapsed);
+			let next = virtual_instant(state.elapsed + duration);
+			for stream in &mut state.streams {
+				match &mut stream.callback {
+					MockCallback::Input(on_data) => on_data(
+						&input,
+						InputStreamTimestamp {
+							callback: next,
+							capture: now,
+						},
+					),
+					MockCallback::Output(fill) => {
+						output.fill(0.);
+						fill(
+							&mut output,
+							OutputStreamTimestamp {
+								callback: now,
+								playback: now,
+							},
+						);
+						state.output.extend_from_slice(&output);
+					}
+				}
+			}
+			state.elapsed += duration;
+		});
+	}
+
+	/// Take what the output streams have played so far. The output of concurrent streams
+	/// is recorded chunk after chunk, in the order the streams have been built.
+	///
+	/// # Panics
+	/// - if the mutex guarding the device is poisoned.
+	#[must_use]
+	pub fn take_output(&self) -> InterleavedAudioBuffer<Vec<f32>> {
+		InterleavedAudioBuffer::new(
+			self.sampling_ctx,
+			self.state
+				.with_lock_mut(|state| std::mem::take(&mut state.output)),
+		)
+	}
+
+	/// Report `err` to every stream built on the device, e.g. [`AudioStreamError::DeviceNotAvailable`]
+	/// to simulate a disconnection.
+	///
+	/// # Panics
+	/// - if the mutex guarding the device is poisoned.
+	pub fn fail(&self, err: &AudioStreamError) {
+		self.state.with_lock_mut(|state| {
+			for stream in &mut state.streams {
+				(stream.on_error)(err.clone());
+			}
+		});
+	}
+
+	/// The number of streams of the given mode that are running on the device.
+	///
+	/// # Panics
+	/// - if the mutex guarding the device is poisoned.
+	#[must_use]
+	pub fn n_of_streams(&self, mode: IOMode) -> usize {
+		self.state.with_lock(|state| {
+			state
+				.streams
+				.iter()
+				.filter(|stream| match stream.callback {
+					MockCallback::Input(_) => mode == IOMode::Input,
+					MockCallback::Output(_) => mode == IOMode::Output,
+				})
+				.count()
+		})
+	}
+
+	fn build_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		callback: MockCallback,
+		on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		if sampling_ctx != self.sampling_ctx {
+			return Err(AudioStreamError::BuildFailed {
+				device_name: self.name.clone(),
+				source: HostError::new(UnsupportedSamplingCtx(self.sampling_ctx)),
+			});
+		}
+		let id = self.state.with_lock_mut(|state| {
+			let id = state.next_id;
+			state.next_id += 1;
+			state.streams.push(MockStreamCallbacks {
+				id,
+				callback,
+				on_error,
+			});
+			id
+		});
+		Ok(Box::new(MockStream {
+			id,
+			state: Arc::downgrade(&self.state),
+		}))
+	}
+}
+
+impl AudioDevice for MockDevice {
+	fn name(&self) -> String {
+		self.name.clone()
+	}
+
+	fn build_input_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		on_data: Box<DeviceInputCallback>,
+		on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		self.build_stream(sampling_ctx, MockCallback::Input(on_data), on_error)
+	}
+
+	fn build_output_stream(
+		&self,
+		sampling_ctx: SamplingCtx,
+		fill: Box<DeviceOutputCallback>,
+		on_error: Box<DeviceErrorCallback>,
+	) -> Result<Box<dyn DeviceStream>, AudioStreamError> {
+		self.build_stream(sampling_ctx, MockCallback::Output(fill), on_error)
+	}
+}
+
+fn virtual_instant(elapsed: Duration) -> StreamInstant {
+	StreamInstant::new(
+		i64::try_from(elapsed.as_secs()).unwrap_or(i64::MAX),
+		elapsed.subsec_nanos(),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn integer_samples() {
+		assert_eq!(i16::from_f32(0.), 0);
+		assert_eq!(i16::from_f32(1.), i16::MAX);
+		assert_eq!(i16::from_f32(-1.), -i16::MAX);
+		assert_eq!(i16::from_f32(2.), i16::MAX);
+		assert_eq!(i16::from_f32(0.5), 16384);
+
+		assert_eq!(u16::from_f32(0.), 32768);
+		assert_eq!(u16::from_f32(1.), u16::MAX);
+		assert_eq!(u16::from_f32(-1.), 0);
+		assert_eq!(u16::from_f32(-2.), 0);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn mock_streams() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let device = MockDevice::new("mock", sampling_ctx);
+		device.push_input(&[1., 2., 3., 4., 5., 6.]);
+
+		let captured = Arc::new(Mutex::new(Vec::new()));
+		let input = device
+			.build_input_stream(
+				sampling_ctx,
+				Box::new({
+					let captured = captured.clone();
+					move |data, timestamp| {
+						captured.with_lock_mut(|captured| {
+							captured.push((data.to_vec(), timestamp.capture));
+						});
+					}
+				}),
+				Box::new(|_| ()),
+			)
+			.unwrap();
+		let output = device
+			.build_output_stream(
+				sampling_ctx,
+				Box::new(|output, _| output.fill(0.5)),
+				Box::new(|_| ()),
+			)
+			.unwrap();
+		assert_eq!(device.n_of_streams(IOMode::Input), 1);
+		assert_eq!(device.n_of_streams(IOMode::Output), 1);
+		assert!(device
+			.build_output_stream(
+				SamplingCtx::new(SampleRate(1000), 1),
+				Box::new(|_, _| ()),
+				Box::new(|_| ()),
+			)
+			.is_err());
+
+		device.process(NOfFrames(2));
+		device.process(NOfFrames(2));
+		assert_eq!(
+			captured.with_lock(Clone::clone),
+			[
+				(vec![1., 2., 3., 4.], StreamInstant::new(0, 0)),
+				(vec![5., 6., 0., 0.], StreamInstant::new(0, 2_000_000)),
+			]
+		);
+		assert_eq!(device.take_output().raw_buffer(), &[0.5; 8]);
+
+		drop(input);
+		drop(output);
+		assert_eq!(device.n_of_streams(IOMode::Input), 0);
+		device.process(NOfFrames(2));
+		assert!(device.take_output().raw_buffer().is_empty());
+	}
+}
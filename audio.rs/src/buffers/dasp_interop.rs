@@ -0,0 +1,144 @@
+use std::borrow::{Borrow, BorrowMut};
+
+use dasp::{signal, Frame, Signal};
+
+use crate::{NOfFrames, SamplingCtx};
+
+use super::{AudioFrame, InterleavedAudioBuffer};
+
+impl<Samples: Borrow<[f32]>> AudioFrame<Samples> {
+	/// Convert to a dasp frame, e.g. `[f32; 2]` for a stereo frame, or `None`
+	/// if `F` has a different number of channels.
+	#[must_use]
+	pub fn to_dasp<F: Frame<Sample = f32>>(&self) -> Option<F> {
+		if self.n_ch() == F::CHANNELS {
+			F::from_samples(&mut self.samples().iter().copied())
+		} else {
+			None
+		}
+	}
+}
+
+impl AudioFrame<Vec<f32>> {
+	#[must_use]
+	pub fn from_dasp<F: Frame<Sample = f32>>(frame: F) -> Self {
+		AudioFrame::new(frame.channels().collect())
+	}
+}
+
+impl<Buffer: Borrow<[f32]>> InterleavedAudioBuffer<Buffer> {
+	/// Iterate over the frames of the buffer as dasp frames.
+	///
+	/// # Panics
+	/// - if `F` has a different number of channels than the buffer.
+	pub fn dasp_frames<F: Frame<Sample = f32>>(&self) -> impl Iterator<Item = F> + '_ {
+		assert_eq!(
+			F::CHANNELS,
+			self.n_ch(),
+			"dasp frame with incompatible number of channels requested"
+		);
+		self.raw_buffer()
+			.borrow()
+			.chunks_exact(F::CHANNELS)
+			.filter_map(|frame| F::from_samples(&mut frame.iter().copied()))
+	}
+
+	/// A dasp signal that yields the frames of the buffer, and then silence
+	/// (reporting that it's exhausted).
+	///
+	/// # Panics
+	/// - if `F` has a different number of channels than the buffer.
+	pub fn to_dasp_signal<F: Frame<Sample = f32>>(&self) -> impl Signal<Frame = F> + '_ {
+		signal::from_iter(self.dasp_frames())
+	}
+}
+
+impl<Buffer: BorrowMut<[f32]>> InterleavedAudioBuffer<Buffer> {
+	/// Overwrite the buffer with the next frames of a dasp signal, e.g. from
+	/// the `DataProducer` of an output stream.
+	///
+	/// # Panics
+	/// - if the frames of the signal have a different number of channels than the buffer.
+	pub fn fill_from_dasp_signal<S: Signal>(&mut self, signal: &mut S)
+	where
+		S::Frame: Frame<Sample = f32>,
+	{
+		assert_eq!(
+			<S::Frame as Frame>::CHANNELS,
+			self.n_ch(),
+			"dasp signal with incompatible number of channels received"
+		);
+		for mut frame in self.iter_mut() {
+			for (sample, value) in frame.samples_mut().iter_mut().zip(signal.next().channels()) {
+				*sample = value;
+			}
+		}
+	}
+}
+
+impl InterleavedAudioBuffer<Vec<f32>> {
+	/// Collect dasp frames into a buffer.
+	///
+	/// # Panics
+	/// - if `F` has a different number of channels than `sampling_ctx`.
+	pub fn from_dasp_frames<F: Frame<Sample = f32>>(
+		sampling_ctx: SamplingCtx,
+		frames: impl IntoIterator<Item = F>,
+	) -> Self {
+		assert_eq!(
+			F::CHANNELS,
+			sampling_ctx.n_ch(),
+			"dasp frames with incompatible number of channels received"
+		);
+		InterleavedAudioBuffer::new(
+			sampling_ctx,
+			frames.into_iter().flat_map(Frame::channels).collect(),
+		)
+	}
+
+	/// Collect the next `n_of_frames` frames of a dasp signal into a buffer.
+	///
+	/// # Panics
+	/// - if the frames of the signal have a different number of channels than `sampling_ctx`.
+	pub fn from_dasp_signal<S: Signal>(
+		sampling_ctx: SamplingCtx,
+		signal: S,
+		n_of_frames: NOfFrames,
+	) -> Self
+	where
+		S::Frame: Frame<Sample = f32>,
+	{
+		Self::from_dasp_frames(sampling_ctx, signal.take(n_of_frames.0))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::SampleRate;
+
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let buffer = InterleavedAudioBuffer::new(sampling_ctx, vec![1., 2., 3., 4., 5., 6.]);
+
+		let frames: Vec<[f32; 2]> = buffer.dasp_frames().collect();
+		assert_eq!(frames, [[1., 2.], [3., 4.], [5., 6.]]);
+		assert_eq!(buffer.at(1).to_dasp::<[f32; 2]>(), Some([3., 4.]));
+		assert_eq!(buffer.at(1).to_dasp::<f32>(), None);
+		assert_eq!(AudioFrame::from_dasp([3., 4.]), buffer.at(1));
+
+		let mut signal = buffer.to_dasp_signal::<[f32; 2]>();
+		let collected =
+			InterleavedAudioBuffer::from_dasp_signal(sampling_ctx, &mut signal, NOfFrames(4));
+		assert_eq!(collected.raw_buffer(), &[1., 2., 3., 4., 5., 6., 0., 0.]);
+		assert!(signal.is_exhausted());
+
+		let mut signal = buffer.to_dasp_signal::<[f32; 2]>();
+		let mut output = vec![0.; 4];
+		InterleavedAudioBuffer::new(sampling_ctx, &mut output[..])
+			.fill_from_dasp_signal(&mut signal);
+		assert_eq!(output, [1., 2., 3., 4.]);
+	}
+}
@@ -0,0 +1,236 @@
+use std::{
+	fs::File,
+	io::{self, Read},
+	path::Path,
+	time::Duration,
+};
+
+use symphonia::core::{
+	audio::SampleBuffer,
+	codecs::{DecoderOptions, CODEC_TYPE_NULL},
+	errors::Error as SymphoniaError,
+	formats::FormatOptions,
+	io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions, ReadOnlySource},
+	meta::MetadataOptions,
+	probe::Hint,
+};
+
+use crate::{NOfFrames, SampleRate, SamplingCtx, SamplingCtxError};
+
+use super::InterleavedAudioBuffer;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeError {
+	#[error("unable to open the file")]
+	Io(#[from] io::Error),
+	#[error("unable to decode the stream")]
+	Decode(#[from] SymphoniaError),
+	#[error("no audio track found")]
+	NoAudioTrack,
+	#[error("invalid stream info")]
+	InvalidStreamInfo(#[from] SamplingCtxError),
+	#[error("the number of channels changed from {expected} to {actual} while decoding")]
+	ChannelsChanged { expected: usize, actual: usize },
+}
+
+/// A signal decoded from a compressed audio file (MP3 or AAC) by [`Self::from_file`].
+#[derive(Debug)]
+pub struct DecodedAudio {
+	signal: InterleavedAudioBuffer<Vec<f32>>,
+}
+
+impl DecodedAudio {
+	/// Decode the first audio track of the file at `path`, whose extension is used
+	/// as a hint to detect the format.
+	///
+	/// Packets that fail to decode (e.g. corrupted frames) are skipped.
+	///
+	/// # Errors
+	/// [`DecodeError`]
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DecodeError> {
+		let path = path.as_ref();
+		decode(
+			Box::new(File::open(path)?),
+			path.extension().and_then(|extension| extension.to_str()),
+		)
+	}
+
+	/// Decode the first audio track of a stream, see [`Self::from_file`]. `extension`
+	/// (e.g. "mp3") is used as a hint to detect the format.
+	///
+	/// # Errors
+	/// [`DecodeError`]
+	pub fn from_reader(
+		reader: impl Read + Send + Sync + 'static,
+		extension: Option<&str>,
+	) -> Result<Self, DecodeError> {
+		decode(Box::new(ReadOnlySource::new(reader)), extension)
+	}
+
+	#[must_use]
+	pub fn signal(&self) -> &InterleavedAudioBuffer<Vec<f32>> {
+		&self.signal
+	}
+
+	#[must_use]
+	pub fn into_signal(self) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.signal
+	}
+
+	/// The duration of the decoded signal.
+	#[must_use]
+	pub fn duration(&self) -> Duration {
+		self.signal
+			.sampling_ctx()
+			.frames_to_duration(self.signal.n_of_frames())
+	}
+
+	#[must_use]
+	pub fn n_of_frames(&self) -> NOfFrames {
+		self.signal.n_of_frames()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.signal.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.signal.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.signal.n_ch()
+	}
+}
+
+impl From<DecodedAudio> for InterleavedAudioBuffer<Vec<f32>> {
+	fn from(decoded: DecodedAudio) -> Self {
+		decoded.into_signal()
+	}
+}
+
+fn decode(
+	source: Box<dyn MediaSource>,
+	extension: Option<&str>,
+) -> Result<DecodedAudio, DecodeError> {
+	let mut hint = Hint::new();
+	if let Some(extension) = extension {
+		hint.with_extension(extension);
+	}
+	let mut format = symphonia::default::get_probe()
+		.format(
+			&hint,
+			MediaSourceStream::new(source, MediaSourceStreamOptions::default()),
+			&FormatOptions::default(),
+			&MetadataOptions::default(),
+		)?
+		.format;
+	let track = format
+		.tracks()
+		.iter()
+		.find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+		.ok_or(DecodeError::NoAudioTrack)?;
+	let track_id = track.id;
+	let codec_params = track.codec_params.clone();
+	let mut decoder =
+		symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+
+	// Taken from the first decoded packet, which is more reliable than the container.
+	let mut sampling_ctx = None;
+	let mut samples = Vec::new();
+	let mut sample_buffer = None;
+	loop {
+		let packet = match format.next_packet() {
+			Ok(packet) => packet,
+			Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+				break
+			}
+			Err(err) => return Err(err.into()),
+		};
+		if packet.track_id() != track_id {
+			continue;
+		}
+		let buffer = match decoder.decode(&packet) {
+			Ok(buffer) => buffer,
+			Err(SymphoniaError::DecodeError(_)) => {
+				#[cfg(feature = "tracing")]
+				tracing::debug!("corrupted packet skipped");
+				continue;
+			}
+			Err(err) => return Err(err.into()),
+		};
+
+		let spec = *buffer.spec();
+		let ctx = if let Some(ctx) = sampling_ctx {
+			ctx
+		} else {
+			let ctx = SamplingCtx::try_new(SampleRate(spec.rate as usize), spec.channels.count())?;
+			if let Some(n_of_frames) = codec_params
+				.n_frames
+				.and_then(|n_of_frames| usize::try_from(n_of_frames).ok())
+			{
+				samples.reserve(ctx.frames_to_samples(NOfFrames(n_of_frames)));
+			}
+			*sampling_ctx.insert(ctx)
+		};
+		if spec.channels.count() != ctx.n_ch() {
+			return Err(DecodeError::ChannelsChanged {
+				expected: ctx.n_ch(),
+				actual: spec.channels.count(),
+			});
+		}
+
+		let sample_buffer = sample_buffer
+			.get_or_insert_with(|| SampleBuffer::<f32>::new(buffer.capacity() as u64, spec));
+		sample_buffer.copy_interleaved_ref(buffer);
+		samples.extend_from_slice(sample_buffer.samples());
+	}
+
+	let sampling_ctx = match sampling_ctx {
+		Some(sampling_ctx) => sampling_ctx,
+		// No packets, so the parameters of the container are the only ones available.
+		None => SamplingCtx::try_new(
+			SampleRate(codec_params.sample_rate.unwrap_or(0) as usize),
+			codec_params.channels.map_or(0, |channels| channels.count()),
+		)?,
+	};
+	Ok(DecodedAudio {
+		signal: InterleavedAudioBuffer::new(sampling_ctx, samples),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unsupported_format() {
+		assert!(matches!(
+			DecodedAudio::from_reader(io::Cursor::new(b"RIFF\0\0\0\0WAVE".to_vec()), Some("wav")),
+			Err(DecodeError::Decode(_))
+		));
+	}
+
+	#[test]
+	fn missing_file() {
+		assert!(matches!(
+			DecodedAudio::from_file("/nonexistent/file.mp3"),
+			Err(DecodeError::Io(_))
+		));
+	}
+
+	#[test]
+	#[ignore = "manually decode the file in the AUDIO_DECODE_FILE environment variable"]
+	fn test_manual() {
+		let decoded = DecodedAudio::from_file(std::env::var("AUDIO_DECODE_FILE").unwrap()).unwrap();
+		println!(
+			"{:?}, {} channels, {:?}",
+			decoded.sample_rate(),
+			decoded.n_ch(),
+			decoded.duration()
+		);
+	}
+}
@@ -0,0 +1,85 @@
+use std::{io::Read, path::Path};
+
+use claxon::FlacReader;
+
+use crate::{NOfFrames, SampleRate, SamplingCtx, SamplingCtxError};
+
+use super::InterleavedAudioBuffer;
+
+#[derive(thiserror::Error, Debug)]
+pub enum FlacError {
+	#[error("unable to decode the FLAC stream")]
+	Decode(#[from] claxon::Error),
+	#[error("invalid FLAC stream info")]
+	InvalidStreamInfo(#[from] SamplingCtxError),
+}
+
+impl InterleavedAudioBuffer<Vec<f32>> {
+	/// Decode the FLAC file at `path`, converting its samples to f32 in the range [-1, 1).
+	///
+	/// # Errors
+	/// [`FlacError`]
+	pub fn from_flac_file(path: impl AsRef<Path>) -> Result<Self, FlacError> {
+		decode(FlacReader::open(path)?)
+	}
+
+	/// Decode a FLAC stream, see [`Self::from_flac_file`].
+	///
+	/// # Errors
+	/// [`FlacError`]
+	pub fn from_flac_reader(reader: impl Read) -> Result<Self, FlacError> {
+		decode(FlacReader::new(reader)?)
+	}
+}
+
+fn decode<R: Read>(
+	mut reader: FlacReader<R>,
+) -> Result<InterleavedAudioBuffer<Vec<f32>>, FlacError> {
+	let info = reader.streaminfo();
+	let sampling_ctx = SamplingCtx::try_new(
+		SampleRate(info.sample_rate as usize),
+		info.channels as usize,
+	)?;
+	let scale = full_scale(info.bits_per_sample);
+
+	let mut samples = Vec::with_capacity(
+		info.samples
+			.and_then(|frames| usize::try_from(frames).ok())
+			.map_or(0, |frames| {
+				sampling_ctx.frames_to_samples(NOfFrames(frames))
+			}),
+	);
+	for sample in reader.samples() {
+		#[allow(clippy::cast_precision_loss)] // REASON: FLAC samples have at most 32 bits
+		samples.push(sample? as f32 / scale);
+	}
+	Ok(InterleavedAudioBuffer::new(sampling_ctx, samples))
+}
+
+/// The absolute value of the most negative sample with the given bit depth.
+#[allow(clippy::cast_precision_loss)] // REASON: powers of two are exact
+fn full_scale(bits_per_sample: u32) -> f32 {
+	(1u64 << bits_per_sample.saturating_sub(1)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn full_scale_by_bit_depth() {
+		assert_eq!(full_scale(8), 128.);
+		assert_eq!(full_scale(16), 32768.);
+		assert_eq!(full_scale(24), 8_388_608.);
+		assert_eq!(full_scale(32), 2_147_483_648.);
+	}
+
+	#[test]
+	fn invalid_stream() {
+		assert!(matches!(
+			InterleavedAudioBuffer::from_flac_reader(&b"RIFF\0\0\0\0WAVE"[..]),
+			Err(FlacError::Decode(_))
+		));
+	}
+}
@@ -6,3 +6,27 @@ pub use frame_buffer::*;
 
 mod interleaved_buffer;
 pub use interleaved_buffer::*;
+
+mod resampler;
+pub use resampler::*;
+
+mod pcm;
+pub use pcm::*;
+
+#[cfg(feature = "dasp")]
+mod dasp_interop;
+
+#[cfg(feature = "decode")]
+mod decode;
+#[cfg(feature = "decode")]
+pub use decode::*;
+
+#[cfg(feature = "flac")]
+mod flac;
+#[cfg(feature = "flac")]
+pub use flac::*;
+
+#[cfg(feature = "rodio")]
+mod rodio_interop;
+#[cfg(feature = "rodio")]
+pub use rodio_interop::*;
@@ -0,0 +1,281 @@
+use std::{
+	borrow::Borrow,
+	io::{self, Read, Write},
+};
+
+use crate::SamplingCtx;
+
+use super::InterleavedAudioBuffer;
+
+/// The encoding of a single sample in a raw PCM stream.
+///
+/// Integer samples are mapped to the range [-1, 1), i.e. the most negative value
+/// becomes -1. Floating point samples are taken as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PcmFormat {
+	I16,
+	/// Packed in 3 bytes.
+	I24,
+	I32,
+	F32,
+	F64,
+}
+
+impl PcmFormat {
+	#[must_use]
+	pub const fn bytes_per_sample(self) -> usize {
+		match self {
+			Self::I16 => 2,
+			Self::I24 => 3,
+			Self::I32 | Self::F32 => 4,
+			Self::F64 => 8,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endianness {
+	Little,
+	Big,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PcmError {
+	#[error("unable to read the stream")]
+	Io(#[from] io::Error),
+	#[error(
+		"the stream length ({len} bytes) is not a multiple of the frame length ({frame_len} bytes)"
+	)]
+	IncompleteFrame { len: usize, frame_len: usize },
+}
+
+impl InterleavedAudioBuffer<Vec<f32>> {
+	/// Decode raw interleaved PCM samples, see [`PcmFormat`].
+	///
+	/// # Errors
+	/// [`PcmError`] if `bytes` don't contain a whole number of frames.
+	pub fn from_pcm_bytes(
+		sampling_ctx: SamplingCtx,
+		bytes: &[u8],
+		format: PcmFormat,
+		endianness: Endianness,
+	) -> Result<Self, PcmError> {
+		let frame_len = format.bytes_per_sample() * sampling_ctx.n_ch();
+		if !bytes.len().is_multiple_of(frame_len) {
+			return Err(PcmError::IncompleteFrame {
+				len: bytes.len(),
+				frame_len,
+			});
+		}
+		let mut samples = Vec::with_capacity(bytes.len() / format.bytes_per_sample());
+		decode_pcm(bytes, format, endianness, &mut samples);
+		Ok(Self::new(sampling_ctx, samples))
+	}
+
+	/// Decode raw interleaved PCM samples until the end of `reader`, see [`Self::from_pcm_bytes`].
+	///
+	/// # Errors
+	/// [`PcmError`]
+	pub fn from_pcm_reader(
+		sampling_ctx: SamplingCtx,
+		mut reader: impl Read,
+		format: PcmFormat,
+		endianness: Endianness,
+	) -> Result<Self, PcmError> {
+		let mut bytes = Vec::new();
+		reader.read_to_end(&mut bytes)?;
+		Self::from_pcm_bytes(sampling_ctx, &bytes, format, endianness)
+	}
+}
+
+impl<Buffer: Borrow<[f32]>> InterleavedAudioBuffer<Buffer> {
+	/// Encode the signal as raw interleaved PCM samples, see [`encode_pcm`].
+	#[must_use]
+	pub fn to_pcm_bytes(&self, format: PcmFormat, endianness: Endianness) -> Vec<u8> {
+		let samples = self.raw_buffer().borrow();
+		let mut bytes = Vec::with_capacity(samples.len() * format.bytes_per_sample());
+		encode_pcm(samples, format, endianness, &mut bytes);
+		bytes
+	}
+
+	/// Encode the signal as raw interleaved PCM samples into `writer`, see [`encode_pcm`].
+	///
+	/// # Errors
+	/// [`io::Error`]
+	pub fn write_pcm(
+		&self,
+		mut writer: impl Write,
+		format: PcmFormat,
+		endianness: Endianness,
+	) -> io::Result<()> {
+		writer.write_all(&self.to_pcm_bytes(format, endianness))
+	}
+}
+
+/// Decode raw PCM samples, appending them to `output`. Trailing bytes that don't make
+/// a whole sample are ignored.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)] // REASON: f32 is the crate's sample type
+pub fn decode_pcm(bytes: &[u8], format: PcmFormat, endianness: Endianness, output: &mut Vec<f32>) {
+	macro_rules! decode {
+		($t:ty, $convert:expr) => {
+			output.extend(bytes.chunks_exact(format.bytes_per_sample()).map(|chunk| {
+				let chunk = chunk
+					.try_into()
+					.expect("chunks have the size of the sample");
+				let sample = match endianness {
+					Endianness::Little => <$t>::from_le_bytes(chunk),
+					Endianness::Big => <$t>::from_be_bytes(chunk),
+				};
+				$convert(sample)
+			}))
+		};
+	}
+
+	match format {
+		PcmFormat::I16 => decode!(i16, |sample: i16| f32::from(sample) / I16_SCALE),
+		PcmFormat::I24 => output.extend(bytes.chunks_exact(3).map(|chunk| {
+			// Sign-extended by the arithmetic shift.
+			let sample = match endianness {
+				Endianness::Little => i32::from_le_bytes([0, chunk[0], chunk[1], chunk[2]]),
+				Endianness::Big => i32::from_be_bytes([chunk[0], chunk[1], chunk[2], 0]),
+			} >> 8;
+			sample as f32 / I24_SCALE
+		})),
+		PcmFormat::I32 => decode!(i32, |sample: i32| (f64::from(sample) / I32_SCALE) as f32),
+		PcmFormat::F32 => decode!(f32, |sample: f32| sample),
+		PcmFormat::F64 => decode!(f64, |sample: f64| sample as f32),
+	}
+}
+
+/// Encode samples as raw PCM, appending them to `output`.
+///
+/// Integer formats clip the samples to the range [-1, 1], which is mapped symmetrically
+/// to [-max, max], as done when feeding integer output devices.
+#[allow(clippy::cast_possible_truncation)] // REASON: the values are clamped before the cast
+pub fn encode_pcm(
+	samples: &[f32],
+	format: PcmFormat,
+	endianness: Endianness,
+	output: &mut Vec<u8>,
+) {
+	macro_rules! encode {
+		($convert:expr) => {
+			for &sample in samples {
+				let sample = $convert(sample);
+				match endianness {
+					Endianness::Little => output.extend_from_slice(&sample.to_le_bytes()),
+					Endianness::Big => output.extend_from_slice(&sample.to_be_bytes()),
+				}
+			}
+		};
+	}
+
+	match format {
+		PcmFormat::I16 => {
+			encode!(|sample: f32| (sample.clamp(-1., 1.) * (I16_SCALE - 1.)).round() as i16)
+		}
+		PcmFormat::I24 => {
+			for &sample in samples {
+				let sample = (sample.clamp(-1., 1.) * (I24_SCALE - 1.)).round() as i32;
+				match endianness {
+					Endianness::Little => output.extend_from_slice(&sample.to_le_bytes()[..3]),
+					Endianness::Big => output.extend_from_slice(&sample.to_be_bytes()[1..]),
+				}
+			}
+		}
+		PcmFormat::I32 => encode!(|sample: f32| {
+			(f64::from(sample.clamp(-1., 1.)) * (I32_SCALE - 1.)).round() as i32
+		}),
+		PcmFormat::F32 => encode!(|sample: f32| sample),
+		PcmFormat::F64 => encode!(f64::from),
+	}
+}
+
+const I16_SCALE: f32 = 32_768.;
+const I24_SCALE: f32 = 8_388_608.;
+const I32_SCALE: f64 = 2_147_483_648.;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::SampleRate;
+
+	const FORMATS: [PcmFormat; 5] = [
+		PcmFormat::I16,
+		PcmFormat::I24,
+		PcmFormat::I32,
+		PcmFormat::F32,
+		PcmFormat::F64,
+	];
+
+	#[test]
+	fn integer_layout() {
+		let signal =
+			InterleavedAudioBuffer::new(SamplingCtx::new(SampleRate(8000), 1), vec![-1., 0.5]);
+		assert_eq!(
+			signal.to_pcm_bytes(PcmFormat::I16, Endianness::Little),
+			[0x01, 0x80, 0x00, 0x40]
+		);
+		assert_eq!(
+			signal.to_pcm_bytes(PcmFormat::I24, Endianness::Big),
+			[0x80, 0x00, 0x01, 0x40, 0x00, 0x00]
+		);
+
+		let decoded = InterleavedAudioBuffer::from_pcm_bytes(
+			SamplingCtx::new(SampleRate(8000), 1),
+			&[0x00, 0x00, 0x80, 0x00, 0x00, 0xc0],
+			PcmFormat::I24,
+			Endianness::Little,
+		)
+		.unwrap();
+		assert_eq!(decoded.raw_buffer(), &[-1., -0.5]);
+	}
+
+	#[test]
+	fn round_trip() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let signal =
+			InterleavedAudioBuffer::new(sampling_ctx, vec![0., 0.25, -0.75, 0.999, -1., 0.1]);
+		for format in FORMATS {
+			for endianness in [Endianness::Little, Endianness::Big] {
+				let bytes = signal.to_pcm_bytes(format, endianness);
+				assert_eq!(bytes.len(), 6 * format.bytes_per_sample());
+
+				let mut written = Vec::new();
+				signal.write_pcm(&mut written, format, endianness).unwrap();
+				assert_eq!(written, bytes);
+
+				let decoded = InterleavedAudioBuffer::from_pcm_reader(
+					sampling_ctx,
+					bytes.as_slice(),
+					format,
+					endianness,
+				)
+				.unwrap();
+				assert_eq!(decoded.sampling_ctx(), sampling_ctx);
+				for (a, b) in signal.raw_buffer().iter().zip(decoded.raw_buffer()) {
+					assert!(
+						(a - b).abs() < 1e-4,
+						"{format:?} {endianness:?}: {a} != {b}"
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn incomplete_frame() {
+		assert!(matches!(
+			InterleavedAudioBuffer::from_pcm_bytes(
+				SamplingCtx::new(SampleRate(48000), 2),
+				&[0; 6],
+				PcmFormat::I16,
+				Endianness::Little,
+			),
+			Err(PcmError::IncompleteFrame {
+				len: 6,
+				frame_len: 4
+			})
+		));
+	}
+}
@@ -0,0 +1,217 @@
+use crate::SampleRate;
+
+/// Streaming sample rate converter for interleaved signals, based on linear interpolation.
+///
+/// The signal can be fed in chunks of any size: the state needed to interpolate
+/// across the chunk boundaries is kept between calls.
+///
+/// Note: linear interpolation doesn't filter the frequencies above the new Nyquist frequency
+/// when downsampling, therefore it's best suited for small conversion ratios (e.g. 48kHz to 44.1kHz).
+#[derive(Debug, Clone)]
+pub struct Resampler {
+	n_ch: usize,
+	from: SampleRate,
+	to: SampleRate,
+	/// Input frames per output frame, at the nominal conversion ratio.
+	nominal_step: f64,
+	/// Input frames per output frame, including the rate correction.
+	step: f64,
+	/// Position of the next output frame, in input frames, relative to the start of the next chunk.
+	/// -1 refers to `previous_frame`.
+	position: f64,
+	previous_frame: Vec<f32>,
+}
+
+impl Resampler {
+	/// # Panics
+	/// - if `n_ch` is 0 or if one of the sample rates is 0.
+	#[must_use]
+	pub fn new(n_ch: usize, from: SampleRate, to: SampleRate) -> Self {
+		assert!(n_ch > 0, "n_ch must be greater than 0");
+		assert!(
+			from.0 > 0 && to.0 > 0,
+			"sample rates must be greater than 0"
+		);
+		#[allow(clippy::cast_precision_loss)]
+		let step = from.0 as f64 / to.0 as f64;
+		Self {
+			n_ch,
+			from,
+			to,
+			nominal_step: step,
+			step,
+			position: 0.,
+			previous_frame: vec![0.; n_ch],
+		}
+	}
+
+	/// Convert the next chunk of the signal, appending the resulting frames to `output`.
+	///
+	/// # Panics
+	/// - if the length of `input` is not a multiple of the number of channels.
+	pub fn process_into(&mut self, input: &[f32], output: &mut Vec<f32>) {
+		assert_eq!(
+			input.len() % self.n_ch,
+			0,
+			"input size must be a multiple of the number of channels"
+		);
+		let n_of_frames = input.len() / self.n_ch;
+		if n_of_frames == 0 {
+			return;
+		}
+
+		let frame = |i: isize| -> &[f32] {
+			if i < 0 {
+				&self.previous_frame
+			} else {
+				#[allow(clippy::cast_sign_loss)]
+				let i = i as usize;
+				&input[i * self.n_ch..(i + 1) * self.n_ch]
+			}
+		};
+
+		#[allow(clippy::cast_precision_loss)]
+		let last_frame_idx = (n_of_frames - 1) as f64;
+		let mut position = self.position;
+		while position < last_frame_idx {
+			let base = position.floor();
+			#[allow(clippy::cast_possible_truncation)]
+			let (a, b) = (frame(base as isize), frame(base as isize + 1));
+			#[allow(clippy::cast_possible_truncation)]
+			let t = (position - base) as f32;
+			output.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * t));
+			position += self.step;
+		}
+
+		#[allow(clippy::cast_precision_loss)]
+		let consumed = n_of_frames as f64;
+		self.position = position - consumed;
+		self.previous_frame
+			.copy_from_slice(&input[input.len() - self.n_ch..]);
+	}
+
+	/// Convert the next chunk of the signal.
+	///
+	/// # Panics
+	/// - if the length of `input` is not a multiple of the number of channels.
+	#[must_use]
+	pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+		let mut output = Vec::with_capacity(self.max_output_len(input.len()));
+		self.process_into(input, &mut output);
+		output
+	}
+
+	/// An upper bound to the number of samples produced by converting `input_len` samples.
+	#[must_use]
+	pub fn max_output_len(&self, input_len: usize) -> usize {
+		#[allow(
+			clippy::cast_precision_loss,
+			clippy::cast_possible_truncation,
+			clippy::cast_sign_loss
+		)]
+		let frames = ((input_len / self.n_ch) as f64 / self.step).ceil() as usize + 1;
+		frames * self.n_ch
+	}
+
+	/// Stretch the output by `factor` on top of the nominal conversion ratio, e.g. to compensate
+	/// for the drift between the clocks of two devices: factors greater than 1 produce more frames.
+	///
+	/// The correction can be changed between chunks without discontinuities.
+	///
+	/// # Panics
+	/// - if `factor` is not a positive finite number.
+	pub fn set_rate_correction(&mut self, factor: f64) {
+		assert!(
+			factor > 0. && factor.is_finite(),
+			"factor must be a positive finite number"
+		);
+		self.step = self.nominal_step / factor;
+	}
+
+	#[must_use]
+	pub fn rate_correction(&self) -> f64 {
+		self.nominal_step / self.step
+	}
+
+	/// Forget the previously processed signal.
+	pub fn reset(&mut self) {
+		self.position = 0.;
+		self.previous_frame.fill(0.);
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.n_ch
+	}
+
+	#[must_use]
+	pub fn from(&self) -> SampleRate {
+		self.from
+	}
+
+	#[must_use]
+	pub fn to(&self) -> SampleRate {
+		self.to
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn sine(frequency: f32, sample_rate: usize, n_of_samples: usize) -> Vec<f32> {
+		(0..n_of_samples)
+			.map(|i| (TAU * frequency * i as f32 / sample_rate as f32).sin())
+			.collect()
+	}
+
+	#[test]
+	#[allow(clippy::cast_possible_wrap)]
+	fn chunked_conversion_matches_the_expected_signal() {
+		let input = sine(440., 48000, 48000);
+		let expected = sine(440., 44100, 44100);
+
+		let mut resampler = Resampler::new(1, SampleRate(48000), SampleRate(44100));
+		let mut output = vec![];
+		for chunk in input.chunks(317) {
+			resampler.process_into(chunk, &mut output);
+		}
+
+		assert!(
+			(output.len() as isize - 44100).abs() <= 1,
+			"{}",
+			output.len()
+		);
+		for (actual, expected) in output.iter().zip(&expected) {
+			assert!((actual - expected).abs() < 1e-3, "{actual} != {expected}");
+		}
+	}
+
+	#[test]
+	#[allow(clippy::cast_possible_wrap)]
+	fn rate_correction() {
+		let mut resampler = Resampler::new(1, SampleRate(48000), SampleRate(48000));
+		resampler.set_rate_correction(1.001);
+		let mut output = vec![];
+		for chunk in sine(440., 48000, 48000).chunks(480) {
+			resampler.process_into(chunk, &mut output);
+		}
+		assert!(
+			(output.len() as isize - 48048).abs() <= 1,
+			"{}",
+			output.len()
+		);
+	}
+
+	#[test]
+	fn interleaved_upsampling() {
+		let mut resampler = Resampler::new(2, SampleRate(1), SampleRate(2));
+		let output = resampler.process(&[0., 10., 1., 20.]);
+		assert_eq!(output, [0., 10., 0.5, 15.]);
+		let output = resampler.process(&[2., 30.]);
+		assert_eq!(output, [1., 20., 1.5, 25.]);
+	}
+}
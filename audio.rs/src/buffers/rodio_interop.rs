@@ -0,0 +1,96 @@
+use std::{borrow::Borrow, time::Duration};
+
+use super::InterleavedAudioBuffer;
+
+/// A [`rodio::Source`] that plays an [`InterleavedAudioBuffer`] once,
+/// see [`InterleavedAudioBuffer::into_rodio_source`].
+#[derive(Debug)]
+pub struct BufferSource<Buffer: Borrow<[f32]>> {
+	buffer: InterleavedAudioBuffer<Buffer>,
+	/// The index of the next sample.
+	position: usize,
+}
+
+impl<Buffer: Borrow<[f32]>> InterleavedAudioBuffer<Buffer> {
+	#[must_use]
+	pub fn into_rodio_source(self) -> BufferSource<Buffer> {
+		BufferSource {
+			buffer: self,
+			position: 0,
+		}
+	}
+}
+
+impl<Buffer: Borrow<[f32]>> BufferSource<Buffer> {
+	#[must_use]
+	pub fn into_inner(self) -> InterleavedAudioBuffer<Buffer> {
+		self.buffer
+	}
+}
+
+impl<Buffer: Borrow<[f32]>> Iterator for BufferSource<Buffer> {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		let sample = self
+			.buffer
+			.raw_buffer()
+			.borrow()
+			.get(self.position)
+			.copied()?;
+		self.position += 1;
+		Some(sample)
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let remaining = self.buffer.raw_buffer().borrow().len() - self.position;
+		(remaining, Some(remaining))
+	}
+}
+
+impl<Buffer: Borrow<[f32]>> ExactSizeIterator for BufferSource<Buffer> {}
+
+impl<Buffer: Borrow<[f32]>> rodio::Source for BufferSource<Buffer> {
+	fn current_frame_len(&self) -> Option<usize> {
+		Some(self.len())
+	}
+
+	fn channels(&self) -> u16 {
+		u16::try_from(self.buffer.n_ch()).unwrap_or(u16::MAX)
+	}
+
+	fn sample_rate(&self) -> u32 {
+		u32::try_from(self.buffer.sample_rate().0).unwrap_or(u32::MAX)
+	}
+
+	fn total_duration(&self) -> Option<Duration> {
+		Some(
+			self.buffer
+				.sampling_ctx()
+				.frames_to_duration(self.buffer.n_of_frames()),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rodio::Source;
+
+	use crate::{SampleRate, SamplingCtx};
+
+	use super::*;
+
+	#[test]
+	fn buffer_source() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let mut source = InterleavedAudioBuffer::new(sampling_ctx, vec![0.5, -0.5, 0.25, -0.25])
+			.into_rodio_source();
+		assert_eq!(source.channels(), 2);
+		assert_eq!(source.sample_rate(), 1000);
+		assert_eq!(source.total_duration(), Some(Duration::from_millis(2)));
+
+		assert_eq!(source.next(), Some(0.5));
+		assert_eq!(source.current_frame_len(), Some(3));
+		assert_eq!(source.collect::<Vec<_>>(), [-0.5, 0.25, -0.25]);
+	}
+}
@@ -0,0 +1,202 @@
+#![allow(clippy::cast_precision_loss)]
+#![allow(clippy::cast_sign_loss)]
+
+use std::sync::{Arc, Mutex};
+
+use cpal::StreamInstant;
+use mutex_ext::LockExt;
+
+use crate::{IOMode, NOfFrames, SampleRate};
+
+/// A timeline shared by input and output streams, which report into it when
+/// their frames are captured and played according to the clock of the audio host,
+/// see `InputStream::set_clock` and `OutputStream::set_clock`.
+///
+/// It maps the frames of one stream to the frames of the other, e.g. to find where
+/// a played signal should appear in the captured one in loopback measurements,
+/// and estimates the skew between the actual rates of the two devices.
+///
+/// Clones share the same timeline.
+#[derive(Debug, Clone, Default)]
+pub struct StreamClock {
+	shared: Arc<Mutex<ClockState>>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+	/// The first instant reported, from which all the times are measured.
+	origin: Option<StreamInstant>,
+	input: Option<Track>,
+	output: Option<Track>,
+}
+
+/// The reports of a single stream.
+#[derive(Debug, Clone, Copy)]
+struct Track {
+	sample_rate: SampleRate,
+	first: Anchor,
+	last: Anchor,
+}
+
+/// A frame and the time, in seconds since the origin, it was captured or played at.
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+	frame: f64,
+	time: f64,
+}
+
+impl Track {
+	/// The time of the given frame, extrapolated from the latest report.
+	fn time_of(&self, frame: f64) -> f64 {
+		self.last.time + (frame - self.last.frame) / self.sample_rate.0 as f64
+	}
+
+	/// The frame at the given time, extrapolated from the latest report.
+	fn frame_at(&self, time: f64) -> f64 {
+		self.last.frame + (time - self.last.time) * self.sample_rate.0 as f64
+	}
+
+	/// The ratio between the measured and the nominal sample rate, if the reports
+	/// span some time.
+	fn rate_ratio(&self) -> Option<f64> {
+		let elapsed = self.last.time - self.first.time;
+		(elapsed > 0.)
+			.then(|| (self.last.frame - self.first.frame) / elapsed / self.sample_rate.0 as f64)
+	}
+}
+
+impl StreamClock {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record that `frame` has been captured (or will be played, depending on `mode`) at `instant`.
+	///
+	/// Called from the audio callbacks, so the report is skipped, rather than waiting, if the clock
+	/// is being read by another thread: the next one extrapolates from the same track anyway.
+	pub(crate) fn report(
+		&self,
+		mode: IOMode,
+		frame: NOfFrames,
+		instant: StreamInstant,
+		sample_rate: SampleRate,
+	) {
+		self.shared.try_with_lock_mut(|state| {
+			let origin = *state.origin.get_or_insert(instant);
+			let anchor = Anchor {
+				frame: frame.0 as f64,
+				time: seconds_since(origin, instant),
+			};
+			let track = match mode {
+				IOMode::Input => &mut state.input,
+				IOMode::Output => &mut state.output,
+			};
+			match track {
+				Some(track) if track.sample_rate == sample_rate => track.last = anchor,
+				_ => {
+					*track = Some(Track {
+						sample_rate,
+						first: anchor,
+						last: anchor,
+					});
+				}
+			}
+		});
+	}
+
+	/// The input frame captured at the same time the given output frame is played,
+	/// or `None` if either stream hasn't reported yet or the frame would precede
+	/// the start of the input.
+	#[must_use]
+	pub fn input_frame_at(&self, output_frame: NOfFrames) -> Option<NOfFrames> {
+		self.shared.with_lock(|state| {
+			let (input, output) = (state.input?, state.output?);
+			to_frames(input.frame_at(output.time_of(output_frame.0 as f64)))
+		})
+	}
+
+	/// The output frame played at the same time the given input frame is captured,
+	/// or `None` if either stream hasn't reported yet or the frame would precede
+	/// the start of the output.
+	#[must_use]
+	pub fn output_frame_at(&self, input_frame: NOfFrames) -> Option<NOfFrames> {
+		self.shared.with_lock(|state| {
+			let (input, output) = (state.input?, state.output?);
+			to_frames(output.frame_at(input.time_of(input_frame.0 as f64)))
+		})
+	}
+
+	/// How much faster the output device runs compared to the input one, in parts per million,
+	/// relative to their nominal sample rates, or `None` until both streams have
+	/// reported for a while.
+	///
+	/// Devices sharing the same clock have a skew close to 0, while independent devices
+	/// drift apart by a few tens of ppm.
+	#[must_use]
+	pub fn skew_ppm(&self) -> Option<f64> {
+		self.shared.with_lock(|state| {
+			let input = state.input?.rate_ratio()?;
+			let output = state.output?.rate_ratio()?;
+			Some((output / input - 1.) * 1e6)
+		})
+	}
+
+	/// Forget all the reports, e.g. after a stream has been rebuilt.
+	pub fn reset(&self) {
+		self.shared
+			.with_lock_mut(|state| *state = ClockState::default());
+	}
+}
+
+fn seconds_since(origin: StreamInstant, instant: StreamInstant) -> f64 {
+	instant.duration_since(&origin).map_or_else(
+		|| {
+			-origin
+				.duration_since(&instant)
+				.unwrap_or_default()
+				.as_secs_f64()
+		},
+		|elapsed| elapsed.as_secs_f64(),
+	)
+}
+
+fn to_frames(frame: f64) -> Option<NOfFrames> {
+	(frame >= 0.).then(|| NOfFrames(frame.round() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn instant(millis: i64) -> StreamInstant {
+		StreamInstant::new(
+			millis.div_euclid(1000),
+			(millis.rem_euclid(1000) * 1_000_000) as u32,
+		)
+	}
+
+	#[test]
+	fn frames_are_mapped_between_streams() {
+		let clock = StreamClock::new();
+		let sample_rate = SampleRate(48000);
+		assert_eq!(clock.input_frame_at(NOfFrames(0)), None);
+
+		clock.report(IOMode::Output, NOfFrames(0), instant(1000), sample_rate);
+		clock.report(IOMode::Input, NOfFrames(0), instant(500), sample_rate);
+		assert_eq!(clock.input_frame_at(NOfFrames(0)), Some(NOfFrames(24000)));
+		assert_eq!(clock.input_frame_at(NOfFrames(480)), Some(NOfFrames(24480)));
+		assert_eq!(clock.output_frame_at(NOfFrames(24000)), Some(NOfFrames(0)));
+		assert_eq!(clock.output_frame_at(NOfFrames(0)), None);
+		assert_eq!(clock.skew_ppm(), None);
+
+		// After a second, the input has delivered 1000 ppm more frames than the output.
+		clock.report(IOMode::Output, NOfFrames(48000), instant(2000), sample_rate);
+		clock.report(IOMode::Input, NOfFrames(48048), instant(1500), sample_rate);
+		let skew = clock.skew_ppm().unwrap();
+		assert!((skew + 999.).abs() < 1., "{skew}");
+
+		clock.reset();
+		assert_eq!(clock.input_frame_at(NOfFrames(0)), None);
+	}
+}
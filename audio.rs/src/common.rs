@@ -1,31 +1,178 @@
+use std::{fmt::Display, ops::RangeInclusive, sync::Arc};
+
+use crate::SamplingCtx;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AudioStreamSamplingState {
 	Sampling,
 	Stopped(AudioStreamError),
 }
 
-#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+/// An error of the audio host, kept as the [`std::error::Error::source`] of the errors
+/// of this crate, so that the original cause of a failure is not lost.
+///
+/// It's shared to keep the errors that contain it cloneable. Two host errors are considered
+/// equal if they have the same description.
+#[derive(Debug, Clone)]
+pub struct HostError(Arc<dyn std::error::Error + Send + Sync + 'static>);
+
+impl HostError {
+	#[cfg(any(feature = "output", feature = "input"))]
+	pub(crate) fn new(err: impl std::error::Error + Send + Sync + 'static) -> Self {
+		Self(Arc::new(err))
+	}
+
+	/// The original error, e.g. to downcast it to the error type of the host.
+	#[must_use]
+	pub fn inner(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+		&*self.0
+	}
+}
+
+impl Display for HostError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl std::error::Error for HostError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.0.source()
+	}
+}
+
+impl PartialEq for HostError {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.0, &other.0) || self.0.to_string() == other.0.to_string()
+	}
+}
+
+impl Eq for HostError {}
+
+/// The errors that prevent a stream from being created.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
 pub enum AudioStreamBuilderError {
-	#[error("unable to list Input devices")]
-	UnableToListDevices,
-	#[error("no available device found")]
-	NoDeviceFound,
-	#[error("no available stream configuration found")]
-	NoConfigFound,
+	#[error("unable to list {mode:?} devices")]
+	UnableToListDevices {
+		mode: IOMode,
+		#[source]
+		source: HostError,
+	},
+	/// No device with the requested name, or no default device if no name was requested.
+	#[error("no available {mode:?} device found{}", device_name.as_ref().map(|name| format!(" with name \"{name}\"")).unwrap_or_default())]
+	NoDeviceFound {
+		mode: IOMode,
+		device_name: Option<String>,
+	},
+	#[error("unable to query the {mode:?} stream configurations of device \"{device_name}\"")]
+	UnableToListConfigs {
+		mode: IOMode,
+		device_name: String,
+		#[source]
+		source: HostError,
+	},
+	/// The device doesn't support the requested sample rate and number of channels.
+	#[error("no available {mode:?} stream configuration found for device \"{device_name}\" with {sampling_ctx}: {mismatch}")]
+	NoConfigFound {
+		mode: IOMode,
+		device_name: String,
+		sampling_ctx: SamplingCtx,
+		/// Which part of the request can't be satisfied.
+		mismatch: ConfigMismatch,
+		/// The supported configuration closest to the requested one, to retry with.
+		/// Configurations with compatible channels are preferred over the ones with the
+		/// closest sample rate.
+		closest: Option<SamplingCtx>,
+	},
+	#[error("the requested host ({host}) is not available")]
+	HostUnavailable {
+		host: String,
+		#[source]
+		source: HostError,
+	},
+}
+
+/// Why none of the configurations supported by a device matches the requested one,
+/// see [`AudioStreamBuilderError::NoConfigFound`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigMismatch {
+	/// None of the configurations has a sample format streams can be opened with
+	/// (f32, or i16/u16 for output streams).
+	#[error("no supported sample format")]
+	SampleFormat,
+	/// None of the configurations with a supported sample format has enough channels,
+	/// taking into account the `ChannelSelection`.
+	#[error("unsupported number of channels, the device supports {supported:?}")]
+	Channels {
+		/// The numbers of channels that can be requested instead.
+		supported: Vec<usize>,
+	},
+	/// Some configurations are compatible with the requested channels,
+	/// but none of them at the requested sample rate.
+	#[error("unsupported sample rate, the device supports {supported:?}")]
+	SampleRate {
+		supported: Vec<RangeInclusive<crate::SampleRate>>,
+	},
 }
 
 #[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
 pub enum AudioStreamError {
-	#[error("unable to build stream")]
-	BuildFailed(String),
-	#[error("unable to start stream")]
-	StartFailed(String),
+	#[error("unable to build stream on device \"{device_name}\"")]
+	BuildFailed {
+		device_name: String,
+		#[source]
+		source: HostError,
+	},
+	#[error("unable to start stream on device \"{device_name}\"")]
+	StartFailed {
+		device_name: String,
+		#[source]
+		source: HostError,
+	},
+	/// The device has been disconnected, or is otherwise not available anymore.
+	#[error("the device is not available")]
+	DeviceNotAvailable,
+	/// The device started delivering buffers that don't match the configuration the stream
+	/// was opened with, e.g. because its number of channels has been changed by the user.
+	#[error("the format of the device has changed")]
+	FormatChanged,
+	/// An error reported by the audio host while the stream was running.
 	#[error("error while sampling")]
-	SamplingError(String),
+	BackendError(#[source] HostError),
 	#[error("stopped")]
 	Cancelled,
 }
 
+impl AudioStreamError {
+	/// Whether rebuilding the stream may solve the problem (e.g. a device that gets plugged back in),
+	/// as opposed to failures that are expected to happen again, such as an unsupported configuration.
+	///
+	/// A `ReconnectPolicy` only retries after the recoverable errors, unless its `retry_fatal` is set.
+	#[must_use]
+	pub fn is_recoverable(&self) -> bool {
+		match self {
+			AudioStreamError::DeviceNotAvailable
+			| AudioStreamError::FormatChanged
+			| AudioStreamError::BackendError(_) => true,
+			AudioStreamError::BuildFailed { .. }
+			| AudioStreamError::StartFailed { .. }
+			| AudioStreamError::Cancelled => false,
+		}
+	}
+}
+
+#[cfg(any(feature = "output", feature = "input"))]
+impl From<cpal::StreamError> for AudioStreamError {
+	fn from(err: cpal::StreamError) -> Self {
+		match err {
+			cpal::StreamError::DeviceNotAvailable => AudioStreamError::DeviceNotAvailable,
+			cpal::StreamError::BackendSpecific { err } => {
+				AudioStreamError::BackendError(HostError::new(err))
+			}
+		}
+	}
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum IOMode {
 	Input,
@@ -33,54 +180,524 @@ pub enum IOMode {
 }
 
 #[cfg(any(feature = "output", feature = "input"))]
-use crate::SamplingCtx;
+use crate::NOfFrames;
 
 #[cfg(any(feature = "output", feature = "input"))]
 use cpal::{
 	traits::{DeviceTrait, HostTrait},
-	Device, SampleFormat, SampleRate, SupportedStreamConfig,
+	BufferSize, Device, Host, HostId, SampleFormat, SampleRate, StreamConfig, SupportedBufferSize,
+	SupportedStreamConfig, SupportedStreamConfigRange,
 };
 
+/// Advanced settings shared by the stream constructors (`new_with_options`).
+#[cfg(any(feature = "output", feature = "input"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StreamOptions {
+	/// The audio host (e.g. ALSA or JACK on Linux, WASAPI or ASIO on Windows) to open
+	/// the device with, see [`crate::devices::available_hosts`]. `None` means the default host.
+	pub host: Option<HostId>,
+	/// When the device doesn't support the requested sample rate, open it at the closest supported
+	/// rate and convert the signal (see [`crate::buffers::Resampler`]) instead of failing.
+	///
+	/// Only supported by input streams.
+	pub resample: bool,
+	/// Which channels of the device are captured, see [`ChannelSelection`].
+	///
+	/// Only supported by input streams.
+	pub channels: ChannelSelection,
+	/// The number of frames exchanged with the device at every callback, which bounds
+	/// the latency of the stream. `None` lets the audio host choose it.
+	///
+	/// The requested size is clamped to the range supported by the device, when known.
+	/// Note that some hosts only use it as a hint, so the callbacks may still receive chunks
+	/// of different sizes.
+	pub buffer_size: Option<NOfFrames>,
+	/// Ask the OS for realtime scheduling of the thread that runs the audio callbacks,
+	/// to reduce xruns at small buffer sizes. If the request is refused (e.g. because of
+	/// missing permissions), the stream keeps running at the normal priority.
+	///
+	/// The threads that hold the streams only wait for them to be stopped, so they
+	/// aren't promoted.
+	///
+	/// Requires the `realtime` feature, without it this option is ignored.
+	pub realtime_priority: bool,
+}
+
+/// Promotes the thread of the audio callbacks to realtime priority,
+/// see [`StreamOptions::realtime_priority`].
+#[cfg(all(feature = "realtime", any(feature = "output", feature = "input")))]
+pub(crate) struct RealtimePromotion {
+	/// The arguments of the promotion, until it has been attempted.
+	pending: Option<(u32, u32)>,
+	#[allow(dead_code)]
+	// REASON: promotions last until explicitly undone, the handle is only kept to allow it
+	handle: Option<audio_thread_priority::RtPriorityHandle>,
+}
+
+#[cfg(all(feature = "realtime", any(feature = "output", feature = "input")))]
+impl RealtimePromotion {
+	pub(crate) fn new(sampling_ctx: SamplingCtx, options: StreamOptions) -> Self {
+		Self {
+			pending: options.realtime_priority.then(|| {
+				(
+					// 0 lets the OS assume a (conservative) default.
+					options.buffer_size.map_or(0, |buffer_size| {
+						u32::try_from(buffer_size.0).unwrap_or(u32::MAX)
+					}),
+					u32::try_from(sampling_ctx.sample_rate().0).unwrap_or(u32::MAX),
+				)
+			}),
+			handle: None,
+		}
+	}
+
+	/// Promote the current thread, if requested. Only the first call has an effect.
+	pub(crate) fn promote_current_thread(&mut self) {
+		if let Some((buffer_frames, sample_rate)) = self.pending.take() {
+			self.handle = audio_thread_priority::promote_current_thread_to_real_time(
+				buffer_frames,
+				sample_rate,
+			)
+			.ok();
+		}
+	}
+}
+
+/// How the channels of the signal delivered by an input stream are obtained from
+/// the ones of the device.
+#[cfg(any(feature = "output", feature = "input"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChannelSelection {
+	/// The device is opened with exactly the number of channels of the requested [`SamplingCtx`].
+	#[default]
+	All,
+	/// Capture the `n_ch` consecutive channels of the device starting from the given
+	/// (0-based) one, where `n_ch` is the number of channels of the requested [`SamplingCtx`],
+	/// e.g. `FromChannel(2)` with 1 channel captures only the third input of a multichannel interface.
+	FromChannel(usize),
+	/// Average all the channels of the device. The requested [`SamplingCtx`] should
+	/// have 1 channel, otherwise the mono signal is copied to each of them.
+	Mixdown,
+}
+
+#[cfg(any(feature = "output", feature = "input"))]
+impl ChannelSelection {
+	/// Whether a device configuration with `device_n_ch` channels can provide `n_ch` channels.
+	fn is_compatible(self, device_n_ch: usize, n_ch: usize) -> bool {
+		match self {
+			ChannelSelection::All => device_n_ch == n_ch,
+			ChannelSelection::FromChannel(first) => device_n_ch >= first + n_ch,
+			ChannelSelection::Mixdown => device_n_ch > 0,
+		}
+	}
+
+	/// Convert the interleaved `input`, with `device_n_ch` channels, appending
+	/// the resulting `n_ch` channels to `output`.
+	pub(crate) fn apply_into(
+		self,
+		input: &[f32],
+		device_n_ch: usize,
+		n_ch: usize,
+		output: &mut Vec<f32>,
+	) {
+		match self {
+			ChannelSelection::All => output.extend_from_slice(input),
+			ChannelSelection::FromChannel(first) => {
+				for frame in input.chunks_exact(device_n_ch) {
+					output.extend_from_slice(&frame[first..first + n_ch]);
+				}
+			}
+			ChannelSelection::Mixdown => {
+				#[allow(clippy::cast_precision_loss)]
+				let device_n_ch_f = device_n_ch as f32;
+				for frame in input.chunks_exact(device_n_ch) {
+					let mono = frame.iter().sum::<f32>() / device_n_ch_f;
+					output.extend(std::iter::repeat_n(mono, n_ch));
+				}
+			}
+		}
+	}
+}
+
+#[cfg(any(feature = "output", feature = "input"))]
+pub(crate) fn host_provider(host: Option<HostId>) -> Result<Host, AudioStreamBuilderError> {
+	match host {
+		None => Ok(cpal::default_host()),
+		Some(host) => {
+			cpal::host_from_id(host).map_err(|err| AudioStreamBuilderError::HostUnavailable {
+				host: host.name().to_owned(),
+				source: HostError::new(err),
+			})
+		}
+	}
+}
+
 #[cfg(any(feature = "output", feature = "input"))]
 pub(crate) fn device_provider(
 	sampling_ctx: SamplingCtx,
 	device_name: Option<&str>,
 	mode: IOMode,
+	options: StreamOptions,
 ) -> Result<(Device, SupportedStreamConfig), AudioStreamBuilderError> {
+	let host = host_provider(options.host)?;
 	let device = match mode {
-		IOMode::Input => cpal::default_host().input_devices(),
-		IOMode::Output => cpal::default_host().output_devices(),
+		IOMode::Input => host.input_devices(),
+		IOMode::Output => host.output_devices(),
 	}
-	.map_err(|_| AudioStreamBuilderError::UnableToListDevices)?
+	.map_err(|err| AudioStreamBuilderError::UnableToListDevices {
+		mode,
+		source: HostError::new(err),
+	})?
 	.find(|d| match device_name {
 		None => true,
 		Some(device_name) => d
 			.name()
 			.is_ok_and(|candidate_name| candidate_name == device_name),
 	})
-	.ok_or(AudioStreamBuilderError::NoDeviceFound)?;
+	.ok_or_else(|| AudioStreamBuilderError::NoDeviceFound {
+		mode,
+		device_name: device_name.map(str::to_owned),
+	})?;
+	let device_name = device.name().unwrap_or_default();
+	let unable_to_list_configs = |err| AudioStreamBuilderError::UnableToListConfigs {
+		mode,
+		device_name: device_name.clone(),
+		source: HostError::new(err),
+	};
 
-	let config = match mode {
+	let configs: Vec<_> = match mode {
 		IOMode::Input => device
 			.supported_input_configs()
-			.map_err(|_| AudioStreamBuilderError::NoConfigFound)?
-			.find(|c| {
-				c.channels() as usize == sampling_ctx.n_ch()
-					&& c.sample_format() == SampleFormat::F32
-			}),
+			.map_err(unable_to_list_configs)?
+			.collect(),
 		IOMode::Output => device
 			.supported_output_configs()
-			.map_err(|_| AudioStreamBuilderError::NoConfigFound)?
-			.find(|c| {
-				c.channels() as usize == sampling_ctx.n_ch()
-					&& c.sample_format() == SampleFormat::F32
-			}),
+			.map_err(unable_to_list_configs)?
+			.collect(),
+	};
+	let config =
+		negotiate_config(configs, sampling_ctx, mode, options).map_err(|(mismatch, closest)| {
+			AudioStreamBuilderError::NoConfigFound {
+				mode,
+				device_name,
+				sampling_ctx,
+				mismatch,
+				closest,
+			}
+		})?;
+
+	Ok((device, config))
+}
+
+/// Pick the configuration to open a device with among the supported ones, or
+/// find which constraint can't be satisfied and the closest [`SamplingCtx`] that can.
+#[cfg(any(feature = "output", feature = "input"))]
+fn negotiate_config(
+	configs: Vec<SupportedStreamConfigRange>,
+	sampling_ctx: SamplingCtx,
+	mode: IOMode,
+	options: StreamOptions,
+) -> Result<SupportedStreamConfig, (ConfigMismatch, Option<SamplingCtx>)> {
+	let channels = match mode {
+		IOMode::Input => options.channels,
+		IOMode::Output => ChannelSelection::All,
+	};
+	let requested_rate = SampleRate(sampling_ctx.sample_rate().0 as u32);
+	let clamp_rate = |c: &SupportedStreamConfigRange| {
+		requested_rate.clamp(c.min_sample_rate(), c.max_sample_rate())
+	};
+	let rate_distance = |c: &SupportedStreamConfigRange| clamp_rate(c).0.abs_diff(requested_rate.0);
+
+	let usable: Vec<_> = configs
+		.into_iter()
+		.filter(|c| {
+			c.sample_format() == SampleFormat::F32
+				|| mode == IOMode::Output
+					&& matches!(c.sample_format(), SampleFormat::I16 | SampleFormat::U16)
+		})
+		.collect();
+	if usable.is_empty() {
+		return Err((ConfigMismatch::SampleFormat, None));
 	}
-	.ok_or(AudioStreamBuilderError::NoConfigFound)?
-	.try_with_sample_rate(SampleRate(sampling_ctx.sample_rate().0 as u32))
-	.ok_or(AudioStreamBuilderError::NoConfigFound)?;
 
-	// TODO: normalize everything to f32 and accept any format?
+	let mut compatible: Vec<_> = usable
+		.iter()
+		.filter(|c| channels.is_compatible(c.channels() as usize, sampling_ctx.n_ch()))
+		.collect();
+	// Integer formats are only used by output streams, converting from f32, when f32 isn't available.
+	compatible.sort_by_key(|c| c.sample_format() != SampleFormat::F32);
+	// Prefer the fewest channels that satisfy the selection, except when mixing down,
+	// where all the available channels should contribute.
+	match channels {
+		ChannelSelection::Mixdown => compatible.sort_by_key(|c| std::cmp::Reverse(c.channels())),
+		_ => compatible.sort_by_key(|c| c.channels()),
+	}
 
-	Ok((device, config))
+	if let Some(config) = compatible
+		.iter()
+		.find_map(|c| (*c).clone().try_with_sample_rate(requested_rate))
+	{
+		return Ok(config);
+	}
+
+	// The supported rate closest to the requested one.
+	if let Some(closest) = compatible.iter().min_by_key(|c| rate_distance(c)) {
+		let rate = clamp_rate(closest);
+		if options.resample && mode == IOMode::Input {
+			return Ok((*closest).clone().with_sample_rate(rate));
+		}
+		let mut supported: Vec<_> = compatible
+			.iter()
+			.map(|c| {
+				crate::SampleRate(c.min_sample_rate().0 as usize)
+					..=crate::SampleRate(c.max_sample_rate().0 as usize)
+			})
+			.collect();
+		supported.sort_by_key(|range| (*range.start(), *range.end()));
+		supported.dedup();
+		return Err((
+			ConfigMismatch::SampleRate { supported },
+			SamplingCtx::try_new(crate::SampleRate(rate.0 as usize), sampling_ctx.n_ch()).ok(),
+		));
+	}
+
+	// The number of channels to request from each configuration to satisfy the selection.
+	let n_ch_for = |c: &SupportedStreamConfigRange| match channels {
+		ChannelSelection::FromChannel(first) => (c.channels() as usize).saturating_sub(first),
+		ChannelSelection::All | ChannelSelection::Mixdown => c.channels() as usize,
+	};
+	let mut supported: Vec<_> = usable
+		.iter()
+		.map(n_ch_for)
+		.filter(|&n_ch| n_ch > 0)
+		.collect();
+	supported.sort_unstable();
+	supported.dedup();
+	let closest = usable
+		.iter()
+		.filter(|c| n_ch_for(c) > 0)
+		.min_by_key(|c| (n_ch_for(c).abs_diff(sampling_ctx.n_ch()), rate_distance(c)))
+		.and_then(|c| {
+			SamplingCtx::try_new(crate::SampleRate(clamp_rate(c).0 as usize), n_ch_for(c)).ok()
+		});
+	Err((ConfigMismatch::Channels { supported }, closest))
+}
+
+/// The minimum number of frames the scratch buffers of the audio callbacks are allocated for,
+/// so that they don't have to grow (i.e. allocate) while the stream is running,
+/// unless the host delivers larger chunks.
+#[cfg(any(feature = "output", feature = "input"))]
+const MIN_CALLBACK_CAPACITY: NOfFrames = NOfFrames(4096);
+
+/// The number of frames to preallocate for the scratch buffers of the audio callbacks,
+/// given the requested [`StreamOptions::buffer_size`].
+#[cfg(any(feature = "output", feature = "input"))]
+pub(crate) fn callback_capacity(buffer_size: Option<NOfFrames>) -> NOfFrames {
+	buffer_size.map_or(MIN_CALLBACK_CAPACITY, |buffer_size| {
+		buffer_size.max(MIN_CALLBACK_CAPACITY)
+	})
+}
+
+/// The configuration to open a device with, see [`StreamOptions::buffer_size`].
+#[cfg(any(feature = "output", feature = "input"))]
+pub(crate) fn stream_config(
+	config: &SupportedStreamConfig,
+	buffer_size: Option<NOfFrames>,
+) -> StreamConfig {
+	let buffer_size = match (buffer_size, *config.buffer_size()) {
+		(None, _) => BufferSize::Default,
+		(Some(requested), SupportedBufferSize::Range { min, max }) => BufferSize::Fixed(
+			u32::try_from(requested.0)
+				.unwrap_or(u32::MAX)
+				.clamp(min, max),
+		),
+		(Some(requested), SupportedBufferSize::Unknown) => {
+			BufferSize::Fixed(u32::try_from(requested.0).unwrap_or(u32::MAX))
+		}
+	};
+	StreamConfig {
+		buffer_size,
+		..config.config()
+	}
+}
+
+#[cfg(all(test, any(feature = "output", feature = "input")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn channel_selection() {
+		let input = [1., 2., 3., 4., 5., 6.];
+
+		let mut output = vec![];
+		ChannelSelection::FromChannel(1).apply_into(&input, 3, 1, &mut output);
+		assert_eq!(output, [2., 5.]);
+
+		output.clear();
+		ChannelSelection::FromChannel(1).apply_into(&input, 3, 2, &mut output);
+		assert_eq!(output, [2., 3., 5., 6.]);
+
+		output.clear();
+		ChannelSelection::Mixdown.apply_into(&input, 3, 1, &mut output);
+		assert_eq!(output, [2., 5.]);
+
+		assert!(ChannelSelection::All.is_compatible(2, 2));
+		assert!(!ChannelSelection::All.is_compatible(8, 1));
+		assert!(ChannelSelection::FromChannel(7).is_compatible(8, 1));
+		assert!(!ChannelSelection::FromChannel(7).is_compatible(8, 2));
+	}
+
+	#[test]
+	fn buffer_size_is_clamped() {
+		let config = |buffer_size| {
+			SupportedStreamConfig::new(2, SampleRate(48000), buffer_size, SampleFormat::F32)
+		};
+		let range = SupportedBufferSize::Range { min: 64, max: 4096 };
+
+		assert_eq!(
+			stream_config(&config(range), None).buffer_size,
+			BufferSize::Default
+		);
+		assert_eq!(
+			stream_config(&config(range), Some(NOfFrames(256))).buffer_size,
+			BufferSize::Fixed(256)
+		);
+		assert_eq!(
+			stream_config(&config(range), Some(NOfFrames(16))).buffer_size,
+			BufferSize::Fixed(64)
+		);
+		assert_eq!(
+			stream_config(&config(SupportedBufferSize::Unknown), Some(NOfFrames(16))).buffer_size,
+			BufferSize::Fixed(16)
+		);
+	}
+
+	#[test]
+	fn config_negotiation() {
+		let range = |channels, min, max, format| {
+			SupportedStreamConfigRange::new(
+				channels,
+				SampleRate(min),
+				SampleRate(max),
+				SupportedBufferSize::Unknown,
+				format,
+			)
+		};
+		let configs = vec![
+			range(2, 44100, 48000, SampleFormat::I16),
+			range(2, 44100, 48000, SampleFormat::F32),
+			range(8, 96000, 96000, SampleFormat::F32),
+		];
+		let negotiate = |sampling_ctx, mode, options| {
+			negotiate_config(configs.clone(), sampling_ctx, mode, options)
+		};
+		let options = StreamOptions::default();
+
+		let config = negotiate(
+			SamplingCtx::new(crate::SampleRate::HZ_48_000, 2),
+			IOMode::Output,
+			options,
+		)
+		.unwrap();
+		assert_eq!(config.sample_format(), SampleFormat::F32);
+
+		assert_eq!(
+			negotiate(
+				SamplingCtx::new(crate::SampleRate::HZ_96_000, 2),
+				IOMode::Input,
+				options
+			),
+			Err((
+				ConfigMismatch::SampleRate {
+					supported: vec![crate::SampleRate::HZ_44_100..=crate::SampleRate::HZ_48_000]
+				},
+				Some(SamplingCtx::new(crate::SampleRate::HZ_48_000, 2))
+			))
+		);
+		let resampled = negotiate(
+			SamplingCtx::new(crate::SampleRate::HZ_96_000, 2),
+			IOMode::Input,
+			StreamOptions {
+				resample: true,
+				..options
+			},
+		)
+		.unwrap();
+		assert_eq!(resampled.sample_rate(), SampleRate(48000));
+
+		assert_eq!(
+			negotiate(
+				SamplingCtx::new(crate::SampleRate::HZ_96_000, 6),
+				IOMode::Input,
+				options
+			),
+			Err((
+				ConfigMismatch::Channels {
+					supported: vec![2, 8]
+				},
+				Some(SamplingCtx::new(crate::SampleRate::HZ_96_000, 8))
+			))
+		);
+		assert_eq!(
+			negotiate_config(
+				vec![range(2, 44100, 48000, SampleFormat::I16)],
+				SamplingCtx::new(crate::SampleRate::HZ_48_000, 2),
+				IOMode::Input,
+				options
+			),
+			Err((ConfigMismatch::SampleFormat, None))
+		);
+	}
+
+	#[test]
+	fn stream_errors_are_classified() {
+		assert_eq!(
+			AudioStreamError::from(cpal::StreamError::DeviceNotAvailable),
+			AudioStreamError::DeviceNotAvailable
+		);
+		let backend_error = AudioStreamError::from(cpal::StreamError::BackendSpecific {
+			err: cpal::BackendSpecificError {
+				description: "xrun".to_owned(),
+			},
+		});
+		assert_eq!(
+			backend_error,
+			AudioStreamError::BackendError(HostError::new(cpal::BackendSpecificError {
+				description: "xrun".to_owned(),
+			}))
+		);
+
+		assert!(backend_error.is_recoverable());
+		assert!(AudioStreamError::FormatChanged.is_recoverable());
+		assert!(!AudioStreamError::StartFailed {
+			device_name: "default".to_owned(),
+			source: HostError::new(cpal::PlayStreamError::DeviceNotAvailable),
+		}
+		.is_recoverable());
+		assert!(!AudioStreamError::Cancelled.is_recoverable());
+	}
+
+	#[test]
+	fn host_errors_are_the_source() {
+		let err = AudioStreamError::from(cpal::StreamError::BackendSpecific {
+			err: cpal::BackendSpecificError {
+				description: "xrun".to_owned(),
+			},
+		});
+		let source = std::error::Error::source(&err).unwrap();
+		assert_eq!(source.to_string(), "xrun");
+		assert!(source
+			.downcast_ref::<HostError>()
+			.and_then(|source| source.inner().downcast_ref::<cpal::BackendSpecificError>())
+			.is_some());
+
+		let err = AudioStreamBuilderError::NoDeviceFound {
+			mode: IOMode::Input,
+			device_name: Some("USB".to_owned()),
+		};
+		assert_eq!(
+			err.to_string(),
+			"no available Input device found with name \"USB\""
+		);
+	}
 }
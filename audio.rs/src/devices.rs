@@ -0,0 +1,195 @@
+use cpal::{
+	traits::{DeviceTrait, HostTrait},
+	SupportedStreamConfigRange,
+};
+
+pub use cpal::{HostId, SampleFormat};
+
+use crate::{host_provider, AudioStreamBuilderError, HostError, IOMode, SampleRate};
+
+/// A stream configuration supported by a device: any sample rate
+/// between `min_sample_rate` and `max_sample_rate` (both included) can be used
+/// with the given number of channels and sample format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceConfig {
+	n_ch: usize,
+	min_sample_rate: SampleRate,
+	max_sample_rate: SampleRate,
+	sample_format: SampleFormat,
+}
+
+impl DeviceConfig {
+	/// The number of channels
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.n_ch
+	}
+
+	#[must_use]
+	pub fn min_sample_rate(&self) -> SampleRate {
+		self.min_sample_rate
+	}
+
+	#[must_use]
+	pub fn max_sample_rate(&self) -> SampleRate {
+		self.max_sample_rate
+	}
+
+	#[must_use]
+	pub fn sample_format(&self) -> SampleFormat {
+		self.sample_format
+	}
+
+	/// Whether `sample_rate` falls in the supported range.
+	#[must_use]
+	pub fn supports_sample_rate(&self, sample_rate: SampleRate) -> bool {
+		(self.min_sample_rate..=self.max_sample_rate).contains(&sample_rate)
+	}
+}
+
+impl From<SupportedStreamConfigRange> for DeviceConfig {
+	fn from(value: SupportedStreamConfigRange) -> Self {
+		Self {
+			n_ch: value.channels() as usize,
+			min_sample_rate: SampleRate(value.min_sample_rate().0 as usize),
+			max_sample_rate: SampleRate(value.max_sample_rate().0 as usize),
+			sample_format: value.sample_format(),
+		}
+	}
+}
+
+/// An audio device, as seen by its host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+	name: String,
+	configs: Vec<DeviceConfig>,
+}
+
+impl DeviceInfo {
+	/// The name of the device, which can be passed as `device_name` to the stream constructors.
+	#[must_use]
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// The stream configurations supported by the device.
+	#[must_use]
+	pub fn configs(&self) -> &[DeviceConfig] {
+		&self.configs
+	}
+}
+
+/// List the input devices, see [`list`].
+///
+/// # Errors
+/// [`AudioStreamBuilderError::UnableToListDevices`]
+#[cfg(feature = "input")]
+pub fn list_input_devices() -> Result<Vec<DeviceInfo>, AudioStreamBuilderError> {
+	list(IOMode::Input)
+}
+
+/// List the output devices, see [`list`].
+///
+/// # Errors
+/// [`AudioStreamBuilderError::UnableToListDevices`]
+#[cfg(feature = "output")]
+pub fn list_output_devices() -> Result<Vec<DeviceInfo>, AudioStreamBuilderError> {
+	list(IOMode::Output)
+}
+
+/// List the devices of the default host for the given direction, together with
+/// their supported configurations, e.g. to pick a [`crate::SamplingCtx`] they accept
+/// before building a stream.
+///
+/// Devices whose name or configurations can't be queried are skipped.
+///
+/// # Errors
+/// [`AudioStreamBuilderError::UnableToListDevices`]
+pub fn list(mode: IOMode) -> Result<Vec<DeviceInfo>, AudioStreamBuilderError> {
+	list_with_host(mode, None)
+}
+
+/// Like [`list`], but for the devices of `host` (see [`available_hosts`]),
+/// the one to pass as [`crate::StreamOptions::host`] to open them. `None` means the default host.
+///
+/// # Errors
+/// - [`AudioStreamBuilderError::HostUnavailable`]
+/// - [`AudioStreamBuilderError::UnableToListDevices`]
+pub fn list_with_host(
+	mode: IOMode,
+	host: Option<HostId>,
+) -> Result<Vec<DeviceInfo>, AudioStreamBuilderError> {
+	let host = host_provider(host)?;
+	let devices = match mode {
+		IOMode::Input => host.input_devices(),
+		IOMode::Output => host.output_devices(),
+	}
+	.map_err(|err| AudioStreamBuilderError::UnableToListDevices {
+		mode,
+		source: HostError::new(err),
+	})?;
+
+	Ok(devices
+		.filter_map(|device| {
+			let name = device.name().ok()?;
+			let configs = match mode {
+				IOMode::Input => device
+					.supported_input_configs()
+					.ok()?
+					.map(DeviceConfig::from)
+					.collect(),
+				IOMode::Output => device
+					.supported_output_configs()
+					.ok()?
+					.map(DeviceConfig::from)
+					.collect(),
+			};
+			Some(DeviceInfo { name, configs })
+		})
+		.collect())
+}
+
+/// The audio hosts available on this platform, which can be selected with [`crate::StreamOptions::host`].
+#[must_use]
+pub fn available_hosts() -> Vec<HostId> {
+	cpal::available_hosts()
+}
+
+#[cfg(test)]
+mod tests {
+	use cpal::SupportedBufferSize;
+
+	use super::*;
+
+	#[test]
+	fn config_conversion() {
+		let config = DeviceConfig::from(SupportedStreamConfigRange::new(
+			2,
+			cpal::SampleRate(8000),
+			cpal::SampleRate(48000),
+			SupportedBufferSize::Unknown,
+			SampleFormat::F32,
+		));
+		assert_eq!(config.n_ch(), 2);
+		assert_eq!(config.sample_format(), SampleFormat::F32);
+		assert!(config.supports_sample_rate(SampleRate(44100)));
+		assert!(config.supports_sample_rate(SampleRate(48000)));
+		assert!(!config.supports_sample_rate(SampleRate(96000)));
+	}
+
+	#[test]
+	#[ignore = "manually check the devices available on this machine"]
+	fn test_manual() {
+		for device in list(IOMode::Input).unwrap() {
+			println!("input: {device:?}");
+		}
+		for device in list(IOMode::Output).unwrap() {
+			println!("output: {device:?}");
+		}
+		for host in available_hosts() {
+			for device in list_with_host(IOMode::Output, Some(host)).unwrap() {
+				println!("output ({}): {device:?}", host.name());
+			}
+		}
+	}
+}
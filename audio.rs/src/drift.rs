@@ -0,0 +1,189 @@
+#![allow(clippy::cast_precision_loss)]
+
+use crate::{buffers::Resampler, NOfFrames, SamplingCtx};
+
+/// Estimates how much faster an output device consumes frames than an input device produces them,
+/// comparing the frame counters of the two streams over time.
+///
+/// The counters are only sampled when the callbacks run, so each update is off by up to
+/// a callback worth of frames. The skew is therefore fitted (by least squares) over all
+/// the updates, so that the estimate keeps improving the longer the streams run.
+///
+/// See [`crate::StreamClock::skew_ppm`] for an estimate based on the timestamps reported
+/// by the audio host instead.
+#[derive(Debug, Clone)]
+pub struct DriftEstimator {
+	min_span: NOfFrames,
+	first_output_frame: Option<NOfFrames>,
+	/// The number of output frames since the first update, at the latest update.
+	span: f64,
+	n_of_updates: f64,
+	/// The running means of the output frames since the first update (x) and of the difference
+	/// between the input and output counters (y).
+	mean_x: f64,
+	mean_y: f64,
+	/// The running sums of the squared deviations of x and of the products of the deviations of x and y.
+	m2_x: f64,
+	c_xy: f64,
+}
+
+impl DriftEstimator {
+	/// Build an estimator that only reports a skew once the updates span at least `min_span` output frames.
+	#[must_use]
+	pub fn new(min_span: NOfFrames) -> Self {
+		Self {
+			min_span,
+			first_output_frame: None,
+			span: 0.,
+			n_of_updates: 0.,
+			mean_x: 0.,
+			mean_y: 0.,
+			m2_x: 0.,
+			c_xy: 0.,
+		}
+	}
+
+	/// Record the total number of frames produced by the input and consumed by the output so far.
+	pub fn update(&mut self, input_frames: NOfFrames, output_frames: NOfFrames) {
+		let first_output_frame = *self.first_output_frame.get_or_insert(output_frames);
+		let x = output_frames.0.saturating_sub(first_output_frame.0) as f64;
+		let y = input_frames.0 as f64 - output_frames.0 as f64;
+
+		self.span = x;
+		self.n_of_updates += 1.;
+		let dx = x - self.mean_x;
+		self.mean_x += dx / self.n_of_updates;
+		self.mean_y += (y - self.mean_y) / self.n_of_updates;
+		self.m2_x += dx * (x - self.mean_x);
+		self.c_xy += dx * (y - self.mean_y);
+	}
+
+	/// How much faster the output consumes frames than the input produces them, in parts per million,
+	/// or `None` until the updates span `min_span` output frames.
+	#[must_use]
+	pub fn skew_ppm(&self) -> Option<f64> {
+		if self.span < self.min_span.0 as f64 || self.m2_x <= 0. {
+			return None;
+		}
+		// The input frames gained per output frame, i.e. the ratio between the input
+		// and the output rate minus 1.
+		let slope = self.c_xy / self.m2_x;
+		Some((1. / (1. + slope) - 1.) * 1e6)
+	}
+
+	#[must_use]
+	pub fn min_span(&self) -> NOfFrames {
+		self.min_span
+	}
+
+	/// Forget all the updates, e.g. after a stream has been rebuilt.
+	pub fn reset(&mut self) {
+		*self = Self::new(self.min_span);
+	}
+}
+
+/// Stretches or shrinks a signal by a few parts per million, so that the frames produced
+/// by an input device match the ones consumed by an output device whose clock runs
+/// at a slightly different rate, see [`DriftEstimator`] and [`crate::StreamClock::skew_ppm`].
+#[derive(Debug, Clone)]
+pub struct DriftCompensator {
+	resampler: Resampler,
+	skew_ppm: f64,
+}
+
+impl DriftCompensator {
+	#[must_use]
+	pub fn new(sampling_ctx: SamplingCtx) -> Self {
+		let sample_rate = sampling_ctx.sample_rate();
+		Self {
+			resampler: Resampler::new(sampling_ctx.n_ch(), sample_rate, sample_rate),
+			skew_ppm: 0.,
+		}
+	}
+
+	/// Set how much faster the output runs compared to the input, in parts per million:
+	/// positive values produce more frames than the ones received.
+	///
+	/// The skew can be changed between chunks without discontinuities.
+	///
+	/// # Panics
+	/// - if `skew_ppm` is not finite or not greater than -1e6.
+	pub fn set_skew_ppm(&mut self, skew_ppm: f64) {
+		self.resampler.set_rate_correction(1. + skew_ppm * 1e-6);
+		self.skew_ppm = skew_ppm;
+	}
+
+	#[must_use]
+	pub fn skew_ppm(&self) -> f64 {
+		self.skew_ppm
+	}
+
+	/// Compensate the next chunk of the signal, appending the resulting frames to `output`.
+	///
+	/// Part of the last frame of each chunk is only produced with the next one, which is needed
+	/// to interpolate it.
+	///
+	/// # Panics
+	/// - if the length of `input` is not a multiple of the number of channels.
+	pub fn process_into(&mut self, input: &[f32], output: &mut Vec<f32>) {
+		self.resampler.process_into(input, output);
+	}
+
+	/// An upper bound to the number of samples produced by compensating `input_len` samples.
+	#[must_use]
+	pub fn max_output_len(&self, input_len: usize) -> usize {
+		self.resampler.max_output_len(input_len)
+	}
+
+	/// Forget the previously processed signal.
+	pub fn reset(&mut self) {
+		self.resampler.reset();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::SampleRate;
+
+	#[test]
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	fn skew_is_estimated_despite_the_callback_jitter() {
+		let mut estimator = DriftEstimator::new(NOfFrames(48000));
+		// The input runs 50 ppm faster than the output, and the two callbacks are not aligned.
+		let input_rate = 48000. * (1. + 50e-6);
+		let mut output_frames = 0;
+		for callback in 0..20_000 {
+			output_frames += 512;
+			let elapsed = f64::from(callback) * 512. / 48000. + 0.003;
+			let input_frames = ((elapsed * input_rate) / 441.).floor() as usize * 441;
+			estimator.update(NOfFrames(input_frames), NOfFrames(output_frames));
+			if callback == 10 {
+				assert_eq!(estimator.skew_ppm(), None);
+			}
+		}
+		let skew = estimator.skew_ppm().unwrap();
+		assert!((skew + 50.).abs() < 1., "{skew}");
+
+		estimator.reset();
+		assert_eq!(estimator.skew_ppm(), None);
+	}
+
+	#[test]
+	#[allow(clippy::cast_possible_wrap)]
+	fn compensation_adds_frames_when_the_output_is_faster() {
+		let mut compensator = DriftCompensator::new(SamplingCtx::new(SampleRate(48000), 2));
+		compensator.set_skew_ppm(500.);
+		let input = vec![0.5; 2 * 96000];
+		let mut output = Vec::with_capacity(compensator.max_output_len(input.len()));
+		for chunk in input.chunks(2 * 480) {
+			compensator.process_into(chunk, &mut output);
+		}
+		assert!(
+			(output.len() as isize / 2 - 96048).abs() <= 1,
+			"{}",
+			output.len()
+		);
+		assert!(output.iter().all(|&sample| (sample - 0.5).abs() < 1e-6));
+	}
+}
@@ -0,0 +1,463 @@
+//! Composition of sources, effects and sinks into a processing graph, see [`AudioGraph`].
+
+use std::{
+	mem,
+	sync::{Arc, Mutex},
+};
+
+use mutex_ext::LockExt;
+
+use crate::buffers::InterleavedAudioBuffer;
+
+/// A step of an [`AudioGraph`], which processes a chunk in place: sources overwrite it,
+/// effects modify it and sinks (e.g. meters) only read it.
+///
+/// Closures with the same signature (such as the processors of the streams) are nodes,
+/// as well as nodes shared through an `Arc<Mutex<_>>`, which can be read or reconfigured
+/// while the graph is running.
+pub trait AudioNode: Send {
+	fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>);
+}
+
+impl<F> AudioNode for F
+where
+	F: FnMut(&mut InterleavedAudioBuffer<&mut [f32]>) + Send,
+{
+	fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		self(chunk);
+	}
+}
+
+impl<N: AudioNode> AudioNode for Arc<Mutex<N>> {
+	fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		self.with_lock_mut(|node| node.process(chunk));
+	}
+}
+
+/// Identifies a node of an [`AudioGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+	#[error("no node with identifier {0:?} in the graph")]
+	UnknownNode(NodeId),
+	#[error("connecting {from:?} to {to:?} would create a cycle")]
+	Cycle { from: NodeId, to: NodeId },
+}
+
+struct Node {
+	processor: Box<dyn AudioNode>,
+	inputs: Vec<usize>,
+	/// The output of the node during the last [`AudioGraph::process`].
+	buffer: Vec<f32>,
+}
+
+/// A set of [`AudioNode`]s connected without cycles, processed in topological order,
+/// so that every node runs after the ones connected to its input.
+///
+/// The input of a node is the sum of the outputs of the nodes connected to it or, for nodes
+/// without connections, the chunk passed to [`Self::process`]. The output of the graph
+/// is the sum of the outputs of the nodes that are not connected to any other node.
+///
+/// A graph is itself an [`AudioNode`], so it can be nested, and it can be attached to the device
+/// streams with [`Self::into_data_producer`] and [`Self::into_on_data`].
+///
+/// ```ignore
+/// let mut graph = AudioGraph::new();
+/// let oscillator = graph.add_node(Oscillator::new_offline(sampling_ctx));
+/// let limiter = graph.add_node(Limiter::new(sampling_ctx, LimiterConfig::default()));
+/// graph.connect(oscillator, limiter)?;
+/// let stream = OutputStream::new(sampling_ctx, None, graph.into_data_producer(), None)?;
+/// ```
+#[derive(Default)]
+pub struct AudioGraph {
+	nodes: Vec<Node>,
+	/// The indices of the nodes, each after its inputs.
+	order: Vec<usize>,
+	/// The indices of the nodes whose output is not connected to any other node.
+	terminals: Vec<usize>,
+}
+
+impl AudioGraph {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a node, not connected to any other one.
+	pub fn add_node(&mut self, node: impl AudioNode + 'static) -> NodeId {
+		let idx = self.nodes.len();
+		self.nodes.push(Node {
+			processor: Box::new(node),
+			inputs: Vec::new(),
+			buffer: Vec::new(),
+		});
+		self.order.push(idx);
+		self.terminals.push(idx);
+		NodeId(idx)
+	}
+
+	/// Feed the output of `from` to the input of `to`. Connecting two nodes twice has no effect.
+	///
+	/// # Errors
+	/// - [`GraphError::UnknownNode`] if one of the nodes is not part of the graph.
+	/// - [`GraphError::Cycle`] if `from` depends on the output of `to`, or they are the same node.
+	pub fn connect(&mut self, from: NodeId, to: NodeId) -> Result<(), GraphError> {
+		self.check_node(from)?;
+		self.check_node(to)?;
+		if self.depends_on(from.0, to.0) {
+			return Err(GraphError::Cycle { from, to });
+		}
+		if !self.nodes[to.0].inputs.contains(&from.0) {
+			self.nodes[to.0].inputs.push(from.0);
+			self.sort();
+		}
+		Ok(())
+	}
+
+	/// Remove the connection from `from` to `to`, returning whether it existed.
+	pub fn disconnect(&mut self, from: NodeId, to: NodeId) -> bool {
+		let Some(inputs) = self.nodes.get_mut(to.0).map(|node| &mut node.inputs) else {
+			return false;
+		};
+		let len = inputs.len();
+		inputs.retain(|&input| input != from.0);
+		let removed = inputs.len() != len;
+		if removed {
+			self.sort();
+		}
+		removed
+	}
+
+	/// The nodes whose output is fed to `node`.
+	///
+	/// # Errors
+	/// [`GraphError::UnknownNode`]
+	pub fn inputs(&self, node: NodeId) -> Result<Vec<NodeId>, GraphError> {
+		self.check_node(node)?;
+		Ok(self.nodes[node.0]
+			.inputs
+			.iter()
+			.copied()
+			.map(NodeId)
+			.collect())
+	}
+
+	#[must_use]
+	pub fn n_of_nodes(&self) -> usize {
+		self.nodes.len()
+	}
+
+	/// Run all the nodes on `chunk`, replacing it with the output of the graph.
+	/// An empty graph leaves the chunk unchanged.
+	pub fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		if self.nodes.is_empty() {
+			return;
+		}
+		let sampling_ctx = chunk.sampling_ctx();
+		for &idx in &self.order {
+			let mut buffer = mem::take(&mut self.nodes[idx].buffer);
+			buffer.clear();
+			if self.nodes[idx].inputs.is_empty() {
+				buffer.extend_from_slice(chunk.raw_buffer());
+			} else {
+				buffer.resize(chunk.raw_buffer().len(), 0.);
+				for &input in &self.nodes[idx].inputs {
+					add_into(&mut buffer, &self.nodes[input].buffer);
+				}
+			}
+			self.nodes[idx]
+				.processor
+				.process(&mut InterleavedAudioBuffer::new(
+					sampling_ctx,
+					&mut buffer[..],
+				));
+			self.nodes[idx].buffer = buffer;
+		}
+
+		let output = &mut **chunk.raw_buffer_mut();
+		output.fill(0.);
+		for &idx in &self.terminals {
+			add_into(output, &self.nodes[idx].buffer);
+		}
+	}
+
+	/// Use the graph as the data producer of an output stream, see [`crate::output::OutputStream`].
+	/// The nodes without inputs receive silence.
+	#[cfg(feature = "output")]
+	#[must_use]
+	pub fn into_data_producer(mut self) -> Box<crate::output::DataProducer> {
+		Box::new(move |mut chunk| {
+			chunk.raw_buffer_mut().fill(0.);
+			self.process(&mut chunk);
+		})
+	}
+
+	/// Feed the chunks captured by an input stream to the graph, see [`crate::input::InputStream`].
+	/// The output of the graph is discarded, so it's meant to end with sinks shared
+	/// through an `Arc<Mutex<_>>`, e.g. a `LevelMeter`.
+	#[cfg(feature = "input")]
+	#[must_use]
+	pub fn into_on_data(mut self) -> Box<crate::input::OnDataCallback> {
+		let mut buffer = Vec::new();
+		Box::new(move |chunk, _| {
+			buffer.clear();
+			buffer.extend_from_slice(chunk.raw_buffer());
+			self.process(&mut InterleavedAudioBuffer::new(
+				chunk.sampling_ctx(),
+				&mut buffer[..],
+			));
+		})
+	}
+
+	fn check_node(&self, node: NodeId) -> Result<(), GraphError> {
+		if node.0 < self.nodes.len() {
+			Ok(())
+		} else {
+			Err(GraphError::UnknownNode(node))
+		}
+	}
+
+	/// Whether `node` is `dependency` or receives its output, directly or indirectly.
+	fn depends_on(&self, node: usize, dependency: usize) -> bool {
+		let mut visited = vec![false; self.nodes.len()];
+		let mut stack = vec![node];
+		while let Some(idx) = stack.pop() {
+			if idx == dependency {
+				return true;
+			}
+			if !mem::replace(&mut visited[idx], true) {
+				stack.extend_from_slice(&self.nodes[idx].inputs);
+			}
+		}
+		false
+	}
+
+	/// Recompute the execution order (Kahn's algorithm) and the terminal nodes.
+	fn sort(&mut self) {
+		let mut outputs = vec![Vec::new(); self.nodes.len()];
+		let mut pending_inputs = Vec::with_capacity(self.nodes.len());
+		for (idx, node) in self.nodes.iter().enumerate() {
+			for &input in &node.inputs {
+				outputs[input].push(idx);
+			}
+			pending_inputs.push(node.inputs.len());
+		}
+
+		self.order.clear();
+		self.order
+			.extend((0..self.nodes.len()).filter(|&idx| pending_inputs[idx] == 0));
+		let mut next = 0;
+		while let Some(&idx) = self.order.get(next) {
+			next += 1;
+			for &output in &outputs[idx] {
+				pending_inputs[output] -= 1;
+				if pending_inputs[output] == 0 {
+					self.order.push(output);
+				}
+			}
+		}
+
+		self.terminals.clear();
+		self.terminals
+			.extend((0..self.nodes.len()).filter(|&idx| outputs[idx].is_empty()));
+	}
+}
+
+impl AudioNode for AudioGraph {
+	fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		Self::process(self, chunk);
+	}
+}
+
+fn add_into(output: &mut [f32], input: &[f32]) {
+	for (sample, input) in output.iter_mut().zip(input) {
+		*sample += input;
+	}
+}
+
+#[cfg(all(feature = "input", feature = "analysis"))]
+mod input_nodes {
+	use crate::{
+		buffers::InterleavedAudioBuffer,
+		input::{AutomaticGainControl, LevelMeter},
+	};
+
+	use super::AudioNode;
+
+	impl AudioNode for AutomaticGainControl {
+		fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+			Self::process(self, chunk);
+		}
+	}
+
+	/// A sink, which leaves the chunk unchanged.
+	impl AudioNode for LevelMeter {
+		fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+			Self::process(
+				self,
+				&InterleavedAudioBuffer::new(chunk.sampling_ctx(), &**chunk.raw_buffer()),
+			);
+		}
+	}
+}
+
+#[cfg(feature = "output")]
+mod output_nodes {
+	use crate::{
+		buffers::InterleavedAudioBuffer,
+		output::{
+			AudioPlayer, Envelope, Limiter, Metronome, Mixer, Oscillator, OutputStream,
+			QueuedPlayer, Synth,
+		},
+	};
+
+	use super::AudioNode;
+
+	impl AudioNode for Limiter {
+		fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+			Self::process(self, chunk);
+		}
+	}
+
+	impl AudioNode for Envelope {
+		fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+			Self::process(self, chunk);
+		}
+	}
+
+	/// Offline streams and players are sources: each one overwrites the chunk with its next
+	/// frames, see e.g. [`OutputStream::render_into`]. They panic if they're not offline.
+	macro_rules! impl_audio_node_for_offline_sources {
+		($($source:ty),*) => {
+			$(
+				impl AudioNode for $source {
+					fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+						self.render_into(InterleavedAudioBuffer::new(
+							chunk.sampling_ctx(),
+							&mut **chunk.raw_buffer_mut(),
+						));
+					}
+				}
+			)*
+		};
+	}
+
+	impl_audio_node_for_offline_sources!(
+		OutputStream,
+		Oscillator,
+		Synth,
+		Mixer,
+		AudioPlayer,
+		QueuedPlayer,
+		Metronome
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{SampleRate, SamplingCtx};
+
+	use super::*;
+
+	fn constant(value: f32) -> impl AudioNode {
+		move |chunk: &mut InterleavedAudioBuffer<&mut [f32]>| chunk.raw_buffer_mut().fill(value)
+	}
+
+	fn gain(gain: f32) -> impl AudioNode {
+		move |chunk: &mut InterleavedAudioBuffer<&mut [f32]>| {
+			chunk.raw_buffer_mut().iter_mut().for_each(|s| *s *= gain);
+		}
+	}
+
+	fn process(graph: &mut AudioGraph, input: &[f32]) -> Vec<f32> {
+		let mut buffer = input.to_vec();
+		graph.process(&mut InterleavedAudioBuffer::new(
+			SamplingCtx::new(SampleRate(1000), 1),
+			&mut buffer[..],
+		));
+		buffer
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn topological_execution() {
+		let mut graph = AudioGraph::new();
+		assert_eq!(process(&mut graph, &[1., 2.]), [1., 2.]);
+
+		// Added in reverse order, to check that the nodes run after their inputs.
+		let recorded = Arc::new(Mutex::new(Vec::new()));
+		let sink = graph.add_node({
+			let recorded = recorded.clone();
+			move |chunk: &mut InterleavedAudioBuffer<&mut [f32]>| {
+				recorded.with_lock_mut(|recorded| recorded.extend_from_slice(chunk.raw_buffer()));
+			}
+		});
+		let double = graph.add_node(gain(2.));
+		let source = graph.add_node(constant(1.));
+		let input_gain = graph.add_node(gain(10.));
+
+		graph.connect(source, double).unwrap();
+		graph.connect(double, sink).unwrap();
+		graph.connect(input_gain, sink).unwrap();
+		graph.connect(input_gain, sink).unwrap();
+		assert_eq!(graph.inputs(sink), Ok(vec![double, input_gain]));
+
+		// The sink receives 1 * 2 from the source and 10 times the input.
+		assert_eq!(process(&mut graph, &[1., 2.]), [12., 22.]);
+		assert_eq!(*recorded.lock().unwrap(), [12., 22.]);
+
+		assert!(graph.disconnect(input_gain, sink));
+		assert!(!graph.disconnect(input_gain, sink));
+		// The input gain is now a terminal node, so its output is mixed in the output of the graph.
+		assert_eq!(process(&mut graph, &[1.]), [2. + 10.]);
+	}
+
+	#[test]
+	fn connection_errors() {
+		let mut graph = AudioGraph::new();
+		let a = graph.add_node(gain(1.));
+		let b = graph.add_node(gain(1.));
+		let c = graph.add_node(gain(1.));
+		graph.connect(a, b).unwrap();
+		graph.connect(b, c).unwrap();
+
+		assert_eq!(
+			graph.connect(c, a),
+			Err(GraphError::Cycle { from: c, to: a })
+		);
+		assert_eq!(
+			graph.connect(b, b),
+			Err(GraphError::Cycle { from: b, to: b })
+		);
+		assert_eq!(
+			graph.connect(a, NodeId(3)),
+			Err(GraphError::UnknownNode(NodeId(3)))
+		);
+		assert_eq!(graph.n_of_nodes(), 3);
+	}
+
+	#[test]
+	#[cfg(feature = "output")]
+	#[allow(clippy::float_cmp)]
+	fn offline_sources() {
+		use crate::{
+			output::{Limiter, LimiterConfig, OutputStream},
+			NOfFrames,
+		};
+
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut graph = AudioGraph::new();
+		let source = graph.add_node(OutputStream::new_offline(
+			sampling_ctx,
+			Box::new(|mut chunk| chunk.raw_buffer_mut().fill(4.)),
+		));
+		let limiter = graph.add_node(Limiter::new(sampling_ctx, LimiterConfig::default()));
+		graph.connect(source, limiter).unwrap();
+
+		let stream = OutputStream::new_offline(sampling_ctx, graph.into_data_producer());
+		let output = stream.render(NOfFrames(100));
+		assert!(output.raw_buffer().iter().all(|sample| sample.abs() <= 1.));
+	}
+}
@@ -0,0 +1,272 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	analysis::{EnvelopeFollower, EnvelopeMode},
+	buffers::InterleavedAudioBuffer,
+	metering::{amplitude_to_dB, dB_to_amplitude, rms},
+	AudioStreamBuilderError, AudioStreamSamplingState, SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::{InputStream, OnDataCallback};
+
+/// The time constant of the RMS level measurement, long enough to smooth
+/// the ripple of low frequencies.
+const LEVEL_TIME_CONSTANT: Duration = Duration::from_millis(100);
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgcConfig {
+	/// The RMS level, in dBFS, the signal is brought to.
+	pub target_dB: f32,
+	/// The maximum amplification, in dB, which prevents the background noise
+	/// from being brought to the target level during silence.
+	pub max_gain_dB: f32,
+	/// The time constant of the gain reduction when the level rises.
+	pub attack: Duration,
+	/// The time constant of the gain increase when the level falls.
+	pub release: Duration,
+}
+
+impl Default for AgcConfig {
+	fn default() -> Self {
+		Self {
+			target_dB: -20.,
+			max_gain_dB: 30.,
+			attack: Duration::from_millis(500),
+			release: Duration::from_secs(2),
+		}
+	}
+}
+
+/// Slowly adapts the gain applied to a signal so that its RMS level stays close to a target,
+/// e.g. to normalize the level of speech captured at different distances from the microphone.
+///
+/// This is the processing of [`AgcInputStream`], which can also be used
+/// on its own (e.g. on a signal loaded from a file).
+#[derive(Debug, Clone)]
+pub struct AutomaticGainControl {
+	config: AgcConfig,
+	level: EnvelopeFollower,
+	target: f32,
+	max_gain: f32,
+	attack_coefficient: f32,
+	release_coefficient: f32,
+	gain: f32,
+}
+
+impl AutomaticGainControl {
+	#[must_use]
+	pub fn new(sample_rate: SampleRate, config: AgcConfig) -> Self {
+		#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+		let coefficient = |time_constant: Duration| {
+			(-1. / (time_constant.as_secs_f64() * sample_rate.0 as f64)).exp() as f32
+		};
+		Self {
+			config,
+			level: EnvelopeFollower::new(
+				sample_rate,
+				EnvelopeMode::Rms,
+				LEVEL_TIME_CONSTANT,
+				LEVEL_TIME_CONSTANT,
+			),
+			target: dB_to_amplitude(config.target_dB),
+			max_gain: dB_to_amplitude(config.max_gain_dB),
+			attack_coefficient: coefficient(config.attack),
+			release_coefficient: coefficient(config.release),
+			gain: 1.,
+		}
+	}
+
+	/// Apply the gain to the next chunk of the signal, in place.
+	pub fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		for mut frame in chunk.iter_mut() {
+			let samples = frame.samples_mut();
+			let level = self.level.process_sample(rms(samples));
+
+			let target_gain = if level > 0. {
+				(self.target / level).min(self.max_gain)
+			} else {
+				self.max_gain
+			};
+			let coefficient = if target_gain < self.gain {
+				self.attack_coefficient
+			} else {
+				self.release_coefficient
+			};
+			self.gain = target_gain + coefficient * (self.gain - target_gain);
+			for sample in samples {
+				*sample *= self.gain;
+			}
+		}
+	}
+
+	/// The gain applied to the latest frame, in dB.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn gain_dB(&self) -> f32 {
+		amplitude_to_dB(self.gain)
+	}
+
+	/// Forget the level of the previously processed signal.
+	pub fn reset(&mut self) {
+		self.level.reset();
+		self.gain = 1.;
+	}
+
+	#[must_use]
+	pub fn config(&self) -> AgcConfig {
+		self.config
+	}
+}
+
+/// An input stream whose signal is level-normalized by an [`AutomaticGainControl`]
+/// before being passed to the callback.
+pub struct AgcInputStream {
+	shared: Arc<Mutex<AutomaticGainControl>>,
+	base_stream: InputStream,
+}
+
+impl AgcInputStream {
+	/// Build and start sampling an input stream
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		config: AgcConfig,
+		on_data: Box<OnDataCallback>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			config,
+			on_data,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		config: AgcConfig,
+		on_data: Box<OnDataCallback>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let shared = Arc::new(Mutex::new(AutomaticGainControl::new(
+			sampling_ctx.sample_rate(),
+			config,
+		)));
+
+		let base_stream = InputStream::new_with_processors(
+			sampling_ctx,
+			device_name,
+			vec![Box::new({
+				let shared = shared.clone();
+				move |chunk| shared.with_lock_mut(|agc| agc.process(chunk))
+			})],
+			on_data,
+			None,
+			options,
+		)?;
+
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// The gain currently applied, in dB.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn gain_dB(&self) -> f32 {
+		self.shared.with_lock(AutomaticGainControl::gain_dB)
+	}
+
+	#[must_use]
+	pub fn config(&self) -> AgcConfig {
+		self.shared.with_lock(AutomaticGainControl::config)
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream.avg_input_delay()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn brings_the_level_to_the_target() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(8000), 2);
+		let mut agc = AutomaticGainControl::new(
+			sampling_ctx.sample_rate(),
+			AgcConfig {
+				target_dB: -20.,
+				max_gain_dB: 40.,
+				attack: Duration::from_millis(50),
+				release: Duration::from_millis(200),
+			},
+		);
+
+		// A quiet stereo sinusoid at -40 dBFS RMS.
+		let amplitude = 0.01 / FRAC_1_SQRT_2;
+		let mut signal: Vec<f32> = (0..8000 * 2)
+			.flat_map(|i| [(TAU * 100. * i as f32 / 8000.).sin() * amplitude; 2])
+			.collect();
+		for chunk in signal.chunks_mut(2 * 256) {
+			agc.process(&mut InterleavedAudioBuffer::new(sampling_ctx, chunk));
+		}
+
+		assert!((agc.gain_dB() - 20.).abs() < 1., "{}", agc.gain_dB());
+		let tail = &signal[signal.len() - 8000..];
+		let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+		assert!((20. * rms.log10() + 20.).abs() < 1., "{rms}");
+	}
+
+	#[test]
+	fn limits_the_gain_during_silence() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(8000), 1);
+		let mut agc = AutomaticGainControl::new(sampling_ctx.sample_rate(), AgcConfig::default());
+		let mut signal = vec![1e-6; 8000 * 20];
+		agc.process(&mut InterleavedAudioBuffer::new(
+			sampling_ctx,
+			&mut signal[..],
+		));
+		assert!((agc.gain_dB() - 30.).abs() < 0.01, "{}", agc.gain_dB());
+	}
+}
@@ -0,0 +1,353 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames,
+	SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::{InputStream, OnDataCallback};
+
+/// Maximum deviation from the nominal rate applied to compensate the drift between two devices.
+const MAX_CORRECTION: f64 = 0.005;
+/// Rate correction applied per unit of relative fill error.
+const CORRECTION_GAIN: f64 = 0.001;
+
+/// Captures several input devices as if they were a single one, e.g. to record multiple microphones
+/// connected to different interfaces.
+///
+/// The first device acts as the clock of the aggregate: every time it delivers a chunk, the same
+/// number of frames is taken from each of the other devices, whose signal is slightly stretched
+/// or compressed to compensate the drift between their clocks and the one of the first device.
+/// The channels of the aggregate are the ones of the devices, in the order they are passed
+/// to the constructor.
+pub struct AggregateInputStream {
+	sampling_ctx: SamplingCtx,
+	shared: Arc<Mutex<Vec<DriftCompensator>>>,
+	// The first one is the clock of the aggregate.
+	base_streams: Vec<InputStream>,
+}
+
+impl AggregateInputStream {
+	/// Build and start sampling an input stream for each of the `devices`, given
+	/// as (name, number of channels) pairs.
+	///
+	/// `latency` is the delay added to all the devices but the first one, needed to absorb
+	/// the differences between the timings of their callbacks. It should be at least twice
+	/// the size of their buffers.
+	///
+	/// # Panics
+	/// - if `devices` is empty or `latency` is 0.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sample_rate: SampleRate,
+		devices: &[(&str, usize)],
+		latency: NOfFrames,
+		on_data: Box<OnDataCallback>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sample_rate,
+			devices,
+			latency,
+			on_data,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream for each of the `devices`, see [`StreamOptions`]
+	///
+	/// # Panics
+	/// - if `devices` is empty or `latency` is 0.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sample_rate: SampleRate,
+		devices: &[(&str, usize)],
+		latency: NOfFrames,
+		mut on_data: Box<OnDataCallback>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		assert!(!devices.is_empty(), "at least one device is required");
+		assert!(latency > NOfFrames(0), "latency must be greater than 0");
+
+		let sampling_ctx =
+			SamplingCtx::new(sample_rate, devices.iter().map(|(_, n_ch)| n_ch).sum());
+		let shared = Arc::new(Mutex::new(
+			devices[1..]
+				.iter()
+				.map(|&(_, n_ch)| DriftCompensator::new(n_ch, latency))
+				.collect::<Vec<_>>(),
+		));
+
+		let (clock_name, clock_n_ch) = devices[0];
+		let mut base_streams = vec![InputStream::new_with_options(
+			SamplingCtx::new(sample_rate, clock_n_ch),
+			Some(clock_name),
+			Box::new({
+				let shared = shared.clone();
+				let mut aggregated = Vec::new();
+				let mut followers_chunk = Vec::new();
+				move |chunk, info| {
+					let n_of_frames = chunk.n_of_frames();
+					aggregated.clear();
+					aggregated.resize(sampling_ctx.frames_to_samples(n_of_frames), 0.);
+
+					for (frame, aggregated_frame) in chunk
+						.raw_buffer()
+						.chunks_exact(clock_n_ch)
+						.zip(aggregated.chunks_exact_mut(sampling_ctx.n_ch()))
+					{
+						aggregated_frame[..clock_n_ch].copy_from_slice(frame);
+					}
+
+					shared.with_lock_mut(|followers| {
+						let mut offset = clock_n_ch;
+						for follower in followers {
+							followers_chunk.clear();
+							follower.pull(n_of_frames, &mut followers_chunk);
+							for (frame, aggregated_frame) in followers_chunk
+								.chunks_exact(follower.n_ch)
+								.zip(aggregated.chunks_exact_mut(sampling_ctx.n_ch()))
+							{
+								aggregated_frame[offset..offset + follower.n_ch]
+									.copy_from_slice(frame);
+							}
+							offset += follower.n_ch;
+						}
+					});
+
+					on_data(InterleavedAudioBuffer::new(sampling_ctx, &aggregated), info);
+				}
+			}),
+			None,
+			options,
+		)?];
+
+		for (i, &(name, n_ch)) in devices.iter().enumerate().skip(1) {
+			base_streams.push(InputStream::new_with_options(
+				SamplingCtx::new(sample_rate, n_ch),
+				Some(name),
+				Box::new({
+					let shared = shared.clone();
+					move |chunk, _| {
+						shared.with_lock_mut(|followers| followers[i - 1].push(chunk.raw_buffer()));
+					}
+				}),
+				None,
+				options,
+			)?);
+		}
+
+		Ok(Self {
+			sampling_ctx,
+			shared,
+			base_streams,
+		})
+	}
+
+	/// The state of the aggregate, i.e. the first error among the ones of the devices, if any.
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_streams
+			.iter()
+			.map(InputStream::state)
+			.find(|state| state != &AudioStreamSamplingState::Sampling)
+			.unwrap_or(AudioStreamSamplingState::Sampling)
+	}
+
+	/// The number of times each device but the first one didn't have enough frames
+	/// to fill a chunk, e.g. because `latency` is too small. The missing frames are replaced by silence.
+	#[must_use]
+	pub fn underruns(&self) -> Vec<usize> {
+		self.shared
+			.with_lock(|followers| followers.iter().map(|f| f.underruns).collect())
+	}
+
+	/// The rate correction currently applied to each device but the first one,
+	/// e.g. 1.0001 means that its clock is 100ppm faster than the one of the first device.
+	#[must_use]
+	pub fn drift_ratios(&self) -> Vec<f64> {
+		self.shared
+			.with_lock(|followers| followers.iter().map(|f| f.ratio).collect())
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sampling_ctx.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.sampling_ctx.n_ch()
+	}
+
+	/// The average input delay of each device.
+	#[must_use]
+	pub fn avg_input_delays(&self) -> Vec<Duration> {
+		self.base_streams
+			.iter()
+			.map(InputStream::avg_input_delay)
+			.collect()
+	}
+}
+
+/// Buffers the signal of a device and reads it at the pace of another one, adapting the reading
+/// rate so that the amount of buffered frames stays close to the target.
+#[derive(Debug, Clone)]
+struct DriftCompensator {
+	n_ch: usize,
+	target: NOfFrames,
+	buffer: VecDeque<f32>,
+	/// Position of the next frame to read, relative to the first frame in the buffer.
+	position: f64,
+	/// Frames consumed per frame read.
+	ratio: f64,
+	/// Whether the buffer has been filled up to the target, after the start or an underrun.
+	primed: bool,
+	underruns: usize,
+}
+
+impl DriftCompensator {
+	fn new(n_ch: usize, target: NOfFrames) -> Self {
+		Self {
+			n_ch,
+			target,
+			buffer: VecDeque::new(),
+			position: 0.,
+			ratio: 1.,
+			primed: false,
+			underruns: 0,
+		}
+	}
+
+	fn push(&mut self, data: &[f32]) {
+		self.buffer.extend(data);
+	}
+
+	fn buffered_frames(&self) -> usize {
+		self.buffer.len() / self.n_ch
+	}
+
+	/// Read `n_of_frames` frames, appending them to `output`.
+	#[allow(clippy::cast_precision_loss)]
+	fn pull(&mut self, n_of_frames: NOfFrames, output: &mut Vec<f32>) {
+		if !self.primed {
+			if self.buffered_frames() < self.target.0 {
+				output.resize(output.len() + n_of_frames.0 * self.n_ch, 0.);
+				return;
+			}
+			self.primed = true;
+		}
+
+		let fill_error =
+			(self.buffered_frames() as f64 - self.target.0 as f64) / self.target.0 as f64;
+		self.ratio = 1. + (fill_error * CORRECTION_GAIN).clamp(-MAX_CORRECTION, MAX_CORRECTION);
+
+		for i in 0..n_of_frames.0 {
+			#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+			let base = self.position as usize;
+			if base + 1 >= self.buffered_frames() {
+				self.underruns += 1;
+				#[cfg(feature = "tracing")]
+				tracing::warn!(underruns = self.underruns, "follower stream underrun");
+				self.primed = false;
+				output.resize(output.len() + (n_of_frames.0 - i) * self.n_ch, 0.);
+				break;
+			}
+			#[allow(clippy::cast_possible_truncation)]
+			let t = (self.position - self.position.floor()) as f32;
+			for ch in 0..self.n_ch {
+				let a = self.buffer[base * self.n_ch + ch];
+				let b = self.buffer[(base + 1) * self.n_ch + ch];
+				output.push(a + (b - a) * t);
+			}
+			self.position += self.ratio;
+		}
+
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		let consumed = (self.position.floor() as usize).min(self.buffered_frames());
+		self.buffer.drain(..consumed * self.n_ch);
+		self.position -= consumed as f64;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn reads_a_ramp_without_drift() {
+		let mut compensator = DriftCompensator::new(2, NOfFrames(4));
+		let signal: Vec<f32> = (0..16).map(|i| i as f32).collect();
+		compensator.push(&signal);
+
+		let mut output = vec![];
+		compensator.pull(NOfFrames(3), &mut output);
+		assert_eq!(compensator.underruns, 0);
+		assert_eq!(output.len(), 6);
+		// Slightly faster than 1 frame per frame, as the buffer is above the target.
+		assert!(compensator.ratio > 1.);
+		for (actual, expected) in output.iter().zip([0., 1., 2., 3., 4., 5.]) {
+			assert!((actual - expected).abs() < 0.01, "{actual} != {expected}");
+		}
+	}
+
+	#[test]
+	fn compensates_a_faster_clock() {
+		let target = NOfFrames(512);
+		let mut compensator = DriftCompensator::new(1, target);
+		let mut output = vec![];
+
+		// The device delivers 1001 frames every 1000 read, i.e. its clock is 1000ppm faster.
+		for i in 0..5000 {
+			compensator.push(&[0.; 100]);
+			if i % 10 == 0 {
+				compensator.push(&[0.; 1]);
+			}
+			output.clear();
+			compensator.pull(NOfFrames(100), &mut output);
+			assert_eq!(output.len(), 100);
+		}
+
+		assert_eq!(compensator.underruns, 0);
+		assert!(
+			compensator.buffered_frames().abs_diff(target.0) < target.0,
+			"{}",
+			compensator.buffered_frames()
+		);
+		assert!(
+			(compensator.ratio - 1.001).abs() < 0.0005,
+			"{}",
+			compensator.ratio
+		);
+	}
+
+	#[test]
+	fn underruns_are_filled_with_silence() {
+		let mut compensator = DriftCompensator::new(1, NOfFrames(4));
+		let mut output = vec![];
+		compensator.pull(NOfFrames(4), &mut output);
+		assert_eq!(output, [0.; 4]);
+
+		compensator.push(&[1.; 5]);
+		output.clear();
+		compensator.pull(NOfFrames(8), &mut output);
+		assert_eq!(output.len(), 8);
+		assert_eq!(compensator.underruns, 1);
+		assert!(!compensator.primed);
+	}
+}
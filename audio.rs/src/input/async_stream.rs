@@ -0,0 +1,117 @@
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use tokio::sync::mpsc::{self, Receiver};
+
+use crate::{
+	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, SampleRate,
+	SamplingCtx, StreamOptions,
+};
+
+use super::InputStream;
+
+/// An input stream that delivers its chunks through an async channel, see [`InputStream::new_async`].
+pub struct AsyncInputStream {
+	receiver: Receiver<InterleavedAudioBuffer<Vec<f32>>>,
+	dropped_chunks: Arc<AtomicUsize>,
+	base_stream: InputStream,
+}
+
+impl InputStream {
+	/// Build and start sampling an input stream whose chunks can be awaited with [`AsyncInputStream::recv`],
+	/// instead of being passed to a callback.
+	///
+	/// The channel holds up to `channel_capacity` chunks: the audio callback never waits for
+	/// the consumer, so the chunks that arrive when the channel is full are dropped and counted
+	/// by [`AsyncInputStream::dropped_chunks`].
+	///
+	/// # Panics
+	/// - if `channel_capacity` is 0.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_async(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		channel_capacity: usize,
+		options: StreamOptions,
+	) -> Result<AsyncInputStream, AudioStreamBuilderError> {
+		let (sender, receiver) = mpsc::channel(channel_capacity);
+		let dropped_chunks = Arc::new(AtomicUsize::new(0));
+
+		let base_stream = InputStream::new_with_options(
+			sampling_ctx,
+			device_name,
+			Box::new({
+				let dropped_chunks = dropped_chunks.clone();
+				move |chunk, _| {
+					if sender.try_send(chunk.cloned()).is_err() {
+						#[cfg(feature = "tracing")]
+						tracing::warn!(
+							frames = chunk.n_of_frames().0,
+							"channel full, chunk dropped"
+						);
+						dropped_chunks.fetch_add(1, Ordering::Relaxed);
+					}
+				}
+			}),
+			None,
+			options,
+		)?;
+
+		Ok(AsyncInputStream {
+			receiver,
+			dropped_chunks,
+			base_stream,
+		})
+	}
+}
+
+impl AsyncInputStream {
+	/// Wait for the next chunk. Returns `None` once the stream has stopped
+	/// and all the buffered chunks have been received.
+	pub async fn recv(&mut self) -> Option<InterleavedAudioBuffer<Vec<f32>>> {
+		self.receiver.recv().await
+	}
+
+	/// Get the next chunk, if one is already available.
+	pub fn try_recv(&mut self) -> Option<InterleavedAudioBuffer<Vec<f32>>> {
+		self.receiver.try_recv().ok()
+	}
+
+	/// The number of chunks dropped because the channel was full.
+	#[must_use]
+	pub fn dropped_chunks(&self) -> usize {
+		self.dropped_chunks.load(Ordering::Relaxed)
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream.avg_input_delay()
+	}
+}
@@ -0,0 +1,544 @@
+use std::{
+	fs::File,
+	io::{self, BufWriter, Seek, SeekFrom, Write},
+	path::Path,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		mpsc::{self, Receiver, SyncSender, TrySendError},
+		Arc, Mutex,
+	},
+	thread::{self, JoinHandle},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamError, AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback,
+	ReconnectPolicy, SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::{InputStream, OnDataCallback, ReplayPace};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FileRecorderError {
+	#[error("unable to create the file")]
+	Io(#[from] io::Error),
+	#[error(transparent)]
+	Stream(#[from] AudioStreamBuilderError),
+}
+
+/// Records the input directly to a WAV file (32-bit float samples).
+///
+/// Unlike [`super::AudioRecorder`], the recording doesn't have to fit in memory:
+/// the chunks are passed, through a bounded queue, to a worker thread that writes them
+/// to the file. If the writer can't keep up and the queue fills up, the chunks that don't fit
+/// are dropped and counted by [`Self::dropped_frames`].
+///
+/// The file is finalized when the recorder is dropped, or explicitly with [`Self::finish`].
+///
+/// For captures that last hours, consider [`Self::new_with_reconnect`], so that a device
+/// hiccup doesn't end the recording. Note that WAV files are limited to 4 GiB, i.e. about
+/// 3 hours of stereo audio at 48 kHz: once the limit is reached, the writer stops and
+/// [`Self::finish`] reports the error.
+pub struct FileRecorder {
+	counters: Arc<Counters>,
+	base_stream: Option<InputStream>,
+	sink: Arc<Mutex<Option<WavFileSink>>>,
+	sampling_ctx: SamplingCtx,
+}
+
+#[derive(Default)]
+struct Counters {
+	written_frames: AtomicUsize,
+	dropped_frames: AtomicUsize,
+}
+
+/// Writes chunks to a WAV file from a worker thread, through a bounded queue,
+/// so that the audio callbacks never wait for the disk.
+///
+/// The buffers of the written chunks are sent back to be reused, so that, once enough
+/// of them are in circulation, queueing a chunk doesn't allocate.
+pub(crate) struct WavFileSink {
+	sender: SyncSender<Vec<f32>>,
+	recycled: Receiver<Vec<f32>>,
+	/// The buffer of the last chunk that didn't fit in the queue.
+	spare: Option<Vec<f32>>,
+	/// `None` once handed over to a [`WavFileFinisher`].
+	writer_thread: Option<JoinHandle<io::Result<()>>>,
+	counters: Arc<Counters>,
+	sampling_ctx: SamplingCtx,
+}
+
+/// Finalizes the file of a [`WavFileSink`] while the sink is owned by someone else,
+/// e.g. an audio callback, see [`WavFileSink::new_detached`].
+pub(crate) struct WavFileFinisher {
+	sender: SyncSender<Vec<f32>>,
+	writer_thread: JoinHandle<io::Result<()>>,
+}
+
+impl WavFileSink {
+	/// Create (or truncate) the file at `path`. `queue_len` is the maximum number
+	/// of chunks waiting to be written.
+	pub(crate) fn new(
+		sampling_ctx: SamplingCtx,
+		path: &Path,
+		queue_len: usize,
+	) -> io::Result<Self> {
+		let (mut sink, finisher) = Self::new_detached(sampling_ctx, path, queue_len)?;
+		sink.writer_thread = Some(finisher.writer_thread);
+		Ok(sink)
+	}
+
+	/// Like [`Self::new`], but the file is finalized by the returned [`WavFileFinisher`],
+	/// without waiting for the sink to be dropped. Chunks pushed after that are discarded.
+	pub(crate) fn new_detached(
+		sampling_ctx: SamplingCtx,
+		path: &Path,
+		queue_len: usize,
+	) -> io::Result<(Self, WavFileFinisher)> {
+		let mut writer = WavWriter::new(BufWriter::new(File::create(path)?), sampling_ctx)?;
+		let counters = Arc::new(Counters::default());
+		let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(queue_len);
+		let (recycle_sender, recycled) = mpsc::channel();
+
+		let writer_thread = thread::spawn({
+			let counters = counters.clone();
+			move || {
+				for chunk in receiver {
+					// Sent by `WavFileFinisher::finish`, as `push` skips empty chunks.
+					if chunk.is_empty() {
+						break;
+					}
+					writer.write(&chunk)?;
+					counters.written_frames.fetch_add(
+						sampling_ctx.samples_to_frames(chunk.len()).0,
+						Ordering::Relaxed,
+					);
+					let _ = recycle_sender.send(chunk);
+				}
+				writer.finalize()
+			}
+		});
+
+		Ok((
+			Self {
+				sender: sender.clone(),
+				recycled,
+				spare: None,
+				writer_thread: None,
+				counters,
+				sampling_ctx,
+			},
+			WavFileFinisher {
+				sender,
+				writer_thread,
+			},
+		))
+	}
+
+	/// Queue a chunk to be written, dropping it if the queue is full.
+	pub(crate) fn push(&mut self, chunk: &[f32]) {
+		if chunk.is_empty() {
+			return;
+		}
+		let mut buffer = self
+			.spare
+			.take()
+			.or_else(|| self.recycled.try_recv().ok())
+			.unwrap_or_default();
+		buffer.clear();
+		buffer.extend_from_slice(chunk);
+		match self.sender.try_send(buffer) {
+			Ok(()) => (),
+			Err(TrySendError::Full(buffer)) => {
+				self.spare = Some(buffer);
+				#[cfg(feature = "tracing")]
+				tracing::warn!(samples = chunk.len(), "WAV writer overrun, chunk dropped");
+				self.counters.dropped_frames.fetch_add(
+					self.sampling_ctx.samples_to_frames(chunk.len()).0,
+					Ordering::Relaxed,
+				);
+			}
+			// The writer has stopped, because of an error (which is reported by `finish`)
+			// or because the file has been finalized. The buffer is kept rather than freed,
+			// as this may run inside an audio callback.
+			Err(TrySendError::Disconnected(buffer)) => self.spare = Some(buffer),
+		}
+	}
+
+	/// Wait for the queued chunks to be written and finalize the file.
+	pub(crate) fn finish(self) -> io::Result<()> {
+		// Closing the queue makes the writer finalize the file.
+		drop(self.sender);
+		self.writer_thread.map_or(Ok(()), join_writer)
+	}
+}
+
+impl WavFileFinisher {
+	/// Wait for the chunks queued so far to be written and finalize the file.
+	pub(crate) fn finish(self) -> io::Result<()> {
+		// The sender of the sink may still be alive, so the writer is stopped by an empty chunk.
+		// If it has already stopped because of an error, the error is returned by the join.
+		let _ = self.sender.send(Vec::new());
+		join_writer(self.writer_thread)
+	}
+}
+
+fn join_writer(writer_thread: JoinHandle<io::Result<()>>) -> io::Result<()> {
+	writer_thread
+		.join()
+		.unwrap_or_else(|_| Err(io::Error::other("the writer thread panicked")))
+}
+
+impl FileRecorder {
+	/// Create (or truncate) the file at `path` and start recording to it.
+	/// `queue_len` is the maximum number of chunks waiting to be written.
+	///
+	/// # Errors
+	/// [`FileRecorderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		path: impl AsRef<Path>,
+		device_name: Option<&str>,
+		queue_len: usize,
+	) -> Result<Self, FileRecorderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			path,
+			device_name,
+			queue_len,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Create (or truncate) the file at `path` and start recording to it, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`FileRecorderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		path: impl AsRef<Path>,
+		device_name: Option<&str>,
+		queue_len: usize,
+		options: StreamOptions,
+	) -> Result<Self, FileRecorderError> {
+		Self::new_with_base_stream(sampling_ctx, path.as_ref(), queue_len, |on_data| {
+			InputStream::new_with_options(sampling_ctx, device_name, on_data, None, options)
+				.map_err(Into::into)
+		})
+	}
+
+	/// Create (or truncate) the file at `path` and start recording to it from an input stream
+	/// that reconnects following the given [`ReconnectPolicy`], see [`InputStream::new_with_reconnect`].
+	///
+	/// The frames are not recorded while the stream is reconnecting, so the file
+	/// contains the chunks captured before and after the interruption back to back.
+	///
+	/// # Errors
+	/// [`FileRecorderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		path: impl AsRef<Path>,
+		device_name: Option<&str>,
+		queue_len: usize,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, FileRecorderError> {
+		Self::new_with_base_stream(sampling_ctx, path.as_ref(), queue_len, |on_data| {
+			InputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				on_data,
+				policy,
+				on_event,
+				options,
+			)
+			.map_err(Into::into)
+		})
+	}
+
+	/// Create (or truncate) the file at `path` and record the frames replayed from `signal`
+	/// to it, see [`InputStream::new_virtual`].
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	pub fn new_virtual(
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		path: impl AsRef<Path>,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+		queue_len: usize,
+	) -> io::Result<Self> {
+		Self::new_with_base_stream(signal.sampling_ctx(), path.as_ref(), queue_len, |on_data| {
+			Ok(InputStream::new_virtual(signal, chunk_len, pace, on_data))
+		})
+	}
+
+	/// Create (or truncate) the file at `path` and record the frames captured by `device`
+	/// to it, see [`InputStream::new_on_device`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		path: impl AsRef<Path>,
+		queue_len: usize,
+	) -> io::Result<Self> {
+		Self::new_with_base_stream(sampling_ctx, path.as_ref(), queue_len, |on_data| {
+			Ok(InputStream::new_on_device(
+				device,
+				sampling_ctx,
+				on_data,
+				None,
+			))
+		})
+	}
+
+	fn new_with_base_stream<E: From<io::Error>>(
+		sampling_ctx: SamplingCtx,
+		path: &Path,
+		queue_len: usize,
+		base_stream_builder: impl FnOnce(Box<OnDataCallback>) -> Result<InputStream, E>,
+	) -> Result<Self, E> {
+		let sink = WavFileSink::new(sampling_ctx, path, queue_len)?;
+		let counters = sink.counters.clone();
+		let sink = Arc::new(Mutex::new(Some(sink)));
+
+		let base_stream = base_stream_builder(Box::new({
+			let sink = sink.clone();
+			move |chunk, _| {
+				sink.with_lock_mut(|sink| {
+					if let Some(sink) = sink {
+						sink.push(chunk.raw_buffer());
+					}
+				});
+			}
+		}))?;
+
+		Ok(Self {
+			counters,
+			base_stream: Some(base_stream),
+			sink,
+			sampling_ctx,
+		})
+	}
+
+	/// Stop recording and wait for the file to be finalized.
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn finish(mut self) -> io::Result<()> {
+		self.stop()
+	}
+
+	fn stop(&mut self) -> io::Result<()> {
+		drop(self.base_stream.take());
+		self.sink
+			.with_lock_mut(Option::take)
+			.map_or(Ok(()), WavFileSink::finish)
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.as_ref().map_or(
+			AudioStreamSamplingState::Stopped(AudioStreamError::Cancelled),
+			InputStream::state,
+		)
+	}
+
+	/// The number of frames written to the file so far.
+	#[must_use]
+	pub fn written_frames(&self) -> NOfFrames {
+		NOfFrames(self.counters.written_frames.load(Ordering::Relaxed))
+	}
+
+	/// The number of frames dropped because the writer couldn't keep up with the input.
+	#[must_use]
+	pub fn dropped_frames(&self) -> NOfFrames {
+		NOfFrames(self.counters.dropped_frames.load(Ordering::Relaxed))
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sampling_ctx.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.sampling_ctx.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream
+			.as_ref()
+			.map_or(Duration::ZERO, InputStream::avg_input_delay)
+	}
+}
+
+impl Drop for FileRecorder {
+	fn drop(&mut self) {
+		let _ = self.stop();
+	}
+}
+
+pub(super) const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const HEADER_LEN: u32 = 44;
+
+/// Minimal writer of 32-bit float WAV files, whose sizes are patched into the header when finalized.
+pub(crate) struct WavWriter<W: Write + Seek> {
+	inner: W,
+	data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+	pub(crate) fn new(mut inner: W, sampling_ctx: SamplingCtx) -> io::Result<Self> {
+		let n_ch = u16::try_from(sampling_ctx.n_ch())
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many channels"))?;
+		let sample_rate = u32::try_from(sampling_ctx.sample_rate().0)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "sample rate too high"))?;
+		let block_align = n_ch * 4;
+
+		inner.write_all(b"RIFF")?;
+		inner.write_all(&(HEADER_LEN - 8).to_le_bytes())?;
+		inner.write_all(b"WAVE")?;
+		inner.write_all(b"fmt ")?;
+		inner.write_all(&16u32.to_le_bytes())?;
+		inner.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+		inner.write_all(&n_ch.to_le_bytes())?;
+		inner.write_all(&sample_rate.to_le_bytes())?;
+		inner.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+		inner.write_all(&block_align.to_le_bytes())?;
+		inner.write_all(&32u16.to_le_bytes())?;
+		inner.write_all(b"data")?;
+		inner.write_all(&0u32.to_le_bytes())?;
+
+		Ok(Self { inner, data_len: 0 })
+	}
+
+	pub(crate) fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+		let len = u32::try_from(samples.len() * 4)
+			.ok()
+			.and_then(|len| len.checked_add(self.data_len))
+			.filter(|len| len.checked_add(HEADER_LEN - 8).is_some())
+			.ok_or_else(|| io::Error::new(io::ErrorKind::FileTooLarge, "WAV size limit reached"))?;
+		for sample in samples {
+			self.inner.write_all(&sample.to_le_bytes())?;
+		}
+		self.data_len = len;
+		Ok(())
+	}
+
+	pub(crate) fn finalize(mut self) -> io::Result<()> {
+		self.inner.seek(SeekFrom::Start(4))?;
+		self.inner
+			.write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+		self.inner
+			.seek(SeekFrom::Start(u64::from(HEADER_LEN) - 4))?;
+		self.inner.write_all(&self.data_len.to_le_bytes())?;
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	#[test]
+	fn wav_header() {
+		let mut file = Cursor::new(vec![]);
+		let mut writer = WavWriter::new(&mut file, SamplingCtx::new(SampleRate(48000), 2)).unwrap();
+		writer.write(&[0.5, -0.5]).unwrap();
+		writer.write(&[1., -1.]).unwrap();
+		writer.finalize().unwrap();
+
+		let bytes = file.into_inner();
+		let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+		let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+
+		assert_eq!(bytes.len(), 44 + 16);
+		assert_eq!(&bytes[0..4], b"RIFF");
+		assert_eq!(u32_at(4), 36 + 16);
+		assert_eq!(&bytes[8..16], b"WAVEfmt ");
+		assert_eq!(u16_at(20), WAVE_FORMAT_IEEE_FLOAT);
+		assert_eq!(u16_at(22), 2);
+		assert_eq!(u32_at(24), 48000);
+		assert_eq!(u32_at(28), 48000 * 8);
+		assert_eq!(u16_at(32), 8);
+		assert_eq!(u16_at(34), 32);
+		assert_eq!(&bytes[36..40], b"data");
+		assert_eq!(u32_at(40), 16);
+		assert_eq!(&bytes[44..48], &0.5f32.to_le_bytes());
+	}
+
+	#[test]
+	fn records_a_virtual_stream() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		#[allow(clippy::cast_precision_loss)]
+		let signal = InterleavedAudioBuffer::new(
+			sampling_ctx,
+			(0..500).map(|i| i as f32 / 500.).collect::<Vec<_>>(),
+		);
+		let path = std::env::temp_dir().join("file_recorder_records_a_virtual_stream.wav");
+		let recorder = FileRecorder::new_virtual(
+			signal.cloned(),
+			&path,
+			NOfFrames(64),
+			ReplayPace::Unthrottled,
+			16,
+		)
+		.unwrap();
+		while recorder.state() == AudioStreamSamplingState::Sampling {
+			thread::sleep(Duration::from_millis(1));
+		}
+		recorder.finish().unwrap();
+
+		let replayed = Arc::new(Mutex::new(Vec::new()));
+		let stream = super::super::VirtualInputStream::from_wav_file(
+			&path,
+			NOfFrames(1000),
+			ReplayPace::Unthrottled,
+			Box::new({
+				let replayed = replayed.clone();
+				move |chunk, _| {
+					replayed
+						.with_lock_mut(|replayed| replayed.extend_from_slice(chunk.raw_buffer()));
+				}
+			}),
+		)
+		.unwrap();
+		stream.wait_until_finished();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(
+			replayed.with_lock(Clone::clone),
+			signal.raw_buffer().as_slice()
+		);
+	}
+
+	#[test]
+	#[ignore = "manually record a file and listen to it"]
+	fn test_manual() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(44100), 2);
+		let path = std::env::temp_dir().join("file_recorder_test_manual.wav");
+		let recorder = FileRecorder::new(sampling_ctx, &path, None, 16).unwrap();
+		thread::sleep(Duration::from_secs(3));
+		assert_eq!(recorder.dropped_frames(), NOfFrames(0));
+		recorder.finish().unwrap();
+		println!("recorded to {}", path.display());
+	}
+}
@@ -0,0 +1,315 @@
+use std::time::Duration;
+
+use mutex_ext::{CondvarExt, LockExt, ReactiveCondvar};
+
+use crate::{
+	analysis::{EnvelopeFollower, EnvelopeMode},
+	buffers::InterleavedAudioBuffer,
+	AudioStreamBuilderError, AudioStreamSamplingState, SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::InputStream;
+
+/// How quickly the readings of a [`LevelMeter`] react to the changes of the signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeterBallistics {
+	/// The time constant of the peak reading when the level rises.
+	pub peak_attack: Duration,
+	/// The time constant of the peak reading when the level falls.
+	pub peak_release: Duration,
+	/// The averaging time constant of the RMS reading.
+	pub rms_time_constant: Duration,
+}
+
+impl Default for MeterBallistics {
+	/// Instant peaks with a slow fall-back, and an RMS reading close to the one of a VU meter.
+	fn default() -> Self {
+		Self {
+			peak_attack: Duration::ZERO,
+			peak_release: Duration::from_millis(750),
+			rms_time_constant: Duration::from_millis(300),
+		}
+	}
+}
+
+/// The level of a channel, in dBFS.
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+	pub peak_dB: f32,
+	pub rms_dB: f32,
+}
+
+impl ChannelLevel {
+	/// The difference between the peak and the RMS level, see [`crate::metering::Levels::crest_factor_dB`].
+	///
+	/// Note: the two readings have different ballistics, so this is only meaningful for steady signals.
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn crest_factor_dB(&self) -> f32 {
+		self.peak_dB - self.rms_dB
+	}
+}
+
+/// The latest levels measured by an [`InputLevelMeter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelReading {
+	/// One per channel.
+	pub levels: Vec<ChannelLevel>,
+	/// Incremented at every update, to tell new readings apart from the ones already seen.
+	pub update_idx: usize,
+}
+
+/// Measures the peak and RMS level of each channel of a signal.
+///
+/// This is the processing of [`InputLevelMeter`], which can also be used
+/// on its own (e.g. on a signal loaded from a file).
+#[derive(Debug, Clone)]
+pub struct LevelMeter {
+	sampling_ctx: SamplingCtx,
+	ballistics: MeterBallistics,
+	/// (peak, RMS) for each channel.
+	followers: Vec<(EnvelopeFollower, EnvelopeFollower)>,
+}
+
+impl LevelMeter {
+	#[must_use]
+	pub fn new(sampling_ctx: SamplingCtx, ballistics: MeterBallistics) -> Self {
+		let sample_rate = sampling_ctx.sample_rate();
+		Self {
+			sampling_ctx,
+			ballistics,
+			followers: (0..sampling_ctx.n_ch())
+				.map(|_| {
+					(
+						EnvelopeFollower::new(
+							sample_rate,
+							EnvelopeMode::Peak,
+							ballistics.peak_attack,
+							ballistics.peak_release,
+						),
+						EnvelopeFollower::new(
+							sample_rate,
+							EnvelopeMode::Rms,
+							ballistics.rms_time_constant,
+							ballistics.rms_time_constant,
+						),
+					)
+				})
+				.collect(),
+		}
+	}
+
+	/// Feed the next chunk of the signal.
+	///
+	/// # Panics
+	/// - if the chunk has a different number of channels than the configured one.
+	pub fn process(&mut self, chunk: &InterleavedAudioBuffer<&[f32]>) {
+		assert_eq!(
+			chunk.n_ch(),
+			self.sampling_ctx.n_ch(),
+			"chunk with incompatible number of channels received"
+		);
+		for frame in chunk {
+			for (&sample, (peak, rms)) in frame.samples().iter().zip(&mut self.followers) {
+				peak.process_sample(sample);
+				rms.process_sample(sample);
+			}
+		}
+	}
+
+	/// The current level of each channel.
+	#[must_use]
+	pub fn levels(&self) -> Vec<ChannelLevel> {
+		self.followers
+			.iter()
+			.map(|(peak, rms)| ChannelLevel {
+				peak_dB: peak.dB(),
+				rms_dB: rms.dB(),
+			})
+			.collect()
+	}
+
+	/// Bring all the readings back to silence.
+	pub fn reset(&mut self) {
+		for (peak, rms) in &mut self.followers {
+			peak.reset();
+			rms.reset();
+		}
+	}
+
+	#[must_use]
+	pub fn ballistics(&self) -> MeterBallistics {
+		self.ballistics
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+}
+
+/// Measures the level of each channel of an input stream, see [`LevelMeter`].
+pub struct InputLevelMeter {
+	shared: ReactiveCondvar<LevelReading>,
+	base_stream: InputStream,
+}
+
+impl InputLevelMeter {
+	/// Build and start sampling an input stream
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		ballistics: MeterBallistics,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			ballistics,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		ballistics: MeterBallistics,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let mut meter = LevelMeter::new(sampling_ctx, ballistics);
+		let shared = ReactiveCondvar::new(LevelReading {
+			levels: meter.levels(),
+			update_idx: 0,
+		});
+
+		let base_stream = InputStream::new_with_options(
+			sampling_ctx,
+			device_name,
+			Box::new({
+				let shared = shared.clone();
+				move |chunk, _| {
+					meter.process(&chunk);
+					shared.mutex().with_lock_mut(|reading| {
+						reading.levels = meter.levels();
+						reading.update_idx += 1;
+					});
+					shared.notify_all();
+				}
+			}),
+			None,
+			options,
+		)?;
+
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// The current level of each channel.
+	#[must_use]
+	pub fn levels(&self) -> Vec<ChannelLevel> {
+		self.shared.with_lock(|reading| reading.levels.clone())
+	}
+
+	/// Wait for a reading newer than the one with the given `update_idx`, or for the timeout to expire.
+	#[must_use]
+	pub fn wait_for_update(&self, update_idx: usize, timeout: Duration) -> Option<LevelReading> {
+		self.shared.wait_timeout_while_and_then(
+			|reading| reading.update_idx <= update_idx,
+			timeout,
+			Clone::clone,
+		)
+	}
+
+	/// The shared state updated by the audio callback, which can be used to
+	/// wait for new readings from another thread (e.g. the one of a UI).
+	#[must_use]
+	pub fn subscribe(&self) -> ReactiveCondvar<LevelReading> {
+		self.shared.clone()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream.avg_input_delay()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn per_channel_levels() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let mut meter = LevelMeter::new(sampling_ctx, MeterBallistics::default());
+
+		// A full-scale sinusoid on the left channel, a -20 dBFS DC on the right one.
+		let signal: Vec<f32> = (0..48000 * 3)
+			.flat_map(|i| [(TAU * 1000. * i as f32 / 48000.).sin(), 0.1])
+			.collect();
+		meter.process(&InterleavedAudioBuffer::new(sampling_ctx, &signal));
+
+		let levels = meter.levels();
+		assert!(levels[0].peak_dB.abs() < 0.1, "{levels:?}");
+		assert!((levels[0].rms_dB + 3.01).abs() < 0.1, "{levels:?}");
+		assert!((levels[1].peak_dB + 20.).abs() < 0.1, "{levels:?}");
+		assert!((levels[1].rms_dB + 20.).abs() < 0.1, "{levels:?}");
+		assert!(
+			(levels[0].crest_factor_dB() - 3.01).abs() < 0.1,
+			"{levels:?}"
+		);
+
+		meter.reset();
+		assert!(meter.levels()[0].peak_dB.is_infinite());
+	}
+
+	#[test]
+	#[ignore = "manually check the levels of the default input device"]
+	fn test_manual() {
+		let meter = InputLevelMeter::new(
+			SamplingCtx::new(SampleRate(44100), 1),
+			None,
+			MeterBallistics::default(),
+		)
+		.unwrap();
+		let mut update_idx = 0;
+		for _ in 0..50 {
+			let reading = meter
+				.wait_for_update(update_idx, Duration::from_secs(1))
+				.unwrap();
+			update_idx = reading.update_idx;
+			println!("{:?}", reading.levels);
+		}
+	}
+}
@@ -6,3 +6,35 @@ pub use stream::*;
 
 mod record;
 pub use record::*;
+
+mod triggered_record;
+pub use triggered_record::*;
+
+mod file_record;
+pub use file_record::*;
+
+mod segmenter;
+pub use segmenter::*;
+
+mod aggregate;
+pub use aggregate::*;
+
+mod virtual_stream;
+pub use virtual_stream::*;
+
+#[cfg(feature = "analysis")]
+mod agc;
+#[cfg(feature = "analysis")]
+pub use agc::*;
+
+#[cfg(feature = "analysis")]
+mod level_meter;
+#[cfg(feature = "analysis")]
+pub use level_meter::*;
+
+#[cfg(feature = "tokio")]
+mod async_stream;
+#[cfg(feature = "tokio")]
+pub use async_stream::*;
+
+mod snapshot_ring_buffer;
@@ -1,21 +1,49 @@
 use std::{
-	sync::{Arc, Mutex},
-	time::Duration,
+	convert::Infallible,
+	sync::{
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
 };
 
-use mutex_ext::LockExt;
-use ringbuffer::{AllocRingBuffer, RingBuffer};
-
 use crate::{
-	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames,
-	SampleRate, SamplingCtx,
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate,
+	SamplingCtx, StreamOptions,
 };
 
-use super::InputStream;
+use super::{snapshot_ring_buffer::SnapshotRingBuffer, InputStream, OnDataCallback, ReplayPace};
+
+/// Counters describing how the internal buffer of an [`InputStreamPoller`] is being filled and read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PollerStats {
+	/// The number of times the input stream delivered new frames.
+	pub callbacks: usize,
+	/// The number of frames received from the input stream.
+	pub received_frames: NOfFrames,
+	/// The number of frames that were pushed out of the buffer before being read
+	/// by any of the methods that extract frames (e.g. [`InputStreamPoller::snapshot`]).
+	/// If this keeps growing, the buffer is too small or the consumer too slow.
+	pub overwritten_frames: NOfFrames,
+	/// The shortest time elapsed between two consecutive callbacks.
+	pub min_callback_interval: Option<Duration>,
+	/// The longest time elapsed between two consecutive callbacks, which
+	/// reveals the stalls of the input stream.
+	pub max_callback_interval: Option<Duration>,
+}
 
+/// Keeps the latest `n_of_frames` frames received from an input stream, which can be
+/// extracted at any time.
+///
+/// The audio callback never blocks: it writes into a lock-free ring buffer, and readers
+/// retry when they race with it (which only happens when reading lasts longer than
+/// the time needed to fill `n_of_frames` frames).
 pub struct InputStreamPoller {
 	n_of_frames: NOfFrames,
-	shared: Arc<Mutex<PollerState>>,
+	shared: Arc<PollerState>,
+	/// The value of the committed samples counter at the time of the last [`Self::drain_new_frames`].
+	drained_samples: usize,
 	base_stream: InputStream,
 }
 
@@ -29,34 +57,117 @@ impl InputStreamPoller {
 		n_of_frames: NOfFrames,
 		device_name: Option<&str>,
 	) -> Result<Self, AudioStreamBuilderError> {
-		let shared = Arc::new(Mutex::new({
-			PollerState {
-				buffer: {
-					let mut buf = AllocRingBuffer::new(sampling_ctx.frames_to_samples(n_of_frames));
-					buf.fill(0.);
-					buf
-				},
-				collected_frames: n_of_frames, // buffer pre-filled with 0.
-			}
-		}));
-
-		let base_stream = InputStream::new(
+		Self::new_with_options(
 			sampling_ctx,
+			n_of_frames,
 			device_name,
-			Box::new({
-				let shared = shared.clone();
-				move |chunk| {
-					shared.with_lock_mut(|shared| {
-						shared.buffer.extend_from_slice(chunk.raw_buffer());
-						shared.collected_frames += chunk.n_of_frames();
-					});
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		n_of_frames: NOfFrames,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_base_stream(sampling_ctx, n_of_frames, |on_data| {
+			InputStream::new_with_options(sampling_ctx, device_name, on_data, None, options)
+		})
+	}
+
+	/// Build and start sampling an input stream that reconnects following
+	/// the given [`ReconnectPolicy`], see [`InputStream::new_with_reconnect`].
+	///
+	/// Frames are not collected while the stream is reconnecting.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		n_of_frames: NOfFrames,
+		device_name: Option<&str>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_base_stream(sampling_ctx, n_of_frames, |on_data| {
+			InputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				on_data,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build a poller that collects the frames replayed from `signal`, see [`InputStream::new_virtual`].
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	#[must_use]
+	pub fn new_virtual(
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		n_of_frames: NOfFrames,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+	) -> Self {
+		let Ok(poller) = Self::new_with_base_stream::<Infallible>(
+			signal.sampling_ctx(),
+			n_of_frames,
+			|on_data| Ok(InputStream::new_virtual(signal, chunk_len, pace, on_data)),
+		);
+		poller
+	}
+
+	/// Build a poller that collects the frames captured by `device`, see [`InputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		n_of_frames: NOfFrames,
+	) -> Self {
+		let Ok(poller) =
+			Self::new_with_base_stream::<Infallible>(sampling_ctx, n_of_frames, |on_data| {
+				Ok(InputStream::new_on_device(
+					device,
+					sampling_ctx,
+					on_data,
+					None,
+				))
+			});
+		poller
+	}
+
+	fn new_with_base_stream<E>(
+		sampling_ctx: SamplingCtx,
+		n_of_frames: NOfFrames,
+		base_stream_builder: impl FnOnce(Box<OnDataCallback>) -> Result<InputStream, E>,
+	) -> Result<Self, E> {
+		let shared = Arc::new(PollerState::new(sampling_ctx, n_of_frames));
+
+		let base_stream = base_stream_builder(Box::new({
+			let shared = shared.clone();
+			let mut last_callback = None;
+			move |chunk, _| {
+				let now = Instant::now();
+				shared.push(chunk.raw_buffer());
+				if let Some(last_callback) = last_callback.replace(now) {
+					shared.record_callback_interval(now.saturating_duration_since(last_callback));
 				}
-			}),
-			None,
-		)?;
+			}
+		}))?;
 
 		Ok(Self {
 			n_of_frames,
+			drained_samples: shared.capacity,
 			shared,
 			base_stream,
 		})
@@ -70,10 +181,20 @@ impl InputStreamPoller {
 	/// Get the latest snapshot of the internal buffer
 	#[must_use]
 	pub fn snapshot(&self) -> InterleavedAudioBuffer<Vec<f32>> {
-		InterleavedAudioBuffer::new(
-			self.sampling_ctx(),
-			self.shared.with_lock(|shared| shared.buffer.to_vec()),
-		)
+		let mut out = vec![0.; self.sampling_ctx().frames_to_samples(self.n_of_frames)];
+		let end = self.shared.buffer.read_latest(&mut out);
+		self.shared.mark_read(end);
+		InterleavedAudioBuffer::new(self.sampling_ctx(), out)
+	}
+
+	/// Get the statistics collected since the stream was built or since the last call to [`Self::reset_stats`].
+	#[must_use]
+	pub fn stats(&self) -> PollerStats {
+		self.shared.stats()
+	}
+
+	pub fn reset_stats(&self) {
+		self.shared.reset_stats();
 	}
 
 	/// Extract the last N frames from the internal buffer
@@ -95,9 +216,6 @@ impl InputStreamPoller {
 	/// Note: if between the two snapshots the buffer has already been
 	/// overwritten, None is returned.
 	///
-	/// # Panics
-	/// - if the mutex guarding the internal data is poisoned.
-	///
 	/// Example (pseudocode):
 	/// ```rust ignore
 	/// let (beginning, collected_frames) = poller.frames_from_ref(NOfFrames::new(10), None);
@@ -111,30 +229,38 @@ impl InputStreamPoller {
 		frames_to_extract: NOfFrames,
 		previously_collected_frames: Option<NOfFrames>,
 	) -> Option<(InterleavedAudioBuffer<Vec<f32>>, NOfFrames)> {
-		let shared = self.shared.lock().unwrap();
-		let collected_frames = shared.collected_frames;
+		let sampling_ctx = self.sampling_ctx();
+		loop {
+			let end = self.shared.buffer.committed();
+			let collected_frames = sampling_ctx.samples_to_frames(end);
 
-		let skip = match previously_collected_frames {
-			Some(prev) if collected_frames - prev >= self.n_of_frames => None,
-			Some(prev) => Some(self.n_of_frames - (collected_frames - prev)),
-			None => Some(self.n_of_frames - frames_to_extract.min(self.n_of_frames)),
-		};
+			let skip = match previously_collected_frames {
+				Some(prev) if collected_frames - prev >= self.n_of_frames => None,
+				Some(prev) => Some(self.n_of_frames - (collected_frames - prev)),
+				None => Some(self.n_of_frames - frames_to_extract.min(self.n_of_frames)),
+			}?;
 
-		skip.map(|skip| {
-			(
-				InterleavedAudioBuffer::new(self.sampling_ctx(), {
-					let mut out =
-						vec![0.; shared.buffer.len() - self.sampling_ctx().frames_to_samples(skip)];
-					if !out.is_empty() {
-						shared
-							.buffer
-							.copy_to_slice(self.sampling_ctx().frames_to_samples(skip), &mut out);
-					}
-					out
-				}),
-				collected_frames,
-			)
-		})
+			let mut out = vec![0.; sampling_ctx.frames_to_samples(self.n_of_frames - skip)];
+			if self.shared.buffer.try_read(end, &mut out) {
+				self.shared.mark_read(end);
+				return Some((
+					InterleavedAudioBuffer::new(sampling_ctx, out),
+					collected_frames,
+				));
+			}
+		}
+	}
+
+	/// Extract all the frames received since the previous call (or since the stream was built),
+	/// so that consecutive calls return a contiguous signal.
+	///
+	/// If more than [`Self::n_of_frames`] frames have been received in the meantime, only the
+	/// latest [`Self::n_of_frames`] are returned, and the others are counted in [`PollerStats::overwritten_frames`].
+	#[must_use]
+	pub fn drain_new_frames(&mut self) -> InterleavedAudioBuffer<Vec<f32>> {
+		let (samples, end) = self.shared.read_since(self.drained_samples);
+		self.drained_samples = end;
+		InterleavedAudioBuffer::new(self.sampling_ctx(), samples)
 	}
 
 	#[must_use]
@@ -164,18 +290,199 @@ impl InputStreamPoller {
 }
 
 struct PollerState {
-	buffer: AllocRingBuffer<f32>,
-	collected_frames: NOfFrames,
+	n_ch: usize,
+	capacity: usize,
+	buffer: SnapshotRingBuffer,
+	/// The number of samples committed at the time of the last read.
+	read_samples: AtomicUsize,
+	callbacks: AtomicUsize,
+	received_samples: AtomicUsize,
+	overwritten_samples: AtomicUsize,
+	callback_intervals: AtomicUsize,
+	min_callback_interval_ns: AtomicU64,
+	max_callback_interval_ns: AtomicU64,
+}
+
+impl PollerState {
+	fn new(sampling_ctx: SamplingCtx, n_of_frames: NOfFrames) -> Self {
+		let capacity = sampling_ctx.frames_to_samples(n_of_frames);
+		Self {
+			n_ch: sampling_ctx.n_ch(),
+			capacity,
+			buffer: SnapshotRingBuffer::new(capacity), // pre-filled with 0.
+			read_samples: AtomicUsize::new(capacity),
+			callbacks: AtomicUsize::new(0),
+			received_samples: AtomicUsize::new(0),
+			overwritten_samples: AtomicUsize::new(0),
+			callback_intervals: AtomicUsize::new(0),
+			min_callback_interval_ns: AtomicU64::new(u64::MAX),
+			max_callback_interval_ns: AtomicU64::new(0),
+		}
+	}
+
+	/// Only called by the audio callback.
+	fn push(&self, chunk: &[f32]) {
+		let read_samples = self.read_samples.load(Ordering::Relaxed);
+		let unread_before = self.buffer.committed() - read_samples;
+		self.buffer.push(chunk);
+		let unread_after = unread_before + chunk.len();
+		self.overwritten_samples.fetch_add(
+			unread_after.saturating_sub(self.capacity)
+				- unread_before.saturating_sub(self.capacity),
+			Ordering::Relaxed,
+		);
+
+		self.callbacks.fetch_add(1, Ordering::Relaxed);
+		self.received_samples
+			.fetch_add(chunk.len(), Ordering::Relaxed);
+	}
+
+	fn record_callback_interval(&self, interval: Duration) {
+		let interval_ns = u64::try_from(interval.as_nanos()).unwrap_or(u64::MAX);
+		self.min_callback_interval_ns
+			.fetch_min(interval_ns, Ordering::Relaxed);
+		self.max_callback_interval_ns
+			.fetch_max(interval_ns, Ordering::Relaxed);
+		self.callback_intervals.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Read the samples committed after `start` (up to the capacity), returning them
+	/// together with the value of the committed samples counter at the end of the read.
+	fn read_since(&self, start: usize) -> (Vec<f32>, usize) {
+		loop {
+			let end = self.buffer.committed();
+			let mut out = vec![0.; (end - start).min(self.capacity)];
+			if self.buffer.try_read(end, &mut out) {
+				self.mark_read(end);
+				return (out, end);
+			}
+		}
+	}
+
+	fn mark_read(&self, committed: usize) {
+		self.read_samples.fetch_max(committed, Ordering::Relaxed);
+	}
+
+	fn stats(&self) -> PollerStats {
+		let has_intervals = self.callback_intervals.load(Ordering::Relaxed) > 0;
+		let interval = |ns: &AtomicU64| {
+			has_intervals.then(|| Duration::from_nanos(ns.load(Ordering::Relaxed)))
+		};
+		PollerStats {
+			callbacks: self.callbacks.load(Ordering::Relaxed),
+			received_frames: NOfFrames(self.received_samples.load(Ordering::Relaxed) / self.n_ch),
+			overwritten_frames: NOfFrames(
+				self.overwritten_samples.load(Ordering::Relaxed) / self.n_ch,
+			),
+			min_callback_interval: interval(&self.min_callback_interval_ns),
+			max_callback_interval: interval(&self.max_callback_interval_ns),
+		}
+	}
+
+	fn reset_stats(&self) {
+		self.callbacks.store(0, Ordering::Relaxed);
+		self.received_samples.store(0, Ordering::Relaxed);
+		self.overwritten_samples.store(0, Ordering::Relaxed);
+		self.callback_intervals.store(0, Ordering::Relaxed);
+		self.min_callback_interval_ns
+			.store(u64::MAX, Ordering::Relaxed);
+		self.max_callback_interval_ns.store(0, Ordering::Relaxed);
+	}
 }
 
 #[cfg(test)]
 mod tests {
 	use std::{thread::sleep, time::Duration};
 
-	use crate::output::AudioPlayer;
+	use crate::{backend::MockDevice, output::AudioPlayer};
 
 	use super::*;
 
+	#[test]
+	fn stats_track_overwritten_frames_and_intervals() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let poller_state = PollerState::new(sampling_ctx, NOfFrames(10));
+
+		poller_state.push(&[0.; 2 * 8]);
+		assert_eq!(poller_state.stats().overwritten_frames, NOfFrames(0));
+		poller_state.push(&[0.; 2 * 8]);
+		assert_eq!(poller_state.stats().overwritten_frames, NOfFrames(6));
+		poller_state.mark_read(poller_state.buffer.committed());
+		poller_state.push(&[0.; 2 * 8]);
+		assert_eq!(poller_state.stats().overwritten_frames, NOfFrames(6));
+
+		assert_eq!(poller_state.stats().min_callback_interval, None);
+		poller_state.record_callback_interval(Duration::from_millis(10));
+		poller_state.record_callback_interval(Duration::from_millis(20));
+
+		let stats = poller_state.stats();
+		assert_eq!(stats.callbacks, 3);
+		assert_eq!(stats.received_frames, NOfFrames(24));
+		assert_eq!(stats.min_callback_interval, Some(Duration::from_millis(10)));
+		assert_eq!(stats.max_callback_interval, Some(Duration::from_millis(20)));
+
+		poller_state.reset_stats();
+		assert_eq!(poller_state.stats(), PollerStats::default());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn reads_since_a_previous_read() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let poller_state = PollerState::new(sampling_ctx, NOfFrames(4));
+		let start = poller_state.capacity;
+
+		let (samples, end) = poller_state.read_since(start);
+		assert!(samples.is_empty());
+		assert_eq!(end, start);
+
+		poller_state.push(&[1., 2.]);
+		poller_state.push(&[3.]);
+		let (samples, end) = poller_state.read_since(end);
+		assert_eq!(samples, [1., 2., 3.]);
+
+		poller_state.push(&[4., 5., 6., 7., 8., 9.]);
+		let (samples, _) = poller_state.read_since(end);
+		assert_eq!(samples, [6., 7., 8., 9.]);
+		assert_eq!(poller_state.stats().overwritten_frames, NOfFrames(2));
+	}
+
+	#[test]
+	fn virtual_stream() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let signal = InterleavedAudioBuffer::new(sampling_ctx, (0..10u16).map(f32::from).collect());
+		let mut poller = InputStreamPoller::new_virtual(
+			signal,
+			NOfFrames(4),
+			NOfFrames(2),
+			ReplayPace::Unthrottled,
+		);
+		while poller.state() == AudioStreamSamplingState::Sampling {
+			sleep(Duration::from_millis(1));
+		}
+		assert_eq!(*poller.snapshot().raw_buffer(), [6., 7., 8., 9.]);
+		assert_eq!(*poller.drain_new_frames().raw_buffer(), [6., 7., 8., 9.]);
+		assert!(poller.drain_new_frames().raw_buffer().is_empty());
+		assert_eq!(poller.stats().received_frames, NOfFrames(10));
+	}
+
+	#[test]
+	fn mock_device() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let device = MockDevice::new("mock", sampling_ctx);
+		let mut poller =
+			InputStreamPoller::new_on_device(device.clone(), sampling_ctx, NOfFrames(4));
+		assert_eq!(poller.state(), AudioStreamSamplingState::Sampling);
+
+		device.push_input(&[1., 2., 3., 4., 5., 6.]);
+		device.process(NOfFrames(3));
+		assert_eq!(*poller.drain_new_frames().raw_buffer(), [1., 2., 3.]);
+		device.process(NOfFrames(3));
+		assert_eq!(*poller.snapshot().raw_buffer(), [3., 4., 5., 6.]);
+		assert_eq!(*poller.drain_new_frames().raw_buffer(), [4., 5., 6.]);
+		assert_eq!(poller.stats().callbacks, 2);
+	}
+
 	#[test]
 	#[ignore = "manually record and listen to the registered audio file"]
 	fn test_manual() {
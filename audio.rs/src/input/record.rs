@@ -1,5 +1,6 @@
 use std::{
-	mem::replace,
+	collections::VecDeque,
+	convert::Infallible,
 	sync::{Arc, Mutex},
 	time::Duration,
 };
@@ -7,13 +8,31 @@ use std::{
 use mutex_ext::LockExt;
 
 use crate::{
+	backend::AudioDevice,
 	buffers::InterleavedAudioBuffer,
 	common::{AudioStreamBuilderError, AudioStreamSamplingState},
-	NOfFrames, SampleRate, SamplingCtx,
+	NOfFrames, SampleRate, SamplingCtx, StreamOptions,
 };
 
-use super::InputStream;
+use super::{InputStream, OnDataCallback, ReplayPace};
 
+/// What an [`AudioRecorder`] does with the incoming audio once its capacity has been reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RecorderMode {
+	/// Keep the collected audio and discard the new one.
+	#[default]
+	StopWhenFull,
+	/// Discard the oldest audio to make room for the new one, so that the
+	/// recorder always contains the latest `capacity` frames.
+	Circular,
+}
+
+/// Collects the input in memory, up to a given capacity.
+///
+/// See [`super::FileRecorder`] for recordings that don't fit in memory.
+///
+/// The recorder starts recording as soon as it's built, and can be controlled
+/// with [`Self::start`], [`Self::pause`] and [`Self::stop`].
 pub struct AudioRecorder {
 	capacity: NOfFrames,
 	shared: Arc<Mutex<RecorderState>>,
@@ -30,30 +49,86 @@ impl AudioRecorder {
 		capacity: NOfFrames,
 		device_name: Option<&str>,
 	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			capacity,
+			device_name,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		capacity: NOfFrames,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_base_stream(sampling_ctx, capacity, |on_data| {
+			InputStream::new_with_options(sampling_ctx, device_name, on_data, None, options)
+		})
+	}
+
+	/// Build a recorder that collects the frames replayed from `signal`, see [`InputStream::new_virtual`].
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	#[must_use]
+	pub fn new_virtual(
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		capacity: NOfFrames,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+	) -> Self {
+		let Ok(recorder) =
+			Self::new_with_base_stream::<Infallible>(signal.sampling_ctx(), capacity, |on_data| {
+				Ok(InputStream::new_virtual(signal, chunk_len, pace, on_data))
+			});
+		recorder
+	}
+
+	/// Build a recorder that collects the frames captured by `device`, see [`InputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		capacity: NOfFrames,
+	) -> Self {
+		let Ok(recorder) =
+			Self::new_with_base_stream::<Infallible>(sampling_ctx, capacity, |on_data| {
+				Ok(InputStream::new_on_device(
+					device,
+					sampling_ctx,
+					on_data,
+					None,
+				))
+			});
+		recorder
+	}
+
+	fn new_with_base_stream<E>(
+		sampling_ctx: SamplingCtx,
+		capacity: NOfFrames,
+		base_stream_builder: impl FnOnce(Box<OnDataCallback>) -> Result<InputStream, E>,
+	) -> Result<Self, E> {
 		let buffer_size = sampling_ctx.frames_to_samples(capacity);
 		let shared = Arc::new(Mutex::new(RecorderState {
 			buffer_size,
-			buffer: Vec::with_capacity(buffer_size),
+			buffer: VecDeque::with_capacity(buffer_size),
+			recording: true,
+			mode: RecorderMode::default(),
 		}));
 
-		let base_stream = InputStream::new(
-			sampling_ctx,
-			device_name,
-			Box::new({
-				let shared = shared.clone();
-				move |chunk| {
-					shared.with_lock_mut(|shared| {
-						shared.buffer.extend_from_slice(
-							&chunk.raw_buffer()[0..chunk
-								.raw_buffer()
-								.len()
-								.min(shared.buffer_size - chunk.raw_buffer().len())],
-						);
-					});
-				}
-			}),
-			None,
-		)?;
+		let base_stream = base_stream_builder(Box::new({
+			let shared = shared.clone();
+			move |chunk, _| {
+				shared.with_lock_mut(|shared| shared.push(chunk.raw_buffer()));
+			}
+		}))?;
 
 		Ok(Self {
 			capacity,
@@ -67,13 +142,13 @@ impl AudioRecorder {
 		self.base_stream.state()
 	}
 
+	/// Take the collected audio, emptying the recorder.
 	#[must_use]
 	pub fn take(&mut self) -> InterleavedAudioBuffer<Vec<f32>> {
 		InterleavedAudioBuffer::new(
 			self.sampling_ctx(),
-			self.shared.with_lock_mut(|shared| {
-				replace(&mut shared.buffer, Vec::with_capacity(shared.buffer_size))
-			}),
+			self.shared
+				.with_lock_mut(|shared| shared.buffer.drain(..).collect()),
 		)
 	}
 
@@ -82,10 +157,50 @@ impl AudioRecorder {
 	pub fn snapshot(&self) -> InterleavedAudioBuffer<Vec<f32>> {
 		InterleavedAudioBuffer::new(
 			self.sampling_ctx(),
-			self.shared.with_lock(|shared| shared.buffer.clone()),
+			self.shared
+				.with_lock(|shared| shared.buffer.iter().copied().collect()),
 		)
 	}
 
+	/// Resume collecting the incoming audio, after [`Self::pause`] or [`Self::stop`].
+	pub fn start(&mut self) {
+		self.shared.with_lock_mut(|shared| shared.recording = true);
+	}
+
+	/// Stop collecting the incoming audio, keeping what has been collected so far.
+	pub fn pause(&mut self) {
+		self.shared.with_lock_mut(|shared| shared.recording = false);
+	}
+
+	/// Stop collecting the incoming audio and take what has been collected so far.
+	#[must_use]
+	pub fn stop(&mut self) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.pause();
+		self.take()
+	}
+
+	/// Whether the incoming audio is being collected.
+	#[must_use]
+	pub fn is_recording(&self) -> bool {
+		self.shared.with_lock(|shared| shared.recording)
+	}
+
+	#[must_use]
+	pub fn mode(&self) -> RecorderMode {
+		self.shared.with_lock(|shared| shared.mode)
+	}
+
+	pub fn set_mode(&mut self, mode: RecorderMode) {
+		self.shared.with_lock_mut(|shared| shared.mode = mode);
+	}
+
+	/// Whether the capacity has been reached.
+	#[must_use]
+	pub fn is_full(&self) -> bool {
+		self.shared
+			.with_lock(|shared| shared.buffer.len() >= shared.buffer_size)
+	}
+
 	#[must_use]
 	pub fn capacity(&self) -> NOfFrames {
 		self.capacity
@@ -114,7 +229,29 @@ impl AudioRecorder {
 
 struct RecorderState {
 	buffer_size: usize,
-	buffer: Vec<f32>,
+	buffer: VecDeque<f32>,
+	recording: bool,
+	mode: RecorderMode,
+}
+
+impl RecorderState {
+	fn push(&mut self, data: &[f32]) {
+		if !self.recording {
+			return;
+		}
+		match self.mode {
+			RecorderMode::StopWhenFull => {
+				let free = self.buffer_size - self.buffer.len();
+				self.buffer.extend(&data[..data.len().min(free)]);
+			}
+			RecorderMode::Circular => {
+				let data = &data[data.len().saturating_sub(self.buffer_size)..];
+				let overflow = (self.buffer.len() + data.len()).saturating_sub(self.buffer_size);
+				self.buffer.drain(..overflow);
+				self.buffer.extend(data);
+			}
+		}
+	}
 }
 
 #[cfg(test)]
@@ -125,6 +262,58 @@ mod tests {
 
 	use super::*;
 
+	fn state(mode: RecorderMode) -> RecorderState {
+		RecorderState {
+			buffer_size: 4,
+			buffer: VecDeque::new(),
+			recording: true,
+			mode,
+		}
+	}
+
+	#[test]
+	fn stop_when_full() {
+		let mut state = state(RecorderMode::StopWhenFull);
+		state.push(&[1., 2., 3.]);
+		state.push(&[4., 5., 6.]);
+		assert_eq!(state.buffer, [1., 2., 3., 4.]);
+	}
+
+	#[test]
+	fn circular() {
+		let mut state = state(RecorderMode::Circular);
+		state.push(&[1., 2., 3.]);
+		state.push(&[4., 5.]);
+		assert_eq!(state.buffer, [2., 3., 4., 5.]);
+		state.push(&[6., 7., 8., 9., 10.]);
+		assert_eq!(state.buffer, [7., 8., 9., 10.]);
+	}
+
+	#[test]
+	fn paused() {
+		let mut state = state(RecorderMode::Circular);
+		state.recording = false;
+		state.push(&[1., 2., 3.]);
+		assert!(state.buffer.is_empty());
+	}
+
+	#[test]
+	fn virtual_stream() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let signal = InterleavedAudioBuffer::new(sampling_ctx, (0..20u16).map(f32::from).collect());
+		let mut recorder =
+			AudioRecorder::new_virtual(signal, NOfFrames(6), NOfFrames(3), ReplayPace::Unthrottled);
+		recorder.set_mode(RecorderMode::Circular);
+		while recorder.state() == AudioStreamSamplingState::Sampling {
+			sleep(Duration::from_millis(1));
+		}
+		assert!(recorder.is_full());
+		assert_eq!(
+			*recorder.take().raw_buffer(),
+			(8..20u16).map(f32::from).collect::<Vec<_>>()
+		);
+	}
+
 	#[test]
 	#[ignore = "manually record and listen to the registered audio file"]
 	fn test_manual() {
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use buffer_hopper::BufferHopper;
+
+use crate::{
+	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames,
+	SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::InputStream;
+
+pub type OnWindowCallback =
+	dyn FnMut(InterleavedAudioBuffer<&[f32]>, usize /* window_idx */) + Send + 'static;
+
+/// Splits the input into fixed-size, optionally overlapping, windows, e.g. to feed
+/// an analyzer that works on a fixed number of frames.
+pub struct InputSegmenter {
+	window_len: NOfFrames,
+	overlap: NOfFrames,
+	base_stream: InputStream,
+}
+
+impl InputSegmenter {
+	/// Build and start sampling an input stream. `on_window` is called from the audio thread
+	/// with every window of `window_len` frames, the first `overlap` of which are the last
+	/// ones of the previous window.
+	///
+	/// # Panics
+	/// - if `window_len` is 0 or `overlap` is not less than `window_len`.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		window_len: NOfFrames,
+		overlap: NOfFrames,
+		on_window: Box<OnWindowCallback>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			window_len,
+			overlap,
+			on_window,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Panics
+	/// - if `window_len` is 0 or `overlap` is not less than `window_len`.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		window_len: NOfFrames,
+		overlap: NOfFrames,
+		mut on_window: Box<OnWindowCallback>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		assert!(
+			overlap < window_len,
+			"window_len ({window_len}) must be greater than the overlap ({overlap})"
+		);
+
+		let mut hopper = BufferHopper::new_with_overlap(
+			sampling_ctx.frames_to_samples(window_len),
+			sampling_ctx.frames_to_samples(overlap),
+		);
+
+		let base_stream = InputStream::new_with_options(
+			sampling_ctx,
+			device_name,
+			Box::new(move |chunk, _| {
+				hopper.feed(*chunk.raw_buffer(), |window, window_idx| {
+					on_window(
+						InterleavedAudioBuffer::new(sampling_ctx, window),
+						window_idx,
+					);
+				});
+			}),
+			None,
+			options,
+		)?;
+
+		Ok(Self {
+			window_len,
+			overlap,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	#[must_use]
+	pub fn window_len(&self) -> NOfFrames {
+		self.window_len
+	}
+
+	#[must_use]
+	pub fn overlap(&self) -> NOfFrames {
+		self.overlap
+	}
+
+	/// The number of new frames in each window, i.e. the distance between the starts of two consecutive windows.
+	#[must_use]
+	pub fn hop(&self) -> NOfFrames {
+		self.window_len - self.overlap
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream.avg_input_delay()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{thread::sleep, time::Duration};
+
+	use super::*;
+
+	#[test]
+	#[ignore = "manually check the windows received from the default input device"]
+	fn test_manual() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(44100), 1);
+		let segmenter = InputSegmenter::new(
+			sampling_ctx,
+			None,
+			NOfFrames(4096),
+			NOfFrames(2048),
+			Box::new(|window, window_idx| {
+				assert_eq!(window.n_of_frames(), NOfFrames(4096));
+				println!("{window_idx}: {:?}", window.at(0));
+			}),
+		)
+		.unwrap();
+		sleep(Duration::from_secs(1));
+		assert_eq!(segmenter.state(), AudioStreamSamplingState::Sampling);
+	}
+}
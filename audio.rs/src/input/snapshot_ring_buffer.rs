@@ -0,0 +1,134 @@
+use std::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+
+/// A single-producer ring buffer of samples that never blocks the producer.
+///
+/// Readers copy a range of the latest samples and detect, seqlock-style, whether the
+/// producer overwrote part of it in the meantime, in which case they simply try again.
+/// The buffer is allocated with some slack over the readable length, so that
+/// retries are rare.
+pub(crate) struct SnapshotRingBuffer {
+	samples: Box<[AtomicU32]>,
+	/// The number of samples whose writing has started.
+	reserved: AtomicUsize,
+	/// The number of samples that have been completely written.
+	committed: AtomicUsize,
+}
+
+impl SnapshotRingBuffer {
+	/// Create a buffer from which the latest `readable_len` samples can always be read.
+	/// The first `readable_len` samples are zeros.
+	pub(crate) fn new(readable_len: usize) -> Self {
+		Self {
+			samples: (0..(readable_len * 2).max(1))
+				.map(|_| AtomicU32::new(0f32.to_bits()))
+				.collect(),
+			reserved: AtomicUsize::new(readable_len),
+			committed: AtomicUsize::new(readable_len),
+		}
+	}
+
+	/// Append `data` to the buffer.
+	///
+	/// Must only be called by one thread at a time (the audio callback).
+	pub(crate) fn push(&self, data: &[f32]) {
+		let start = self.committed.load(Ordering::Relaxed);
+		let end = start + data.len();
+
+		self.reserved.store(end, Ordering::Relaxed);
+		// Readers that see any of the samples below will also see the reservation.
+		fence(Ordering::Release);
+
+		let capacity = self.samples.len();
+		for (i, sample) in data.iter().enumerate() {
+			self.samples[(start + i) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+		}
+
+		self.committed.store(end, Ordering::Release);
+	}
+
+	/// The total number of samples pushed so far, including the initial zeros.
+	pub(crate) fn committed(&self) -> usize {
+		self.committed.load(Ordering::Acquire)
+	}
+
+	/// Copy the latest `out.len()` samples, returning the value of [`Self::committed`]
+	/// corresponding to the end of the copied range.
+	///
+	/// `out` must not be longer than the `readable_len` passed to the constructor.
+	pub(crate) fn read_latest(&self, out: &mut [f32]) -> usize {
+		loop {
+			let end = self.committed();
+			if self.try_read(end, out) {
+				return end;
+			}
+		}
+	}
+
+	/// Copy the `out.len()` samples preceding `end` (a value previously returned by [`Self::committed`]).
+	///
+	/// Returns `false` if the producer overwrote some of them.
+	pub(crate) fn try_read(&self, end: usize, out: &mut [f32]) -> bool {
+		let capacity = self.samples.len();
+		let start = end - out.len();
+		for (i, sample) in out.iter_mut().enumerate() {
+			*sample = f32::from_bits(self.samples[(start + i) % capacity].load(Ordering::Relaxed));
+		}
+		fence(Ordering::Acquire);
+		self.reserved.load(Ordering::Relaxed) <= start + capacity
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{sync::Arc, thread};
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn reads_the_latest_samples() {
+		let buffer = SnapshotRingBuffer::new(4);
+		let mut out = [1.; 4];
+		assert_eq!(buffer.read_latest(&mut out), 4);
+		assert_eq!(out, [0.; 4]);
+
+		buffer.push(&[1., 2., 3.]);
+		buffer.push(&[4., 5., 6., 7., 8., 9.]);
+		assert_eq!(buffer.read_latest(&mut out), 13);
+		assert_eq!(out, [6., 7., 8., 9.]);
+
+		let mut out = [0.; 2];
+		assert!(buffer.try_read(11, &mut out));
+		assert_eq!(out, [6., 7.]);
+		assert!(!buffer.try_read(4, &mut out));
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn concurrent_reads_are_never_torn() {
+		let buffer = Arc::new(SnapshotRingBuffer::new(64));
+		let producer = thread::spawn({
+			let buffer = buffer.clone();
+			move || {
+				// Each sample holds its own absolute position.
+				let mut next = 64;
+				for _ in 0..20_000 {
+					let chunk: Vec<f32> = (next..next + 16).map(|i| i as f32).collect();
+					buffer.push(&chunk);
+					next += 16;
+				}
+			}
+		});
+
+		let mut out = [0.; 64];
+		while !producer.is_finished() {
+			let end = buffer.read_latest(&mut out);
+			if end > 64 + 64 {
+				for (i, sample) in out.iter().enumerate() {
+					assert!((sample - (end - 64 + i) as f32).abs() < f32::EPSILON);
+				}
+			}
+		}
+		producer.join().unwrap();
+	}
+}
@@ -1,33 +1,98 @@
 use std::{
-	sync::{Arc, Mutex},
-	time::Duration,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		mpsc::{self, Sender},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
 };
 
-use cpal::{
-	traits::{DeviceTrait, StreamTrait},
-	Stream,
-};
 use math_utils::moving_avg::MovingAverage;
 use mutex_ext::LockExt;
 use resource_daemon::ResourceDaemon;
 
+#[cfg(feature = "realtime")]
+use crate::RealtimePromotion;
 use crate::{
-	buffers::InterleavedAudioBuffer, device_provider, AudioStreamBuilderError, AudioStreamError,
-	AudioStreamSamplingState, SampleRate, SamplingCtx,
+	backend::{AudioDevice, CpalDevice, InputStreamTimestamp},
+	buffers::{InterleavedAudioBuffer, Resampler},
+	callback_capacity, device_provider,
+	input::{ReplayPace, VirtualInputStream},
+	reconnect::{ErrorReporter, Reconnector, ReconnectorConfig, StreamDaemon, StreamEvent},
+	AudioStreamBuilderError, AudioStreamError, AudioStreamSamplingState, ChannelSelection, IOMode,
+	NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate, SamplingCtx, StatsCollector,
+	StreamClock, StreamOptions, StreamStats,
 };
 
-pub type OnDataCallback = dyn FnMut(InterleavedAudioBuffer<&[f32]>) + Send + 'static;
+pub use cpal::StreamInstant;
+
+pub type OnDataCallback = dyn FnMut(InterleavedAudioBuffer<&[f32]>, CaptureInfo) + Send + 'static;
+
+/// Timing information about a chunk passed to [`OnDataCallback`], useful to align
+/// the captured audio with other sources of data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CaptureInfo {
+	/// When the first frame of the chunk was captured by the device,
+	/// according to the clock of the audio host.
+	pub capture: StreamInstant,
+	/// When the chunk was delivered by the audio host, according to the same clock.
+	pub callback: StreamInstant,
+	/// The number of frames delivered before this chunk since the stream was built,
+	/// i.e. the position of the first frame of the chunk.
+	///
+	/// Streams built with [`InputStream::new_with_reconnect`] keep counting across reconnections.
+	pub first_frame: NOfFrames,
+}
+
+pub type OnErrorCallback = dyn FnOnce(&AudioStreamError) + Send + 'static;
+
+/// A step of the processing chain of an input stream (e.g. DC removal, filtering, gain),
+/// which modifies the chunks in place, see [`InputStream::new_with_processors`].
+pub type InputProcessor = dyn FnMut(&mut InterleavedAudioBuffer<&mut [f32]>) + Send + 'static;
 
-pub type OnErrorCallback = dyn FnOnce(&str) + Send + 'static;
+struct Shared {
+	/// Kept outside of the lock, so that the callback can tell the position of a chunk
+	/// to `on_data` without waiting for it, see [`CaptureInfo::first_frame`].
+	delivered_frames: AtomicUsize,
+	state: Mutex<StreamState>,
+}
+
+impl Shared {
+	fn new() -> Self {
+		Self {
+			delivered_frames: AtomicUsize::new(0),
+			state: Mutex::new(StreamState {
+				input_delay_moving_avg: MovingAverage::new(10),
+				clock: None,
+				stats: StatsCollector::default(),
+			}),
+		}
+	}
+}
 
 struct StreamState {
 	input_delay_moving_avg: MovingAverage<Duration>,
+	/// See [`InputStream::set_clock`].
+	clock: Option<StreamClock>,
+	/// See [`InputStream::stats`].
+	stats: StatsCollector,
 }
 
 pub struct InputStream {
 	sampling_ctx: SamplingCtx,
-	shared: Arc<Mutex<StreamState>>,
-	stream_daemon: ResourceDaemon<Stream, AudioStreamError>,
+	shared: Arc<Shared>,
+	backend: Backend,
+}
+
+enum Backend {
+	Device {
+		// Declared before the daemon so that it stops replacing it before the daemon is dropped.
+		#[allow(dead_code)] // REASON: only held for its Drop implementation
+		reconnector: Option<Reconnector>,
+		stream_daemon: Arc<Mutex<StreamDaemon>>,
+	},
+	/// See [`InputStream::new_virtual`].
+	Virtual(VirtualInputStream),
 }
 
 impl InputStream {
@@ -38,82 +103,285 @@ impl InputStream {
 	pub fn new(
 		sampling_ctx: SamplingCtx,
 		device_name: Option<&str>,
-		mut on_data: Box<OnDataCallback>,
-		mut on_error: Option<Box<OnErrorCallback>>,
+		on_data: Box<OnDataCallback>,
+		on_error: Option<Box<OnErrorCallback>>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			on_data,
+			on_error,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		on_data: Box<OnDataCallback>,
+		on_error: Option<Box<OnErrorCallback>>,
+		options: StreamOptions,
 	) -> Result<Self, AudioStreamBuilderError> {
-		let (device, config) = device_provider(sampling_ctx, device_name, crate::IOMode::Input)?;
+		let (device, config) =
+			device_provider(sampling_ctx, device_name, crate::IOMode::Input, options)?;
 
-		let shared = Arc::new(Mutex::new(StreamState {
-			input_delay_moving_avg: MovingAverage::new(10),
-		}));
+		let shared = Arc::new(Shared::new());
 
-		let stream_daemon = ResourceDaemon::new({
+		let device = CpalDevice::new(device, config, options);
+		let device_sampling_ctx = device.sampling_ctx();
+		let stream_daemon = spawn_stream_daemon(
+			sampling_ctx,
+			Arc::new(device),
+			device_sampling_ctx,
+			options,
+			shared.clone(),
+			on_data,
+			on_error,
+			None,
+		);
+
+		Ok(Self {
+			sampling_ctx,
+			shared,
+			backend: Backend::Device {
+				reconnector: None,
+				stream_daemon: Arc::new(Mutex::new(stream_daemon)),
+			},
+		})
+	}
+
+	/// Build and start sampling an input stream on a custom [`AudioDevice`], e.g. a [`crate::backend::MockDevice`]
+	/// to feed it scripted input without audio hardware.
+	///
+	/// Returns once the device has started the stream, or has failed to, see [`Self::state`].
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		on_data: Box<OnDataCallback>,
+		on_error: Option<Box<OnErrorCallback>>,
+	) -> Self {
+		let shared = Arc::new(Shared::new());
+		let (events, receiver) = mpsc::channel();
+		let stream_daemon = spawn_stream_daemon(
+			sampling_ctx,
+			Arc::new(device),
+			sampling_ctx,
+			StreamOptions::default(),
+			shared.clone(),
+			on_data,
+			on_error,
+			Some(events),
+		);
+		// Either `Started` or `Failed`, the errors reported later are dropped with the receiver.
+		let _ = receiver.recv();
+
+		Self {
+			sampling_ctx,
+			shared,
+			backend: Backend::Device {
+				reconnector: None,
+				stream_daemon: Arc::new(Mutex::new(stream_daemon)),
+			},
+		}
+	}
+
+	/// Build an input stream that, instead of capturing from a device, replays `signal`
+	/// in chunks of `chunk_len` frames through a [`VirtualInputStream`].
+	///
+	/// Everything built on top of an [`InputStream`] (pollers, recorders, clocks...) can
+	/// therefore be exercised deterministically, without any audio hardware.
+	/// Once the whole signal has been replayed, [`Self::state`] reports
+	/// [`AudioStreamError::Cancelled`].
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	#[must_use]
+	pub fn new_virtual(
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+		mut on_data: Box<OnDataCallback>,
+	) -> Self {
+		let sampling_ctx = signal.sampling_ctx();
+		let shared = Arc::new(Shared::new());
+
+		let virtual_stream = VirtualInputStream::new(signal, chunk_len, pace, {
 			let shared = shared.clone();
+			Box::new(move |chunk, info| {
+				let callback_start = Instant::now();
+				let n_of_frames = chunk.n_of_frames();
+				shared
+					.delivered_frames
+					.store((info.first_frame + n_of_frames).0, Ordering::Relaxed);
+				on_data(chunk, info);
+				shared.state.with_lock_mut(|state| {
+					state.input_delay_moving_avg.push(
+						info.callback
+							.duration_since(&info.capture)
+							.unwrap_or(Duration::ZERO),
+					);
+					if let Some(clock) = &state.clock {
+						clock.report(
+							IOMode::Input,
+							info.first_frame,
+							info.capture,
+							sampling_ctx.sample_rate(),
+						);
+					}
+					state
+						.stats
+						.record_callback(n_of_frames, callback_start.elapsed());
+				});
+			})
+		});
 
-			move |quit_signal| {
-				device
-					.build_input_stream(
-						&config.into(),
-						{
-							let shared = shared.clone();
-
-							move |data: &[f32], info| {
-								let wrapped = InterleavedAudioBuffer::new(sampling_ctx, data);
-								let input_buffer_frames = wrapped.n_of_frames();
-
-								on_data(wrapped);
-
-								shared.with_lock_mut(
-									|StreamState {
-									     ref mut input_delay_moving_avg,
-									 }| {
-										input_delay_moving_avg.push(
-											info.timestamp()
-												.callback
-												.duration_since(&info.timestamp().capture)
-												.unwrap_or(Duration::ZERO) + sampling_ctx
-												.frames_to_duration(input_buffer_frames),
-										);
-									},
-								);
-							}
-						},
-						move |err| {
-							quit_signal.dispatch(AudioStreamError::SamplingError(err.to_string()));
-							if let Some(on_error) = on_error.take() {
-								on_error(&err.to_string());
-							}
-						},
-						None,
-					)
-					.map_err(|err| AudioStreamError::BuildFailed(err.to_string()))
-					.and_then(|stream| {
-						stream
-							.play()
-							.map(|()| stream)
-							.map_err(|err| AudioStreamError::StartFailed(err.to_string()))
-					})
+		Self {
+			sampling_ctx,
+			shared,
+			backend: Backend::Virtual(virtual_stream),
+		}
+	}
+
+	/// Build and start sampling an input stream whose chunks go through `processors`, in order,
+	/// before being passed to `on_data`.
+	///
+	/// Processors with their own state, such as an `AutomaticGainControl`, can be wrapped
+	/// in a closure, e.g. `Box::new(move |chunk| agc.process(chunk))`.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_processors(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		mut processors: Vec<Box<InputProcessor>>,
+		mut on_data: Box<OnDataCallback>,
+		on_error: Option<Box<OnErrorCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let mut buffer = Vec::with_capacity(
+			sampling_ctx.frames_to_samples(callback_capacity(options.buffer_size)),
+		);
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			Box::new(move |chunk, info| {
+				buffer.clear();
+				buffer.extend_from_slice(chunk.raw_buffer());
+				let mut processed =
+					InterleavedAudioBuffer::new(chunk.sampling_ctx(), &mut buffer[..]);
+				for processor in &mut processors {
+					processor(&mut processed);
+				}
+				on_data(
+					InterleavedAudioBuffer::new(chunk.sampling_ctx(), &buffer),
+					info,
+				);
+			}),
+			on_error,
+			options,
+		)
+	}
+
+	/// Build and start sampling an input stream that, instead of stopping when an error occurs
+	/// (e.g. because the device has been disconnected), tries to rebuild itself following
+	/// the given [`ReconnectPolicy`].
+	///
+	/// While reconnecting, [`Self::state`] reports the error that stopped the last stream.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`] if the stream can't be built the first time.
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		on_data: Box<OnDataCallback>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let (device, config) =
+			device_provider(sampling_ctx, device_name, crate::IOMode::Input, options)?;
+
+		let shared = Arc::new(Shared::new());
+
+		// Every stream built by the reconnector feeds the same callback.
+		let on_data = Arc::new(Mutex::new(on_data));
+		let spawner = {
+			let shared = shared.clone();
+			move |device, config, events| {
+				let on_data = on_data.clone();
+				let device = CpalDevice::new(device, config, options);
+				let device_sampling_ctx = device.sampling_ctx();
+				spawn_stream_daemon(
+					sampling_ctx,
+					Arc::new(device),
+					device_sampling_ctx,
+					options,
+					shared.clone(),
+					Box::new(move |chunk, info| {
+						// Only contended while the stream is being replaced,
+						// in which case the chunk of the new one is dropped.
+						on_data.try_with_lock_mut(|on_data| on_data(chunk, info));
+					}),
+					None,
+					Some(events),
+				)
 			}
-		});
+		};
+
+		let (events, receiver) = mpsc::channel();
+		let stream_daemon = Arc::new(Mutex::new(spawner(device, config, events.clone())));
+		let reconnector = Reconnector::new(
+			ReconnectorConfig {
+				policy,
+				sampling_ctx,
+				device_name: device_name.map(ToOwned::to_owned),
+				mode: crate::IOMode::Input,
+				options,
+			},
+			stream_daemon.clone(),
+			Box::new(spawner),
+			events,
+			receiver,
+			on_event,
+		);
 
 		Ok(Self {
 			sampling_ctx,
 			shared,
-			stream_daemon,
+			backend: Backend::Device {
+				reconnector: Some(reconnector),
+				stream_daemon,
+			},
 		})
 	}
 
 	#[must_use]
 	pub fn state(&self) -> AudioStreamSamplingState {
-		match self.stream_daemon.state() {
-			resource_daemon::DaemonState::Holding => AudioStreamSamplingState::Sampling,
-			resource_daemon::DaemonState::Quitting(reason)
-			| resource_daemon::DaemonState::Quit(reason) => {
-				AudioStreamSamplingState::Stopped(reason.unwrap_or(AudioStreamError::Cancelled))
+		match &self.backend {
+			Backend::Device { stream_daemon, .. } => {
+				match stream_daemon.with_lock(ResourceDaemon::state) {
+					resource_daemon::DaemonState::Holding => AudioStreamSamplingState::Sampling,
+					resource_daemon::DaemonState::Quitting(reason)
+					| resource_daemon::DaemonState::Quit(reason) => AudioStreamSamplingState::Stopped(
+						reason.unwrap_or(AudioStreamError::Cancelled),
+					),
+				}
 			}
+			Backend::Virtual(virtual_stream) => virtual_stream.state(),
 		}
 	}
 
+	/// Report when the frames are captured into `clock`, or stop with `None`, see [`StreamClock`].
+	/// The frames are counted as in [`CaptureInfo::first_frame`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.shared.state.with_lock_mut(|state| state.clock = clock);
+	}
+
 	#[must_use]
 	pub fn sampling_ctx(&self) -> SamplingCtx {
 		self.sampling_ctx
@@ -132,6 +400,249 @@ impl InputStream {
 	#[must_use]
 	pub fn avg_input_delay(&self) -> Duration {
 		self.shared
-			.with_lock(|shared| shared.input_delay_moving_avg.avg())
+			.state
+			.with_lock(|state| state.input_delay_moving_avg.avg())
+	}
+
+	/// A snapshot of the callback timings, buffer sizes and errors of the stream, see [`StreamStats`].
+	///
+	/// The callback never waits for this snapshot: if it finds it in progress, it publishes
+	/// its stats with the next chunk.
+	#[must_use]
+	pub fn stats(&self) -> StreamStats {
+		self.shared
+			.state
+			.with_lock(|state| state.stats.snapshot(state.input_delay_moving_avg.avg()))
+	}
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)] // REASON: private helper shared by the constructors
+fn spawn_stream_daemon(
+	sampling_ctx: SamplingCtx,
+	device: Arc<dyn AudioDevice>,
+	device_sampling_ctx: SamplingCtx,
+	options: StreamOptions,
+	shared: Arc<Shared>,
+	mut on_data: Box<OnDataCallback>,
+	mut on_error: Option<Box<OnErrorCallback>>,
+	events: Option<Sender<StreamEvent>>,
+) -> StreamDaemon {
+	let channels = options.channels;
+	let device_n_ch = device_sampling_ctx.n_ch();
+	// The device signal with the selected channels, still at the device rate.
+	let selected_sampling_ctx =
+		SamplingCtx::new(device_sampling_ctx.sample_rate(), sampling_ctx.n_ch());
+	let capacity = callback_capacity(options.buffer_size);
+	let mut selected = Vec::with_capacity(selected_sampling_ctx.frames_to_samples(capacity));
+	// Set when the device has been opened at a different rate, see `StreamOptions::resample`.
+	let mut resampler = (selected_sampling_ctx != sampling_ctx).then(|| {
+		(
+			Resampler::new(
+				sampling_ctx.n_ch(),
+				selected_sampling_ctx.sample_rate(),
+				sampling_ctx.sample_rate(),
+			),
+			// Enough for the resampled chunk, plus a frame of margin for the rounding.
+			Vec::with_capacity(
+				sampling_ctx.frames_to_samples(
+					NOfFrames(
+						(capacity.0 * sampling_ctx.sample_rate().0)
+							.div_ceil(selected_sampling_ctx.sample_rate().0),
+					) + NOfFrames(1),
+				),
+			),
+		)
+	});
+
+	ResourceDaemon::new(move |quit_signal| {
+		let error_reporter = Arc::new(Mutex::new(ErrorReporter::new(
+			quit_signal,
+			on_error.take(),
+			events.clone(),
+		)));
+		let on_data = {
+			let shared = shared.clone();
+			let error_reporter = error_reporter.clone();
+			#[cfg(feature = "realtime")]
+			let mut realtime_promotion = RealtimePromotion::new(device_sampling_ctx, options);
+			// Not yet published to the shared state, see below.
+			let mut pending_stats = StatsCollector::default();
+			move |data: &[f32], timestamp: InputStreamTimestamp| {
+				let callback_start = Instant::now();
+				#[cfg(feature = "realtime")]
+				realtime_promotion.promote_current_thread();
+				#[cfg(feature = "tracing")]
+				let _span = tracing::trace_span!("input_callback", samples = data.len()).entered();
+				#[cfg(feature = "audit")]
+				let _audit = crate::audit::CallbackGuard::enter();
+
+				if !data.len().is_multiple_of(device_n_ch) {
+					pending_stats.record_error();
+					shared.state.try_with_lock_mut(|state| {
+						state.stats.merge(std::mem::take(&mut pending_stats));
+					});
+					// Only contended while the error callback is reporting, in which case
+					// the stream is stopping anyway.
+					error_reporter.try_with_lock_mut(|reporter| {
+						reporter.report(AudioStreamError::FormatChanged);
+					});
+					return;
+				}
+
+				let input_buffer_frames =
+					InterleavedAudioBuffer::new(device_sampling_ctx, data).n_of_frames();
+
+				let data = if channels == ChannelSelection::All {
+					data
+				} else {
+					selected.clear();
+					channels.apply_into(data, device_n_ch, sampling_ctx.n_ch(), &mut selected);
+					&selected
+				};
+
+				let chunk = if let Some((resampler, resampled)) = resampler.as_mut() {
+					resampled.clear();
+					resampler.process_into(data, resampled);
+					InterleavedAudioBuffer::new(sampling_ctx, &resampled[..])
+				} else {
+					InterleavedAudioBuffer::new(selected_sampling_ctx, data)
+				};
+
+				let first_frame = NOfFrames(
+					shared
+						.delivered_frames
+						.fetch_add(chunk.n_of_frames().0, Ordering::Relaxed),
+				);
+
+				on_data(
+					chunk,
+					CaptureInfo {
+						capture: timestamp.capture,
+						callback: timestamp.callback,
+						first_frame,
+					},
+				);
+
+				pending_stats.record_callback(input_buffer_frames, callback_start.elapsed());
+				// The lock is also taken by the getters, from other threads: rather than
+				// waiting for them, the delay and the clock skip this chunk, and the stats
+				// are published with the next one.
+				shared.state.try_with_lock_mut(|state| {
+					state.input_delay_moving_avg.push(
+						timestamp
+							.callback
+							.duration_since(&timestamp.capture)
+							.unwrap_or(Duration::ZERO)
+							+ device_sampling_ctx.frames_to_duration(input_buffer_frames),
+					);
+					if let Some(clock) = &state.clock {
+						clock.report(
+							IOMode::Input,
+							first_frame,
+							timestamp.capture,
+							sampling_ctx.sample_rate(),
+						);
+					}
+					state.stats.merge(std::mem::take(&mut pending_stats));
+				});
+			}
+		};
+		let on_stream_error = {
+			let mut pending_stats = StatsCollector::default();
+			move |err: AudioStreamError| {
+				pending_stats.record_error();
+				shared.state.try_with_lock_mut(|state| {
+					state.stats.merge(std::mem::take(&mut pending_stats));
+				});
+				error_reporter.try_with_lock_mut(|reporter| reporter.report(err));
+			}
+		};
+
+		device
+			.build_input_stream(
+				device_sampling_ctx,
+				Box::new(on_data),
+				Box::new(on_stream_error),
+			)
+			.inspect(|_| {
+				#[cfg(feature = "tracing")]
+				tracing::info!(device = %device.name(), %device_sampling_ctx, "input stream started");
+				if let Some(events) = &events {
+					let _ = events.send(StreamEvent::Started);
+				}
+			})
+			.inspect_err(|err| {
+				#[cfg(feature = "tracing")]
+				tracing::error!(error = %err, %device_sampling_ctx, "unable to start the input stream");
+				if let Some(events) = &events {
+					let _ = events.send(StreamEvent::Failed(err.clone()));
+				}
+			})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+
+	use crate::backend::MockDevice;
+
+	use super::*;
+
+	#[test]
+	fn stats_of_a_virtual_stream() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let stream = InputStream::new_virtual(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![0.; 250]),
+			NOfFrames(100),
+			ReplayPace::Unthrottled,
+			Box::new(|_, _| thread::sleep(Duration::from_millis(1))),
+		);
+		while stream.state() == AudioStreamSamplingState::Sampling {
+			thread::sleep(Duration::from_millis(1));
+		}
+
+		let stats = stream.stats();
+		assert_eq!(stats.callbacks, 3);
+		assert_eq!(stats.min_buffer_size, Some(NOfFrames(50)));
+		assert_eq!(stats.max_buffer_size, Some(NOfFrames(100)));
+		assert_eq!(stats.last_buffer_size, Some(NOfFrames(50)));
+		assert!(stats.avg_callback_duration >= Duration::from_millis(1));
+		assert!(stats.max_callback_duration >= stats.avg_callback_duration);
+		assert_eq!(stats.avg_delay, stream.avg_input_delay());
+		assert_eq!(stats.errors, 0);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn capture_on_a_mock_device() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let device = MockDevice::new("mock", sampling_ctx);
+		let captured = Arc::new(Mutex::new(Vec::new()));
+		let stream = InputStream::new_on_device(
+			device.clone(),
+			sampling_ctx,
+			Box::new({
+				let captured = captured.clone();
+				move |chunk, info| {
+					captured.with_lock_mut(|captured| {
+						captured.push((chunk.raw_buffer().to_vec(), info.first_frame));
+					});
+				}
+			}),
+			None,
+		);
+		assert_eq!(stream.state(), AudioStreamSamplingState::Sampling);
+
+		device.push_input(&[1., 2., 3.]);
+		device.process(NOfFrames(2));
+		device.process(NOfFrames(2));
+		assert_eq!(
+			captured.with_lock(Clone::clone),
+			[(vec![1., 2.], NOfFrames(0)), (vec![3., 0.], NOfFrames(2))]
+		);
+		assert_eq!(stream.stats().callbacks, 2);
+		// The chunk is delivered as soon as it has been captured.
+		assert_eq!(stream.avg_input_delay(), Duration::from_millis(4));
 	}
 }
@@ -0,0 +1,340 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	buffers::InterleavedAudioBuffer, metering::dB_to_amplitude, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, SampleRate, SamplingCtx, StreamOptions,
+};
+
+use super::InputStream;
+
+pub type OnSegmentCallback = dyn FnMut(InterleavedAudioBuffer<Vec<f32>>) + Send + 'static;
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriggerConfig {
+	/// The level, in dBFS, that starts a capture when reached by any channel.
+	pub threshold_dB: f32,
+	/// How long the level must stay below the threshold before the capture stops.
+	/// The emitted segment includes this tail.
+	pub hang_time: Duration,
+	/// How much of the signal preceding the threshold crossing is included at the
+	/// beginning of the emitted segment, so that the attack of transients (claps, plosives, ...)
+	/// is not cut.
+	pub pre_roll: Duration,
+}
+
+impl Default for TriggerConfig {
+	fn default() -> Self {
+		Self {
+			threshold_dB: -40.,
+			hang_time: Duration::from_millis(500),
+			pre_roll: Duration::ZERO,
+		}
+	}
+}
+
+/// Splits a signal into segments of activity, i.e. the parts where the level
+/// of the signal is above a threshold, separated by silence.
+///
+/// This is the detection logic of [`TriggeredRecorder`], which can also be used
+/// on its own (e.g. on a signal loaded from a file).
+#[derive(Debug, Clone)]
+pub struct LevelTrigger {
+	sampling_ctx: SamplingCtx,
+	config: TriggerConfig,
+	threshold: f32,
+	hang_frames: NOfFrames,
+	/// The latest samples received while not triggered, up to `pre_roll`.
+	pre_roll: VecDeque<f32>,
+	pre_roll_len: usize,
+	segment: Option<Vec<f32>>,
+	silent_frames: NOfFrames,
+}
+
+impl LevelTrigger {
+	#[must_use]
+	pub fn new(sampling_ctx: SamplingCtx, config: TriggerConfig) -> Self {
+		let pre_roll_len =
+			sampling_ctx.frames_to_samples(sampling_ctx.duration_to_frames(config.pre_roll));
+		Self {
+			sampling_ctx,
+			config,
+			threshold: dB_to_amplitude(config.threshold_dB),
+			hang_frames: sampling_ctx.duration_to_frames(config.hang_time),
+			pre_roll: VecDeque::with_capacity(pre_roll_len),
+			pre_roll_len,
+			segment: None,
+			silent_frames: NOfFrames(0),
+		}
+	}
+
+	/// Feed the next chunk of the signal, calling `on_segment` for each segment completed
+	/// within the chunk.
+	///
+	/// # Panics
+	/// - if the chunk has a different number of channels than the configured one.
+	pub fn process(
+		&mut self,
+		chunk: &InterleavedAudioBuffer<&[f32]>,
+		mut on_segment: impl FnMut(InterleavedAudioBuffer<Vec<f32>>),
+	) {
+		assert_eq!(
+			chunk.n_ch(),
+			self.sampling_ctx.n_ch(),
+			"chunk with incompatible number of channels received"
+		);
+
+		for frame in chunk {
+			let active = frame
+				.samples()
+				.iter()
+				.any(|sample| sample.abs() >= self.threshold);
+
+			if active {
+				self.silent_frames = NOfFrames(0);
+			} else if self.segment.is_some() {
+				self.silent_frames += NOfFrames(1);
+			}
+
+			if let Some(segment) = self.segment.as_mut() {
+				segment.extend_from_slice(frame.samples());
+				if self.silent_frames >= self.hang_frames {
+					if let Some(segment) = self.flush() {
+						on_segment(segment);
+					}
+				}
+			} else if active {
+				let mut segment = Vec::from(std::mem::take(&mut self.pre_roll));
+				segment.extend_from_slice(frame.samples());
+				self.segment = Some(segment);
+			} else if self.pre_roll_len > 0 {
+				if self.pre_roll.len() == self.pre_roll_len {
+					self.pre_roll.drain(..frame.n_ch());
+				}
+				self.pre_roll.extend(frame.samples());
+			}
+		}
+	}
+
+	/// Whether a segment is currently being captured.
+	#[must_use]
+	pub fn is_triggered(&self) -> bool {
+		self.segment.is_some()
+	}
+
+	/// End the segment being captured, if any, and return it.
+	pub fn flush(&mut self) -> Option<InterleavedAudioBuffer<Vec<f32>>> {
+		self.silent_frames = NOfFrames(0);
+		self.segment
+			.take()
+			.map(|segment| InterleavedAudioBuffer::new(self.sampling_ctx, segment))
+	}
+
+	#[must_use]
+	pub fn config(&self) -> TriggerConfig {
+		self.config
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+}
+
+/// Captures only the parts of the input where some activity is detected, see [`LevelTrigger`].
+pub struct TriggeredRecorder {
+	shared: Arc<Mutex<LevelTrigger>>,
+	base_stream: InputStream,
+}
+
+impl TriggeredRecorder {
+	/// Build and start sampling an input stream. `on_segment` is called
+	/// with every completed segment from the audio thread, so it should return quickly.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		config: TriggerConfig,
+		on_segment: Box<OnSegmentCallback>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			config,
+			on_segment,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start sampling an input stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		config: TriggerConfig,
+		mut on_segment: Box<OnSegmentCallback>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let shared = Arc::new(Mutex::new(LevelTrigger::new(sampling_ctx, config)));
+
+		let base_stream = InputStream::new_with_options(
+			sampling_ctx,
+			device_name,
+			Box::new({
+				let shared = shared.clone();
+				move |chunk, _| {
+					shared.with_lock_mut(|trigger| trigger.process(&chunk, &mut on_segment));
+				}
+			}),
+			None,
+			options,
+		)?;
+
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// Whether a segment is currently being captured.
+	#[must_use]
+	pub fn is_triggered(&self) -> bool {
+		self.shared.with_lock(LevelTrigger::is_triggered)
+	}
+
+	/// End the segment being captured, if any, and return it instead of passing it to the callback.
+	pub fn flush(&mut self) -> Option<InterleavedAudioBuffer<Vec<f32>>> {
+		self.shared.with_lock_mut(LevelTrigger::flush)
+	}
+
+	#[must_use]
+	pub fn config(&self) -> TriggerConfig {
+		self.shared.with_lock(LevelTrigger::config)
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.base_stream.avg_input_delay()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn splits_activity_into_segments() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let mut trigger = LevelTrigger::new(
+			sampling_ctx,
+			TriggerConfig {
+				threshold_dB: -20.,
+				hang_time: Duration::from_millis(10),
+				pre_roll: Duration::ZERO,
+			},
+		);
+
+		// 20 silent frames, 5 loud frames on the right channel, 30 silent frames, 3 loud frames.
+		let mut signal = vec![0.; 2 * 20];
+		signal.extend([0., 0.5].repeat(5));
+		signal.extend(vec![0.01; 2 * 30]);
+		signal.extend([-0.5, 0.].repeat(3));
+
+		let mut segments = vec![];
+		// Feed the signal in small chunks, as an input stream would.
+		for chunk in signal.chunks(2 * 7) {
+			trigger.process(
+				&InterleavedAudioBuffer::new(sampling_ctx, chunk),
+				|segment| {
+					segments.push(segment);
+				},
+			);
+		}
+
+		assert_eq!(segments.len(), 1);
+		assert_eq!(segments[0].n_of_frames(), NOfFrames(5 + 10));
+		assert!((segments[0].at(0).samples()[1] - 0.5).abs() < f32::EPSILON);
+
+		assert!(trigger.is_triggered());
+		let last = trigger.flush().unwrap();
+		assert_eq!(last.n_of_frames(), NOfFrames(3));
+		assert!(!trigger.is_triggered());
+	}
+
+	#[test]
+	fn segments_include_the_pre_roll() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut trigger = LevelTrigger::new(
+			sampling_ctx,
+			TriggerConfig {
+				threshold_dB: -20.,
+				hang_time: Duration::from_millis(5),
+				pre_roll: Duration::from_millis(4),
+			},
+		);
+
+		// A slowly rising attack below the threshold, followed by a transient.
+		#[allow(clippy::cast_precision_loss)]
+		let mut signal: Vec<f32> = (0..10).map(|i| i as f32 / 1024.).collect();
+		signal.extend([0.9, 0.5]);
+		signal.extend([0.; 10]);
+
+		let mut segments = vec![];
+		for chunk in signal.chunks(3) {
+			trigger.process(
+				&InterleavedAudioBuffer::new(sampling_ctx, chunk),
+				|segment| {
+					segments.push(segment);
+				},
+			);
+		}
+
+		assert_eq!(segments.len(), 1);
+		assert_eq!(
+			segments[0].raw_buffer(),
+			&[
+				6. / 1024.,
+				7. / 1024.,
+				8. / 1024.,
+				9. / 1024.,
+				0.9,
+				0.5,
+				0.,
+				0.,
+				0.,
+				0.,
+				0.
+			]
+		);
+	}
+}
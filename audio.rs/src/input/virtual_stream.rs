@@ -0,0 +1,446 @@
+use std::{
+	fs,
+	io::{self, ErrorKind},
+	path::Path,
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+use mutex_ext::{CondvarExt, ReactiveCondvar};
+
+use crate::{
+	buffers::InterleavedAudioBuffer, AudioStreamError, AudioStreamSamplingState, NOfFrames,
+	SampleRate, SamplingCtx,
+};
+
+use super::{file_record::WAVE_FORMAT_IEEE_FLOAT, CaptureInfo, OnDataCallback, StreamInstant};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// How fast a [`VirtualInputStream`] replays its signal.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplayPace {
+	/// One second of signal per second, like a real device.
+	#[default]
+	RealTime,
+	/// The given number of seconds of signal per second, e.g. 10.0 replays
+	/// a 10 seconds signal in about 1 second.
+	Accelerated(f64),
+	/// As fast as the callback can consume the chunks.
+	Unthrottled,
+}
+
+impl ReplayPace {
+	fn speed(self) -> Option<f64> {
+		match self {
+			ReplayPace::RealTime => Some(1.),
+			ReplayPace::Accelerated(speed) => Some(speed),
+			ReplayPace::Unthrottled => None,
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+	replayed_frames: NOfFrames,
+	finished: bool,
+	cancelled: bool,
+}
+
+/// Replays a signal through an [`OnDataCallback`], as if it were captured by an input device,
+/// e.g. to test the processing of a stream deterministically, without any hardware.
+///
+/// The signal is split in chunks of a fixed number of frames (the last one can be shorter),
+/// which are passed to the callback by a dedicated thread. Each chunk is delivered once
+/// its last frame would have been captured, according to the [`ReplayPace`].
+///
+/// The timestamps of [`CaptureInfo`] are based on a virtual clock that starts at 0
+/// and follows the position in the signal, regardless of the pace.
+pub struct VirtualInputStream {
+	sampling_ctx: SamplingCtx,
+	n_of_frames: NOfFrames,
+	shared: ReactiveCondvar<ReplayState>,
+	replay_thread: Option<JoinHandle<()>>,
+}
+
+impl VirtualInputStream {
+	/// Start replaying `signal` in chunks of `chunk_len` frames.
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	#[must_use]
+	pub fn new(
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+		mut on_data: Box<OnDataCallback>,
+	) -> Self {
+		assert!(chunk_len > NOfFrames(0), "chunk_len must be greater than 0");
+		if let ReplayPace::Accelerated(speed) = pace {
+			assert!(speed > 0., "the replay speed must be greater than 0");
+		}
+
+		let n_of_frames = signal.n_of_frames();
+		let (sampling_ctx, raw_buffer) = signal.into_raw();
+		let shared = ReactiveCondvar::new(ReplayState::default());
+
+		let replay_thread = thread::spawn({
+			let shared = shared.clone();
+			move || {
+				let start = Instant::now();
+				let mut first_frame = NOfFrames(0);
+				for chunk in raw_buffer.chunks(sampling_ctx.frames_to_samples(chunk_len)) {
+					let end_frame = first_frame + sampling_ctx.samples_to_frames(chunk.len());
+					let cancelled = match pace.speed() {
+						Some(speed) => {
+							let deadline =
+								start + sampling_ctx.frames_to_duration(end_frame).div_f64(speed);
+							shared
+								.wait_timeout_while(
+									|state| !state.cancelled,
+									deadline.saturating_duration_since(Instant::now()),
+								)
+								.is_some()
+						}
+						None => shared.with_lock(|state| state.cancelled),
+					};
+					if cancelled {
+						return;
+					}
+
+					on_data(
+						InterleavedAudioBuffer::new(sampling_ctx, chunk),
+						CaptureInfo {
+							capture: virtual_instant(sampling_ctx.frames_to_duration(first_frame)),
+							callback: virtual_instant(sampling_ctx.frames_to_duration(end_frame)),
+							first_frame,
+						},
+					);
+					shared.with_lock_mut(|state| state.replayed_frames = end_frame);
+					first_frame = end_frame;
+				}
+				shared.with_lock_mut(|state| state.finished = true);
+			}
+		});
+
+		Self {
+			sampling_ctx,
+			n_of_frames,
+			shared,
+			replay_thread: Some(replay_thread),
+		}
+	}
+
+	/// Start replaying the WAV file at `path` in chunks of `chunk_len` frames.
+	///
+	/// Integer PCM (8, 16, 24 and 32 bits) and floating point (32 and 64 bits) files are supported.
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	/// - if the speed of [`ReplayPace::Accelerated`] is not greater than 0.
+	///
+	/// # Errors
+	/// - if the file can't be read.
+	/// - if the file is not a valid WAV file, or its sample format is not supported
+	///   ([`ErrorKind::InvalidData`]).
+	pub fn from_wav_file(
+		path: impl AsRef<Path>,
+		chunk_len: NOfFrames,
+		pace: ReplayPace,
+		on_data: Box<OnDataCallback>,
+	) -> io::Result<Self> {
+		let signal = parse_wav(&fs::read(path)?)?;
+		Ok(Self::new(signal, chunk_len, pace, on_data))
+	}
+
+	/// Stopped (with [`AudioStreamError::Cancelled`]) once the whole signal has been replayed.
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		if self.is_finished() {
+			AudioStreamSamplingState::Stopped(AudioStreamError::Cancelled)
+		} else {
+			AudioStreamSamplingState::Sampling
+		}
+	}
+
+	#[must_use]
+	pub fn is_finished(&self) -> bool {
+		self.shared.with_lock(|state| state.finished)
+	}
+
+	/// Block until the whole signal has been passed to the callback.
+	pub fn wait_until_finished(&self) {
+		self.shared.wait_while(|state| !state.finished);
+	}
+
+	/// The number of frames passed to the callback so far.
+	#[must_use]
+	pub fn replayed_frames(&self) -> NOfFrames {
+		self.shared.with_lock(|state| state.replayed_frames)
+	}
+
+	/// The length of the replayed signal.
+	#[must_use]
+	pub fn n_of_frames(&self) -> NOfFrames {
+		self.n_of_frames
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sampling_ctx.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.sampling_ctx.n_ch()
+	}
+
+	/// Always zero, as there is no device between the signal and the callback.
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		Duration::ZERO
+	}
+}
+
+impl Drop for VirtualInputStream {
+	fn drop(&mut self) {
+		self.shared.with_lock_mut(|state| state.cancelled = true);
+		if let Some(replay_thread) = self.replay_thread.take() {
+			let _ = replay_thread.join();
+		}
+	}
+}
+
+fn virtual_instant(elapsed: Duration) -> StreamInstant {
+	StreamInstant::new(
+		i64::try_from(elapsed.as_secs()).unwrap_or(i64::MAX),
+		elapsed.subsec_nanos(),
+	)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+	io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Decode a WAV file, converting its samples to f32.
+fn parse_wav(bytes: &[u8]) -> io::Result<InterleavedAudioBuffer<Vec<f32>>> {
+	if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+		return Err(invalid_data("not a WAV file"));
+	}
+
+	// (format, number of channels, sample rate, bits per sample)
+	let mut format = None;
+	let mut data = None;
+	let mut rest = &bytes[12..];
+	while rest.len() >= 8 {
+		let id = &rest[0..4];
+		let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+		let body = rest
+			.get(8..8 + len)
+			// Some writers don't patch the size of the data chunk when they are interrupted.
+			.or_else(|| (id == b"data").then(|| &rest[8..]))
+			.ok_or_else(|| invalid_data("truncated chunk"))?;
+
+		match id {
+			b"fmt " => {
+				if body.len() < 16 {
+					return Err(invalid_data("truncated format chunk"));
+				}
+				let u16_at = |i: usize| u16::from_le_bytes(body[i..i + 2].try_into().unwrap());
+				let mut tag = u16_at(0);
+				if tag == WAVE_FORMAT_EXTENSIBLE {
+					if body.len() < 26 {
+						return Err(invalid_data("truncated format chunk"));
+					}
+					// The first two bytes of the sub-format GUID.
+					tag = u16_at(24);
+				}
+				format = Some((
+					tag,
+					usize::from(u16_at(2)),
+					u32::from_le_bytes(body[4..8].try_into().unwrap()),
+					u16_at(14),
+				));
+			}
+			b"data" => data = Some(body),
+			_ => {}
+		}
+
+		// Chunks are padded to an even number of bytes.
+		rest = rest.get(8 + len + len % 2..).unwrap_or(&[]);
+	}
+
+	let (tag, n_ch, sample_rate, bits) =
+		format.ok_or_else(|| invalid_data("missing format chunk"))?;
+	let data = data.ok_or_else(|| invalid_data("missing data chunk"))?;
+	if n_ch == 0 || sample_rate == 0 {
+		return Err(invalid_data("invalid format chunk"));
+	}
+
+	let bytes_per_sample = usize::from(bits.div_ceil(8));
+	let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+		(WAVE_FORMAT_PCM, 8) => |bytes| f32::from(bytes[0] ^ 0x80).mul_add(1. / 128., -1.),
+		(WAVE_FORMAT_PCM, 9..=32) => |bytes| {
+			// Aligned to the most significant bytes, to keep the sign.
+			let mut aligned = [0u8; 4];
+			aligned[4 - bytes.len()..].copy_from_slice(bytes);
+			#[allow(clippy::cast_precision_loss)]
+			let sample = i32::from_le_bytes(aligned) as f32;
+			sample / 2_147_483_648.
+		},
+		(WAVE_FORMAT_IEEE_FLOAT, 32) => |bytes| f32::from_le_bytes(bytes.try_into().unwrap()),
+		(WAVE_FORMAT_IEEE_FLOAT, 64) => {
+			|bytes| f64::from_le_bytes(bytes.try_into().unwrap()) as f32
+		}
+		_ => return Err(invalid_data("unsupported sample format")),
+	};
+
+	let block_align = bytes_per_sample * n_ch;
+	let raw_buffer = data[..data.len() - data.len() % block_align]
+		.chunks_exact(bytes_per_sample)
+		.map(decode)
+		.collect();
+
+	Ok(InterleavedAudioBuffer::new(
+		SamplingCtx::new(SampleRate(sample_rate as usize), n_ch),
+		raw_buffer,
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::Cursor,
+		sync::{Arc, Mutex},
+	};
+
+	use mutex_ext::LockExt;
+
+	use super::{super::file_record::WavWriter, *};
+
+	#[allow(clippy::cast_precision_loss)]
+	fn ramp(sampling_ctx: SamplingCtx, n_of_frames: usize) -> InterleavedAudioBuffer<Vec<f32>> {
+		InterleavedAudioBuffer::new(
+			sampling_ctx,
+			(0..sampling_ctx.frames_to_samples(NOfFrames(n_of_frames)))
+				.map(|i| i as f32)
+				.collect(),
+		)
+	}
+
+	#[test]
+	fn replays_the_whole_signal_in_chunks() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let signal = ramp(sampling_ctx, 250);
+		let received = Arc::new(Mutex::new(Vec::new()));
+
+		let stream = VirtualInputStream::new(
+			signal.cloned(),
+			NOfFrames(100),
+			ReplayPace::Unthrottled,
+			Box::new({
+				let received = received.clone();
+				move |chunk, info| {
+					received.with_lock_mut(|received| {
+						received.push((chunk.raw_buffer().to_vec(), info));
+					});
+				}
+			}),
+		);
+		stream.wait_until_finished();
+		assert_eq!(stream.replayed_frames(), NOfFrames(250));
+		assert_eq!(
+			stream.state(),
+			AudioStreamSamplingState::Stopped(AudioStreamError::Cancelled)
+		);
+
+		let received = received.with_lock_mut(std::mem::take);
+		let first_frames: Vec<_> = received.iter().map(|(_, info)| info.first_frame).collect();
+		assert_eq!(first_frames, [NOfFrames(0), NOfFrames(100), NOfFrames(200)]);
+		let chunks: Vec<_> = received.iter().map(|(chunk, _)| chunk.as_slice()).collect();
+		assert_eq!(&chunks.concat(), signal.raw_buffer());
+
+		let (_, last) = received[2];
+		assert_eq!(
+			last.capture.duration_since(&received[0].1.capture),
+			Some(Duration::from_millis(200))
+		);
+		assert_eq!(
+			last.callback.duration_since(&last.capture),
+			Some(Duration::from_millis(50))
+		);
+	}
+
+	#[test]
+	fn follows_the_pace() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let start = Instant::now();
+		let stream = VirtualInputStream::new(
+			ramp(sampling_ctx, 200),
+			NOfFrames(50),
+			ReplayPace::Accelerated(2.),
+			Box::new(|_, _| {}),
+		);
+		stream.wait_until_finished();
+		assert!(start.elapsed() >= Duration::from_millis(100));
+	}
+
+	#[test]
+	fn stops_when_dropped() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let start = Instant::now();
+		let stream = VirtualInputStream::new(
+			ramp(sampling_ctx, 60_000),
+			NOfFrames(100),
+			ReplayPace::RealTime,
+			Box::new(|_, _| {}),
+		);
+		assert_eq!(stream.state(), AudioStreamSamplingState::Sampling);
+		drop(stream);
+		assert!(start.elapsed() < Duration::from_secs(5));
+	}
+
+	#[test]
+	fn decodes_wav_files() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let mut file = Cursor::new(vec![]);
+		let mut writer = WavWriter::new(&mut file, sampling_ctx).unwrap();
+		writer.write(&[0.5, -0.5, 1., -1.]).unwrap();
+		writer.finalize().unwrap();
+		let signal = parse_wav(&file.into_inner()).unwrap();
+		assert_eq!(signal.sampling_ctx(), sampling_ctx);
+		assert_eq!(signal.raw_buffer(), &[0.5, -0.5, 1., -1.]);
+
+		// 16-bit PCM, mono, 8 kHz, with an unknown chunk before the data.
+		let mut bytes = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+		bytes.extend(16u32.to_le_bytes());
+		for field in [WAVE_FORMAT_PCM, 1] {
+			bytes.extend(field.to_le_bytes());
+		}
+		bytes.extend(8000u32.to_le_bytes());
+		bytes.extend(16000u32.to_le_bytes());
+		for field in [2u16, 16] {
+			bytes.extend(field.to_le_bytes());
+		}
+		bytes.extend(b"LIST\x03\0\0\0abc\0");
+		bytes.extend(b"data\x06\0\0\0");
+		for sample in [i16::MIN, 0, 16384] {
+			bytes.extend(sample.to_le_bytes());
+		}
+		let signal = parse_wav(&bytes).unwrap();
+		assert_eq!(signal.sampling_ctx(), SamplingCtx::new(SampleRate(8000), 1));
+		assert_eq!(signal.raw_buffer(), &[-1., 0., 0.5]);
+
+		assert_eq!(
+			parse_wav(b"not a wav file").unwrap_err().kind(),
+			ErrorKind::InvalidData
+		);
+	}
+}
@@ -2,8 +2,14 @@
 
 pub mod buffers;
 
+pub mod graph;
+
 #[cfg(feature = "analysis")]
 pub mod analysis;
+#[cfg(any(feature = "input", feature = "output"))]
+pub mod backend;
+#[cfg(any(feature = "input", feature = "output"))]
+pub mod devices;
 #[cfg(feature = "input")]
 pub mod input;
 #[cfg(feature = "output")]
@@ -12,6 +18,42 @@ pub mod output;
 mod common;
 pub use common::*;
 
+#[cfg(feature = "audit")]
+pub mod audit;
+
+#[cfg(feature = "midi")]
+pub mod midi;
+
+#[cfg(any(feature = "input", feature = "output"))]
+mod clock;
+#[cfg(any(feature = "input", feature = "output"))]
+pub use clock::*;
+
+pub mod metering;
+
+mod drift;
+pub use drift::*;
+
+#[cfg(any(feature = "input", feature = "output"))]
+mod reconnect;
+#[cfg(any(feature = "input", feature = "output"))]
+pub use reconnect::*;
+
+#[cfg(any(feature = "input", feature = "output"))]
+mod stats;
+#[cfg(any(feature = "input", feature = "output"))]
+pub use stats::*;
+
+#[cfg(all(feature = "input", feature = "output"))]
+mod passthrough;
+#[cfg(all(feature = "input", feature = "output"))]
+pub use passthrough::*;
+
+#[cfg(all(feature = "input", feature = "output", feature = "analysis"))]
+mod round_trip;
+#[cfg(all(feature = "input", feature = "output", feature = "analysis"))]
+pub use round_trip::*;
+
 mod n_of_frames;
 pub use n_of_frames::*;
 
@@ -21,4 +63,7 @@ pub use sample_rate::*;
 mod sampling_ctx;
 pub use sampling_ctx::*;
 
+mod timed_frames;
+pub use timed_frames::*;
+
 pub use rustfft::num_complex;
@@ -0,0 +1,230 @@
+//! Level measurements shared by the meters and the dynamics processors of this crate.
+//!
+//! Levels are expressed as amplitudes relative to full scale (1) or in dBFS, where 0 dBFS
+//! is the level of a full-scale peak. Silence is -∞ dBFS.
+#![allow(non_snake_case)]
+
+use std::{collections::VecDeque, time::Duration};
+
+use crate::SampleRate;
+
+/// Convert an amplitude to dB, e.g. a sample or an RMS level to dBFS.
+#[must_use]
+pub fn amplitude_to_dB(amplitude: f32) -> f32 {
+	20. * amplitude.log10()
+}
+
+/// Convert a level in dB to an amplitude, e.g. a threshold in dBFS to the corresponding sample value.
+#[must_use]
+pub fn dB_to_amplitude(dB: f32) -> f32 {
+	10f32.powf(dB / 20.)
+}
+
+/// The largest absolute value of `signal`, 0 if empty.
+#[must_use]
+pub fn peak(signal: &[f32]) -> f32 {
+	signal
+		.iter()
+		.fold(0f32, |peak, sample| peak.max(sample.abs()))
+}
+
+/// The root mean square of `signal`, 0 if empty.
+#[must_use]
+pub fn rms(signal: &[f32]) -> f32 {
+	if signal.is_empty() {
+		return 0.;
+	}
+	#[allow(clippy::cast_precision_loss)] // REASON: the length only scales the sum
+	let mean_square = signal.iter().map(|sample| sample * sample).sum::<f32>() / signal.len() as f32;
+	mean_square.sqrt()
+}
+
+/// The peak and RMS level of a signal, as amplitudes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Levels {
+	pub peak: f32,
+	pub rms: f32,
+}
+
+impl Levels {
+	/// Measure the whole `signal`, see [`IntegratingMeter`] to measure a stream.
+	#[must_use]
+	pub fn of(signal: &[f32]) -> Self {
+		Self {
+			peak: peak(signal),
+			rms: rms(signal),
+		}
+	}
+
+	#[must_use]
+	pub fn peak_dB(&self) -> f32 {
+		amplitude_to_dB(self.peak)
+	}
+
+	#[must_use]
+	pub fn rms_dB(&self) -> f32 {
+		amplitude_to_dB(self.rms)
+	}
+
+	/// The ratio between the peak and the RMS level, e.g. √2 for a sinusoid and 1 for a square wave.
+	/// NaN for silence.
+	#[must_use]
+	pub fn crest_factor(&self) -> f32 {
+		self.peak / self.rms
+	}
+
+	/// The crest factor in dB, e.g. about 3 dB for a sinusoid.
+	#[must_use]
+	pub fn crest_factor_dB(&self) -> f32 {
+		amplitude_to_dB(self.crest_factor())
+	}
+}
+
+/// Measures the peak and RMS level of the latest samples of a stream, over a configurable
+/// integration time (a sliding window).
+///
+/// Unlike [`crate::analysis::EnvelopeFollower`], which smooths the level exponentially,
+/// every sample in the window weighs the same and the older ones are forgotten entirely,
+/// which matches the way peak-program and loudness meters integrate the signal.
+#[derive(Debug, Clone)]
+pub struct IntegratingMeter {
+	integration_time: Duration,
+	window_len: usize,
+	/// The squares of the samples in the window.
+	squares: VecDeque<f32>,
+	sum_of_squares: f64,
+	/// The candidates for the peak of the window, as (sample index, absolute value),
+	/// in increasing order of index and decreasing order of value.
+	peaks: VecDeque<(usize, f32)>,
+	sample_idx: usize,
+}
+
+impl IntegratingMeter {
+	/// The integration time is rounded to at least one sample.
+	#[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)] // REASON: windows are far below the precision limit
+	#[must_use]
+	pub fn new(sample_rate: SampleRate, integration_time: Duration) -> Self {
+		let window_len =
+			((integration_time.as_secs_f64() * sample_rate.0 as f64).round() as usize).max(1);
+		Self {
+			integration_time,
+			window_len,
+			squares: VecDeque::with_capacity(window_len),
+			sum_of_squares: 0.,
+			peaks: VecDeque::new(),
+			sample_idx: 0,
+		}
+	}
+
+	/// Feed the next sample, returning the updated levels.
+	pub fn process_sample(&mut self, sample: f32) -> Levels {
+		if self.squares.len() == self.window_len {
+			self.sum_of_squares -= self.squares.pop_front().map_or(0., f64::from);
+		}
+		let square = sample * sample;
+		self.squares.push_back(square);
+		self.sum_of_squares += f64::from(square);
+
+		let magnitude = sample.abs();
+		while self
+			.peaks
+			.back()
+			.is_some_and(|&(_, peak)| peak <= magnitude)
+		{
+			self.peaks.pop_back();
+		}
+		self.peaks.push_back((self.sample_idx, magnitude));
+		while self
+			.peaks
+			.front()
+			.is_some_and(|&(idx, _)| idx + self.window_len <= self.sample_idx)
+		{
+			self.peaks.pop_front();
+		}
+		self.sample_idx += 1;
+
+		self.levels()
+	}
+
+	/// Feed the next chunk of the signal, returning the updated levels.
+	pub fn process(&mut self, signal: &[f32]) -> Levels {
+		for &sample in signal {
+			self.process_sample(sample);
+		}
+		self.levels()
+	}
+
+	/// The levels of the samples in the window. Before the window fills up, the missing samples
+	/// count as silence.
+	#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)] // REASON: levels are f32 throughout the crate
+	#[must_use]
+	pub fn levels(&self) -> Levels {
+		let mean_square = (self.sum_of_squares.max(0.) / self.window_len as f64) as f32;
+		Levels {
+			peak: self.peaks.front().map_or(0., |&(_, peak)| peak),
+			rms: mean_square.sqrt(),
+		}
+	}
+
+	/// Bring the levels back to silence.
+	pub fn reset(&mut self) {
+		self.squares.clear();
+		self.sum_of_squares = 0.;
+		self.peaks.clear();
+		self.sample_idx = 0;
+	}
+
+	#[must_use]
+	pub fn integration_time(&self) -> Duration {
+		self.integration_time
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::{SQRT_2, TAU};
+
+	use super::*;
+
+	#[test]
+	fn conversions() {
+		assert!(amplitude_to_dB(1.).abs() < 1e-6);
+		assert!((amplitude_to_dB(0.5) + 6.02).abs() < 0.01);
+		assert!(amplitude_to_dB(0.).is_infinite());
+		assert!((dB_to_amplitude(-20.) - 0.1).abs() < 1e-6);
+		assert!((dB_to_amplitude(amplitude_to_dB(0.3)) - 0.3).abs() < 1e-6);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn levels_of_a_signal() {
+		let sine: Vec<f32> = (0..4800u16)
+			.map(|i| (TAU * 100. * f32::from(i) / 48000.).sin())
+			.collect();
+		let levels = Levels::of(&sine);
+		assert!((levels.peak - 1.).abs() < 1e-3, "{levels:?}");
+		assert!((levels.rms - 1. / SQRT_2).abs() < 1e-3, "{levels:?}");
+		assert!((levels.crest_factor_dB() - 3.01).abs() < 0.01, "{levels:?}");
+
+		let square = [0.5, -0.5, 0.5, -0.5];
+		assert_eq!(Levels::of(&square).crest_factor(), 1.);
+		assert_eq!(Levels::of(&[]), Levels::default());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn the_window_slides() {
+		// 4 samples at 1 kHz.
+		let mut meter = IntegratingMeter::new(SampleRate(1000), Duration::from_millis(4));
+		let levels = meter.process(&[1., -0.5]);
+		assert_eq!(levels.peak, 1.);
+		assert!((levels.rms - (1.25f32 / 4.).sqrt()).abs() < 1e-6);
+
+		let levels = meter.process(&[0.5, 0.5, 0.5]);
+		assert_eq!(levels.peak, 0.5);
+		assert!((levels.rms - 0.5).abs() < 1e-6);
+
+		meter.reset();
+		assert_eq!(meter.levels(), Levels::default());
+	}
+}
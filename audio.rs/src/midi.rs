@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use midir::{ConnectErrorKind, Ignore, InitError, MidiInputConnection, PortInfoError};
+use thiserror::Error;
+
+/// The name under which the crate registers itself to the MIDI host.
+const CLIENT_NAME: &str = "audio";
+
+/// The controller that sets the volume of a channel.
+pub const CC_VOLUME: u8 = 7;
+/// The controller that immediately silences all the notes of a channel.
+pub const CC_ALL_SOUND_OFF: u8 = 120;
+/// The controller that releases all the notes of a channel.
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// A channel voice message received from a MIDI input port.
+///
+/// Channels are numbered from 0 to 15.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MidiEvent {
+	NoteOn {
+		channel: u8,
+		note: u8,
+		velocity: u8,
+	},
+	NoteOff {
+		channel: u8,
+		note: u8,
+		velocity: u8,
+	},
+	ControlChange {
+		channel: u8,
+		controller: u8,
+		value: u8,
+	},
+}
+
+impl MidiEvent {
+	/// Parse a raw MIDI message, returning `None` for the messages that are not
+	/// note on, note off or control change.
+	///
+	/// A note on with a velocity of 0 is reported as a note off, as most controllers
+	/// use it that way.
+	#[must_use]
+	pub fn parse(message: &[u8]) -> Option<Self> {
+		let (&status, data) = message.split_first()?;
+		let channel = status & 0x0F;
+		match (status & 0xF0, data) {
+			(0x80, &[note, velocity, ..]) | (0x90, &[note, velocity @ 0, ..]) => {
+				Some(Self::NoteOff {
+					channel,
+					note,
+					velocity,
+				})
+			}
+			(0x90, &[note, velocity, ..]) => Some(Self::NoteOn {
+				channel,
+				note,
+				velocity,
+			}),
+			(0xB0, &[controller, value, ..]) => Some(Self::ControlChange {
+				channel,
+				controller,
+				value,
+			}),
+			_ => None,
+		}
+	}
+
+	#[must_use]
+	pub fn channel(&self) -> u8 {
+		match *self {
+			Self::NoteOn { channel, .. }
+			| Self::NoteOff { channel, .. }
+			| Self::ControlChange { channel, .. } => channel,
+		}
+	}
+}
+
+/// Receives the events of a [`MidiInput`], together with their timestamp, measured
+/// from an unspecified point in time chosen by the MIDI host.
+pub type OnMidiEventCallback = dyn FnMut(MidiEvent, Duration) + Send + 'static;
+
+#[derive(Error, Debug)]
+pub enum MidiError {
+	#[error("unable to initialize the MIDI host")]
+	InitFailed(#[source] InitError),
+	#[error("unable to query the MIDI input ports")]
+	UnableToListPorts(#[source] PortInfoError),
+	#[error("no MIDI input port found{}", port_name.as_ref().map(|name| format!(" with name \"{name}\"")).unwrap_or_default())]
+	NoPortFound { port_name: Option<String> },
+	#[error("unable to connect to MIDI input port \"{port_name}\"")]
+	ConnectFailed {
+		port_name: String,
+		#[source]
+		source: ConnectErrorKind,
+	},
+}
+
+/// List the names of the MIDI input ports, which can be passed as `port_name` to [`MidiInput::new`].
+///
+/// # Errors
+/// [`MidiError`]
+pub fn list_midi_input_ports() -> Result<Vec<String>, MidiError> {
+	let input = midir::MidiInput::new(CLIENT_NAME).map_err(MidiError::InitFailed)?;
+	input
+		.ports()
+		.iter()
+		.map(|port| input.port_name(port).map_err(MidiError::UnableToListPorts))
+		.collect()
+}
+
+/// A connection to a MIDI input port, which passes the note on, note off and control change
+/// messages to a callback (see [`MidiEvent`]), ignoring everything else.
+///
+/// The events can drive a `Synth` or an `Oscillator` through their `apply_midi_event` method,
+/// e.g. by sharing them with the callback behind a mutex.
+///
+/// The connection is closed when dropped.
+pub struct MidiInput {
+	port_name: String,
+	#[allow(dead_code)] // REASON: only held for its Drop implementation
+	connection: MidiInputConnection<()>,
+}
+
+impl MidiInput {
+	/// Connect to the MIDI input port with the given name, or to the first available one
+	/// if `port_name` is `None`.
+	///
+	/// # Errors
+	/// [`MidiError`]
+	pub fn new(
+		port_name: Option<&str>,
+		mut on_event: Box<OnMidiEventCallback>,
+	) -> Result<Self, MidiError> {
+		let mut input = midir::MidiInput::new(CLIENT_NAME).map_err(MidiError::InitFailed)?;
+		input.ignore(Ignore::All);
+
+		let mut selected = None;
+		for port in input.ports() {
+			let name = input
+				.port_name(&port)
+				.map_err(MidiError::UnableToListPorts)?;
+			if port_name.is_none_or(|port_name| port_name == name) {
+				selected = Some((port, name));
+				break;
+			}
+		}
+		let (port, name) = selected.ok_or_else(|| MidiError::NoPortFound {
+			port_name: port_name.map(ToOwned::to_owned),
+		})?;
+
+		let connection = input
+			.connect(
+				&port,
+				CLIENT_NAME,
+				move |timestamp, message, ()| {
+					if let Some(event) = MidiEvent::parse(message) {
+						on_event(event, Duration::from_micros(timestamp));
+					}
+				},
+				(),
+			)
+			.map_err(|err| MidiError::ConnectFailed {
+				port_name: name.clone(),
+				source: err.kind(),
+			})?;
+
+		Ok(Self {
+			port_name: name,
+			connection,
+		})
+	}
+
+	/// The name of the connected port.
+	#[must_use]
+	pub fn port_name(&self) -> &str {
+		&self.port_name
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse() {
+		assert_eq!(
+			MidiEvent::parse(&[0x91, 60, 100]),
+			Some(MidiEvent::NoteOn {
+				channel: 1,
+				note: 60,
+				velocity: 100
+			})
+		);
+		assert_eq!(
+			MidiEvent::parse(&[0x80, 60, 64]),
+			Some(MidiEvent::NoteOff {
+				channel: 0,
+				note: 60,
+				velocity: 64
+			})
+		);
+		assert_eq!(
+			MidiEvent::parse(&[0x9F, 60, 0]),
+			Some(MidiEvent::NoteOff {
+				channel: 15,
+				note: 60,
+				velocity: 0
+			})
+		);
+		assert_eq!(
+			MidiEvent::parse(&[0xB2, CC_VOLUME, 90]),
+			Some(MidiEvent::ControlChange {
+				channel: 2,
+				controller: CC_VOLUME,
+				value: 90
+			})
+		);
+		// Pitch bend, truncated messages and system messages are ignored.
+		assert_eq!(MidiEvent::parse(&[0xE0, 0, 64]), None);
+		assert_eq!(MidiEvent::parse(&[0x90, 60]), None);
+		assert_eq!(MidiEvent::parse(&[0xF8]), None);
+		assert_eq!(MidiEvent::parse(&[]), None);
+	}
+
+	#[test]
+	#[ignore = "manually play some notes on the first MIDI controller"]
+	fn test_manual() {
+		println!("{:?}", list_midi_input_ports().unwrap());
+		let _input = MidiInput::new(
+			None,
+			Box::new(|event, timestamp| println!("{timestamp:?} {event:?}")),
+		)
+		.unwrap();
+		std::thread::sleep(Duration::from_secs(10));
+	}
+}
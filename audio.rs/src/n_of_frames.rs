@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, iter::Sum, ops::Range};
 
 use derive_more::derive::{
 	Add, AddAssign, Div, DivAssign, Mul, MulAssign, Rem, RemAssign, Sub, SubAssign,
@@ -27,6 +27,16 @@ use derive_more::derive::{
 )]
 pub struct NOfFrames(pub usize);
 
+impl NOfFrames {
+	/// Iterate over the frame indices in `range`, as a stand-in for ranges of `NOfFrames`
+	/// (which would require the unstable `Step` trait).
+	pub fn iter(
+		range: Range<NOfFrames>,
+	) -> impl DoubleEndedIterator<Item = NOfFrames> + ExactSizeIterator {
+		(range.start.0..range.end.0).map(NOfFrames)
+	}
+}
+
 impl Display for NOfFrames {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		Display::fmt(&self.0, f)
@@ -44,3 +54,31 @@ impl From<NOfFrames> for usize {
 		value.0
 	}
 }
+
+impl Sum for NOfFrames {
+	fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+		Self(iter.map(|n_of_frames| n_of_frames.0).sum())
+	}
+}
+
+impl<'a> Sum<&'a NOfFrames> for NOfFrames {
+	fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+		iter.copied().sum()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sum_and_iter() {
+		let chunks = [NOfFrames(128), NOfFrames(64), NOfFrames(32)];
+		assert_eq!(chunks.iter().sum::<NOfFrames>(), NOfFrames(224));
+		assert_eq!(chunks.into_iter().sum::<NOfFrames>(), NOfFrames(224));
+
+		let frames: Vec<_> = NOfFrames::iter(NOfFrames(2)..NOfFrames(5)).rev().collect();
+		assert_eq!(frames, [NOfFrames(4), NOfFrames(3), NOfFrames(2)]);
+		assert_eq!(NOfFrames::iter(NOfFrames(5)..NOfFrames(2)).len(), 0);
+	}
+}
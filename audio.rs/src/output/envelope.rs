@@ -0,0 +1,237 @@
+#![allow(clippy::cast_precision_loss)]
+
+use std::time::Duration;
+
+use crate::{buffers::InterleavedAudioBuffer, NOfFrames, SampleRate, SamplingCtx};
+
+/// The phase of an [`Envelope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeStage {
+	/// No note is being played, the gain is 0.
+	Idle,
+	/// The gain is rising to 1 after [`Envelope::note_on`].
+	Attack,
+	/// The gain is falling from 1 to the sustain level.
+	Decay,
+	/// The gain is held at the sustain level until [`Envelope::note_off`].
+	Sustain,
+	/// The gain is falling to 0 after [`Envelope::note_off`].
+	Release,
+}
+
+/// An ADSR (attack, decay, sustain, release) envelope generator, which shapes the amplitude
+/// of a signal like a musical note.
+///
+/// [`Self::note_on`] starts the attack, in which the gain rises linearly from its current value
+/// to 1, followed by the decay to the sustain level, where it stays until [`Self::note_off`]
+/// starts the release back to 0. All the transitions are linear.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+	sample_rate: SampleRate,
+	attack: NOfFrames,
+	decay: NOfFrames,
+	sustain: f32,
+	release: NOfFrames,
+	stage: EnvelopeStage,
+	gain: f32,
+	/// The change of the gain in each frame of the current stage.
+	step: f32,
+}
+
+impl Envelope {
+	/// Create an idle envelope with the given durations and sustain level.
+	///
+	/// # Panics
+	/// - if `sustain` is not in the range [0, 1].
+	#[must_use]
+	pub fn new(
+		sample_rate: SampleRate,
+		attack: Duration,
+		decay: Duration,
+		sustain: f32,
+		release: Duration,
+	) -> Self {
+		assert!(
+			(0. ..=1.).contains(&sustain),
+			"the sustain level must be between 0 and 1"
+		);
+		let sampling_ctx = SamplingCtx::new(sample_rate, 1);
+		Self {
+			sample_rate,
+			attack: sampling_ctx.duration_to_frames(attack),
+			decay: sampling_ctx.duration_to_frames(decay),
+			sustain,
+			release: sampling_ctx.duration_to_frames(release),
+			stage: EnvelopeStage::Idle,
+			gain: 0.,
+			step: 0.,
+		}
+	}
+
+	/// Start (or restart) the attack, from the current gain.
+	pub fn note_on(&mut self) {
+		self.step = 1. / self.attack.0.max(1) as f32;
+		self.stage = EnvelopeStage::Attack;
+	}
+
+	/// Start the release, from the current gain. Has no effect if the envelope is idle.
+	pub fn note_off(&mut self) {
+		if self.stage == EnvelopeStage::Idle {
+			return;
+		}
+		self.step = -self.gain / self.release.0.max(1) as f32;
+		self.stage = EnvelopeStage::Release;
+	}
+
+	/// The gain of the next frame, advancing the envelope by one frame.
+	pub fn next_gain(&mut self) -> f32 {
+		match self.stage {
+			EnvelopeStage::Idle | EnvelopeStage::Sustain => {}
+			EnvelopeStage::Attack => {
+				self.gain += self.step;
+				if self.gain >= 1. {
+					self.gain = 1.;
+					self.step = (self.sustain - 1.) / self.decay.0.max(1) as f32;
+					self.stage = EnvelopeStage::Decay;
+				}
+			}
+			EnvelopeStage::Decay => {
+				self.gain += self.step;
+				if self.gain <= self.sustain {
+					self.gain = self.sustain;
+					self.stage = EnvelopeStage::Sustain;
+				}
+			}
+			EnvelopeStage::Release => {
+				self.gain += self.step;
+				if self.gain <= 0. {
+					self.gain = 0.;
+					self.stage = EnvelopeStage::Idle;
+				}
+			}
+		}
+		self.gain
+	}
+
+	/// Multiply each frame of `chunk` by the gain of the envelope, advancing it accordingly.
+	pub fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		for mut frame in chunk.iter_mut() {
+			let gain = self.next_gain();
+			for sample in frame.samples_mut() {
+				*sample *= gain;
+			}
+		}
+	}
+
+	#[must_use]
+	pub fn stage(&self) -> EnvelopeStage {
+		self.stage
+	}
+
+	/// Whether a note is being played, i.e. the envelope is not [`EnvelopeStage::Idle`].
+	#[must_use]
+	pub fn is_active(&self) -> bool {
+		self.stage != EnvelopeStage::Idle
+	}
+
+	/// The gain of the last frame.
+	#[must_use]
+	pub fn gain(&self) -> f32 {
+		self.gain
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	#[must_use]
+	pub fn attack(&self) -> Duration {
+		self.frames_to_duration(self.attack)
+	}
+
+	#[must_use]
+	pub fn decay(&self) -> Duration {
+		self.frames_to_duration(self.decay)
+	}
+
+	#[must_use]
+	pub fn sustain(&self) -> f32 {
+		self.sustain
+	}
+
+	#[must_use]
+	pub fn release(&self) -> Duration {
+		self.frames_to_duration(self.release)
+	}
+
+	fn frames_to_duration(&self, n_of_frames: NOfFrames) -> Duration {
+		SamplingCtx::new(self.sample_rate, 1).frames_to_duration(n_of_frames)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn gains(envelope: &mut Envelope, n: usize) -> Vec<f32> {
+		(0..n).map(|_| envelope.next_gain()).collect()
+	}
+
+	fn assert_close(actual: &[f32], expected: &[f32]) {
+		assert_eq!(actual.len(), expected.len());
+		for (a, e) in actual.iter().zip(expected) {
+			assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+		}
+	}
+
+	#[test]
+	fn adsr_stages() {
+		// 4 frames of attack, 2 of decay and 4 of release.
+		let mut envelope = Envelope::new(
+			SampleRate(1000),
+			Duration::from_millis(4),
+			Duration::from_millis(2),
+			0.5,
+			Duration::from_millis(4),
+		);
+
+		assert_close(&gains(&mut envelope, 2), &[0., 0.]);
+		envelope.note_on();
+		assert_close(
+			&gains(&mut envelope, 8),
+			&[0.25, 0.5, 0.75, 1., 0.75, 0.5, 0.5, 0.5],
+		);
+		assert_eq!(envelope.stage(), EnvelopeStage::Sustain);
+
+		envelope.note_off();
+		assert_close(&gains(&mut envelope, 5), &[0.375, 0.25, 0.125, 0., 0.]);
+		assert!(!envelope.is_active());
+	}
+
+	#[test]
+	fn retriggered_notes_start_from_the_current_gain() {
+		let mut envelope = Envelope::new(
+			SampleRate(1000),
+			Duration::from_millis(4),
+			Duration::ZERO,
+			1.,
+			Duration::from_millis(4),
+		);
+		envelope.note_on();
+		assert_close(&gains(&mut envelope, 2), &[0.25, 0.5]);
+		envelope.note_off();
+		assert_close(&gains(&mut envelope, 1), &[0.375]);
+		envelope.note_on();
+		assert_close(&gains(&mut envelope, 1), &[0.625]);
+
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let mut output = vec![1.; 6];
+		envelope.process(&mut InterleavedAudioBuffer::new(
+			sampling_ctx,
+			&mut output[..],
+		));
+		assert_close(&output, &[0.875, 0.875, 1., 1., 1., 1.]);
+		assert_eq!(envelope.stage(), EnvelopeStage::Sustain);
+	}
+}
@@ -0,0 +1,460 @@
+use std::{
+	fs::File,
+	io::{self, BufReader},
+	mem,
+	path::Path,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+		Arc,
+	},
+	thread::{self, sleep, JoinHandle},
+	time::Duration,
+};
+
+use lewton::{inside_ogg::OggStreamReader, samples::InterleavedSamples, VorbisError};
+use mutex_ext::{CondvarExt, ReactiveCondvar};
+
+use crate::{
+	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames,
+	SampleRate, SamplingCtx, SamplingCtxError, StreamOptions,
+};
+
+use super::{DataProducer, OutputStream};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FileSourceError {
+	#[error("unable to open the file")]
+	Io(#[from] io::Error),
+	#[error("unable to decode the Ogg/Vorbis stream")]
+	Decode(#[from] VorbisError),
+	#[error("invalid Ogg/Vorbis stream info")]
+	InvalidStreamInfo(#[from] SamplingCtxError),
+	#[error(transparent)]
+	Stream(#[from] AudioStreamBuilderError),
+}
+
+/// Plays an Ogg/Vorbis file without loading it into memory.
+///
+/// The file is decoded a packet at a time by a worker thread, which passes the packets
+/// to the output stream through a bounded queue, so that the audio callbacks never wait
+/// for the disk or the decoder. If the decoder can't keep up and the queue runs empty,
+/// silence is played and the event is counted by [`Self::underruns`].
+///
+/// The stream uses the sample rate and the number of channels of the file.
+pub struct FileSource {
+	/// Whether the end of the file has been played.
+	finished: ReactiveCondvar<bool>,
+	underruns: Arc<AtomicUsize>,
+	/// Taken before joining the decoder, see [`Self::stop`].
+	base_stream: Option<OutputStream>,
+	decoder_thread: Option<JoinHandle<Result<(), VorbisError>>>,
+	sampling_ctx: SamplingCtx,
+}
+
+impl FileSource {
+	/// Open the file at `path` and start playing it. `queue_len` is the maximum number
+	/// of decoded packets waiting to be played.
+	///
+	/// # Errors
+	/// [`FileSourceError`]
+	pub fn new(
+		path: impl AsRef<Path>,
+		device_name: Option<&str>,
+		queue_len: usize,
+	) -> Result<Self, FileSourceError> {
+		Self::new_with_options(path, device_name, queue_len, StreamOptions::default())
+	}
+
+	/// Open the file at `path` and start playing it, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`FileSourceError`]
+	pub fn new_with_options(
+		path: impl AsRef<Path>,
+		device_name: Option<&str>,
+		queue_len: usize,
+		options: StreamOptions,
+	) -> Result<Self, FileSourceError> {
+		Self::build(
+			path.as_ref(),
+			queue_len,
+			false,
+			|sampling_ctx, data_producer| {
+				OutputStream::new_with_options(
+					sampling_ctx,
+					device_name,
+					data_producer,
+					None,
+					options,
+				)
+			},
+		)
+	}
+
+	/// Open the file at `path` for offline rendering (see [`OutputStream::new_offline`]).
+	///
+	/// Rendering waits for the decoder instead of playing silence, so offline sources never underrun.
+	///
+	/// # Errors
+	/// [`FileSourceError`]
+	pub fn new_offline(path: impl AsRef<Path>, queue_len: usize) -> Result<Self, FileSourceError> {
+		Self::build(
+			path.as_ref(),
+			queue_len,
+			true,
+			|sampling_ctx, data_producer| {
+				Ok::<_, AudioStreamBuilderError>(OutputStream::new_offline(
+					sampling_ctx,
+					data_producer,
+				))
+			},
+		)
+	}
+
+	fn build(
+		path: &Path,
+		queue_len: usize,
+		blocking: bool,
+		base_stream: impl FnOnce(
+			SamplingCtx,
+			Box<DataProducer>,
+		) -> Result<OutputStream, AudioStreamBuilderError>,
+	) -> Result<Self, FileSourceError> {
+		let mut reader = OggStreamReader::new(BufReader::new(File::open(path)?))?;
+		let sampling_ctx = SamplingCtx::try_new(
+			SampleRate(reader.ident_hdr.audio_sample_rate as usize),
+			usize::from(reader.ident_hdr.audio_channels),
+		)?;
+
+		let (sender, receiver) = mpsc::sync_channel(queue_len);
+		let (recycle_sender, recycled) = mpsc::channel();
+		let decoder_thread =
+			thread::spawn(move || decode(&mut reader, sampling_ctx.n_ch(), &sender, &recycled));
+
+		let finished = ReactiveCondvar::new(false);
+		let underruns = Arc::new(AtomicUsize::new(0));
+		let mut queue = PacketQueue {
+			receiver,
+			recycle_sender,
+			current: Vec::new(),
+			position: 0,
+			blocking,
+			started: false,
+			finished: false,
+			underruns: underruns.clone(),
+		};
+		let base_stream = base_stream(
+			sampling_ctx,
+			Box::new({
+				let finished = finished.clone();
+				move |mut chunk| {
+					if queue.fill(chunk.raw_buffer_mut()) {
+						finished.with_lock_mut(|finished| *finished = true);
+						finished.notify_all();
+					}
+				}
+			}),
+		)?;
+
+		Ok(Self {
+			finished,
+			underruns,
+			base_stream: Some(base_stream),
+			decoder_thread: Some(decoder_thread),
+			sampling_ctx,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream().state()
+	}
+
+	/// Whether the whole file has been passed to the device, or its decoding failed.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_finished(&self) -> bool {
+		self.finished.with_lock(|finished| *finished)
+	}
+
+	/// Block until the whole file has been played, or its decoding failed.
+	///
+	/// Note: the wait time is based on when the file is exhausted and an estimate on when the output
+	/// device should play its last samples.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn wait(&self) {
+		self.finished.wait_while(|finished| !*finished);
+		sleep(self.avg_output_delay());
+	}
+
+	/// Stop the playback and close the file.
+	///
+	/// # Errors
+	/// The error that stopped the decoder, if any.
+	pub fn finish(mut self) -> Result<(), FileSourceError> {
+		self.stop()
+	}
+
+	/// The number of times the output didn't find any decoded packet in the queue, and played silence instead.
+	#[must_use]
+	pub fn underruns(&self) -> usize {
+		self.underruns.load(Ordering::Relaxed)
+	}
+
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the source is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream().render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the source is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the file.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream().render_into(output);
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sampling_ctx.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.sampling_ctx.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.base_stream().avg_output_delay()
+	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream().volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream().set_volume(volume);
+	}
+
+	fn base_stream(&self) -> &OutputStream {
+		// Only taken when the source is finished or dropped.
+		self.base_stream.as_ref().unwrap()
+	}
+
+	fn stop(&mut self) -> Result<(), FileSourceError> {
+		// Dropping the stream closes the queue, which stops the decoder.
+		self.base_stream.take();
+		match self.decoder_thread.take().map(JoinHandle::join) {
+			Some(Ok(result)) => Ok(result?),
+			Some(Err(_)) => Err(io::Error::other("the decoder thread panicked").into()),
+			None => Ok(()),
+		}
+	}
+}
+
+impl Drop for FileSource {
+	fn drop(&mut self) {
+		let _ = self.stop();
+	}
+}
+
+/// Decode the packets of `reader` until the end of the stream or until the queue is closed,
+/// reusing the buffers of the played packets.
+fn decode(
+	reader: &mut OggStreamReader<BufReader<File>>,
+	n_ch: usize,
+	sender: &SyncSender<Vec<f32>>,
+	recycled: &Receiver<Vec<f32>>,
+) -> Result<(), VorbisError> {
+	while let Some(packet) = reader.read_dec_packet_generic::<InterleavedSamples<f32>>()? {
+		// Chained streams may change the number of channels, which can't be played on the same stream.
+		if packet.channel_count != n_ch {
+			#[cfg(feature = "tracing")]
+			tracing::warn!(
+				channels = packet.channel_count,
+				"Ogg/Vorbis stream with a different number of channels, playback stopped"
+			);
+			break;
+		}
+		let mut buffer = recycled.try_recv().unwrap_or_default();
+		buffer.clear();
+		buffer.extend_from_slice(&packet.samples);
+		if sender.send(buffer).is_err() {
+			break;
+		}
+	}
+	Ok(())
+}
+
+/// The consumer side of the queue of decoded packets, owned by the audio callback.
+struct PacketQueue {
+	receiver: Receiver<Vec<f32>>,
+	recycle_sender: Sender<Vec<f32>>,
+	/// The packet being played.
+	current: Vec<f32>,
+	/// The index of the next sample of `current`.
+	position: usize,
+	/// Whether to wait for the decoder when the queue is empty, instead of playing silence.
+	blocking: bool,
+	/// Whether the first packet has been received, before which an empty queue is not an underrun.
+	started: bool,
+	finished: bool,
+	underruns: Arc<AtomicUsize>,
+}
+
+impl PacketQueue {
+	/// Fill `output` with the next decoded samples, and with silence when they are not available,
+	/// returning whether the end of the file has just been reached.
+	fn fill(&mut self, mut output: &mut [f32]) -> bool {
+		while !output.is_empty() && !self.finished {
+			if self.position == self.current.len() {
+				let next = if self.blocking {
+					self.receiver.recv().map_err(|_| TryRecvError::Disconnected)
+				} else {
+					self.receiver.try_recv()
+				};
+				match next {
+					Ok(packet) => {
+						let played = mem::replace(&mut self.current, packet);
+						if played.capacity() > 0 {
+							let _ = self.recycle_sender.send(played);
+						}
+						self.position = 0;
+						self.started = true;
+					}
+					Err(TryRecvError::Empty) => {
+						if self.started {
+							self.underruns.fetch_add(1, Ordering::Relaxed);
+						}
+						break;
+					}
+					Err(TryRecvError::Disconnected) => {
+						self.finished = true;
+						output.fill(0.);
+						return true;
+					}
+				}
+				continue;
+			}
+
+			let remaining = &self.current[self.position..];
+			let n = remaining.len().min(output.len());
+			output[..n].copy_from_slice(&remaining[..n]);
+			output = &mut output[n..];
+			self.position += n;
+		}
+		output.fill(0.);
+		false
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn queue(blocking: bool) -> (PacketQueue, SyncSender<Vec<f32>>, Receiver<Vec<f32>>) {
+		let (sender, receiver) = mpsc::sync_channel(4);
+		let (recycle_sender, recycled) = mpsc::channel();
+		(
+			PacketQueue {
+				receiver,
+				recycle_sender,
+				current: Vec::new(),
+				position: 0,
+				blocking,
+				started: false,
+				finished: false,
+				underruns: Arc::new(AtomicUsize::new(0)),
+			},
+			sender,
+			recycled,
+		)
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn packets_are_played_back_to_back() {
+		let (mut queue, sender, recycled) = queue(false);
+
+		let mut output = [1.; 2];
+		assert!(!queue.fill(&mut output));
+		assert_eq!(output, [0.; 2]);
+		assert_eq!(queue.underruns.load(Ordering::Relaxed), 0);
+
+		sender.send(vec![1., 2., 3.]).unwrap();
+		sender.send(vec![4.]).unwrap();
+		let mut output = [0.; 5];
+		assert!(!queue.fill(&mut output));
+		assert_eq!(output, [1., 2., 3., 4., 0.]);
+		assert_eq!(queue.underruns.load(Ordering::Relaxed), 1);
+		assert_eq!(recycled.try_recv(), Ok(vec![1., 2., 3.]));
+
+		sender.send(vec![5.]).unwrap();
+		drop(sender);
+		let mut output = [1.; 3];
+		assert!(queue.fill(&mut output));
+		assert_eq!(output, [5., 0., 0.]);
+		assert!(!queue.fill(&mut output));
+		assert_eq!(output, [0.; 3]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn blocking_queue_waits_for_the_decoder() {
+		let (mut queue, sender, _recycled) = queue(true);
+		let decoder = thread::spawn(move || {
+			for i in 0..4u8 {
+				sleep(Duration::from_millis(5));
+				sender.send(vec![f32::from(i); 2]).unwrap();
+			}
+		});
+
+		let mut output = [0.; 6];
+		assert!(!queue.fill(&mut output));
+		assert_eq!(output, [0., 0., 1., 1., 2., 2.]);
+		let mut output = [1.; 4];
+		assert!(queue.fill(&mut output));
+		assert_eq!(output, [3., 3., 0., 0.]);
+		assert_eq!(queue.underruns.load(Ordering::Relaxed), 0);
+		decoder.join().unwrap();
+	}
+
+	#[test]
+	fn invalid_file() {
+		let path = std::env::temp_dir().join("audio-file-source-invalid.ogg");
+		std::fs::write(&path, b"RIFF\0\0\0\0WAVE").unwrap();
+		assert!(matches!(
+			FileSource::new_offline(&path, 4),
+			Err(FileSourceError::Decode(_))
+		));
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	#[ignore = "manually listen to the Ogg/Vorbis file in the AUDIO_OGG_FILE environment variable"]
+	fn test_manual() {
+		let source = FileSource::new(std::env::var("AUDIO_OGG_FILE").unwrap(), None, 32).unwrap();
+		source.wait();
+		println!("underruns: {}", source.underruns());
+		source.finish().unwrap();
+	}
+}
@@ -0,0 +1,240 @@
+use std::{
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use crate::{NOfFrames, SamplingCtx};
+
+/// The time it takes for the gain to go from 0 to 1, short enough to feel immediate
+/// but long enough to avoid clicks.
+const GAIN_RAMP: Duration = Duration::from_millis(20);
+
+/// The default duration of the crossfade between the old and the new content of a player.
+pub(super) const DEFAULT_CROSSFADE: Duration = Duration::from_millis(10);
+
+/// The master volume and per-channel gains a [`GainStage`] moves towards.
+///
+/// They are stored as the bits of `f32`s, so that they can be changed from any thread
+/// without ever holding up the audio callback. Each gain is replaced atomically: a chunk
+/// processed while [`Self::set_channel_gains`] is running may see only some of the new gains,
+/// which the ramp of the stage smooths over anyway.
+#[derive(Debug)]
+pub(super) struct GainTargets {
+	volume: AtomicU32,
+	channel_gains: Box<[AtomicU32]>,
+}
+
+impl GainTargets {
+	fn new(n_ch: usize) -> Self {
+		Self {
+			volume: AtomicU32::new(1f32.to_bits()),
+			channel_gains: (0..n_ch).map(|_| AtomicU32::new(1f32.to_bits())).collect(),
+		}
+	}
+
+	pub(super) fn volume(&self) -> f32 {
+		f32::from_bits(self.volume.load(Ordering::Relaxed))
+	}
+
+	pub(super) fn set_volume(&self, volume: f32) {
+		self.volume.store(volume.to_bits(), Ordering::Relaxed);
+	}
+
+	pub(super) fn channel_gains(&self) -> Vec<f32> {
+		self.channel_gains
+			.iter()
+			.map(|gain| f32::from_bits(gain.load(Ordering::Relaxed)))
+			.collect()
+	}
+
+	pub(super) fn set_channel_gains(&self, gains: &[f32]) {
+		for (target, gain) in self.channel_gains.iter().zip(gains) {
+			target.store(gain.to_bits(), Ordering::Relaxed);
+		}
+	}
+}
+
+/// The master volume and per-channel gains applied by an [`super::OutputStream`].
+///
+/// Changes are not applied abruptly: the gain of each channel moves towards its target
+/// (see [`GainTargets`]) at a limited rate, see `GAIN_RAMP`.
+#[derive(Debug)]
+pub(super) struct GainStage {
+	targets: Arc<GainTargets>,
+	/// The target of each channel, read from [`Self::targets`] at the start of every chunk.
+	target: Vec<f32>,
+	/// The gain currently applied to each channel.
+	current: Vec<f32>,
+	/// The maximum change of the gain from one frame to the next.
+	max_step: f32,
+}
+
+impl GainStage {
+	pub(super) fn new(sampling_ctx: SamplingCtx) -> Self {
+		#[allow(clippy::cast_precision_loss)]
+		let ramp_frames = sampling_ctx.duration_to_frames(GAIN_RAMP).0.max(1) as f32;
+		Self {
+			targets: Arc::new(GainTargets::new(sampling_ctx.n_ch())),
+			target: vec![1.; sampling_ctx.n_ch()],
+			current: vec![1.; sampling_ctx.n_ch()],
+			max_step: 1. / ramp_frames,
+		}
+	}
+
+	/// The targets of the stage, which can be shared with the threads that change them.
+	pub(super) fn targets(&self) -> &Arc<GainTargets> {
+		&self.targets
+	}
+
+	pub(super) fn set_volume(&self, volume: f32) {
+		self.targets.set_volume(volume);
+	}
+
+	pub(super) fn set_channel_gains(&self, gains: &[f32]) {
+		self.targets.set_channel_gains(gains);
+	}
+
+	/// Apply the gains to an interleaved chunk, in place.
+	#[allow(clippy::float_cmp)] // REASON: the ramp ends by assigning the exact target
+	pub(super) fn process(&mut self, output: &mut [f32]) {
+		let volume = self.targets.volume();
+		for (target, gain) in self.target.iter_mut().zip(&self.targets.channel_gains) {
+			*target = volume * f32::from_bits(gain.load(Ordering::Relaxed));
+		}
+
+		let n_ch = self.current.len();
+		if self.current == self.target {
+			if self.current.iter().any(|&current| current != 1.) {
+				for frame in output.chunks_exact_mut(n_ch) {
+					for (sample, current) in frame.iter_mut().zip(&self.current) {
+						*sample *= current;
+					}
+				}
+			}
+			return;
+		}
+
+		for frame in output.chunks_exact_mut(n_ch) {
+			for ((sample, current), target) in
+				frame.iter_mut().zip(&mut self.current).zip(&self.target)
+			{
+				let delta = target - *current;
+				*current = if delta.abs() <= self.max_step {
+					*target
+				} else {
+					*current + self.max_step.copysign(delta)
+				};
+				*sample *= *current;
+			}
+		}
+	}
+}
+
+/// A linear transition from an old to a new signal, lasting a given number of frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Crossfade {
+	len: NOfFrames,
+	position: NOfFrames,
+}
+
+impl Crossfade {
+	/// An inactive crossfade, see [`Self::start`].
+	pub(super) fn new(len: NOfFrames) -> Self {
+		Self { len, position: len }
+	}
+
+	pub(super) fn len(self) -> NOfFrames {
+		self.len
+	}
+
+	/// Change the length of the next transitions, terminating the current one.
+	pub(super) fn set_len(&mut self, len: NOfFrames) {
+		*self = Self::new(len);
+	}
+
+	pub(super) fn start(&mut self) {
+		self.position = NOfFrames(0);
+	}
+
+	pub(super) fn is_active(self) -> bool {
+		self.position < self.len
+	}
+
+	/// The weight of the new signal in the next frame, from 0 (excluded) to 1.
+	pub(super) fn next_weight(&mut self) -> f32 {
+		if !self.is_active() {
+			return 1.;
+		}
+		self.position += NOfFrames(1);
+		#[allow(clippy::cast_precision_loss)]
+		let weight = self.position.0 as f32 / self.len.0 as f32;
+		weight
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::SampleRate;
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn gain_changes_are_ramped() {
+		// 20ms at 1 kHz, i.e. a step of 0.05 per frame.
+		let mut gain_stage = GainStage::new(SamplingCtx::new(SampleRate(1000), 2));
+
+		let mut output = [1.; 4];
+		gain_stage.process(&mut output);
+		assert_eq!(output, [1.; 4]);
+
+		gain_stage.set_volume(0.5);
+		gain_stage.set_channel_gains(&[1., 0.]);
+		let mut output = [1.; 2 * 30];
+		gain_stage.process(&mut output);
+		assert!((output[0] - 0.95).abs() < 1e-6, "{}", output[0]);
+		assert!((output[1] - 0.95).abs() < 1e-6, "{}", output[1]);
+		for frame in output.chunks_exact(2).skip(1) {
+			assert!(frame[0] <= 0.95 && frame[0] >= 0.5);
+		}
+		assert!((output[2 * 29] - 0.5).abs() < 1e-6);
+		assert!(output[2 * 29 + 1].abs() < 1e-6);
+
+		let mut output = [1.; 4];
+		gain_stage.process(&mut output);
+		assert!((output[0] - 0.5).abs() < 1e-6);
+		assert!(output[1].abs() < 1e-6);
+
+		// The targets can be changed through a shared handle, e.g. from another thread.
+		let targets = gain_stage.targets().clone();
+		std::thread::spawn(move || targets.set_channel_gains(&[1., 1.]))
+			.join()
+			.unwrap();
+		assert_eq!(gain_stage.targets().channel_gains(), [1., 1.]);
+		assert_eq!(gain_stage.targets().volume(), 0.5);
+		let mut output = [1.; 2 * 30];
+		gain_stage.process(&mut output);
+		assert!((output[2 * 29] - 0.5).abs() < 1e-6);
+		assert!((output[2 * 29 + 1] - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn crossfade_weights() {
+		let mut crossfade = Crossfade::new(NOfFrames(4));
+		assert!(!crossfade.is_active());
+		assert_eq!(crossfade.next_weight(), 1.);
+
+		crossfade.start();
+		let weights: Vec<_> = (0..5).map(|_| crossfade.next_weight()).collect();
+		assert_eq!(weights, [0.25, 0.5, 0.75, 1., 1.]);
+		assert!(!crossfade.is_active());
+
+		crossfade.set_len(NOfFrames(0));
+		crossfade.start();
+		assert!(!crossfade.is_active());
+	}
+}
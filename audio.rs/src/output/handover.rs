@@ -0,0 +1,97 @@
+use std::{mem, sync::Mutex};
+
+use mutex_ext::LockExt;
+
+/// Hands values over to an audio callback without ever making it wait.
+///
+/// [`Self::send`] stores the value in a slot, which the callback polls with [`Self::poll`]:
+/// if the slot is busy, the value is picked up by the next callback. The value it replaces
+/// is moved back to the slot and dropped by the next [`Self::send`], rather than inside
+/// the callback, where freeing memory or joining threads is not realtime-safe.
+pub(super) struct Handover<T> {
+	slot: Mutex<Slot<T>>,
+}
+
+enum Slot<T> {
+	Empty,
+	/// Sent, but not yet picked up by the callback.
+	Pending(T),
+	/// Replaced by the last value picked up by the callback.
+	Replaced(T),
+}
+
+impl<T> Handover<T> {
+	pub(super) fn new() -> Self {
+		Self {
+			slot: Mutex::new(Slot::Empty),
+		}
+	}
+
+	/// Send `value` to the callback, returning the value it last replaced, or the one sent before
+	/// that it hasn't picked up yet, so that it's dropped by the caller.
+	pub(super) fn send(&self, value: T) -> Option<T> {
+		match self
+			.slot
+			.with_lock_mut(|slot| mem::replace(slot, Slot::Pending(value)))
+		{
+			Slot::Empty => None,
+			Slot::Pending(value) | Slot::Replaced(value) => Some(value),
+		}
+	}
+
+	/// Replace `current` with the last value sent, if it hasn't been picked up yet
+	/// and the slot isn't being accessed by [`Self::send`]. Meant to be called by the callback.
+	pub(super) fn poll(&self, current: &mut T) {
+		self.slot.try_with_lock_mut(|slot| {
+			*slot = match mem::replace(slot, Slot::Empty) {
+				Slot::Pending(value) => Slot::Replaced(mem::replace(current, value)),
+				slot => slot,
+			};
+		});
+	}
+}
+
+impl<T> Default for Handover<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn values_are_picked_up_and_returned() {
+		let handover = Handover::new();
+		let mut current = 0;
+		handover.poll(&mut current);
+		assert_eq!(current, 0);
+
+		assert_eq!(handover.send(1), None);
+		// Not picked up yet.
+		assert_eq!(handover.send(2), Some(1));
+		handover.poll(&mut current);
+		assert_eq!(current, 2);
+		handover.poll(&mut current);
+		assert_eq!(current, 2);
+
+		// The replaced value is returned by the next send.
+		assert_eq!(handover.send(3), Some(0));
+		handover.poll(&mut current);
+		assert_eq!(current, 3);
+	}
+
+	#[test]
+	fn busy_slots_are_skipped() {
+		let handover = Handover::new();
+		let mut current = 0;
+		handover.send(1);
+		let guard = handover.slot.lock().unwrap();
+		handover.poll(&mut current);
+		assert_eq!(current, 0);
+		drop(guard);
+		handover.poll(&mut current);
+		assert_eq!(current, 1);
+	}
+}
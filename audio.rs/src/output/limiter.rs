@@ -0,0 +1,256 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+	buffers::InterleavedAudioBuffer,
+	metering::{self, amplitude_to_dB, dB_to_amplitude},
+	NOfFrames, SamplingCtx,
+};
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimiterConfig {
+	/// The maximum absolute value of the output samples, in dBFS.
+	pub ceiling_dB: f32,
+	/// How long before a peak the gain starts going down, which is also the delay
+	/// added to the signal. Rounded up to at least one frame.
+	pub lookahead: Duration,
+	/// The time constant of the gain increase after a peak.
+	pub release: Duration,
+}
+
+impl Default for LimiterConfig {
+	fn default() -> Self {
+		Self {
+			ceiling_dB: -1.,
+			lookahead: Duration::from_millis(5),
+			release: Duration::from_millis(100),
+		}
+	}
+}
+
+/// A look-ahead limiter, which keeps the peaks of a signal under a ceiling, e.g. to prevent
+/// digital clipping when several sources are mixed or the gains exceed unity.
+///
+/// The signal is delayed by the look-ahead, so that the gain can be brought down smoothly
+/// before each peak instead of distorting it. The same gain is applied to all the channels,
+/// to preserve the stereo image.
+///
+/// It's applied by [`super::OutputStream::set_limiter`], and can also be used on its own
+/// (e.g. as one of [`super::OutputStream::set_processors`] or on a signal loaded from a file).
+#[derive(Debug, Clone)]
+pub struct Limiter {
+	config: LimiterConfig,
+	ceiling: f32,
+	n_ch: usize,
+	lookahead: NOfFrames,
+	release_coefficient: f32,
+	/// The interleaved samples waiting to be output.
+	delay: VecDeque<f32>,
+	/// The candidates for the minimum gain required by the frames in the look-ahead window,
+	/// as (frame index, gain), in increasing order of both.
+	required: VecDeque<(usize, f32)>,
+	frame_idx: usize,
+	/// The required gain, with the release applied.
+	released: f32,
+	/// The latest `lookahead` values of `released`, averaged to smooth the gain reductions.
+	smoothing: VecDeque<f32>,
+	smoothing_sum: f64,
+	gain: f32,
+}
+
+impl Limiter {
+	#[must_use]
+	pub fn new(sampling_ctx: SamplingCtx, config: LimiterConfig) -> Self {
+		let lookahead = sampling_ctx
+			.duration_to_frames(config.lookahead)
+			.max(NOfFrames(1));
+		#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+		let release_coefficient = (-1.
+			/ (config.release.as_secs_f64() * sampling_ctx.sample_rate().0 as f64))
+			.exp() as f32;
+		let mut limiter = Self {
+			config,
+			ceiling: dB_to_amplitude(config.ceiling_dB),
+			n_ch: sampling_ctx.n_ch(),
+			lookahead,
+			release_coefficient,
+			delay: VecDeque::new(),
+			required: VecDeque::new(),
+			frame_idx: 0,
+			released: 1.,
+			smoothing: VecDeque::new(),
+			smoothing_sum: 0.,
+			gain: 1.,
+		};
+		limiter.reset();
+		limiter
+	}
+
+	/// Limit the next chunk of the signal, in place.
+	///
+	/// # Panics
+	/// - if `chunk` has a different number of channels than the limiter.
+	pub fn process(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		assert_eq!(
+			chunk.n_ch(),
+			self.n_ch,
+			"buffer with incompatible number of channels received"
+		);
+		for mut frame in chunk.iter_mut() {
+			let samples = frame.samples_mut();
+			let peak = metering::peak(samples);
+			let required = if peak > self.ceiling {
+				self.ceiling / peak
+			} else {
+				1.
+			};
+
+			// The minimum over the frames still in the delay line, including this one.
+			while self
+				.required
+				.back()
+				.is_some_and(|&(_, gain)| gain >= required)
+			{
+				self.required.pop_back();
+			}
+			self.required.push_back((self.frame_idx, required));
+			while self
+				.required
+				.front()
+				.is_some_and(|&(frame_idx, _)| frame_idx + self.lookahead.0 < self.frame_idx)
+			{
+				self.required.pop_front();
+			}
+			let min_required = self.required.front().map_or(1., |&(_, gain)| gain);
+			self.frame_idx += 1;
+
+			self.released = if min_required < self.released {
+				min_required
+			} else {
+				min_required + self.release_coefficient * (self.released - min_required)
+			};
+			// Every value being averaged is already low enough for the peaks that are
+			// about to leave the delay line, so the average is too.
+			self.smoothing.push_back(self.released);
+			self.smoothing_sum += f64::from(self.released);
+			self.smoothing_sum -= self.smoothing.pop_front().map_or(0., f64::from);
+			#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+			{
+				self.gain = (self.smoothing_sum / self.lookahead.0 as f64) as f32;
+			}
+
+			for sample in samples {
+				self.delay.push_back(*sample);
+				let delayed = self.delay.pop_front().unwrap_or(0.);
+				// Guards against the rounding errors of the average.
+				*sample = (delayed * self.gain).clamp(-self.ceiling, self.ceiling);
+			}
+		}
+	}
+
+	/// The gain applied to the latest frame, in dB (0 when the signal is not being limited).
+	#[allow(non_snake_case)]
+	#[must_use]
+	pub fn gain_dB(&self) -> f32 {
+		amplitude_to_dB(self.gain)
+	}
+
+	/// The delay added to the signal, see [`LimiterConfig::lookahead`].
+	#[must_use]
+	pub fn latency(&self) -> NOfFrames {
+		self.lookahead
+	}
+
+	/// Forget the previously processed signal, emptying the delay line.
+	pub fn reset(&mut self) {
+		self.delay.clear();
+		self.delay.resize(self.lookahead.0 * self.n_ch, 0.);
+		self.required.clear();
+		self.frame_idx = 0;
+		self.released = 1.;
+		self.smoothing.clear();
+		self.smoothing.resize(self.lookahead.0, 1.);
+		#[allow(clippy::cast_precision_loss)]
+		{
+			self.smoothing_sum = self.lookahead.0 as f64;
+		}
+		self.gain = 1.;
+	}
+
+	#[must_use]
+	pub fn config(&self) -> LimiterConfig {
+		self.config
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::TAU;
+
+	use crate::SampleRate;
+
+	use super::*;
+
+	fn process(limiter: &mut Limiter, signal: &mut [f32]) {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), limiter.n_ch);
+		limiter.process(&mut InterleavedAudioBuffer::new(sampling_ctx, signal));
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn peaks_are_limited() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let mut limiter = Limiter::new(
+			sampling_ctx,
+			LimiterConfig {
+				ceiling_dB: 0.,
+				lookahead: Duration::from_millis(10),
+				release: Duration::from_millis(50),
+			},
+		);
+		assert_eq!(limiter.latency(), NOfFrames(10));
+
+		// A quiet sine with a loud burst in the middle.
+		let mut signal: Vec<f32> = (0..1000)
+			.flat_map(|i| {
+				let amplitude = if (400..500).contains(&i) { 4. } else { 0.5 };
+				[amplitude * f32::sin(TAU * 50. * i as f32 / 1000.); 2]
+			})
+			.collect();
+		let original = signal.clone();
+		process(&mut limiter, &mut signal);
+
+		assert!(signal.iter().all(|sample| sample.abs() <= 1.));
+		// Until the limiter sees the burst, the signal is only delayed.
+		for (output, input) in signal[2 * 10..2 * 390].iter().zip(&original) {
+			assert!((output - input).abs() < 1e-6, "{output} != {input}");
+		}
+		// The peaks of the burst reach the ceiling.
+		let burst_peak = signal[2 * 420..2 * 500]
+			.iter()
+			.fold(0f32, |peak, sample| peak.max(sample.abs()));
+		assert!(burst_peak > 0.9, "{burst_peak}");
+		// The gain is restored after the burst.
+		assert!(limiter.gain_dB() > -0.1, "{}", limiter.gain_dB());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn reset_empties_the_delay_line() {
+		let mut limiter = Limiter::new(
+			SamplingCtx::new(SampleRate(1000), 1),
+			LimiterConfig {
+				lookahead: Duration::from_millis(2),
+				..Default::default()
+			},
+		);
+		let mut signal = [0.5, 0.25, 0.];
+		process(&mut limiter, &mut signal);
+		assert_eq!(signal, [0., 0., 0.5]);
+
+		limiter.reset();
+		let mut signal = [0.1, 0.1, 0.1];
+		process(&mut limiter, &mut signal);
+		assert_eq!(signal, [0., 0., 0.1]);
+	}
+}
@@ -0,0 +1,493 @@
+#![allow(clippy::cast_precision_loss)]
+
+use std::{
+	convert::Infallible,
+	f32::consts::TAU,
+	io,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate,
+	SamplingCtx, StreamClock, StreamOptions,
+};
+
+use super::{DataProducer, LimiterConfig, OutputProcessor, OutputStream};
+
+/// The duration of a click, short enough to be rhythmically precise even at high tempos.
+const CLICK_DURATION: Duration = Duration::from_millis(30);
+
+/// A decaying sine burst.
+fn click(sample_rate: SampleRate, frequency: f32, amplitude: f32) -> Vec<f32> {
+	let n_of_frames = SamplingCtx::new(sample_rate, 1)
+		.duration_to_frames(CLICK_DURATION)
+		.0;
+	(0..n_of_frames)
+		.map(|i| {
+			let t = i as f32 / sample_rate.0 as f32;
+			amplitude * f32::sin(TAU * frequency * t) * f32::exp(-t / 0.005)
+		})
+		.collect()
+}
+
+struct MetronomeState {
+	sample_rate: SampleRate,
+	bpm: f64,
+	beats_per_bar: usize,
+	accent_click: Vec<f32>,
+	click: Vec<f32>,
+	/// The index in the bar of the next beat.
+	next_beat: usize,
+	/// The number of frames before the next beat, fractional to keep the tempo exact.
+	frames_to_next_beat: f64,
+	/// The click being played (accented or not), with its position.
+	playing: Option<(bool, usize)>,
+}
+
+impl MetronomeState {
+	fn beat_len(&self) -> f64 {
+		self.sample_rate.0 as f64 * 60. / self.bpm
+	}
+
+	fn fill(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		for mut frame in chunk.iter_mut() {
+			if self.frames_to_next_beat <= 0. {
+				self.playing = Some((self.next_beat == 0, 0));
+				self.next_beat = (self.next_beat + 1) % self.beats_per_bar;
+				self.frames_to_next_beat += self.beat_len();
+			}
+			self.frames_to_next_beat -= 1.;
+
+			let sample = match &mut self.playing {
+				Some((accent, position)) => {
+					let click = if *accent {
+						&self.accent_click
+					} else {
+						&self.click
+					};
+					let sample = click.get(*position).copied();
+					*position += 1;
+					sample
+				}
+				None => None,
+			};
+			if sample.is_none() {
+				self.playing = None;
+			}
+			frame.samples_mut().fill(sample.unwrap_or(0.));
+		}
+	}
+
+	fn set_bpm(&mut self, bpm: f64) {
+		// Keep the position within the current beat.
+		self.frames_to_next_beat *= self.bpm / bpm;
+		self.bpm = bpm;
+	}
+}
+
+/// Plays a click on every beat, at a given tempo, with an accented click on the first
+/// beat of each bar.
+///
+/// Beats are scheduled with sample accuracy on the output stream, so the tempo
+/// doesn't drift regardless of the size of the buffers passed to the device.
+pub struct Metronome {
+	shared: Arc<Mutex<MetronomeState>>,
+	base_stream: OutputStream,
+}
+
+impl Metronome {
+	/// Build and start an output stream, playing the first beat of a bar immediately.
+	///
+	/// # Panics
+	/// - if `bpm` is not positive.
+	/// - if `beats_per_bar` is 0.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		bpm: f64,
+		beats_per_bar: usize,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			bpm,
+			beats_per_bar,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Panics
+	/// - if `bpm` is not positive.
+	/// - if `beats_per_bar` is 0.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		bpm: f64,
+		beats_per_bar: usize,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, bpm, beats_per_bar, |data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		bpm: f64,
+		beats_per_bar: usize,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, bpm, beats_per_bar, |data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline metronome, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	///
+	/// # Panics
+	/// - if `bpm` is not positive.
+	/// - if `beats_per_bar` is 0.
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx, bpm: f64, beats_per_bar: usize) -> Self {
+		let Ok(metronome) = Self::build(sampling_ctx, bpm, beats_per_bar, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		metronome
+	}
+
+	/// Build a metronome that plays on `device`, see [`OutputStream::new_on_device`].
+	///
+	/// # Panics
+	/// - if `bpm` is not positive.
+	/// - if `beats_per_bar` is 0.
+	#[must_use]
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		bpm: f64,
+		beats_per_bar: usize,
+	) -> Self {
+		let Ok(metronome) = Self::build(sampling_ctx, bpm, beats_per_bar, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		metronome
+	}
+
+	fn build<E>(
+		sampling_ctx: SamplingCtx,
+		bpm: f64,
+		beats_per_bar: usize,
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
+		assert!(bpm > 0., "the tempo must be positive");
+		assert!(beats_per_bar > 0, "a bar must have at least one beat");
+		let sample_rate = sampling_ctx.sample_rate();
+		let shared = Arc::new(Mutex::new(MetronomeState {
+			sample_rate,
+			bpm,
+			beats_per_bar,
+			accent_click: click(sample_rate, 1760., 1.),
+			click: click(sample_rate, 880., 0.6),
+			next_beat: 0,
+			frames_to_next_beat: 0.,
+			playing: None,
+		}));
+
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			move |mut chunk| {
+				// Only contended while the metronome is being changed, in which case
+				// the chunk is skipped.
+				if shared
+					.try_with_lock_mut(|shared| shared.fill(&mut chunk))
+					.is_none()
+				{
+					chunk.raw_buffer_mut().fill(0.);
+				}
+			}
+		}))?;
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// Change the tempo, in beats per minute, keeping the position within the current beat.
+	///
+	/// # Panics
+	/// - if `bpm` is not positive.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_bpm(&mut self, bpm: f64) {
+		assert!(bpm > 0., "the tempo must be positive");
+		self.shared.with_lock_mut(|shared| shared.set_bpm(bpm));
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn bpm(&self) -> f64 {
+		self.shared.with_lock(|shared| shared.bpm)
+	}
+
+	/// Change the number of beats in a bar, i.e. the numerator of the time signature.
+	/// If the current bar is already longer, the next beat starts a new one.
+	///
+	/// # Panics
+	/// - if `beats_per_bar` is 0.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_beats_per_bar(&mut self, beats_per_bar: usize) {
+		assert!(beats_per_bar > 0, "a bar must have at least one beat");
+		self.shared.with_lock_mut(|shared| {
+			shared.beats_per_bar = beats_per_bar;
+			if shared.next_beat >= beats_per_bar {
+				shared.next_beat = 0;
+			}
+		});
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn beats_per_bar(&self) -> usize {
+		self.shared.with_lock(|shared| shared.beats_per_bar)
+	}
+
+	/// Start a new bar immediately.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn restart(&mut self) {
+		self.shared.with_lock_mut(|shared| {
+			shared.next_beat = 0;
+			shared.frames_to_next_beat = 0.;
+		});
+	}
+
+	/// The index in the bar of the last beat that has been passed to the device,
+	/// from 0 to [`Self::beats_per_bar`] (excluded).
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn current_beat(&self) -> usize {
+		self.shared.with_lock(|shared| {
+			(shared.next_beat + shared.beats_per_bar - 1) % shared.beats_per_bar
+		})
+	}
+
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the metronome is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the metronome is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the metronome.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the metronome is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.base_stream.avg_output_delay()
+	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread::sleep;
+
+	use super::*;
+
+	fn metronome_state(bpm: f64, beats_per_bar: usize) -> MetronomeState {
+		let sample_rate = SampleRate(1000);
+		MetronomeState {
+			sample_rate,
+			bpm,
+			beats_per_bar,
+			accent_click: click(sample_rate, 100., 1.),
+			click: click(sample_rate, 100., 0.5),
+			next_beat: 0,
+			frames_to_next_beat: 0.,
+			playing: None,
+		}
+	}
+
+	/// The frames at which a click starts.
+	fn onsets(state: &mut MetronomeState, chunk_len: usize, n_of_chunks: usize) -> Vec<usize> {
+		let mut output = vec![];
+		for _ in 0..n_of_chunks {
+			let mut chunk = vec![0.; chunk_len];
+			state.fill(&mut InterleavedAudioBuffer::new(
+				SamplingCtx::new(state.sample_rate, 1),
+				&mut chunk[..],
+			));
+			output.extend(chunk);
+		}
+		// Clicks start from 0 after silence and are followed by a positive sample.
+		(0..output.len() - 1)
+			.filter(|&i| (i == 0 || output[i - 1] == 0.) && output[i] == 0. && output[i + 1] > 0.)
+			.collect()
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn beats_are_sample_accurate() {
+		// 600 BPM, i.e. a beat every 100 frames, regardless of the chunk size.
+		let mut state = metronome_state(600., 2);
+		assert_eq!(onsets(&mut state, 7, 50), [0, 100, 200, 300]);
+		assert_eq!(state.next_beat, 0);
+
+		// 400 BPM, i.e. a beat every 150 frames.
+		let mut state = metronome_state(400., 3);
+		let mut output = vec![0.; 400];
+		state.fill(&mut InterleavedAudioBuffer::new(
+			SamplingCtx::new(state.sample_rate, 1),
+			&mut output[..],
+		));
+		assert_eq!(output[..30], state.accent_click[..]);
+		assert_eq!(output[150..180], state.click[..]);
+		assert_eq!(output[300..330], state.click[..]);
+		assert!(output[30..150].iter().all(|&sample| sample == 0.));
+	}
+
+	#[test]
+	fn tempo_changes_keep_the_position_in_the_beat() {
+		let mut state = metronome_state(600., 4);
+		assert_eq!(onsets(&mut state, 50, 1), [0]);
+		// Half way through the beat, the next one comes after 100 more frames.
+		state.set_bpm(300.);
+		assert_eq!(onsets(&mut state, 100, 2), [100]);
+	}
+
+	#[test]
+	#[ignore = "manually listen to a 3/4 metronome speeding up"]
+	fn test_manual() {
+		let mut metronome =
+			Metronome::new(SamplingCtx::new(SampleRate(44100), 1), None, 90., 3).unwrap();
+		sleep(Duration::from_secs(4));
+		metronome.set_bpm(150.);
+		sleep(Duration::from_secs(4));
+		assert!(metronome.current_beat() < 3);
+	}
+}
@@ -0,0 +1,619 @@
+use std::{
+	convert::Infallible,
+	io,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, callback_capacity,
+	AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback,
+	ReconnectPolicy, SampleRate, SamplingCtx, StreamClock, StreamOptions,
+};
+
+#[cfg(feature = "osc")]
+use super::OscCommand;
+use super::{
+	gain::GainStage, retirer::Retirer, DataProducer, LimiterConfig, OutputProcessor, OutputStream,
+};
+
+/// The gain of each channel for a given pan, from -1 (left) to 1 (right).
+///
+/// Only stereo signals are panned, by attenuating the opposite channel, so that a centered
+/// source keeps its level.
+fn pan_gains(n_ch: usize, pan: f32) -> Vec<f32> {
+	if n_ch == 2 {
+		vec![(1. - pan).min(1.), (1. + pan).min(1.)]
+	} else {
+		vec![1.; n_ch]
+	}
+}
+
+struct Source {
+	id: usize,
+	data_producer: Box<DataProducer>,
+	gain: f32,
+	pan: f32,
+	gain_stage: GainStage,
+	/// The frame at which the source starts playing, see [`Mixer::schedule`].
+	start: NOfFrames,
+	/// The number of frames left to play, for sources that end on their own.
+	remaining: Option<NOfFrames>,
+}
+
+struct MixerState {
+	sampling_ctx: SamplingCtx,
+	sources: Vec<Source>,
+	next_id: usize,
+	/// The number of frames generated so far.
+	clock: NOfFrames,
+	/// The output of the source being mixed, preallocated like the other scratch buffers
+	/// of the audio callbacks.
+	scratch: Vec<f32>,
+}
+
+impl MixerState {
+	/// Mix the next frames of the sources into `chunk`. The scheduled signals that have ended
+	/// are removed and passed to `retire`, rather than dropped.
+	fn fill(
+		&mut self,
+		chunk: &mut InterleavedAudioBuffer<&mut [f32]>,
+		retire: &mut impl FnMut(Source),
+	) {
+		let chunk_len = chunk.n_of_frames();
+		let output = chunk.raw_buffer_mut();
+		output.fill(0.);
+		self.scratch.resize(output.len(), 0.);
+		for source in &mut self.sources {
+			let offset = if source.start > self.clock {
+				source.start - self.clock
+			} else {
+				NOfFrames(0)
+			};
+			if offset >= chunk_len {
+				continue;
+			}
+			let mut len = chunk_len - offset;
+			if let Some(remaining) = &mut source.remaining {
+				len = len.min(*remaining);
+				*remaining -= len;
+			}
+
+			self.scratch.fill(0.);
+			(source.data_producer)(InterleavedAudioBuffer::new(
+				self.sampling_ctx,
+				&mut self.scratch[self.sampling_ctx.frames_to_samples(offset)
+					..self.sampling_ctx.frames_to_samples(offset + len)],
+			));
+			source.gain_stage.process(&mut self.scratch);
+			for (sample, source_sample) in output.iter_mut().zip(&self.scratch) {
+				*sample += source_sample;
+			}
+		}
+		self.clock += chunk_len;
+		let mut idx = 0;
+		while idx < self.sources.len() {
+			if self.sources[idx].remaining == Some(NOfFrames(0)) {
+				retire(self.sources.remove(idx));
+			} else {
+				idx += 1;
+			}
+		}
+	}
+
+	fn add_source(
+		&mut self,
+		data_producer: Box<DataProducer>,
+		start: NOfFrames,
+		remaining: Option<NOfFrames>,
+	) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.sources.push(Source {
+			id,
+			data_producer,
+			gain: 1.,
+			pan: 0.,
+			gain_stage: GainStage::new(self.sampling_ctx),
+			start,
+			remaining,
+		});
+		id
+	}
+
+	fn source_mut(&mut self, id: usize) -> &mut Source {
+		self.sources
+			.iter_mut()
+			.find(|source| source.id == id)
+			.expect("no source with the given identifier")
+	}
+}
+
+/// Plays multiple sources on a single output stream, summing them after applying
+/// the gain and pan of each one.
+///
+/// A source is a data producer, just like the one passed to an [`OutputStream`]. Offline players
+/// and oscillators (see e.g. [`super::Oscillator::new_offline`]) can be mixed by calling their
+/// `render_into` method from the data producer.
+///
+/// Each call to [`Self::add_source`] returns an identifier of the source, which can be
+/// used to change its gain and pan, or to remove it.
+pub struct Mixer {
+	shared: Arc<Mutex<MixerState>>,
+	base_stream: OutputStream,
+}
+
+impl Mixer {
+	/// Build and start an output stream, which plays silence until a source is added.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(sampling_ctx, device_name, StreamOptions::default())
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, options.buffer_size, |data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, options.buffer_size, |data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline mixer, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx) -> Self {
+		let Ok(mixer) = Self::build(sampling_ctx, None, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		mixer
+	}
+
+	/// Build a mixer that plays on `device`, see [`OutputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(device: impl AudioDevice, sampling_ctx: SamplingCtx) -> Self {
+		let Ok(mixer) = Self::build(sampling_ctx, None, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		mixer
+	}
+
+	fn build<E>(
+		sampling_ctx: SamplingCtx,
+		buffer_size: Option<NOfFrames>,
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
+		let shared = Arc::new(Mutex::new(MixerState {
+			sampling_ctx,
+			sources: vec![],
+			next_id: 0,
+			clock: NOfFrames(0),
+			scratch: Vec::with_capacity(
+				sampling_ctx.frames_to_samples(callback_capacity(buffer_size)),
+			),
+		}));
+
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			// Drops the scheduled signals that have ended.
+			let retirer = Retirer::new(drop);
+			move |mut chunk| {
+				// Only contended while the sources are being changed, in which case
+				// the chunk is skipped.
+				let mixed = shared.try_with_lock_mut(|shared| {
+					shared.fill(&mut chunk, &mut |source| retirer.retire(source));
+				});
+				if mixed.is_none() {
+					chunk.raw_buffer_mut().fill(0.);
+				}
+			}
+		}))?;
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// Start mixing a source, with a gain of 1 and centered, returning its identifier.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[allow(clippy::must_use_candidate)] // REASON: the identifier is only needed to control the source
+	pub fn add_source(&self, data_producer: Box<DataProducer>) -> usize {
+		self.shared
+			.with_lock_mut(|shared| shared.add_source(data_producer, NOfFrames(0), None))
+	}
+
+	/// Play `signal` starting exactly at the given frame of the clock of the mixer
+	/// (see [`Self::position`]), or as soon as possible if that frame has already been generated.
+	///
+	/// The signal is mixed as a source, with a gain of 1 and centered, which is removed
+	/// when the signal ends. The returned identifier can be used to control it like the ones
+	/// returned by [`Self::add_source`].
+	///
+	/// Note: the clock counts the frames passed to the device, which plays them after
+	/// [`Self::avg_output_delay`].
+	///
+	/// # Panics
+	/// - if the signal has a different number of channels than the stream.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[allow(clippy::must_use_candidate)] // REASON: the identifier is only needed to control the source
+	pub fn schedule(&self, signal: InterleavedAudioBuffer<Vec<f32>>, at_frame: NOfFrames) -> usize {
+		assert_eq!(
+			signal.n_ch(),
+			self.n_ch(),
+			"signal with incompatible number of channels received"
+		);
+		let n_of_frames = signal.n_of_frames();
+		let mut position = 0;
+		let data_producer: Box<DataProducer> = Box::new(move |mut chunk| {
+			let output = chunk.raw_buffer_mut();
+			let remaining = &signal.raw_buffer()[position..];
+			let n = remaining.len().min(output.len());
+			output[..n].copy_from_slice(&remaining[..n]);
+			position += n;
+		});
+		self.shared
+			.with_lock_mut(|shared| shared.add_source(data_producer, at_frame, Some(n_of_frames)))
+	}
+
+	/// The number of frames generated so far, i.e. the clock used by [`Self::schedule`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn position(&self) -> NOfFrames {
+		self.shared.with_lock(|shared| shared.clock)
+	}
+
+	/// Stop mixing a source, returning whether it was found (it's not if a scheduled signal
+	/// has ended).
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[allow(clippy::must_use_candidate)] // REASON: removing a source that doesn't exist is not an error
+	pub fn remove_source(&self, id: usize) -> bool {
+		self.shared.with_lock_mut(|shared| {
+			let n_of_sources = shared.sources.len();
+			shared.sources.retain(|source| source.id != id);
+			shared.sources.len() < n_of_sources
+		})
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn n_of_sources(&self) -> usize {
+		self.shared.with_lock(|shared| shared.sources.len())
+	}
+
+	/// Whether a source with the given identifier is being mixed.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn has_source(&self, id: usize) -> bool {
+		self.shared
+			.with_lock(|shared| shared.sources.iter().any(|source| source.id == id))
+	}
+
+	/// Apply a command received by an `OscServer`: source gains and volume are handled,
+	/// other commands (and gains of unknown sources) are ignored.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[cfg(feature = "osc")]
+	pub fn apply_osc_command(&self, command: &OscCommand) {
+		match *command {
+			OscCommand::SetSourceGain { id, gain } if self.has_source(id) => {
+				self.set_source_gain(id, gain);
+			}
+			OscCommand::SetVolume(volume) => self.set_volume(volume),
+			_ => {}
+		}
+	}
+
+	/// Set the gain of a source (1.0 by default).
+	///
+	/// The change is ramped over a few milliseconds, to avoid clicks.
+	///
+	/// # Panics
+	/// - if no source with the given identifier is being mixed.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_source_gain(&self, id: usize, gain: f32) {
+		self.shared.with_lock_mut(|shared| {
+			let source = shared.source_mut(id);
+			source.gain = gain;
+			source.gain_stage.set_volume(gain);
+		});
+	}
+
+	/// # Panics
+	/// - if no source with the given identifier is being mixed.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn source_gain(&self, id: usize) -> f32 {
+		self.shared
+			.with_lock_mut(|shared| shared.source_mut(id).gain)
+	}
+
+	/// Set the pan of a source, from -1 (left) to 1 (right), 0 by default.
+	///
+	/// Only stereo streams are affected: the channel opposite to the pan is attenuated,
+	/// so that a centered source plays at its full level on both channels.
+	/// The change is ramped over a few milliseconds, to avoid clicks.
+	///
+	/// # Panics
+	/// - if `pan` is not in the range [-1, 1].
+	/// - if no source with the given identifier is being mixed.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_source_pan(&self, id: usize, pan: f32) {
+		assert!(
+			(-1. ..=1.).contains(&pan),
+			"the pan must be between -1 and 1"
+		);
+		let gains = pan_gains(self.n_ch(), pan);
+		self.shared.with_lock_mut(|shared| {
+			let source = shared.source_mut(id);
+			source.pan = pan;
+			source.gain_stage.set_channel_gains(&gains);
+		});
+	}
+
+	/// # Panics
+	/// - if no source with the given identifier is being mixed.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn source_pan(&self, id: usize) -> f32 {
+		self.shared
+			.with_lock_mut(|shared| shared.source_mut(id).pan)
+	}
+
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the mixer is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the mixer is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the mixer.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the mixer is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.base_stream.avg_output_delay()
+	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+
+	/// See [`OutputStream::channel_gains`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.base_stream.channel_gains()
+	}
+
+	/// See [`OutputStream::set_channel_gains`].
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		self.base_stream.set_channel_gains(gains);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::output::QueuedPlayer;
+
+	use super::*;
+
+	fn constant(value: f32) -> Box<DataProducer> {
+		Box::new(move |mut chunk| chunk.raw_buffer_mut().fill(value))
+	}
+
+	fn assert_last_frame(mixer: &Mixer, expected: [f32; 2]) {
+		// Long enough for the gains to settle.
+		let output = mixer.render(NOfFrames(100));
+		let last = output.at(99);
+		for (actual, expected) in last.samples().iter().zip(expected) {
+			assert!((actual - expected).abs() < 1e-6, "{last:?} != {expected:?}");
+		}
+	}
+
+	#[test]
+	fn sources_are_mixed() {
+		let mixer = Mixer::new_offline(SamplingCtx::new(SampleRate(1000), 2));
+		assert_last_frame(&mixer, [0., 0.]);
+
+		let a = mixer.add_source(constant(1.));
+		let b = mixer.add_source(constant(0.5));
+		assert_last_frame(&mixer, [1.5, 1.5]);
+
+		mixer.set_source_pan(a, 1.);
+		mixer.set_source_gain(b, 2.);
+		assert_last_frame(&mixer, [1., 2.]);
+		assert!((mixer.source_pan(a) - 1.).abs() < f32::EPSILON);
+		assert!((mixer.source_gain(b) - 2.).abs() < f32::EPSILON);
+
+		assert!(mixer.remove_source(a));
+		assert!(!mixer.remove_source(a));
+		assert_eq!(mixer.n_of_sources(), 1);
+		assert_last_frame(&mixer, [1., 1.]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn scheduled_signals_are_sample_accurate() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mixer = Mixer::new_offline(sampling_ctx);
+		let signal = |samples: &[f32]| InterleavedAudioBuffer::new(sampling_ctx, samples.to_vec());
+
+		let first = mixer.schedule(signal(&[1., 2.]), NOfFrames(3));
+		mixer.schedule(signal(&[10., 20., 30.]), NOfFrames(4));
+		assert_eq!(
+			mixer.render(NOfFrames(8)).raw_buffer(),
+			&[0., 0., 0., 1., 12., 20., 30., 0.]
+		);
+		assert_eq!(mixer.position(), NOfFrames(8));
+		assert_eq!(mixer.n_of_sources(), 0);
+		assert!(!mixer.remove_source(first));
+
+		// Across the chunks rendered by the offline stream, and late.
+		mixer.schedule(signal(&[1.; 4]), NOfFrames(8 + 510));
+		mixer.schedule(signal(&[2.]), NOfFrames(0));
+		let output = mixer.render(NOfFrames(520));
+		assert_eq!(output.raw_buffer()[0], 2.);
+		assert!(output.raw_buffer()[1..510]
+			.iter()
+			.all(|&sample| sample == 0.));
+		assert_eq!(
+			output.raw_buffer()[510..],
+			[1., 1., 1., 1., 0., 0., 0., 0., 0., 0.]
+		);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn offline_players_can_be_mixed() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let player = Arc::new(QueuedPlayer::new_offline(sampling_ctx));
+		let mixer = Mixer::new_offline(sampling_ctx);
+		mixer.add_source({
+			let player = player.clone();
+			Box::new(move |chunk| player.render_into(chunk))
+		});
+		mixer.add_source(constant(1.));
+
+		player.enqueue(InterleavedAudioBuffer::new(sampling_ctx, vec![1., 2.]));
+		assert_eq!(mixer.render(NOfFrames(3)).raw_buffer(), &[2., 3., 1.]);
+		assert_eq!(player.queue_len(), 0);
+	}
+}
@@ -1,8 +1,50 @@
+#[cfg(feature = "vorbis")]
+mod file_source;
+#[cfg(feature = "vorbis")]
+pub use file_source::*;
+
+mod gain;
+
+mod handover;
+
+mod envelope;
+pub use envelope::*;
+
+mod limiter;
+pub use limiter::*;
+
+mod metronome;
+pub use metronome::*;
+
+mod mixer;
+pub use mixer::*;
+
+#[cfg(feature = "osc")]
+mod osc;
+#[cfg(feature = "osc")]
+pub use osc::*;
+
 mod oscillating;
 pub use oscillating::*;
 
 mod playback;
 pub use playback::*;
 
+mod queued_player;
+pub use queued_player::*;
+
+mod retirer;
+
+#[cfg(feature = "rodio")]
+mod rodio_source;
+#[cfg(feature = "rodio")]
+pub use rodio_source::*;
+
 mod stream;
 pub use stream::*;
+
+mod synth;
+pub use synth::*;
+
+mod sweep;
+pub use sweep::*;
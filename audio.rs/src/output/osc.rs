@@ -0,0 +1,298 @@
+use std::{
+	io,
+	net::{SocketAddr, ToSocketAddrs, UdpSocket},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	thread::{self, JoinHandle},
+	time::Duration,
+};
+
+use rosc::{OscMessage, OscPacket, OscType};
+use rustfft::num_complex::Complex32;
+
+use crate::analysis::Harmonic;
+
+/// How often the server thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A parameter change received by an [`OscServer`].
+///
+/// Numeric arguments can be sent as any OSC number type (int32, int64, float32 or float64),
+/// except for identifiers, which must be integers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscCommand {
+	/// `/oscillator/harmonics`, followed by pairs of frequency (in Hz) and amplitude,
+	/// see [`super::Oscillator::set_harmonics`].
+	SetHarmonics(Vec<Harmonic>),
+	/// `/oscillator/note_on`, see [`super::Oscillator::note_on`].
+	NoteOn,
+	/// `/oscillator/note_off`, see [`super::Oscillator::note_off`].
+	NoteOff,
+	/// `/player/pause`, see [`super::AudioPlayer::pause`].
+	Pause,
+	/// `/player/resume`, see [`super::AudioPlayer::resume`].
+	Resume,
+	/// `/player/seek`, followed by the time from the start of the signal in seconds,
+	/// see [`super::AudioPlayer::seek_to_time`].
+	Seek(Duration),
+	/// `/player/rate`, followed by a positive rate, see [`super::AudioPlayer::set_playback_rate`].
+	SetPlaybackRate(f32),
+	/// `/mixer/gain`, followed by the identifier of a source and its gain,
+	/// see [`super::Mixer::set_source_gain`].
+	SetSourceGain { id: usize, gain: f32 },
+	/// `/volume`, followed by the volume of the output stream, see [`super::OutputStream::set_volume`].
+	SetVolume(f32),
+}
+
+impl OscCommand {
+	/// Map an OSC message to a command, returning `None` if the address is unknown
+	/// or the arguments don't match it.
+	#[must_use]
+	pub fn from_message(message: &OscMessage) -> Option<Self> {
+		let args = &message.args[..];
+		match (message.addr.as_str(), args) {
+			("/oscillator/harmonics", _) if args.len().is_multiple_of(2) => args
+				.chunks_exact(2)
+				.map(|pair| {
+					Some(Harmonic::new(
+						Complex32::new(number(&pair[1])?, 0.),
+						number(&pair[0])?,
+					))
+				})
+				.collect::<Option<_>>()
+				.map(Self::SetHarmonics),
+			("/oscillator/note_on", []) => Some(Self::NoteOn),
+			("/oscillator/note_off", []) => Some(Self::NoteOff),
+			("/player/pause", []) => Some(Self::Pause),
+			("/player/resume", []) => Some(Self::Resume),
+			("/player/seek", [time]) => Duration::try_from_secs_f32(number(time)?)
+				.ok()
+				.map(Self::Seek),
+			("/player/rate", [rate]) => {
+				let rate = number(rate)?;
+				(rate > 0. && rate.is_finite()).then_some(Self::SetPlaybackRate(rate))
+			}
+			("/mixer/gain", [id, gain]) => Some(Self::SetSourceGain {
+				id: identifier(id)?,
+				gain: number(gain)?,
+			}),
+			("/volume", [volume]) => Some(Self::SetVolume(number(volume)?)),
+			_ => None,
+		}
+	}
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)] // REASON: parameters are f32 throughout the crate
+fn number(arg: &OscType) -> Option<f32> {
+	match *arg {
+		OscType::Int(value) => Some(value as f32),
+		OscType::Long(value) => Some(value as f32),
+		OscType::Float(value) => Some(value),
+		OscType::Double(value) => Some(value as f32),
+		_ => None,
+	}
+}
+
+fn identifier(arg: &OscType) -> Option<usize> {
+	match *arg {
+		OscType::Int(value) => usize::try_from(value).ok(),
+		OscType::Long(value) => usize::try_from(value).ok(),
+		_ => None,
+	}
+}
+
+pub type OnOscCommandCallback = dyn FnMut(OscCommand) + Send + 'static;
+
+/// Listens for OSC messages on a UDP socket, passing the ones it recognizes to a callback
+/// as [`OscCommand`]s, so that the output can be driven by external controllers and DAWs.
+///
+/// Messages inside bundles are handled in order, ignoring their time tag.
+/// The commands can be applied with the `apply_osc_command` method of [`super::Oscillator`],
+/// [`super::AudioPlayer`] and [`super::Mixer`], e.g. by sharing them with the callback
+/// behind a mutex.
+///
+/// The server stops when dropped.
+pub struct OscServer {
+	local_addr: SocketAddr,
+	quit: Arc<AtomicBool>,
+	server_thread: Option<JoinHandle<()>>,
+}
+
+impl OscServer {
+	/// Bind a UDP socket to `addr` and start listening.
+	///
+	/// # Errors
+	/// - if the socket can't be bound or configured.
+	pub fn new(
+		addr: impl ToSocketAddrs,
+		mut on_command: Box<OnOscCommandCallback>,
+	) -> io::Result<Self> {
+		let socket = UdpSocket::bind(addr)?;
+		socket.set_read_timeout(Some(POLL_INTERVAL))?;
+		let local_addr = socket.local_addr()?;
+		let quit = Arc::new(AtomicBool::new(false));
+
+		let server_thread = thread::spawn({
+			let quit = quit.clone();
+			move || {
+				let mut buffer = [0; rosc::decoder::MTU];
+				while !quit.load(Ordering::Relaxed) {
+					let len = match socket.recv(&mut buffer) {
+						Ok(len) => len,
+						Err(err)
+							if matches!(
+								err.kind(),
+								io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+							) =>
+						{
+							continue
+						}
+						Err(_) => break,
+					};
+					if let Ok((_, packet)) = rosc::decoder::decode_udp(&buffer[..len]) {
+						dispatch(packet, &mut on_command);
+					}
+				}
+			}
+		});
+
+		Ok(Self {
+			local_addr,
+			quit,
+			server_thread: Some(server_thread),
+		})
+	}
+
+	/// The address the server is listening on, useful when binding to port 0.
+	#[must_use]
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+}
+
+impl Drop for OscServer {
+	fn drop(&mut self) {
+		self.quit.store(true, Ordering::Relaxed);
+		if let Some(server_thread) = self.server_thread.take() {
+			let _ = server_thread.join();
+		}
+	}
+}
+
+fn dispatch(packet: OscPacket, on_command: &mut Box<OnOscCommandCallback>) {
+	match packet {
+		OscPacket::Message(message) => {
+			let command = OscCommand::from_message(&message);
+			#[cfg(feature = "tracing")]
+			if command.is_none() {
+				tracing::debug!(addr = %message.addr, "unrecognized OSC message");
+			}
+			if let Some(command) = command {
+				on_command(command);
+			}
+		}
+		OscPacket::Bundle(bundle) => {
+			for packet in bundle.content {
+				dispatch(packet, on_command);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+
+	use rosc::{OscBundle, OscTime};
+
+	use super::*;
+
+	fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+		OscMessage {
+			addr: addr.to_owned(),
+			args,
+		}
+	}
+
+	#[test]
+	fn commands() {
+		assert_eq!(
+			OscCommand::from_message(&message(
+				"/oscillator/harmonics",
+				vec![OscType::Int(440), OscType::Float(0.5)]
+			)),
+			Some(OscCommand::SetHarmonics(vec![Harmonic::new(
+				Complex32::new(0.5, 0.),
+				440.
+			)]))
+		);
+		assert_eq!(
+			OscCommand::from_message(&message("/player/seek", vec![OscType::Double(1.5)])),
+			Some(OscCommand::Seek(Duration::from_millis(1500)))
+		);
+		assert_eq!(
+			OscCommand::from_message(&message(
+				"/mixer/gain",
+				vec![OscType::Int(3), OscType::Float(0.25)]
+			)),
+			Some(OscCommand::SetSourceGain { id: 3, gain: 0.25 })
+		);
+		// Odd harmonics, negative times, invalid rates and non-integer identifiers are rejected.
+		assert_eq!(
+			OscCommand::from_message(&message("/oscillator/harmonics", vec![OscType::Int(440)])),
+			None
+		);
+		assert_eq!(
+			OscCommand::from_message(&message("/player/seek", vec![OscType::Float(-1.)])),
+			None
+		);
+		assert_eq!(
+			OscCommand::from_message(&message("/player/rate", vec![OscType::Float(0.)])),
+			None
+		);
+		assert_eq!(
+			OscCommand::from_message(&message(
+				"/mixer/gain",
+				vec![OscType::Float(3.), OscType::Float(0.25)]
+			)),
+			None
+		);
+		assert_eq!(OscCommand::from_message(&message("/unknown", vec![])), None);
+	}
+
+	#[test]
+	fn server() {
+		let (sender, receiver) = mpsc::channel();
+		let server = OscServer::new(
+			"127.0.0.1:0",
+			Box::new(move |command| sender.send(command).unwrap()),
+		)
+		.unwrap();
+
+		let packet = OscPacket::Bundle(OscBundle {
+			timetag: OscTime::default(),
+			content: vec![
+				OscPacket::Message(message("/player/pause", vec![])),
+				OscPacket::Message(message("/unknown", vec![])),
+				OscPacket::Message(message("/volume", vec![OscType::Float(0.5)])),
+			],
+		});
+		let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket
+			.send_to(
+				&rosc::encoder::encode(&packet).unwrap(),
+				server.local_addr(),
+			)
+			.unwrap();
+
+		let timeout = Duration::from_secs(5);
+		assert_eq!(receiver.recv_timeout(timeout), Ok(OscCommand::Pause));
+		assert_eq!(
+			receiver.recv_timeout(timeout),
+			Ok(OscCommand::SetVolume(0.5))
+		);
+		drop(server);
+	}
+}
@@ -3,24 +3,419 @@
 #![allow(clippy::cast_sign_loss)]
 
 use std::{
+	convert::Infallible,
 	f32::consts::TAU,
+	io,
+	path::Path,
 	sync::{Arc, Mutex},
 	time::Duration,
 };
 
 use mutex_ext::LockExt;
 
+use rustfft::num_complex::Complex32;
+
 use crate::{
-	analysis::Harmonic, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames, SampleRate,
-	SamplingCtx,
+	analysis::Harmonic, backend::AudioDevice, buffers::InterleavedAudioBuffer,
+	AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback,
+	ReconnectPolicy, SampleRate, SamplingCtx, StreamClock, StreamOptions,
+};
+
+#[cfg(feature = "midi")]
+use crate::midi::{MidiEvent, CC_ALL_NOTES_OFF, CC_ALL_SOUND_OFF, CC_VOLUME};
+
+#[cfg(feature = "midi")]
+use super::midi_note_to_frequency;
+#[cfg(feature = "osc")]
+use super::OscCommand;
+use super::{
+	gain::{Crossfade, DEFAULT_CROSSFADE},
+	retirer::Retirer,
+	DataProducer, Envelope, LimiterConfig, OutputProcessor, OutputStream,
 };
 
-use super::OutputStream;
+/// The shape of the periodic signal generated for each harmonic of an [`Oscillator`].
+///
+/// All the waveforms have a peak amplitude of 1 and start their cycle at the phase
+/// of the harmonic. Square, sawtooth and triangle waves are band-limited with the polyBLEP
+/// (and polyBLAMP) method, which attenuates the partials above the Nyquist frequency that
+/// would otherwise fold back as audible aliasing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+	/// A cosine wave.
+	#[default]
+	Sine,
+	/// A wave with a 50% duty cycle, high during the first and last quarter of its cycle,
+	/// so that its fundamental has the same phase as a [`Self::Sine`].
+	Square,
+	/// A rising ramp from -1 to 1.
+	Sawtooth,
+	/// A wave starting from its peak, with the same phase as a [`Self::Sine`].
+	Triangle,
+}
+
+impl Waveform {
+	/// The value of the waveform at the given point of its cycle, where `t` is in
+	/// the range [0, 1) and `dt` is the fraction of the cycle covered by a single sample.
+	pub(super) fn value(self, t: f32, dt: f32) -> f32 {
+		match self {
+			Self::Sine => f32::cos(TAU * t),
+			Self::Square => {
+				let naive = if (0.25..0.75).contains(&t) { -1. } else { 1. };
+				naive - poly_blep((t + 0.75).fract(), dt) + poly_blep((t + 0.25).fract(), dt)
+			}
+			Self::Sawtooth => 2. * t - 1. - poly_blep(t, dt),
+			Self::Triangle => {
+				let naive = 4. * (t - 0.5).abs() - 1.;
+				naive - 4. * dt * poly_blamp(t, dt) + 4. * dt * poly_blamp((t + 0.5).fract(), dt)
+			}
+		}
+	}
+}
+
+/// The correction that smooths a downward step of 2 at the start of the cycle.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+	if t < dt {
+		let x = t / dt;
+		2. * x - x * x - 1.
+	} else if t > 1. - dt {
+		let x = (t - 1.) / dt;
+		x * x + 2. * x + 1.
+	} else {
+		0.
+	}
+}
+
+/// The integral of [`poly_blep`], i.e. the correction that smooths a change of slope
+/// at the start of the cycle, to be scaled by the change of slope and `dt`.
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+	if t < dt {
+		(1. - t / dt).powi(3) / 3.
+	} else if t > 1. - dt {
+		((t - 1.) / dt + 1.).powi(3) / 3.
+	} else {
+		0.
+	}
+}
+
+/// What the audio thread of an [`Oscillator`] is done with, dropped by a [`Retirer`].
+enum Retired {
+	/// The harmonics that have been faded out, see `OscillatorState::previous`.
+	Harmonics(Vec<Vec<(f32, f32, f32)>>),
+	/// A glide that has ended.
+	Glide(Glide),
+}
+
+impl Retired {
+	fn handle(self) {
+		match self {
+			Retired::Harmonics(harmonics_data) => drop(harmonics_data),
+			Retired::Glide(glide) => drop(glide),
+		}
+	}
+}
 
 struct OscillatorState {
+	sample_rate: SampleRate,
 	frame_idx: NOfFrames,
-	harmonics: Vec<Harmonic>,
+	/// The harmonics of each channel.
+	harmonics: Vec<Vec<Harmonic>>,
+	/// The normalized `harmonics`, see [`normalized_harmonics`], computed when they are replaced
+	/// rather than by the audio thread.
+	harmonics_data: Vec<Vec<(f32, f32, f32)>>,
+	waveform: Waveform,
 	mute: bool,
+	channel_mutes: Vec<bool>,
+	/// The normalized harmonics of each channel being faded out, with their waveform and position.
+	previous: Option<(Vec<Vec<(f32, f32, f32)>>, Waveform, NOfFrames)>,
+	crossfade: Crossfade,
+	envelope: Option<Envelope>,
+	glide: Option<Glide>,
+}
+
+impl OscillatorState {
+	/// Fill `chunk` with the next frames of the signal. The faded out harmonics and the glides
+	/// that have ended are passed to `retire`, rather than dropped.
+	fn fill(
+		&mut self,
+		chunk: &mut InterleavedAudioBuffer<&mut [f32]>,
+		retire: &mut impl FnMut(Retired),
+	) {
+		if self.mute {
+			chunk.raw_buffer_mut().fill(0.);
+			return;
+		}
+
+		for i in 0..chunk.n_of_frames().0 {
+			let weight = self
+				.crossfade
+				.is_active()
+				.then(|| self.crossfade.next_weight());
+			let gain = self.envelope.as_mut().map_or(1., Envelope::next_gain);
+			let mut frame = chunk.at_mut(i);
+			for (ch, sample) in frame.samples_mut().iter_mut().enumerate() {
+				// The glide must advance even on muted channels.
+				let mut value = match &mut self.glide {
+					Some(glide) => glide.next_value(ch, self.waveform, self.sample_rate),
+					None => harmonics_value(
+						&self.harmonics_data[ch],
+						self.waveform,
+						self.frame_idx,
+						self.sample_rate,
+					),
+				};
+				if self.channel_mutes[ch] {
+					*sample = 0.;
+					continue;
+				}
+				if let (Some(weight), Some((previous_data, previous_waveform, previous_idx))) =
+					(weight, &self.previous)
+				{
+					let old = harmonics_value(
+						&previous_data[ch],
+						*previous_waveform,
+						*previous_idx,
+						self.sample_rate,
+					);
+					value = old + (value - old) * weight;
+				}
+				*sample = value * gain;
+			}
+
+			self.frame_idx += NOfFrames(1);
+			if let Some((_, _, previous_idx)) = &mut self.previous {
+				*previous_idx += NOfFrames(1);
+			}
+			if self.glide.as_mut().is_some_and(Glide::advance) {
+				self.finish_glide(retire);
+			}
+		}
+
+		if !self.crossfade.is_active() {
+			if let Some((previous_data, _, _)) = self.previous.take() {
+				retire(Retired::Harmonics(previous_data));
+			}
+		}
+	}
+
+	/// Replace the harmonics of every channel, returning the normalized data of the old ones.
+	fn replace_harmonics(&mut self, harmonics: Vec<Vec<Harmonic>>) -> Vec<Vec<(f32, f32, f32)>> {
+		let harmonics_data = harmonics
+			.iter()
+			.map(|harmonics| normalized_harmonics(harmonics))
+			.collect();
+		self.harmonics = harmonics;
+		std::mem::replace(&mut self.harmonics_data, harmonics_data)
+	}
+
+	fn set_harmonics(&mut self, harmonics: Vec<Harmonic>) {
+		self.stop_glide();
+		let n_ch = self.harmonics.len();
+		let previous = self.replace_harmonics(vec![harmonics; n_ch]);
+		// Fading from no harmonics also avoids a click at the start.
+		self.fade_from(previous, self.waveform);
+		self.frame_idx = NOfFrames(0);
+	}
+
+	fn set_channel_harmonics(&mut self, ch: usize, harmonics: Vec<Harmonic>) {
+		self.stop_glide();
+		let mut all_harmonics = self.harmonics.clone();
+		all_harmonics[ch] = harmonics;
+		let previous = self.replace_harmonics(all_harmonics);
+		self.fade_from(previous, self.waveform);
+		self.frame_idx = NOfFrames(0);
+	}
+
+	fn set_waveform(&mut self, waveform: Waveform) {
+		if waveform == self.waveform {
+			return;
+		}
+		self.stop_glide();
+		let previous = std::mem::replace(&mut self.waveform, waveform);
+		self.fade_from(self.harmonics_data.clone(), previous);
+	}
+
+	fn glide_to_harmonics(&mut self, harmonics: Vec<Harmonic>, len: NOfFrames) {
+		if self.mute || len == NOfFrames(0) {
+			self.set_harmonics(harmonics);
+			return;
+		}
+		// Gliding from where a running glide has arrived.
+		self.stop_glide();
+		self.glide = Some(Glide::new(
+			&self.harmonics,
+			self.frame_idx,
+			self.sample_rate,
+			&harmonics,
+			len,
+		));
+		let n_ch = self.harmonics.len();
+		self.replace_harmonics(vec![harmonics; n_ch]);
+	}
+
+	/// Replace the harmonics with the ones reached by the running glide, if any,
+	/// restarting from their current phases.
+	fn stop_glide(&mut self) {
+		if let Some(glide) = self.glide.take() {
+			self.replace_harmonics(glide.harmonics());
+			self.frame_idx = NOfFrames(0);
+		}
+	}
+
+	/// Continue with the target of the glide that has just ended, which is already in `harmonics`,
+	/// restarting from the phases it has reached. Meant to be called by the audio thread,
+	/// so the harmonics are updated in place and the glide is passed to `retire`.
+	fn finish_glide(&mut self, retire: &mut impl FnMut(Retired)) {
+		let Some(glide) = self.glide.take() else {
+			return;
+		};
+		for ((harmonics, harmonics_data), cycles) in self
+			.harmonics
+			.iter_mut()
+			.zip(&mut self.harmonics_data)
+			.zip(&glide.cycles)
+		{
+			for ((harmonic, data), &cycle) in harmonics.iter_mut().zip(harmonics_data).zip(cycles) {
+				*harmonic = Harmonic::new(
+					Complex32::from_polar(
+						harmonic.amplitude(),
+						(cycle * std::f64::consts::TAU) as f32,
+					),
+					harmonic.frequency(),
+				);
+				data.1 = harmonic.phase();
+			}
+		}
+		self.frame_idx = NOfFrames(0);
+		retire(Retired::Glide(glide));
+	}
+
+	fn fade_from(&mut self, harmonics_data: Vec<Vec<(f32, f32, f32)>>, waveform: Waveform) {
+		self.previous = if self.mute {
+			None
+		} else {
+			self.crossfade.start();
+			Some((harmonics_data, waveform, self.frame_idx))
+		};
+	}
+}
+
+/// A continuous transition of the frequency and amplitude of each harmonic,
+/// see [`Oscillator::glide_to_harmonics`].
+struct Glide {
+	/// The (amplitude, frequency) of each harmonic of each channel at the start of the glide,
+	/// with normalized amplitudes. Harmonics that aren't in both sets start or end
+	/// with an amplitude of 0.
+	from: Vec<Vec<(f32, f32)>>,
+	/// Same as `from`, at the end of the glide.
+	to: Vec<Vec<(f32, f32)>>,
+	/// The position in its cycle, from 0 to 1, of each harmonic of each channel.
+	cycles: Vec<Vec<f64>>,
+	len: NOfFrames,
+	elapsed: NOfFrames,
+}
+
+impl Glide {
+	fn new(
+		harmonics: &[Vec<Harmonic>],
+		frame_idx: NOfFrames,
+		sample_rate: SampleRate,
+		target: &[Harmonic],
+		len: NOfFrames,
+	) -> Self {
+		let time = frame_idx.0 as f64 / sample_rate.0 as f64;
+		let to_data = normalized_harmonics(target);
+		let (mut from, mut to, mut cycles) = (vec![], vec![], vec![]);
+		for harmonics in harmonics {
+			let from_data = normalized_harmonics(harmonics);
+			let (mut ch_from, mut ch_to, mut ch_cycles) = (vec![], vec![], vec![]);
+			for k in 0..from_data.len().max(to_data.len()) {
+				let (start, cycle) = if let Some(&(amplitude, phase, frequency)) = from_data.get(k)
+				{
+					(
+						(amplitude, frequency),
+						f64::from(phase) / std::f64::consts::TAU + f64::from(frequency) * time,
+					)
+				} else {
+					let (_, phase, frequency) = to_data[k];
+					((0., frequency), f64::from(phase) / std::f64::consts::TAU)
+				};
+				let end = to_data
+					.get(k)
+					.map_or((0., start.1), |&(amplitude, _, frequency)| {
+						(amplitude, frequency)
+					});
+				ch_from.push(start);
+				ch_to.push(end);
+				ch_cycles.push(cycle.rem_euclid(1.));
+			}
+			from.push(ch_from);
+			to.push(ch_to);
+			cycles.push(ch_cycles);
+		}
+
+		Self {
+			from,
+			to,
+			cycles,
+			len,
+			elapsed: NOfFrames(0),
+		}
+	}
+
+	fn progress(&self) -> f32 {
+		self.elapsed.0 as f32 / self.len.0 as f32
+	}
+
+	/// The value of the current frame on the given channel, moving its harmonics forward.
+	fn next_value(&mut self, ch: usize, waveform: Waveform, sample_rate: SampleRate) -> f32 {
+		let progress = self.progress();
+		let mut value = 0.;
+		for ((&(a0, f0), &(a1, f1)), cycle) in self.from[ch]
+			.iter()
+			.zip(&self.to[ch])
+			.zip(&mut self.cycles[ch])
+		{
+			let frequency = f0 + (f1 - f0) * progress;
+			let step = frequency / sample_rate.0 as f32;
+			value +=
+				(a0 + (a1 - a0) * progress) * waveform.value(*cycle as f32, step.abs().min(0.5));
+			*cycle = (*cycle + f64::from(step)).rem_euclid(1.);
+		}
+		value
+	}
+
+	/// Move to the next frame, returning whether the glide has ended.
+	fn advance(&mut self) -> bool {
+		self.elapsed += NOfFrames(1);
+		self.elapsed >= self.len
+	}
+
+	/// The harmonics of each channel at the current point of the glide, with their current phases.
+	/// See `OscillatorState::finish_glide` for the end of the glide.
+	fn harmonics(&self) -> Vec<Vec<Harmonic>> {
+		let with_cycle = |amplitude: f32, cycle: f64, frequency: f32| {
+			Harmonic::new(
+				Complex32::from_polar(amplitude, (cycle * std::f64::consts::TAU) as f32),
+				frequency,
+			)
+		};
+		let progress = self.progress();
+		self.from
+			.iter()
+			.zip(&self.to)
+			.zip(&self.cycles)
+			.map(|((from, to), cycles)| {
+				from.iter()
+					.zip(to)
+					.zip(cycles)
+					.map(|((&(a0, f0), &(a1, f1)), &cycle)| {
+						with_cycle(a0 + (a1 - a0) * progress, cycle, f0 + (f1 - f0) * progress)
+					})
+					.collect()
+			})
+			.collect()
+	}
 }
 
 pub struct Oscillator {
@@ -37,61 +432,103 @@ impl Oscillator {
 		sampling_ctx: SamplingCtx,
 		device_name: Option<&str>,
 	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(sampling_ctx, device_name, StreamOptions::default())
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, |data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, |data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline oscillator, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx) -> Self {
+		let Ok(oscillator) = Self::build(sampling_ctx, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		oscillator
+	}
+
+	/// Build an oscillator that plays on `device`, see [`OutputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(device: impl AudioDevice, sampling_ctx: SamplingCtx) -> Self {
+		let Ok(oscillator) = Self::build(sampling_ctx, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		oscillator
+	}
+
+	fn build<E>(
+		sampling_ctx: SamplingCtx,
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
 		let shared = Arc::new(Mutex::new(OscillatorState {
+			sample_rate: sampling_ctx.sample_rate(),
 			frame_idx: NOfFrames(0),
 			mute: false,
-			harmonics: vec![],
+			channel_mutes: vec![false; sampling_ctx.n_ch()],
+			harmonics: vec![vec![]; sampling_ctx.n_ch()],
+			harmonics_data: vec![vec![]; sampling_ctx.n_ch()],
+			waveform: Waveform::default(),
+			previous: None,
+			crossfade: Crossfade::new(sampling_ctx.duration_to_frames(DEFAULT_CROSSFADE)),
+			envelope: None,
+			glide: None,
 		}));
 
-		let base_stream = OutputStream::new(
-			sampling_ctx,
-			device_name,
-			Box::new({
-				let shared = shared.clone();
-				move |mut chunk| {
-					shared.with_lock_mut(|shared| {
-						if shared.mute {
-							chunk.raw_buffer_mut().fill(0.);
-						} else {
-							let harmonics = &shared.harmonics;
-
-							let sum_of_amplitudes =
-								harmonics.iter().map(Harmonic::amplitude).sum::<f32>();
-
-							let harmonics_data: Vec<_> = harmonics
-								.iter()
-								.map(|h| {
-									(h.amplitude() / sum_of_amplitudes, h.phase(), h.frequency())
-								})
-								.collect();
-
-							for i in 0..chunk.n_of_frames().0 {
-								chunk.at_mut(i).samples_mut().fill(
-									harmonics_data
-										.iter()
-										.map(|(amplitude, phase, frequency)| {
-											amplitude
-												* f32::cos(
-													phase
-														+ TAU
-															* frequency * ((shared.frame_idx.0 + i)
-															as f32 / sampling_ctx
-															.sample_rate()
-															.0
-															as f32),
-												)
-										})
-										.sum::<f32>(),
-								);
-							}
-
-							shared.frame_idx += chunk.n_of_frames();
-						}
-					});
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			let retirer = Retirer::new(Retired::handle);
+			move |mut chunk| {
+				// Only contended while the signal is being changed, in which case
+				// the chunk is skipped.
+				let filled = shared.try_with_lock_mut(|shared| {
+					shared.fill(&mut chunk, &mut |retired| retirer.retire(retired));
+				});
+				if filled.is_none() {
+					chunk.raw_buffer_mut().fill(0.);
 				}
-			}),
-			None,
-		)?;
+			}
+		}))?;
 		Ok(Self {
 			shared,
 			base_stream,
@@ -103,20 +540,233 @@ impl Oscillator {
 		self.base_stream.state()
 	}
 
+	/// Replace the harmonics of the signal generated on every channel, crossfading the old
+	/// and the new one, see [`Self::set_crossfade`].
+	///
 	/// # Panics
 	/// - if the mutex guarding the internal state is poisoned.
 	pub fn set_harmonics(&mut self, harmonics: Vec<Harmonic>) {
+		self.shared
+			.with_lock_mut(|shared| shared.set_harmonics(harmonics));
+	}
+
+	/// Move the harmonics of the signal generated on every channel to `harmonics` over `duration`,
+	/// ramping linearly the frequency and the amplitude of each one, e.g. for a portamento.
+	///
+	/// The harmonics are paired by their position: the ones without a counterpart fade in
+	/// or out. Each harmonic continues from its current phase, so the phases of `harmonics`
+	/// are only used for the ones fading in. A glide is interrupted where it has arrived
+	/// by another glide, by [`Self::set_harmonics`], [`Self::set_channel_harmonics`]
+	/// and [`Self::set_waveform`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn glide_to_harmonics(&mut self, harmonics: Vec<Harmonic>, duration: Duration) {
+		let len = self.sampling_ctx().duration_to_frames(duration);
+		self.shared
+			.with_lock_mut(|shared| shared.glide_to_harmonics(harmonics, len));
+	}
+
+	/// Whether a glide started by [`Self::glide_to_harmonics`] is running.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_gliding(&self) -> bool {
+		self.shared.with_lock(|shared| shared.glide.is_some())
+	}
+
+	/// Replace the harmonics of the signal generated on a single channel, e.g. to play 440 Hz
+	/// on the left and 443 Hz on the right channel for binaural beats.
+	///
+	/// All the channels restart from the phase of their harmonics, so that they stay
+	/// in sync, crossfading the old and the new signal, see [`Self::set_crossfade`].
+	///
+	/// # Panics
+	/// - if `ch` is not a valid channel index.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_channel_harmonics(&mut self, ch: usize, harmonics: Vec<Harmonic>) {
+		assert!(ch < self.n_ch(), "channel index out of bounds");
+		self.shared
+			.with_lock_mut(|shared| shared.set_channel_harmonics(ch, harmonics));
+	}
+
+	/// # Panics
+	/// - if `ch` is not a valid channel index.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn channel_harmonics(&self, ch: usize) -> Vec<Harmonic> {
+		self.shared.with_lock(|shared| shared.harmonics[ch].clone())
+	}
+
+	/// Silence a single channel, see also [`Self::set_mute`].
+	///
+	/// # Panics
+	/// - if `ch` is not a valid channel index.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_channel_mute(&mut self, ch: usize, mute: bool) {
 		self.shared.with_lock_mut(|shared| {
-			shared.harmonics = harmonics;
-			shared.frame_idx = NOfFrames(0);
+			shared.channel_mutes[ch] = mute;
 		});
 	}
 
+	/// # Panics
+	/// - if `ch` is not a valid channel index.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn channel_mute(&self, ch: usize) -> bool {
+		self.shared.with_lock(|shared| shared.channel_mutes[ch])
+	}
+
+	/// Change the shape of the wave generated for each harmonic ([`Waveform::Sine`] by default),
+	/// crossfading the old and the new signal, see [`Self::set_crossfade`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_waveform(&mut self, waveform: Waveform) {
+		self.shared
+			.with_lock_mut(|shared| shared.set_waveform(waveform));
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn waveform(&self) -> Waveform {
+		self.shared.with_lock(|shared| shared.waveform)
+	}
+
+	/// Set the duration of the crossfade applied when [`Self::set_harmonics`] or
+	/// [`Self::set_waveform`] replace the generated signal (10ms by default).
+	/// [`Duration::ZERO`] disables it.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_crossfade(&mut self, crossfade: Duration) {
+		let len = self.sampling_ctx().duration_to_frames(crossfade);
+		self.shared
+			.with_lock_mut(|shared| shared.crossfade.set_len(len));
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn crossfade(&self) -> Duration {
+		self.sampling_ctx()
+			.frames_to_duration(self.shared.with_lock(|shared| shared.crossfade.len()))
+	}
+
+	/// Shape the amplitude of the generated signal with an ADSR envelope, triggered by [`Self::note_on`]
+	/// and [`Self::note_off`], or remove it with `None`. While the envelope is idle the output
+	/// is silent.
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_envelope(&mut self, envelope: Option<Envelope>) {
+		if let Some(envelope) = &envelope {
+			assert_eq!(
+				envelope.sample_rate(),
+				self.sample_rate(),
+				"envelope with incompatible sample rate received"
+			);
+		}
+		self.shared
+			.with_lock_mut(|shared| shared.envelope = envelope);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn envelope(&self) -> Option<Envelope> {
+		self.shared.with_lock(|shared| shared.envelope.clone())
+	}
+
+	/// Start the attack of the envelope, see [`Envelope::note_on`]. Has no effect without an envelope.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_on(&mut self) {
+		self.shared
+			.with_lock_mut(|shared| shared.envelope.as_mut().map(Envelope::note_on));
+	}
+
+	/// Start the release of the envelope, see [`Envelope::note_off`]. Has no effect without an envelope.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_off(&mut self) {
+		self.shared
+			.with_lock_mut(|shared| shared.envelope.as_mut().map(Envelope::note_off));
+	}
+
+	/// React to an event received from a `MidiInput`, regardless of its channel,
+	/// playing one note at a time: a note on replaces the harmonics with a single one
+	/// at the frequency of the note (see [`midi_note_to_frequency`]), with an amplitude
+	/// proportional to its velocity, and starts the envelope; a note off releases the envelope,
+	/// unless another note has been started in the meantime. [`CC_VOLUME`] sets the volume,
+	/// [`CC_ALL_NOTES_OFF`] and [`CC_ALL_SOUND_OFF`] release the envelope, and other
+	/// controllers are ignored.
+	///
+	/// Without an envelope (see [`Self::set_envelope`]) notes keep playing until the next one.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[cfg(feature = "midi")]
+	pub fn apply_midi_event(&mut self, event: MidiEvent) {
+		match event {
+			MidiEvent::NoteOn { note, velocity, .. } => {
+				self.set_harmonics(vec![Harmonic::new(
+					Complex32::new(f32::from(velocity.min(127)) / 127., 0.),
+					midi_note_to_frequency(note),
+				)]);
+				self.note_on();
+			}
+			MidiEvent::NoteOff { note, .. } => {
+				let frequency = midi_note_to_frequency(note);
+				if self
+					.harmonics()
+					.first()
+					.is_some_and(|harmonic| harmonic.frequency().to_bits() == frequency.to_bits())
+				{
+					self.note_off();
+				}
+			}
+			MidiEvent::ControlChange {
+				controller: CC_VOLUME,
+				value,
+				..
+			} => self.set_volume(f32::from(value) / 127.),
+			MidiEvent::ControlChange {
+				controller: CC_ALL_NOTES_OFF | CC_ALL_SOUND_OFF,
+				..
+			} => self.note_off(),
+			MidiEvent::ControlChange { .. } => {}
+		}
+	}
+
+	/// Apply a command received by an `OscServer`: harmonics, notes and volume are handled,
+	/// other commands are ignored.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[cfg(feature = "osc")]
+	pub fn apply_osc_command(&mut self, command: &OscCommand) {
+		match command {
+			OscCommand::SetHarmonics(harmonics) => self.set_harmonics(harmonics.clone()),
+			OscCommand::NoteOn => self.note_on(),
+			OscCommand::NoteOff => self.note_off(),
+			OscCommand::SetVolume(volume) => self.set_volume(*volume),
+			_ => {}
+		}
+	}
+
+	/// The harmonics of the first channel, see [`Self::channel_harmonics`].
+	///
 	/// # Panics
 	/// - if the mutex guarding the internal state is poisoned.
 	#[must_use]
 	pub fn harmonics(&self) -> Vec<Harmonic> {
-		self.shared.with_lock(|shared| shared.harmonics.clone())
+		self.channel_harmonics(0)
 	}
 
 	/// # Panics
@@ -134,6 +784,73 @@ impl Oscillator {
 		self.shared.with_lock(|shared| shared.mute)
 	}
 
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the oscillator is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the oscillator is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the oscillator.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the oscillator is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
 	#[must_use]
 	pub fn sampling_ctx(&self) -> SamplingCtx {
 		self.base_stream.sampling_ctx()
@@ -153,6 +870,31 @@ impl Oscillator {
 	pub fn avg_output_delay(&self) -> Duration {
 		self.base_stream.avg_output_delay()
 	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+
+	/// See [`OutputStream::channel_gains`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.base_stream.channel_gains()
+	}
+
+	/// See [`OutputStream::set_channel_gains`].
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		self.base_stream.set_channel_gains(gains);
+	}
 }
 
 /// Generate a series of samples computed using a cosine wave with the
@@ -163,28 +905,55 @@ pub fn harmonics_to_samples(
 	n_of_samples: usize,
 	harmonics: &[Harmonic],
 ) -> Vec<f32> {
-	let sum_of_amplitudes = harmonics.iter().map(Harmonic::amplitude).sum::<f32>();
+	waveform_to_samples(sample_rate, n_of_samples, harmonics, Waveform::Sine)
+}
 
-	// precompute all constants
-	let harmonics_data: Vec<_> = harmonics
+/// Generate a series of samples computed using the given waveform with the
+/// specified frequency, phase and amplitude, see [`harmonics_to_samples`].
+#[must_use]
+pub fn waveform_to_samples(
+	sample_rate: SampleRate,
+	n_of_samples: usize,
+	harmonics: &[Harmonic],
+	waveform: Waveform,
+) -> Vec<f32> {
+	let harmonics_data = normalized_harmonics(harmonics);
+
+	(0..n_of_samples)
+		.map(|i| harmonics_value(&harmonics_data, waveform, NOfFrames(i), sample_rate))
+		.collect()
+}
+
+/// The (amplitude, phase, frequency) of each harmonic, with the amplitudes normalized
+/// so that their sum is 1.
+fn normalized_harmonics(harmonics: &[Harmonic]) -> Vec<(f32, f32, f32)> {
+	let sum_of_amplitudes = harmonics.iter().map(Harmonic::amplitude).sum::<f32>();
+	harmonics
 		.iter()
 		.map(|h| (h.amplitude() / sum_of_amplitudes, h.phase(), h.frequency()))
-		.collect();
-
-	let mono = (0..n_of_samples)
-		.map(move |i| {
-			#[allow(clippy::cast_precision_loss)]
-			harmonics_data
-				.iter()
-				.map(|(amplitude, phase, frequency)| {
-					amplitude
-						* f32::cos(phase + TAU * frequency * (i as f32 / sample_rate.0 as f32))
-				})
-				.sum::<f32>()
-		})
-		.collect::<Vec<f32>>();
+		.collect()
+}
 
-	mono
+/// The value of the sum of the harmonics at the given frame.
+fn harmonics_value(
+	harmonics_data: &[(f32, f32, f32)],
+	waveform: Waveform,
+	frame_idx: NOfFrames,
+	sample_rate: SampleRate,
+) -> f32 {
+	let time = frame_idx.0 as f32 / sample_rate.0 as f32;
+	harmonics_data
+		.iter()
+		.map(|(amplitude, phase, frequency)| {
+			if waveform == Waveform::Sine {
+				return amplitude * f32::cos(phase + TAU * frequency * time);
+			}
+			let t = (phase / TAU + frequency * time).rem_euclid(1.);
+			// Beyond half the Nyquist frequency the corrections of consecutive edges overlap.
+			let dt = (frequency.abs() / sample_rate.0 as f32).min(0.5);
+			amplitude * waveform.value(t, dt)
+		})
+		.sum::<f32>()
 }
 
 #[cfg(test)]
@@ -218,6 +987,22 @@ mod tests {
 		sleep(Duration::from_secs(10));
 	}
 
+	#[test]
+	#[ignore = "manually disconnect the output device while the test is running, the tone should move to the new default device"]
+	fn test_manual_reconnect() {
+		let mut oscillator = Oscillator::new_with_reconnect(
+			SamplingCtx::new(SampleRate(44100), 1),
+			None,
+			crate::ReconnectPolicy::default(),
+			Some(Box::new(|event| println!("{event:?}"))),
+			StreamOptions::default(),
+		)
+		.unwrap();
+		oscillator.set_harmonics(vec![Harmonic::new(Complex32::ONE, 440.)]);
+		sleep(Duration::from_secs(30));
+		assert_eq!(oscillator.state(), AudioStreamSamplingState::Sampling);
+	}
+
 	#[test]
 	fn test_frequencies_to_samples() {
 		let samples = harmonics_to_samples(
@@ -290,4 +1075,209 @@ mod tests {
 			}
 		}
 	}
+
+	/// The complex amplitude of the given (integer) frequency in a 1s signal.
+	fn projection(samples: &[f32], frequency: f32) -> Complex32 {
+		let n = samples.len() as f32;
+		samples
+			.iter()
+			.enumerate()
+			.map(|(i, sample)| Complex32::from_polar(*sample, -TAU * frequency * i as f32 / n))
+			.sum::<Complex32>()
+			* (2. / n)
+	}
+
+	#[test]
+	fn waveforms_fundamentals() {
+		let harmonics = [Harmonic::new(Complex32::ONE, 100.)];
+		for (waveform, expected) in [
+			(Waveform::Sine, Complex32::ONE),
+			(Waveform::Square, Complex32::new(4. / PI, 0.)),
+			(Waveform::Sawtooth, Complex32::new(0., 2. / PI)),
+			(Waveform::Triangle, Complex32::new(8. / (PI * PI), 0.)),
+		] {
+			let samples = waveform_to_samples(SampleRate(8000), 8000, &harmonics, waveform);
+			assert!(
+				samples.iter().all(|sample| sample.abs() < 1.1),
+				"{waveform:?} out of range"
+			);
+			let fundamental = projection(&samples, 100.);
+			assert!(
+				(fundamental - expected).norm() < 0.01,
+				"{waveform:?}: {fundamental:?} != {expected:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn waveforms_are_band_limited() {
+		const SAMPLE_RATE: SampleRate = SampleRate(44100);
+		// The 8th harmonic (23200 Hz) of a naive sawtooth folds back to 20900 Hz.
+		let samples = waveform_to_samples(
+			SAMPLE_RATE,
+			44100,
+			&[Harmonic::new(Complex32::ONE, 2900.)],
+			Waveform::Sawtooth,
+		);
+		let naive: Vec<_> = (0..44100)
+			.map(|i| 2. * (2900. * i as f32 / 44100.).fract() - 1.)
+			.collect();
+
+		let alias = projection(&samples, 20900.).norm();
+		let naive_alias = projection(&naive, 20900.).norm();
+		assert!(naive_alias > 0.05, "{naive_alias}");
+		assert!(alias < naive_alias / 2., "{alias} vs {naive_alias}");
+	}
+
+	#[test]
+	fn offline_oscillator() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let harmonics = [Harmonic::new(Complex32::ONE, 440.)];
+		let mut oscillator = Oscillator::new_offline(sampling_ctx);
+		oscillator.set_harmonics(harmonics.to_vec());
+
+		let rendered = oscillator.render(NOfFrames(2000));
+		let expected = harmonics_to_samples(sampling_ctx.sample_rate(), 2000, &harmonics);
+		// After the fade in.
+		for (a, e) in rendered.raw_buffer()[480..].iter().zip(&expected[480..]) {
+			assert!((a - e).abs() < 1e-6, "{a} != {e}");
+		}
+	}
+
+	#[test]
+	fn replaced_harmonics_are_crossfaded() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let mut state = OscillatorState {
+			sample_rate: sampling_ctx.sample_rate(),
+			frame_idx: NOfFrames(0),
+			harmonics: vec![vec![]; 2],
+			harmonics_data: vec![vec![]; 2],
+			waveform: Waveform::Sine,
+			mute: false,
+			channel_mutes: vec![false; 2],
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(4)),
+			envelope: None,
+			glide: None,
+		};
+		let fill = |state: &mut OscillatorState, n_of_frames: usize| {
+			let mut output = vec![0.; sampling_ctx.frames_to_samples(NOfFrames(n_of_frames))];
+			state.fill(
+				&mut InterleavedAudioBuffer::new(sampling_ctx, &mut output[..]),
+				&mut |_| {},
+			);
+			// Every channel carries the same signal.
+			output.into_iter().step_by(2).collect::<Vec<_>>()
+		};
+		let assert_close = |actual: &[f32], expected: &[f32]| {
+			for (a, e) in actual.iter().zip(expected) {
+				assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+			}
+		};
+
+		// Constant signals, from 1 to -1.
+		state.set_harmonics(vec![Harmonic::new(Complex32::ONE, 0.)]);
+		assert_close(&fill(&mut state, 6), &[0.25, 0.5, 0.75, 1., 1., 1.]);
+		state.set_harmonics(vec![Harmonic::new(-Complex32::ONE, 0.)]);
+		assert_close(&fill(&mut state, 2), &[0.5, 0.]);
+		assert_close(&fill(&mut state, 3), &[-0.5, -1., -1.]);
+		assert!(state.previous.is_none());
+
+		// A constant with a phase of PI is the bottom of a triangle, but the midpoint of a sawtooth.
+		state.set_waveform(Waveform::Triangle);
+		assert_close(&fill(&mut state, 2), &[-1., -1.]);
+		state.set_waveform(Waveform::Sawtooth);
+		assert_close(&fill(&mut state, 5), &[-0.75, -0.5, -0.25, 0., 0.]);
+		assert_eq!(state.waveform, Waveform::Sawtooth);
+	}
+
+	#[test]
+	fn channels_have_their_own_harmonics() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let mut state = OscillatorState {
+			sample_rate: sampling_ctx.sample_rate(),
+			frame_idx: NOfFrames(0),
+			harmonics: vec![vec![]; 2],
+			harmonics_data: vec![vec![]; 2],
+			waveform: Waveform::Sine,
+			mute: false,
+			channel_mutes: vec![false; 2],
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			glide: None,
+		};
+		let fill = |state: &mut OscillatorState| {
+			let mut output = vec![0.; 4];
+			state.fill(
+				&mut InterleavedAudioBuffer::new(sampling_ctx, &mut output[..]),
+				&mut |_| {},
+			);
+			output
+		};
+
+		state.set_harmonics(vec![Harmonic::new(Complex32::ONE, 0.)]);
+		state.set_channel_harmonics(1, vec![Harmonic::new(-Complex32::ONE, 0.)]);
+		let output = fill(&mut state);
+		for frame in output.chunks_exact(2) {
+			assert!((frame[0] - 1.).abs() < 1e-6, "{output:?}");
+			assert!((frame[1] + 1.).abs() < 1e-6, "{output:?}");
+		}
+
+		state.channel_mutes[0] = true;
+		let output = fill(&mut state);
+		for frame in output.chunks_exact(2) {
+			assert!(frame[0].abs() < 1e-6, "{output:?}");
+			assert!((frame[1] + 1.).abs() < 1e-6, "{output:?}");
+		}
+	}
+
+	#[test]
+	fn glides_are_continuous() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut state = OscillatorState {
+			sample_rate: sampling_ctx.sample_rate(),
+			frame_idx: NOfFrames(0),
+			harmonics: vec![vec![Harmonic::new(Complex32::ONE, 100.)]],
+			harmonics_data: vec![vec![(1., 0., 100.)]],
+			waveform: Waveform::Sine,
+			mute: false,
+			channel_mutes: vec![false],
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			glide: None,
+		};
+		let fill = |state: &mut OscillatorState, n_of_frames: usize| {
+			let mut output = vec![0.; n_of_frames];
+			state.fill(
+				&mut InterleavedAudioBuffer::new(sampling_ctx, &mut output[..]),
+				&mut |_| {},
+			);
+			output
+		};
+
+		let mut output = fill(&mut state, 15);
+		state.glide_to_harmonics(vec![Harmonic::new(Complex32::ONE, 200.)], NOfFrames(100));
+		output.extend(fill(&mut state, 60));
+		assert!(state.glide.is_some());
+		output.extend(fill(&mut state, 60));
+		assert!(state.glide.is_none());
+		output.extend(fill(&mut state, 20));
+
+		let mut cycle = 0.;
+		for (i, sample) in output.into_iter().enumerate() {
+			let expected = f64::cos(std::f64::consts::TAU * cycle) as f32;
+			assert!(
+				(sample - expected).abs() < 1e-3,
+				"{i}: {sample} != {expected}"
+			);
+			let frequency = 100. + 100. * (i.saturating_sub(15).min(100) as f64 / 100.);
+			cycle += frequency / 1000.;
+		}
+		let harmonics = &state.harmonics[0];
+		assert_eq!(harmonics.len(), 1);
+		assert!((harmonics[0].frequency() - 200.).abs() < 1e-6);
+		assert!((harmonics[0].amplitude() - 1.).abs() < 1e-6);
+	}
 }
@@ -1,21 +1,154 @@
 #![allow(clippy::cast_precision_loss)]
 
-use std::{thread::sleep, time::Duration};
+use std::{convert::Infallible, io, ops::Range, path::Path, thread::sleep, time::Duration};
 
 use mutex_ext::{CondvarExt, LockExt, ReactiveCondvar};
 
 use crate::{
-	buffers::InterleavedAudioBuffer, AudioStreamBuilderError, AudioStreamSamplingState, NOfFrames,
-	SampleRate, SamplingCtx,
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate,
+	SamplingCtx, StreamClock, StreamOptions,
 };
 
-use super::OutputStream;
+#[cfg(feature = "osc")]
+use super::OscCommand;
+use super::{
+	gain::{Crossfade, DEFAULT_CROSSFADE},
+	queued_player::SignalQueue,
+	retirer::Retirer,
+	DataProducer, Envelope, LimiterConfig, OutputProcessor, OutputStream,
+};
+
+/// How the playback of a signal ended, see [`AudioPlayer::play_handle`] and [`AudioPlayer::enqueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlaybackEnd {
+	/// The whole signal has been passed to the device, or the playback has been moved
+	/// to its end with [`AudioPlayer::seek`].
+	Finished,
+	/// The playback has been stopped by [`PlaybackHandle::cancel`] or [`AudioPlayer::stop_with_fade`],
+	/// the signal has been replaced by another one, or it has been removed from the queue
+	/// by [`AudioPlayer::clear_queue`].
+	Cancelled,
+}
+
+/// The shape of the fades applied by an [`AudioPlayer`], see [`AudioPlayer::set_fade_curve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FadeCurve {
+	/// The amplitude changes linearly.
+	#[default]
+	Linear,
+	/// The level in dB changes linearly, between -60 dB and full level, which sounds more
+	/// even than a linear fade, especially for long fades.
+	Exponential,
+}
+
+impl FadeCurve {
+	/// The gain at the given point of a fade, from 0 (silence) to 1 (full level).
+	fn gain(self, level: f32) -> f32 {
+		match self {
+			FadeCurve::Linear => level,
+			FadeCurve::Exponential if level <= 0. => 0.,
+			FadeCurve::Exponential => EXPONENTIAL_FADE_FLOOR.powf(1. - level),
+		}
+	}
+}
+
+/// The gain at which exponential fades start and end (-60 dB).
+const EXPONENTIAL_FADE_FLOOR: f32 = 0.001;
+
+/// Called, only once, when the playback of a signal ends. The callbacks of a player are called
+/// one at a time, in the order in which the signals ended, by a worker thread of the player,
+/// so the playback never waits for them, but a slow callback delays the ones that follow.
+pub type OnPlaybackEnd = dyn FnOnce(PlaybackEnd) + Send + 'static;
+
+/// What the audio thread of an [`AudioPlayer`] is done with, see [`Retirer`].
+enum Retired {
+	/// A signal that is no longer played, to be dropped.
+	Signal(InterleavedAudioBuffer<Vec<f32>>),
+	/// The callback of a signal whose playback has ended, to be called.
+	Ended(Box<OnPlaybackEnd>, PlaybackEnd),
+}
+
+impl Retired {
+	fn handle(self) {
+		match self {
+			Retired::Signal(signal) => drop(signal),
+			Retired::Ended(on_end, end) => on_end(end),
+		}
+	}
+}
 
 pub struct AudioPlayer {
 	shared: ReactiveCondvar<PlayerState>,
+	/// Calls the callbacks of the signals, after the ones ended by the audio thread.
+	retirer: Retirer<Retired>,
 	base_stream: OutputStream,
 }
 
+/// Tracks the playback of a signal started by [`AudioPlayer::play_handle`] or queued
+/// by [`AudioPlayer::enqueue`], without blocking.
+pub struct PlaybackHandle {
+	shared: ReactiveCondvar<PlayerState>,
+	retirer: Retirer<Retired>,
+	/// The signal being tracked, see `PlayerState::generation`.
+	generation: usize,
+	output_delay: Duration,
+}
+
+impl PlaybackHandle {
+	/// Whether the playback has finished, or has been cancelled.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_done(&self) -> bool {
+		self.shared.with_lock(|shared| self.is_done_in(shared))
+	}
+
+	/// Block until the playback has finished, or has been cancelled, see [`AudioPlayer::wait`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn wait(&self) {
+		self.shared.wait_while(|shared| !self.is_done_in(shared));
+		sleep(self.output_delay);
+	}
+
+	/// Stop the playback, unless it's already done. A signal that is still queued
+	/// is removed from the queue.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn cancel(&self) {
+		let on_end = self.shared.with_lock_mut(|shared| {
+			if self.is_done_in(shared) {
+				return None;
+			}
+			if shared.generation != self.generation {
+				return shared
+					.queue
+					.remove(self.generation)
+					.and_then(|queued| queued.on_end);
+			}
+			shared.seek(shared.signal.n_of_frames());
+			shared.on_end.take()
+		});
+		self.shared.notify_all();
+		if let Some(on_end) = on_end {
+			self.retirer
+				.send(Retired::Ended(on_end, PlaybackEnd::Cancelled));
+		}
+	}
+
+	fn is_done_in(&self, shared: &PlayerState) -> bool {
+		if shared.generation == self.generation {
+			shared.end_of_signal
+		} else {
+			!shared.queue.contains(self.generation)
+		}
+	}
+}
+
 impl AudioPlayer {
 	/// Build and start sampling an input stream
 	///
@@ -25,59 +158,121 @@ impl AudioPlayer {
 		sampling_ctx: SamplingCtx,
 		device_name: Option<&str>,
 	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(sampling_ctx, device_name, StreamOptions::default())
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, |data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, |data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline player, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	///
+	/// Note: [`Self::wait`] blocks until the end of the signal is rendered.
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx) -> Self {
+		let Ok(player) = Self::build(sampling_ctx, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		player
+	}
+
+	/// Build a player that plays on `device`, see [`OutputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(device: impl AudioDevice, sampling_ctx: SamplingCtx) -> Self {
+		let Ok(player) = Self::build(sampling_ctx, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		player
+	}
+
+	fn build<E>(
+		sampling_ctx: SamplingCtx,
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
 		let shared = ReactiveCondvar::new(PlayerState {
 			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
 			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![]),
 			end_of_signal: true,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			previous: None,
+			crossfade: Crossfade::new(sampling_ctx.duration_to_frames(DEFAULT_CROSSFADE)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
 		});
 
-		let base_stream = OutputStream::new(
-			sampling_ctx,
-			device_name,
-			Box::new({
-				let shared = shared.clone();
-				move |mut chunk| {
-					let output_frames = chunk.n_of_frames();
-					let should_notify = shared.mutex().with_lock_mut(|shared| {
-						if shared.end_of_signal {
-							chunk.raw_buffer_mut().fill(0.);
-							false
-						} else {
-							let clamped_frames =
-								output_frames.min(shared.signal.n_of_frames() - shared.frame_idx);
-
-							chunk.raw_buffer_mut()
-								[..sampling_ctx.frames_to_samples(clamped_frames)]
-								.copy_from_slice(
-									&shared.signal.raw_buffer()[sampling_ctx
-										.frames_to_samples(shared.frame_idx)
-										..sampling_ctx
-											.frames_to_samples(shared.frame_idx + clamped_frames)],
-								);
-							chunk.raw_buffer_mut()
-								[sampling_ctx.frames_to_samples(clamped_frames)..]
-								.fill(0.);
-
-							shared.frame_idx += clamped_frames;
-
-							if shared.frame_idx == shared.signal.n_of_frames() {
-								shared.end_of_signal = true;
-								true
-							} else {
-								false
-							}
-						}
-					});
-					if should_notify {
-						shared.condvar().notify_all();
-					}
-				}
-			}),
-			None,
-		)?;
+		let retirer = Retirer::new(Retired::handle);
 
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			let retirer = retirer.clone();
+			move |mut chunk| {
+				// Only contended while the state is being changed, in which case
+				// the chunk is skipped.
+				let should_notify = shared.mutex().try_with_lock_mut(|shared| {
+					shared.fill(chunk.raw_buffer_mut(), &mut |retired| {
+						retirer.retire(retired);
+					})
+				});
+				match should_notify {
+					Some(true) => shared.condvar().notify_all(),
+					Some(false) => {}
+					None => chunk.raw_buffer_mut().fill(0.),
+				}
+			}
+		}))?;
 		Ok(Self {
 			shared,
+			retirer,
 			base_stream,
 		})
 	}
@@ -88,22 +283,418 @@ impl AudioPlayer {
 	}
 
 	/// Note: the wait time is based on when the iterator is exhausted and an estimate on when the output
-	/// device should play the last samples.
+	/// device should play the last samples. While the playback is paused, this keeps waiting,
+	/// as it does until the signals queued with [`Self::enqueue`] have been played.
 	/// # Panics
 	/// - if the mutex guarding the state of the associated thread is poisoned
 	pub fn wait(&self) {
-		self.shared.wait_while(|p| !p.end_of_signal);
+		self.shared
+			.wait_while(|p| !p.end_of_signal || !p.queue.is_empty());
 		sleep(self.base_stream.avg_output_delay());
 	}
 
+	/// Start playing `signal` from its beginning, resuming the playback if it was paused.
+	///
+	/// If another signal is being played, it's crossfaded with the new one, see [`Self::set_crossfade`].
+	///
 	/// # Panics
 	/// - if the mutex guarding the internal state is poisoned.
 	pub fn set_signal(&mut self, signal: InterleavedAudioBuffer<Vec<f32>>) {
-		self.shared.with_lock_mut(|shared| {
-			shared.signal = signal;
-			shared.frame_idx = NOfFrames(0);
-			shared.end_of_signal = false;
+		let replaced = self
+			.shared
+			.with_lock_mut(|shared| shared.set_signal(signal));
+		self.end_replaced(replaced);
+	}
+
+	/// Start playing `signal` like [`Self::set_signal`], returning a handle to track
+	/// the playback without blocking. `on_end` is called when the playback ends, see [`PlaybackEnd`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn play_handle(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		on_end: Option<Box<OnPlaybackEnd>>,
+	) -> PlaybackHandle {
+		let (replaced, generation) = self.shared.with_lock_mut(|shared| {
+			let replaced = shared.set_signal(signal);
+			shared.on_end = on_end;
+			(replaced, shared.generation)
+		});
+		self.end_replaced(replaced);
+		PlaybackHandle {
+			shared: self.shared.clone(),
+			retirer: self.retirer.clone(),
+			generation,
+			output_delay: self.base_stream.avg_output_delay(),
+		}
+	}
+
+	/// Queue `signal` to be played right after the current signal and the ones queued before it,
+	/// without any gap, returning a handle to track its playback. If nothing is being played,
+	/// it starts immediately, as with [`Self::play_handle`].
+	///
+	/// `on_end` is called when the playback of this signal ends, see [`PlaybackEnd`]: when several
+	/// queued signals end within the same chunk, each one's callback is called.
+	///
+	/// Queued signals are played with the settings of the player (rate, loop, envelope...).
+	/// When the current signal ends for any reason, e.g. because it has been cancelled
+	/// or moved to its end with [`Self::seek`], the next queued one starts: see [`Self::clear_queue`]
+	/// to drop them.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn enqueue(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		on_end: Option<Box<OnPlaybackEnd>>,
+	) -> PlaybackHandle {
+		let generation = self.shared.with_lock_mut(|shared| {
+			if shared.end_of_signal && shared.queue.is_empty() {
+				shared.set_signal(signal);
+				shared.on_end = on_end;
+				shared.generation
+			} else {
+				shared.queue.push(signal, on_end)
+			}
+		});
+		self.shared.notify_all();
+		PlaybackHandle {
+			shared: self.shared.clone(),
+			retirer: self.retirer.clone(),
+			generation,
+			output_delay: self.base_stream.avg_output_delay(),
+		}
+	}
+
+	/// Remove the signals queued with [`Self::enqueue`] that haven't started yet, calling
+	/// their callbacks with [`PlaybackEnd::Cancelled`]. The current signal keeps playing.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn clear_queue(&mut self) {
+		let cleared = self.shared.with_lock_mut(|shared| shared.queue.clear());
+		self.shared.notify_all();
+		for on_end in cleared.into_iter().filter_map(|queued| queued.on_end) {
+			self.retirer
+				.send(Retired::Ended(on_end, PlaybackEnd::Cancelled));
+		}
+	}
+
+	/// The number of signals queued with [`Self::enqueue`] that haven't started yet.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn queue_len(&self) -> usize {
+		self.shared.with_lock(|shared| shared.queue.len())
+	}
+
+	/// Wake up the handles of a replaced signal, and call its callback.
+	fn end_replaced(&self, on_end: Option<Box<OnPlaybackEnd>>) {
+		self.shared.notify_all();
+		if let Some(on_end) = on_end {
+			self.retirer
+				.send(Retired::Ended(on_end, PlaybackEnd::Cancelled));
+		}
+	}
+
+	/// Start playing `signal` like [`Self::set_signal`], fading it in over `fade_in`
+	/// and out over the last `fade_out` of it, see [`Self::set_fade_curve`].
+	///
+	/// The fade-in starts with the first frame passed to the device, while the fade-out ends
+	/// with the last frame of the signal, so it's not applied while looping (see [`Self::set_loop`]).
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_signal_with_fade(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		fade_in: Duration,
+		fade_out: Duration,
+	) {
+		let fade_in = self.sampling_ctx().duration_to_frames(fade_in);
+		let fade_out = self.sampling_ctx().duration_to_frames(fade_out);
+		let replaced = self.shared.with_lock_mut(|shared| {
+			let replaced = shared.set_signal(signal);
+			shared.fade_in = Crossfade::new(fade_in);
+			shared.fade_in.start();
+			shared.fade_out = fade_out;
+			replaced
+		});
+		self.end_replaced(replaced);
+	}
+
+	/// Blocking version of [`Self::set_signal_with_fade`], see [`Self::play`].
+	pub fn play_with_fade(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		fade_in: Duration,
+		fade_out: Duration,
+	) {
+		self.set_signal_with_fade(signal, fade_in, fade_out);
+		self.wait();
+	}
+
+	/// Fade out the signal being played over `fade_out`, then stop it, reporting
+	/// [`PlaybackEnd::Cancelled`]. Has no effect if the playback has already ended.
+	///
+	/// The playback is stopped immediately if it's paused or if `fade_out` is shorter
+	/// than a frame. While fading out, the playback keeps its position, rate and loop.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn stop_with_fade(&mut self, fade_out: Duration) {
+		let len = self.sampling_ctx().duration_to_frames(fade_out);
+		let on_end = self.shared.with_lock_mut(|shared| {
+			if shared.end_of_signal {
+				return None;
+			}
+			if shared.paused || len == NOfFrames(0) {
+				shared.seek(shared.signal.n_of_frames());
+				return shared.on_end.take();
+			}
+			let mut stop_fade = Crossfade::new(len);
+			stop_fade.start();
+			shared.stop_fade = Some(stop_fade);
+			None
+		});
+		self.end_replaced(on_end);
+	}
+
+	/// Set the shape of the fades of [`Self::set_signal_with_fade`] and [`Self::stop_with_fade`],
+	/// [`FadeCurve::Linear`] by default.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_fade_curve(&mut self, curve: FadeCurve) {
+		self.shared
+			.with_lock_mut(|shared| shared.fade_curve = curve);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn fade_curve(&self) -> FadeCurve {
+		self.shared.with_lock(|shared| shared.fade_curve)
+	}
+
+	/// Set the duration of the crossfade applied when [`Self::set_signal`] replaces a signal
+	/// that is still playing (10ms by default). [`Duration::ZERO`] disables it.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_crossfade(&mut self, crossfade: Duration) {
+		let len = self.sampling_ctx().duration_to_frames(crossfade);
+		self.shared
+			.with_lock_mut(|shared| shared.crossfade.set_len(len));
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn crossfade(&self) -> Duration {
+		self.sampling_ctx()
+			.frames_to_duration(self.shared.with_lock(|shared| shared.crossfade.len()))
+	}
+
+	/// Shape the amplitude of the signal with an ADSR envelope, triggered by [`Self::note_on`]
+	/// and [`Self::note_off`], or remove it with `None`. While the envelope is idle the output
+	/// is silent.
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_envelope(&mut self, envelope: Option<Envelope>) {
+		if let Some(envelope) = &envelope {
+			assert_eq!(
+				envelope.sample_rate(),
+				self.sample_rate(),
+				"envelope with incompatible sample rate received"
+			);
+		}
+		self.shared
+			.with_lock_mut(|shared| shared.envelope = envelope);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn envelope(&self) -> Option<Envelope> {
+		self.shared.with_lock(|shared| shared.envelope.clone())
+	}
+
+	/// Start the attack of the envelope, see [`Envelope::note_on`]. Has no effect without an envelope.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_on(&mut self) {
+		self.shared
+			.with_lock_mut(|shared| shared.envelope.as_mut().map(Envelope::note_on));
+	}
+
+	/// Start the release of the envelope, see [`Envelope::note_off`]. Has no effect without an envelope.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_off(&mut self) {
+		self.shared
+			.with_lock_mut(|shared| shared.envelope.as_mut().map(Envelope::note_off));
+	}
+
+	/// Play the signal faster (above 1) or slower (below 1), e.g. 2.0 for double speed.
+	///
+	/// The signal is resampled while it's played, with linear interpolation, so its pitch
+	/// changes together with its speed, like a tape.
+	///
+	/// # Panics
+	/// - if `rate` is not positive and finite.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_playback_rate(&mut self, rate: f32) {
+		assert!(
+			rate > 0. && rate.is_finite(),
+			"the playback rate must be positive and finite"
+		);
+		self.shared
+			.with_lock_mut(|shared| shared.rate = f64::from(rate));
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	#[allow(clippy::cast_possible_truncation)]
+	pub fn playback_rate(&self) -> f32 {
+		self.shared.with_lock(|shared| shared.rate as f32)
+	}
+
+	/// Play silence, keeping the current position, until [`Self::resume`] is called.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn pause(&mut self) {
+		self.shared.with_lock_mut(|shared| shared.paused = true);
+	}
+
+	/// Continue playing the signal from the position where it was paused.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn resume(&mut self) {
+		self.shared.with_lock_mut(|shared| shared.paused = false);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_paused(&self) -> bool {
+		self.shared.with_lock(|shared| shared.paused)
+	}
+
+	/// Repeat the signal seamlessly, instead of ending with silence (disabled by default),
+	/// e.g. for test tones and background ambiences. See [`Self::set_loop_region`] to only
+	/// repeat a part of it. The setting applies to the signals set later too.
+	///
+	/// While looping, the playback doesn't finish: [`Self::wait`] keeps waiting and
+	/// [`PlaybackEnd::Finished`] isn't reported. Once looping is disabled, the signal
+	/// plays to its end.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_loop(&mut self, looping: bool) {
+		self.shared.with_lock_mut(|shared| shared.looping = looping);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_looping(&self) -> bool {
+		self.shared.with_lock(|shared| shared.looping)
+	}
+
+	/// Only repeat the given frames of the signal (clamped to its length) when looping,
+	/// or the whole signal with `None`, see [`Self::set_loop`].
+	///
+	/// The frames before the region are played once. If the playback is already past the region
+	/// (e.g. after [`Self::seek`]), the signal plays to its end. An empty region disables looping.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_loop_region(&mut self, region: Option<Range<NOfFrames>>) {
+		self.shared
+			.with_lock_mut(|shared| shared.loop_region = region);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn loop_region(&self) -> Option<Range<NOfFrames>> {
+		self.shared.with_lock(|shared| shared.loop_region.clone())
+	}
+
+	/// Move the playback to the given frame of the signal, clamped to its length.
+	///
+	/// Seeking to the end terminates the playback, while seeking back from the end
+	/// of the signal restarts it.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn seek(&mut self, frame_idx: NOfFrames) {
+		let on_end = self.shared.with_lock_mut(|shared| {
+			shared.seek(frame_idx.min(shared.signal.n_of_frames()));
+			if shared.end_of_signal {
+				shared.on_end.take()
+			} else {
+				None
+			}
 		});
+		self.shared.notify_all();
+		if let Some(on_end) = on_end {
+			self.retirer
+				.send(Retired::Ended(on_end, PlaybackEnd::Finished));
+		}
+	}
+
+	/// Move the playback to the given time from the start of the signal, see [`Self::seek`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn seek_to_time(&mut self, time: Duration) {
+		self.seek(self.sampling_ctx().duration_to_frames(time));
+	}
+
+	/// Apply a command received by an `OscServer`: transport (pause, resume, seek and rate)
+	/// and volume are handled, other commands are ignored.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[cfg(feature = "osc")]
+	pub fn apply_osc_command(&mut self, command: &OscCommand) {
+		match command {
+			OscCommand::Pause => self.pause(),
+			OscCommand::Resume => self.resume(),
+			OscCommand::Seek(time) => self.seek_to_time(*time),
+			OscCommand::SetPlaybackRate(rate) => self.set_playback_rate(*rate),
+			OscCommand::SetVolume(volume) => self.set_volume(*volume),
+			_ => {}
+		}
+	}
+
+	/// The index of the next frame of the signal to be passed to the output device.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn position(&self) -> NOfFrames {
+		self.shared.with_lock(|shared| shared.frame_idx)
+	}
+
+	/// The time from the start of the signal corresponding to [`Self::position`].
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn position_time(&self) -> Duration {
+		self.sampling_ctx().frames_to_duration(self.position())
 	}
 
 	/// Note: blocking, `set_signal` is the non-blocking equivalent.
@@ -115,6 +706,73 @@ impl AudioPlayer {
 		self.wait();
 	}
 
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the player.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
 	#[must_use]
 	pub fn sampling_ctx(&self) -> SamplingCtx {
 		self.base_stream.sampling_ctx()
@@ -134,10 +792,847 @@ impl AudioPlayer {
 	pub fn avg_output_delay(&self) -> Duration {
 		self.base_stream.avg_output_delay()
 	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+
+	/// See [`OutputStream::channel_gains`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.base_stream.channel_gains()
+	}
+
+	/// See [`OutputStream::set_channel_gains`].
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		self.base_stream.set_channel_gains(gains);
+	}
 }
 
 struct PlayerState {
 	signal: InterleavedAudioBuffer<Vec<f32>>,
 	end_of_signal: bool,
+	paused: bool,
+	/// See [`AudioPlayer::set_loop`].
+	looping: bool,
+	/// See [`AudioPlayer::set_loop_region`].
+	loop_region: Option<Range<NOfFrames>>,
+	/// See [`AudioPlayer::set_signal_with_fade`].
+	fade_in: Crossfade,
+	fade_out: NOfFrames,
+	fade_curve: FadeCurve,
+	/// See [`AudioPlayer::stop_with_fade`].
+	stop_fade: Option<Crossfade>,
 	frame_idx: NOfFrames,
+	/// The position between `frame_idx` and the next frame, from 0 to 1.
+	fraction: f64,
+	/// The number of frames of the signal played per output frame.
+	rate: f64,
+	/// The signal being faded out, with its position.
+	previous: Option<(InterleavedAudioBuffer<Vec<f32>>, NOfFrames)>,
+	crossfade: Crossfade,
+	envelope: Option<Envelope>,
+	/// The identifier of the signal being played, to tell apart the handles of each one,
+	/// taken from `queue` so that it's never reused.
+	generation: usize,
+	/// The signals to play after the current one, see [`AudioPlayer::enqueue`].
+	queue: SignalQueue,
+	/// See [`AudioPlayer::play_handle`].
+	on_end: Option<Box<OnPlaybackEnd>>,
+}
+
+impl PlayerState {
+	/// Fill `output` with the next frames of the signal, followed by the queued ones, and with
+	/// silence when it's paused or has ended, returning whether the playback of any signal has
+	/// just ended. The callbacks of those signals, with how they ended, and the signals that
+	/// are no longer played are passed to `retire`, rather than called or dropped.
+	fn fill(&mut self, output: &mut [f32], retire: &mut impl FnMut(Retired)) -> bool {
+		if self.paused || (self.end_of_signal && !self.start_queued(retire)) {
+			output.fill(0.);
+			return false;
+		}
+
+		let sampling_ctx = self.signal.sampling_ctx();
+		let mut written = self.fill_signal(output);
+		let mut finished = false;
+		while self.frame_idx == self.signal.n_of_frames() && !self.queue.is_empty() {
+			finished = true;
+			if let Some(on_end) = self.on_end.take() {
+				retire(Retired::Ended(on_end, PlaybackEnd::Finished));
+			}
+			self.start_queued(retire);
+			written += self.fill_signal(&mut output[written..]);
+		}
+
+		if let Some((previous, previous_idx)) = &mut self.previous {
+			for frame in output.chunks_exact_mut(sampling_ctx.n_ch()) {
+				if !self.crossfade.is_active() {
+					break;
+				}
+				let weight = self.crossfade.next_weight();
+				let start = sampling_ctx.frames_to_samples(*previous_idx);
+				let previous_frame = previous.raw_buffer().get(start..start + frame.len());
+				for (i, sample) in frame.iter_mut().enumerate() {
+					let old = previous_frame.map_or(0., |previous_frame| previous_frame[i]);
+					*sample = old + (*sample - old) * weight;
+				}
+				*previous_idx += NOfFrames(1);
+			}
+			if !self.crossfade.is_active() {
+				if let Some((previous, _)) = self.previous.take() {
+					retire(Retired::Signal(previous));
+				}
+			}
+		}
+
+		if let Some(envelope) = &mut self.envelope {
+			envelope.process(&mut InterleavedAudioBuffer::new(sampling_ctx, &mut *output));
+		}
+
+		if let Some(stop_fade) = &mut self.stop_fade {
+			for frame in output.chunks_exact_mut(sampling_ctx.n_ch()) {
+				let gain = if stop_fade.is_active() {
+					self.fade_curve.gain(1. - stop_fade.next_weight())
+				} else {
+					0.
+				};
+				for sample in frame {
+					*sample *= gain;
+				}
+			}
+			if !stop_fade.is_active() {
+				self.stop_fade = None;
+				self.seek(self.signal.n_of_frames());
+				if let Some(on_end) = self.on_end.take() {
+					retire(Retired::Ended(on_end, PlaybackEnd::Cancelled));
+				}
+				return true;
+			}
+		}
+
+		if self.frame_idx == self.signal.n_of_frames() {
+			self.end_of_signal = true;
+			self.stop_fade = None;
+			if let Some(on_end) = self.on_end.take() {
+				retire(Retired::Ended(on_end, PlaybackEnd::Finished));
+			}
+			true
+		} else {
+			finished
+		}
+	}
+
+	/// Apply the fade-in and the fade-out of the signal to the frames just produced from it,
+	/// given the number of output frames that were left until its end (`None` while looping).
+	#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+	fn apply_fades(&mut self, output: &mut [f32], remaining: Option<f64>) {
+		let n_ch = self.signal.n_ch();
+		let fade_out_len = self.fade_out.0 as f64;
+		let fade_out = remaining.filter(|&remaining| {
+			fade_out_len > 0. && remaining < fade_out_len + (output.len() / n_ch) as f64
+		});
+		if !self.fade_in.is_active() && fade_out.is_none() {
+			return;
+		}
+
+		for (i, frame) in output.chunks_exact_mut(n_ch).enumerate() {
+			let mut gain = 1.;
+			if self.fade_in.is_active() {
+				gain *= self.fade_curve.gain(self.fade_in.next_weight());
+			}
+			if let Some(remaining) = fade_out {
+				let left = remaining - i as f64;
+				if left < fade_out_len {
+					gain *= self.fade_curve.gain((left / fade_out_len).max(0.) as f32);
+				}
+			}
+			for sample in frame {
+				*sample *= gain;
+			}
+		}
+	}
+
+	/// Fill `output` with the next frames of the signal, and with silence after its end,
+	/// returning the number of samples taken from the signal.
+	fn fill_signal(&mut self, output: &mut [f32]) -> usize {
+		let sampling_ctx = self.signal.sampling_ctx();
+		// The output frames left until the end of the signal, unless it's looping.
+		#[allow(clippy::cast_precision_loss)]
+		let remaining = self.loop_bounds().is_none().then(|| {
+			((self.signal.n_of_frames() - self.frame_idx).0 as f64 - self.fraction) / self.rate
+		});
+		#[allow(clippy::float_cmp)]
+		// REASON: only the exact values make the interpolation unnecessary
+		let written = if self.rate == 1. && self.fraction == 0. {
+			let mut written = 0;
+			loop {
+				let bounds = self.loop_bounds();
+				let end = bounds.map_or(self.signal.n_of_frames(), |(_, end)| end);
+				let remaining = &self.signal.raw_buffer()
+					[sampling_ctx.frames_to_samples(self.frame_idx)..]
+					[..sampling_ctx.frames_to_samples(end - self.frame_idx)];
+				let n = remaining.len().min(output.len() - written);
+				output[written..written + n].copy_from_slice(&remaining[..n]);
+				written += n;
+				self.frame_idx += sampling_ctx.samples_to_frames(n);
+
+				match bounds {
+					Some((start, end)) if self.frame_idx == end => self.frame_idx = start,
+					_ => break,
+				}
+				if written == output.len() {
+					break;
+				}
+			}
+			output[written..].fill(0.);
+			written
+		} else {
+			self.fill_resampled(output)
+		};
+		self.apply_fades(&mut output[..written], remaining);
+		written
+	}
+
+	/// Replace the signal, which has ended, with the first queued one, returning whether
+	/// there was one. The replaced signal is passed to `retire`.
+	fn start_queued(&mut self, retire: &mut impl FnMut(Retired)) -> bool {
+		let Some(queued) = self.queue.pop() else {
+			return false;
+		};
+		retire(Retired::Signal(std::mem::replace(
+			&mut self.signal,
+			queued.signal,
+		)));
+		self.generation = queued.id;
+		self.on_end = queued.on_end;
+		self.frame_idx = NOfFrames(0);
+		self.fraction = 0.;
+		self.end_of_signal = false;
+		self.fade_in = Crossfade::new(NOfFrames(0));
+		self.fade_out = NOfFrames(0);
+		self.stop_fade = None;
+		true
+	}
+
+	/// Like the default path of [`Self::fill_signal`], but interpolating linearly between
+	/// the frames of the signal, which is played at `rate`.
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	fn fill_resampled(&mut self, output: &mut [f32]) -> usize {
+		let n_ch = self.signal.n_ch();
+		let n_of_frames = self.signal.n_of_frames();
+		let samples = self.signal.raw_buffer();
+		let mut written = 0;
+		for frame in output.chunks_exact_mut(n_ch) {
+			if self.frame_idx >= n_of_frames {
+				frame.fill(0.);
+				continue;
+			}
+			written += n_ch;
+			let bounds = self.loop_bounds();
+			let end = bounds.map_or(n_of_frames, |(_, end)| end);
+			let a = &samples[self.frame_idx.0 * n_ch..(self.frame_idx.0 + 1) * n_ch];
+			// The last frame is interpolated with the start of the loop, or held
+			// rather than faded to silence.
+			let b = if self.frame_idx.0 + 1 < end.0 {
+				Some(self.frame_idx + NOfFrames(1))
+			} else {
+				bounds.map(|(start, _)| start)
+			}
+			.map_or(a, |next| &samples[next.0 * n_ch..(next.0 + 1) * n_ch]);
+			let t = self.fraction as f32;
+			for ((sample, a), b) in frame.iter_mut().zip(a).zip(b) {
+				*sample = a + (b - a) * t;
+			}
+
+			self.fraction += self.rate;
+			let whole = self.fraction.floor();
+			self.fraction -= whole;
+			self.frame_idx += NOfFrames(whole as usize);
+			if let Some((start, end)) = bounds {
+				if self.frame_idx >= end {
+					self.frame_idx =
+						start + NOfFrames((self.frame_idx - start).0 % (end - start).0);
+				}
+			} else if self.frame_idx >= n_of_frames {
+				self.frame_idx = n_of_frames;
+				self.fraction = 0.;
+			}
+		}
+		written
+	}
+
+	/// Replace the signal, returning the callback of the previous one if it hadn't ended.
+	fn set_signal(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+	) -> Option<Box<OnPlaybackEnd>> {
+		let previous = std::mem::replace(&mut self.signal, signal);
+		self.previous = if self.end_of_signal || self.paused {
+			None
+		} else {
+			self.crossfade.start();
+			Some((previous, self.frame_idx))
+		};
+		self.frame_idx = NOfFrames(0);
+		self.fraction = 0.;
+		self.end_of_signal = false;
+		self.paused = false;
+		self.fade_in = Crossfade::new(NOfFrames(0));
+		self.fade_out = NOfFrames(0);
+		self.stop_fade = None;
+		self.generation = self.queue.reserve_id();
+		self.on_end.take()
+	}
+
+	/// The start and the end of the part of the signal being looped, if the playback is looping
+	/// and hasn't gone past it.
+	fn loop_bounds(&self) -> Option<(NOfFrames, NOfFrames)> {
+		if !self.looping {
+			return None;
+		}
+		let n_of_frames = self.signal.n_of_frames();
+		let (start, end) = self
+			.loop_region
+			.clone()
+			.map_or((NOfFrames(0), n_of_frames), |region| {
+				(region.start, region.end.min(n_of_frames))
+			});
+		(start < end && self.frame_idx < end).then_some((start, end))
+	}
+
+	fn seek(&mut self, frame_idx: NOfFrames) {
+		self.frame_idx = frame_idx;
+		self.fraction = 0.;
+		self.end_of_signal = frame_idx == self.signal.n_of_frames();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, Mutex};
+
+	use crate::backend::MockDevice;
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn pause_and_seek() {
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(
+				SamplingCtx::new(SampleRate(48000), 2),
+				vec![1., 1., 2., 2., 3., 3.],
+			),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [0.; 2];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [1., 1.]);
+
+		state.paused = true;
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0., 0.]);
+		assert_eq!(state.frame_idx, NOfFrames(1));
+
+		state.paused = false;
+		state.seek(NOfFrames(2));
+		let mut output = [0.; 4];
+		assert!(state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [3., 3., 0., 0.]);
+		assert!(state.end_of_signal);
+
+		state.seek(NOfFrames(0));
+		assert!(!state.end_of_signal);
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [1., 1., 2., 2.]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn looping() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![0., 1., 2., 3.]),
+			end_of_signal: false,
+			paused: false,
+			looping: true,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [0.; 10];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0., 1., 2., 3., 0., 1., 2., 3., 0., 1.]);
+
+		state.loop_region = Some(NOfFrames(1)..NOfFrames(3));
+		let mut output = [0.; 6];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [2., 1., 2., 1., 2., 1.]);
+
+		// Interpolated across the loop boundary.
+		state.rate = 0.5;
+		let mut output = [0.; 4];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [2., 1.5, 1., 1.5]);
+
+		state.rate = 1.;
+		state.fraction = 0.;
+		state.looping = false;
+		let mut output = [0.; 4];
+		assert!(state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [2., 3., 0., 0.]);
+		assert!(state.end_of_signal);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn replaced_signals_are_crossfaded() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 8]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(4)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [0.; 2];
+		state.fill(&mut output, &mut |_| {});
+		assert_eq!(output, [1., 1.]);
+
+		state.set_signal(InterleavedAudioBuffer::new(sampling_ctx, vec![-1.; 8]));
+		let mut output = [0.; 6];
+		let mut retired = vec![];
+		state.fill(&mut output, &mut |value| retired.push(value));
+		assert_eq!(output, [0.5, 0., -0.5, -1., -1., -1.]);
+		assert!(state.previous.is_none());
+		// The faded out signal is dropped outside of the audio thread.
+		assert!(matches!(retired[..], [Retired::Signal(_)]));
+
+		// Nothing to fade from once the signal has ended.
+		state.fill(&mut output, &mut |_| {});
+		assert!(state.end_of_signal);
+		state.set_signal(InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 2]));
+		assert!(state.previous.is_none());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn playback_rate() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![0., 1., 2., 3., 4.]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 0.5,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [0.; 3];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0., 0.5, 1.]);
+		assert_eq!(state.frame_idx, NOfFrames(1));
+
+		state.rate = 2.;
+		let mut output = [0.; 4];
+		assert!(state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [1.5, 3.5, 0., 0.]);
+		assert!(state.end_of_signal);
+	}
+
+	#[test]
+	fn envelope_shapes_the_signal() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 8]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: Some(Envelope::new(
+				sampling_ctx.sample_rate(),
+				Duration::from_millis(2),
+				Duration::ZERO,
+				1.,
+				Duration::from_millis(2),
+			)),
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [1.; 2];
+		state.fill(&mut output, &mut |_| {});
+		assert!(output.iter().all(|sample| sample.abs() < 1e-6));
+
+		state.envelope.as_mut().unwrap().note_on();
+		let mut output = [0.; 3];
+		state.fill(&mut output, &mut |_| {});
+		assert!((output[0] - 0.5).abs() < 1e-6);
+		assert!((output[1] - 1.).abs() < 1e-6);
+		assert!((output[2] - 1.).abs() < 1e-6);
+
+		state.envelope.as_mut().unwrap().note_off();
+		state.fill(&mut output, &mut |_| {});
+		assert!((output[0] - 0.5).abs() < 1e-6);
+		assert!(output[1].abs() < 1e-6);
+		assert!(output[2].abs() < 1e-6);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn fades() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut fade_in = Crossfade::new(NOfFrames(4));
+		fade_in.start();
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 8]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in,
+			fade_out: NOfFrames(4),
+			fade_curve: FadeCurve::Linear,
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: None,
+		};
+
+		let mut output = [0.; 3];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0.25, 0.5, 0.75]);
+		let mut output = [0.; 6];
+		assert!(state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [1., 1., 0.75, 0.5, 0.25, 0.]);
+
+		let mut fade_in = Crossfade::new(NOfFrames(2));
+		fade_in.start();
+		state.set_signal(InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 2]));
+		state.fade_in = fade_in;
+		state.fade_curve = FadeCurve::Exponential;
+		let mut output = [0.; 2];
+		assert!(state.fill(&mut output, &mut |_| {}));
+		assert!((output[0] - EXPONENTIAL_FADE_FLOOR.sqrt()).abs() < 1e-6);
+		assert_eq!(output[1], 1.);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn stop_fade() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut stop_fade = Crossfade::new(NOfFrames(4));
+		stop_fade.start();
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 8]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::Linear,
+			stop_fade: Some(stop_fade),
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: Some(Box::new(|_| {})),
+		};
+
+		let mut output = [0.; 2];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0.75, 0.5]);
+		let mut output = [0.; 4];
+		let mut retired = vec![];
+		assert!(state.fill(&mut output, &mut |value| retired.push(value)));
+		assert!(matches!(
+			retired[..],
+			[Retired::Ended(_, PlaybackEnd::Cancelled)]
+		));
+		assert_eq!(output, [0.25, 0., 0., 0.]);
+		assert!(state.end_of_signal);
+		assert!(state.stop_fade.is_none());
+
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0.; 4]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn playback_handles() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut player = AudioPlayer::new_offline(sampling_ctx);
+		let ends = Arc::new(Mutex::new(vec![]));
+		let on_end = |ends: &Arc<Mutex<Vec<PlaybackEnd>>>| -> Option<Box<OnPlaybackEnd>> {
+			let ends = ends.clone();
+			Some(Box::new(move |end| {
+				ends.with_lock_mut(|ends| ends.push(end));
+			}))
+		};
+
+		let first = player.play_handle(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 100]),
+			on_end(&ends),
+		);
+		let _ = player.render(NOfFrames(50));
+		assert!(!first.is_done());
+		let _ = player.render(NOfFrames(50));
+		assert!(first.is_done());
+		first.wait();
+
+		let second = player.play_handle(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 100]),
+			on_end(&ends),
+		);
+		let third = player.play_handle(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![1.; 100]),
+			on_end(&ends),
+		);
+		assert!(second.is_done());
+		assert!(!third.is_done());
+		third.cancel();
+		assert!(third.is_done());
+		assert!(player
+			.render(NOfFrames(10))
+			.raw_buffer()
+			.iter()
+			.all(|s| *s == 0.));
+
+		// The callbacks are called by a worker thread.
+		while ends.with_lock(Vec::len) < 3 {
+			sleep(Duration::from_millis(1));
+		}
+		assert_eq!(
+			ends.with_lock(Clone::clone),
+			[
+				PlaybackEnd::Finished,
+				PlaybackEnd::Cancelled,
+				PlaybackEnd::Cancelled
+			]
+		);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn playback_on_a_mock_device() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let device = MockDevice::new("mock", sampling_ctx);
+		let mut player = AudioPlayer::new_on_device(device.clone(), sampling_ctx);
+		assert_eq!(player.state(), AudioStreamSamplingState::Sampling);
+
+		let handle = player.play_handle(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![1., 2., 3.]),
+			None,
+		);
+		device.process(NOfFrames(2));
+		assert!(!handle.is_done());
+		device.process(NOfFrames(2));
+		assert!(handle.is_done());
+		assert_eq!(device.take_output().raw_buffer(), &[1., 2., 3., 0.]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn queued_signals_are_played_back_to_back() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let mut state = PlayerState {
+			signal: InterleavedAudioBuffer::new(sampling_ctx, vec![1., 2., 3.]),
+			end_of_signal: false,
+			paused: false,
+			looping: false,
+			loop_region: None,
+			fade_in: Crossfade::new(NOfFrames(0)),
+			fade_out: NOfFrames(0),
+			fade_curve: FadeCurve::default(),
+			stop_fade: None,
+			frame_idx: NOfFrames(0),
+			fraction: 0.,
+			rate: 1.,
+			previous: None,
+			crossfade: Crossfade::new(NOfFrames(0)),
+			envelope: None,
+			generation: 0,
+			queue: SignalQueue::default(),
+			on_end: Some(Box::new(|_| {})),
+		};
+		state.generation = state.queue.reserve_id();
+		state.queue.push(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![]),
+			Some(Box::new(|_| {})),
+		);
+		let last = state.queue.push(
+			InterleavedAudioBuffer::new(sampling_ctx, vec![4., 5.]),
+			Some(Box::new(|_| {})),
+		);
+
+		let mut retired = vec![];
+		let mut output = [0.; 2];
+		assert!(!state.fill(&mut output, &mut |value| retired.push(value)));
+		assert_eq!(output, [1., 2.]);
+		assert!(retired.is_empty());
+
+		let mut output = [0.; 4];
+		assert!(state.fill(&mut output, &mut |value| retired.push(value)));
+		assert_eq!(output, [3., 4., 5., 0.]);
+		// The callbacks of the three signals, and the two signals that have been replaced.
+		assert_eq!(retired.len(), 5);
+		let ended: Vec<_> = retired
+			.iter()
+			.filter_map(|value| match value {
+				Retired::Ended(_, end) => Some(*end),
+				Retired::Signal(_) => None,
+			})
+			.collect();
+		assert_eq!(ended, [PlaybackEnd::Finished; 3]);
+		assert!(state.end_of_signal);
+		assert_eq!(state.generation, last);
+		assert!(state.queue.is_empty());
+
+		retired.clear();
+		assert!(!state.fill(&mut output, &mut |value| retired.push(value)));
+		assert_eq!(output, [0.; 4]);
+		assert!(retired.is_empty());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn queued_playback() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let mut player = AudioPlayer::new_offline(sampling_ctx);
+		let signal = |value: f32, len| InterleavedAudioBuffer::new(sampling_ctx, vec![value; len]);
+		let ends = Arc::new(Mutex::new(vec![]));
+		let on_end =
+			|ends: &Arc<Mutex<Vec<(f32, PlaybackEnd)>>>, value| -> Option<Box<OnPlaybackEnd>> {
+				let ends = ends.clone();
+				Some(Box::new(move |end| {
+					ends.with_lock_mut(|ends| ends.push((value, end)));
+				}))
+			};
+
+		let first = player.enqueue(signal(1., 3), on_end(&ends, 1.));
+		let second = player.enqueue(signal(2., 1), on_end(&ends, 2.));
+		let third = player.enqueue(signal(3., 2), on_end(&ends, 3.));
+		let fourth = player.enqueue(signal(4., 2), on_end(&ends, 4.));
+		let fifth = player.enqueue(signal(5., 2), on_end(&ends, 5.));
+		assert_eq!(player.queue_len(), 4);
+
+		third.cancel();
+		assert!(third.is_done());
+		assert_eq!(player.queue_len(), 3);
+
+		assert_eq!(
+			player.render(NOfFrames(5)).raw_buffer(),
+			&[1., 1., 1., 2., 4.]
+		);
+		assert!(first.is_done());
+		assert!(second.is_done());
+		assert!(!fourth.is_done());
+
+		player.clear_queue();
+		assert!(fifth.is_done());
+		assert_eq!(player.render(NOfFrames(3)).raw_buffer(), &[4., 0., 0.]);
+		assert!(fourth.is_done());
+		player.wait();
+
+		// The callbacks are called by a worker thread.
+		while ends.with_lock(Vec::len) < 5 {
+			sleep(Duration::from_millis(1));
+		}
+		assert_eq!(
+			ends.with_lock(Clone::clone),
+			[
+				(3., PlaybackEnd::Cancelled),
+				(1., PlaybackEnd::Finished),
+				(2., PlaybackEnd::Finished),
+				(5., PlaybackEnd::Cancelled),
+				(4., PlaybackEnd::Finished),
+			]
+		);
+	}
 }
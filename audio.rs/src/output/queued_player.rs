@@ -0,0 +1,520 @@
+use std::{
+	collections::VecDeque, convert::Infallible, io, path::Path, thread::sleep, time::Duration,
+};
+
+use mutex_ext::{CondvarExt, LockExt, ReactiveCondvar};
+
+use crate::{
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate,
+	SamplingCtx, StreamClock, StreamOptions,
+};
+
+use super::{
+	retirer::Retirer, DataProducer, LimiterConfig, OnPlaybackEnd, OutputProcessor, OutputStream,
+};
+
+/// Plays a sequence of signals back to back, without gaps between them, e.g. to stream
+/// audio that is decoded or synthesized a piece at a time.
+///
+/// Each call to [`Self::enqueue`] returns an identifier of the enqueued signal, which can be
+/// used to know when it has been played (see [`Self::is_finished`] and [`Self::wait_for`]).
+/// Identifiers are assigned in increasing order, starting from 0.
+pub struct QueuedPlayer {
+	shared: ReactiveCondvar<QueueState>,
+	base_stream: OutputStream,
+}
+
+impl QueuedPlayer {
+	/// Build and start an output stream, which plays silence until a signal is enqueued.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(sampling_ctx, device_name, StreamOptions::default())
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(|data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(|data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline player, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	///
+	/// Note: [`Self::wait`] and [`Self::wait_for`] block until the signals are rendered.
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx) -> Self {
+		let Ok(player) = Self::build(|data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		player
+	}
+
+	/// Build a player that plays on `device`, see [`OutputStream::new_on_device`].
+	#[must_use]
+	pub fn new_on_device(device: impl AudioDevice, sampling_ctx: SamplingCtx) -> Self {
+		let Ok(player) = Self::build(|data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		player
+	}
+
+	fn build<E>(
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
+		let shared = ReactiveCondvar::new(QueueState {
+			queue: SignalQueue::default(),
+			frame_idx: NOfFrames(0),
+		});
+
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			// Drops the signals that have been played.
+			let retirer = Retirer::new(drop);
+			move |mut chunk| {
+				// Only contended while the queue is being changed, in which case
+				// the chunk is skipped.
+				let should_notify = shared.mutex().try_with_lock_mut(|state| {
+					state.fill(chunk.raw_buffer_mut(), &mut |queued| {
+						retirer.retire(queued);
+					})
+				});
+				match should_notify {
+					Some(true) => shared.notify_all(),
+					Some(false) => {}
+					None => chunk.raw_buffer_mut().fill(0.),
+				}
+			}
+		}))?;
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// Append `signal` to the queue, returning its identifier.
+	///
+	/// # Panics
+	/// - if the signal has a different number of channels than the stream.
+	/// - if the mutex guarding the internal state is poisoned.
+	#[allow(clippy::must_use_candidate)] // REASON: the identifier is only needed to track the signal
+	pub fn enqueue(&self, signal: InterleavedAudioBuffer<Vec<f32>>) -> usize {
+		assert_eq!(
+			signal.n_ch(),
+			self.n_ch(),
+			"signal with incompatible number of channels received"
+		);
+		self.shared
+			.with_lock_mut(|state| state.queue.push(signal, None))
+	}
+
+	/// Whether the signal with the given identifier has been completely passed to the device
+	/// (or removed by [`Self::clear`]).
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn is_finished(&self, id: usize) -> bool {
+		self.shared.with_lock(|state| state.queue.is_finished(id))
+	}
+
+	/// Block until the signal with the given identifier has been played.
+	///
+	/// Note: the wait time is based on when the signal is exhausted and an estimate on when the output
+	/// device should play its last samples.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn wait_for(&self, id: usize) {
+		self.shared.wait_while(|state| !state.queue.is_finished(id));
+		sleep(self.base_stream.avg_output_delay());
+	}
+
+	/// Block until the queue is empty.
+	///
+	/// Note: the wait time is based on when the queue is exhausted and an estimate on when the output
+	/// device should play the last samples.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn wait(&self) {
+		self.shared.wait_while(|state| !state.queue.is_empty());
+		sleep(self.base_stream.avg_output_delay());
+	}
+
+	/// Remove all the signals from the queue, including the one being played,
+	/// which are considered finished.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn clear(&self) {
+		self.shared.with_lock_mut(|state| {
+			state.queue.clear();
+			state.frame_idx = NOfFrames(0);
+		});
+	}
+
+	/// The number of signals that haven't been completely played yet.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn queue_len(&self) -> usize {
+		self.shared.with_lock(|state| state.queue.len())
+	}
+
+	/// The number of frames left to play, among all the queued signals.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn queued_frames(&self) -> NOfFrames {
+		self.shared.with_lock(|state| {
+			state
+				.queue
+				.iter()
+				.map(|queued| queued.signal.n_of_frames())
+				.fold(NOfFrames(0), |total, n_of_frames| total + n_of_frames)
+				- state.frame_idx
+		})
+	}
+
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the player.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the player is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.base_stream.avg_output_delay()
+	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+
+	/// See [`OutputStream::channel_gains`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.base_stream.channel_gains()
+	}
+
+	/// See [`OutputStream::set_channel_gains`].
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		self.base_stream.set_channel_gains(gains);
+	}
+}
+
+/// A signal waiting in a [`SignalQueue`].
+pub(super) struct QueuedSignal {
+	pub(super) id: usize,
+	pub(super) signal: InterleavedAudioBuffer<Vec<f32>>,
+	/// Called when the signal has been played, or removed from the queue.
+	pub(super) on_end: Option<Box<OnPlaybackEnd>>,
+}
+
+/// The signals to play one after the other, identified by increasing numbers,
+/// shared by [`QueuedPlayer`] and [`super::AudioPlayer::enqueue`].
+#[derive(Default)]
+pub(super) struct SignalQueue {
+	signals: VecDeque<QueuedSignal>,
+	/// The identifier of the next signal.
+	next_id: usize,
+}
+
+impl SignalQueue {
+	/// Append `signal` to the queue, returning its identifier.
+	pub(super) fn push(
+		&mut self,
+		signal: InterleavedAudioBuffer<Vec<f32>>,
+		on_end: Option<Box<OnPlaybackEnd>>,
+	) -> usize {
+		let id = self.reserve_id();
+		self.signals.push_back(QueuedSignal { id, signal, on_end });
+		id
+	}
+
+	/// Take an identifier for a signal that is played without being queued,
+	/// so that it's never confused with a queued one.
+	pub(super) fn reserve_id(&mut self) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+		id
+	}
+
+	pub(super) fn front(&self) -> Option<&QueuedSignal> {
+		self.signals.front()
+	}
+
+	pub(super) fn pop(&mut self) -> Option<QueuedSignal> {
+		self.signals.pop_front()
+	}
+
+	/// Remove the signal with the given identifier, if it's still queued.
+	pub(super) fn remove(&mut self, id: usize) -> Option<QueuedSignal> {
+		let idx = self.signals.iter().position(|queued| queued.id == id)?;
+		self.signals.remove(idx)
+	}
+
+	/// Remove all the signals, returning them.
+	pub(super) fn clear(&mut self) -> VecDeque<QueuedSignal> {
+		std::mem::take(&mut self.signals)
+	}
+
+	pub(super) fn contains(&self, id: usize) -> bool {
+		self.signals.iter().any(|queued| queued.id == id)
+	}
+
+	/// Whether the signal with the given identifier has been assigned and is no longer queued.
+	pub(super) fn is_finished(&self, id: usize) -> bool {
+		id < self.next_id && !self.contains(id)
+	}
+
+	pub(super) fn iter(&self) -> impl Iterator<Item = &QueuedSignal> {
+		self.signals.iter()
+	}
+
+	pub(super) fn len(&self) -> usize {
+		self.signals.len()
+	}
+
+	pub(super) fn is_empty(&self) -> bool {
+		self.signals.is_empty()
+	}
+}
+
+struct QueueState {
+	queue: SignalQueue,
+	/// The position in the first signal of the queue.
+	frame_idx: NOfFrames,
+}
+
+impl QueueState {
+	/// Fill `output` with the next frames of the queue, and with silence when it runs out,
+	/// returning whether any signal has been completed. The completed signals are passed
+	/// to `retire`, rather than dropped.
+	fn fill(&mut self, mut output: &mut [f32], retire: &mut impl FnMut(QueuedSignal)) -> bool {
+		let mut finished = false;
+		while let Some(queued) = self.queue.front() {
+			let sampling_ctx = queued.signal.sampling_ctx();
+			let remaining =
+				&queued.signal.raw_buffer()[sampling_ctx.frames_to_samples(self.frame_idx)..];
+			let n = remaining.len().min(output.len());
+			output[..n].copy_from_slice(&remaining[..n]);
+			output = &mut output[n..];
+			self.frame_idx += sampling_ctx.samples_to_frames(n);
+
+			if n < remaining.len() {
+				break;
+			}
+			if let Some(played) = self.queue.pop() {
+				retire(played);
+			}
+			self.frame_idx = NOfFrames(0);
+			finished = true;
+		}
+		output.fill(0.);
+		finished
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn signal(samples: &[f32]) -> InterleavedAudioBuffer<Vec<f32>> {
+		InterleavedAudioBuffer::new(SamplingCtx::new(SampleRate(48000), 1), samples.to_vec())
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn signals_are_played_back_to_back() {
+		let mut state = QueueState {
+			queue: SignalQueue::default(),
+			frame_idx: NOfFrames(0),
+		};
+		let ids = [
+			state.queue.push(signal(&[1., 2., 3.]), None),
+			state.queue.push(signal(&[]), None),
+			state.queue.push(signal(&[4., 5.]), None),
+		];
+		assert_eq!(ids, [0, 1, 2]);
+
+		let mut output = [0.; 2];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [1., 2.]);
+		assert!(!state.queue.is_finished(0));
+
+		let mut output = [0.; 4];
+		let mut retired = vec![];
+		assert!(state.fill(&mut output, &mut |queued| retired.push(queued.id)));
+		assert_eq!(output, [3., 4., 5., 0.]);
+		assert_eq!(retired, ids);
+		assert!(ids.iter().all(|&id| state.queue.is_finished(id)));
+		assert!(!state.queue.is_finished(3));
+		assert!(state.queue.is_empty());
+
+		let mut output = [1.; 2];
+		assert!(!state.fill(&mut output, &mut |_| {}));
+		assert_eq!(output, [0.; 2]);
+	}
+
+	#[test]
+	#[ignore = "manually listen to three beeps played without gaps"]
+	#[allow(clippy::cast_precision_loss)]
+	fn test_manual() {
+		use std::f32::consts::TAU;
+
+		let sampling_ctx = SamplingCtx::new(SampleRate(44100), 1);
+		let player = QueuedPlayer::new(sampling_ctx, None).unwrap();
+		let ids: Vec<_> = [440., 550., 660.]
+			.into_iter()
+			.map(|frequency| {
+				player.enqueue(InterleavedAudioBuffer::new(
+					sampling_ctx,
+					(0..44100 / 2)
+						.map(|i| (TAU * frequency * i as f32 / 44100.).sin() * 0.2)
+						.collect(),
+				))
+			})
+			.collect();
+		player.wait_for(ids[0]);
+		assert!(player.is_finished(ids[0]));
+		assert!(!player.is_finished(ids[2]));
+		player.wait();
+	}
+}
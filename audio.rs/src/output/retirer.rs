@@ -0,0 +1,95 @@
+use std::{
+	sync::{
+		mpsc::{self, SyncSender, TrySendError},
+		Arc,
+	},
+	thread,
+};
+
+/// The maximum number of values waiting to be handled, see [`Retirer::retire`].
+pub(super) const RETIRE_QUEUE_LEN: usize = 256;
+
+/// Hands the values an audio callback is done with (e.g. signals that have been played,
+/// or the callbacks reporting it) over to a worker thread, which handles them with
+/// the function passed to [`Self::new`]: freeing memory or running user code
+/// inside the callback is not realtime-safe.
+///
+/// The worker stops once every clone of the retirer has been dropped.
+pub(super) struct Retirer<T> {
+	sender: SyncSender<T>,
+	handle: Arc<dyn Fn(T) + Send + Sync>,
+}
+
+impl<T: Send + 'static> Retirer<T> {
+	pub(super) fn new(handle: impl Fn(T) + Send + Sync + 'static) -> Self {
+		let handle: Arc<dyn Fn(T) + Send + Sync> = Arc::new(handle);
+		let (sender, receiver) = mpsc::sync_channel::<T>(RETIRE_QUEUE_LEN);
+		thread::spawn({
+			let handle = handle.clone();
+			move || {
+				for value in receiver {
+					handle(value);
+				}
+			}
+		});
+		Self { sender, handle }
+	}
+
+	/// Hand `value` over to the worker without blocking. Meant to be called by the callback.
+	///
+	/// If [`RETIRE_QUEUE_LEN`] values are already waiting, `value` is handled in place.
+	pub(super) fn retire(&self, value: T) {
+		match self.sender.try_send(value) {
+			Ok(()) => {}
+			Err(TrySendError::Full(value) | TrySendError::Disconnected(value)) => {
+				(self.handle)(value);
+			}
+		}
+	}
+
+	/// Hand `value` over to the worker, waiting for room in the queue, so that it's handled
+	/// after the values retired before it. Meant to be called outside of the callback.
+	pub(super) fn send(&self, value: T) {
+		if let Err(mpsc::SendError(value)) = self.sender.send(value) {
+			(self.handle)(value);
+		}
+	}
+}
+
+impl<T> Clone for Retirer<T> {
+	fn clone(&self) -> Self {
+		Self {
+			sender: self.sender.clone(),
+			handle: self.handle.clone(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::{Arc, Mutex},
+		thread::sleep,
+		time::Duration,
+	};
+
+	use mutex_ext::LockExt;
+
+	use super::*;
+
+	#[test]
+	fn values_are_handled_in_order() {
+		let handled = Arc::new(Mutex::new(vec![]));
+		let retirer = Retirer::new({
+			let handled = handled.clone();
+			move |value| handled.with_lock_mut(|handled| handled.push(value))
+		});
+		retirer.retire(1);
+		retirer.clone().send(2);
+		retirer.retire(3);
+		while handled.with_lock(Vec::len) < 3 {
+			sleep(Duration::from_millis(1));
+		}
+		assert_eq!(handled.with_lock(Clone::clone), [1, 2, 3]);
+	}
+}
@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{buffers::InterleavedAudioBuffer, NOfFrames};
+
+use super::QueuedPlayer;
+
+/// A [`rodio::Source`] that renders an offline [`QueuedPlayer`] (see [`QueuedPlayer::new_offline`])
+/// a chunk at a time, e.g. to play the signals enqueued by this crate through a `rodio::Sink`.
+///
+/// The source never ends: it yields silence while the queue is empty.
+/// Iterating it panics if the player is not offline.
+pub struct QueuedPlayerSource {
+	player: Arc<QueuedPlayer>,
+	chunk: Vec<f32>,
+	/// The index of the next sample of `chunk`.
+	position: usize,
+}
+
+impl QueuedPlayerSource {
+	/// `chunk_len` is the number of frames rendered at a time.
+	///
+	/// # Panics
+	/// - if `chunk_len` is 0.
+	#[must_use]
+	pub fn new(player: Arc<QueuedPlayer>, chunk_len: NOfFrames) -> Self {
+		assert!(chunk_len.0 > 0, "chunk_len must be greater than 0");
+		let chunk = vec![0.; player.sampling_ctx().frames_to_samples(chunk_len)];
+		Self {
+			position: chunk.len(),
+			player,
+			chunk,
+		}
+	}
+
+	#[must_use]
+	pub fn player(&self) -> &Arc<QueuedPlayer> {
+		&self.player
+	}
+}
+
+impl Iterator for QueuedPlayerSource {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		if self.position == self.chunk.len() {
+			self.player.render_into(InterleavedAudioBuffer::new(
+				self.player.sampling_ctx(),
+				&mut self.chunk[..],
+			));
+			self.position = 0;
+		}
+		let sample = self.chunk[self.position];
+		self.position += 1;
+		Some(sample)
+	}
+}
+
+impl rodio::Source for QueuedPlayerSource {
+	fn current_frame_len(&self) -> Option<usize> {
+		None
+	}
+
+	fn channels(&self) -> u16 {
+		u16::try_from(self.player.n_ch()).unwrap_or(u16::MAX)
+	}
+
+	fn sample_rate(&self) -> u32 {
+		u32::try_from(self.player.sample_rate().0).unwrap_or(u32::MAX)
+	}
+
+	fn total_duration(&self) -> Option<Duration> {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{SampleRate, SamplingCtx};
+
+	use super::*;
+
+	#[test]
+	fn queued_player_source() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 1);
+		let player = Arc::new(QueuedPlayer::new_offline(sampling_ctx));
+		let source = QueuedPlayerSource::new(player.clone(), NOfFrames(2));
+		player.enqueue(InterleavedAudioBuffer::new(sampling_ctx, vec![1., 2., 3.]));
+
+		assert_eq!(source.take(5).collect::<Vec<_>>(), [1., 2., 3., 0., 0.]);
+	}
+}
@@ -1,31 +1,162 @@
 use std::{
-	sync::{Arc, Mutex},
-	time::Duration,
+	convert::Infallible,
+	fs::File,
+	io::{self, BufWriter},
+	path::Path,
+	sync::{
+		mpsc::{self, Sender},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
 };
 
-use cpal::{
-	traits::{DeviceTrait, StreamTrait},
-	Stream,
-};
 use math_utils::moving_avg::MovingAverage;
 use mutex_ext::LockExt;
 use resource_daemon::ResourceDaemon;
 
+use super::{
+	gain::{GainStage, GainTargets},
+	handover::Handover,
+	Limiter, LimiterConfig,
+};
+
+#[cfg(feature = "realtime")]
+use crate::RealtimePromotion;
 use crate::{
-	buffers::InterleavedAudioBuffer, device_provider, input::OnErrorCallback,
-	AudioStreamBuilderError, AudioStreamError, AudioStreamSamplingState, SampleRate, SamplingCtx,
+	backend::{AudioDevice, CpalDevice, OutputStreamTimestamp},
+	buffers::InterleavedAudioBuffer,
+	device_provider,
+	input::{OnErrorCallback, WavFileFinisher, WavFileSink, WavWriter},
+	reconnect::{ErrorReporter, Reconnector, ReconnectorConfig, StreamDaemon, StreamEvent},
+	AudioStreamBuilderError, AudioStreamError, AudioStreamSamplingState, IOMode, NOfFrames,
+	OnReconnectEventCallback, ReconnectPolicy, SampleRate, SamplingCtx, StatsCollector,
+	StreamClock, StreamOptions, StreamStats,
 };
 
 pub type DataProducer = dyn FnMut(InterleavedAudioBuffer<&mut [f32]>) + Send + 'static;
 
+/// A step of the processing chain of an output stream (e.g. EQ, limiting), which modifies
+/// the chunks in place, see [`OutputStream::set_processors`].
+pub type OutputProcessor = dyn FnMut(&mut InterleavedAudioBuffer<&mut [f32]>) + Send + 'static;
+
+/// The number of frames passed to the data producer at a time by an offline stream,
+/// in the range of the buffer sizes used by devices.
+const OFFLINE_CHUNK_LEN: NOfFrames = NOfFrames(512);
+
+enum Backend {
+	Device {
+		// Declared before the daemon so that it stops replacing it before the daemon is dropped.
+		#[allow(dead_code)] // REASON: only held for its Drop implementation
+		reconnector: Option<Reconnector>,
+		stream_daemon: Arc<Mutex<StreamDaemon>>,
+	},
+	/// See [`OutputStream::new_offline`].
+	Offline(Mutex<Box<DataProducer>>),
+}
+
+/// What the audio callbacks share with the [`OutputStream`] they belong to.
+struct Shared {
+	/// Only locked by the callback when it's free, see [`spawn_stream_daemon`].
+	state: Mutex<StreamState>,
+	/// Owned by the callback: it's kept behind a lock only to be passed from a stream
+	/// to the next one when reconnecting. The setters hand their changes over through
+	/// [`Self::controls`] instead.
+	processing: Mutex<Processing>,
+	controls: Controls,
+	/// The targets of the gain stage of [`Processing`], changed without taking any lock.
+	gain_targets: Arc<GainTargets>,
+}
+
+impl Shared {
+	fn new(sampling_ctx: SamplingCtx) -> Self {
+		let gain_stage = GainStage::new(sampling_ctx);
+		Self {
+			gain_targets: gain_stage.targets().clone(),
+			state: Mutex::new(StreamState {
+				output_delay_moving_avg: MovingAverage::new(10),
+				stats: StatsCollector::default(),
+			}),
+			processing: Mutex::new(Processing {
+				gain_stage,
+				processors: Vec::new(),
+				limiter: None,
+				capture: None,
+				played_frames: NOfFrames(0),
+				clock: None,
+			}),
+			controls: Controls::default(),
+		}
+	}
+}
+
 struct StreamState {
 	output_delay_moving_avg: MovingAverage<Duration>,
+	/// See [`OutputStream::stats`].
+	stats: StatsCollector,
+}
+
+/// The processing of the output of the data producer.
+struct Processing {
+	gain_stage: GainStage,
+	/// See [`OutputStream::set_processors`].
+	processors: Vec<Box<OutputProcessor>>,
+	/// See [`OutputStream::set_limiter`].
+	limiter: Option<Limiter>,
+	/// See [`OutputStream::start_capture`].
+	capture: Option<WavFileSink>,
+	/// The number of frames passed to the device since the stream was built.
+	played_frames: NOfFrames,
+	/// See [`OutputStream::set_clock`].
+	clock: Option<StreamClock>,
+}
+
+/// The changes to [`Processing`] requested by the setters of [`OutputStream`].
+#[derive(Default)]
+struct Controls {
+	processors: Handover<Vec<Box<OutputProcessor>>>,
+	limiter: Handover<Option<Limiter>>,
+	capture: Handover<Option<WavFileSink>>,
+	clock: Handover<Option<StreamClock>>,
+}
+
+impl Processing {
+	/// Pick up the changes requested through `controls` since the last chunk.
+	fn update(&mut self, controls: &Controls) {
+		controls.processors.poll(&mut self.processors);
+		controls.limiter.poll(&mut self.limiter);
+		controls.capture.poll(&mut self.capture);
+		controls.clock.poll(&mut self.clock);
+	}
+
+	/// Bring the output of the data producer to what is sent to the device.
+	fn process(&mut self, sampling_ctx: SamplingCtx, output: &mut [f32]) {
+		self.gain_stage.process(output);
+		let mut wrapped = InterleavedAudioBuffer::new(sampling_ctx, &mut *output);
+		for processor in &mut self.processors {
+			processor(&mut wrapped);
+		}
+		if let Some(limiter) = &mut self.limiter {
+			limiter.process(&mut wrapped);
+		}
+		if let Some(capture) = &mut self.capture {
+			capture.push(output);
+		}
+	}
 }
 
+/// Plays the signal generated by a data producer.
+///
+/// The audio callback never waits for the other methods of the stream: the changes to the volume,
+/// the gains, the processors, the limiter, the clock and the capture are picked up
+/// at the start of the next chunk.
 pub struct OutputStream {
 	sampling_ctx: SamplingCtx,
-	shared: Arc<Mutex<StreamState>>,
-	stream_daemon: ResourceDaemon<Stream, AudioStreamError>,
+	shared: Arc<Shared>,
+	/// See [`Self::limiter`].
+	limiter_config: Mutex<Option<LimiterConfig>>,
+	/// Finalizes the file of the capture handed over to the callback, see [`Self::start_capture`].
+	capture: Mutex<Option<WavFileFinisher>>,
+	backend: Backend,
 }
 
 impl OutputStream {
@@ -36,76 +167,192 @@ impl OutputStream {
 	pub fn new(
 		sampling_ctx: SamplingCtx,
 		device_name: Option<&str>,
-		mut data_producer: Box<DataProducer>,
-		mut on_error: Option<Box<OnErrorCallback>>,
+		data_producer: Box<DataProducer>,
+		on_error: Option<Box<OnErrorCallback>>,
 	) -> Result<Self, AudioStreamBuilderError> {
-		let (device, config) = device_provider(sampling_ctx, device_name, crate::IOMode::Output)?;
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			data_producer,
+			on_error,
+			StreamOptions::default(),
+		)
+	}
 
-		let shared = Arc::new(Mutex::new({
-			StreamState {
-				output_delay_moving_avg: MovingAverage::new(10),
-			}
-		}));
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		data_producer: Box<DataProducer>,
+		on_error: Option<Box<OnErrorCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let (device, config) =
+			device_provider(sampling_ctx, device_name, crate::IOMode::Output, options)?;
 
-		let stream_daemon = ResourceDaemon::new({
-			let shared = shared.clone();
+		let shared = Arc::new(Shared::new(sampling_ctx));
+		let stream_daemon = spawn_stream_daemon(
+			sampling_ctx,
+			Arc::new(CpalDevice::new(device, config, options)),
+			options,
+			shared.clone(),
+			data_producer,
+			on_error,
+			None,
+		);
 
-			move |quit_signal| {
-				device
-					.build_output_stream(
-						&config.into(),
-						{
-							let shared = shared.clone();
-
-							move |output: &mut [f32], info| {
-								let wrapped = InterleavedAudioBuffer::new(sampling_ctx, output);
-								let output_buffer_frames = wrapped.n_of_frames();
-
-								data_producer(wrapped);
-
-								shared.with_lock_mut(
-									|StreamState {
-									     ref mut output_delay_moving_avg,
-									 }| {
-										output_delay_moving_avg.push(
-											info.timestamp()
-												.playback
-												.duration_since(&info.timestamp().callback)
-												.unwrap_or(Duration::ZERO) + sampling_ctx
-												.frames_to_duration(output_buffer_frames),
-										);
-									},
-								);
-							}
-						},
-						move |err| {
-							quit_signal.dispatch(AudioStreamError::SamplingError(err.to_string()));
-							if let Some(on_error) = on_error.take() {
-								on_error(&err.to_string());
-							}
-						},
-						None,
-					)
-					.map_err(|err| AudioStreamError::BuildFailed(err.to_string()))
-					.and_then(|stream| {
-						stream
-							.play()
-							.map(|()| stream)
-							.map_err(|err| AudioStreamError::StartFailed(err.to_string()))
-					})
+		Ok(Self::with_backend(
+			sampling_ctx,
+			shared,
+			Backend::Device {
+				reconnector: None,
+				stream_daemon: Arc::new(Mutex::new(stream_daemon)),
+			},
+		))
+	}
+
+	/// Build and start an output stream that, instead of stopping when an error occurs
+	/// (e.g. because the device has been disconnected), rebuilds itself following
+	/// the given [`ReconnectPolicy`], e.g. on the new default device when
+	/// [`ReconnectPolicy::fallback_to_default`] is set.
+	///
+	/// Every stream built by the reconnector is fed by the same `data_producer`, so the playback
+	/// resumes where it was, as do the volume, the channel gains and the capture.
+	/// While reconnecting, [`Self::state`] reports the error that stopped the last stream.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`] if the stream can't be built the first time.
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		data_producer: Box<DataProducer>,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		let (device, config) =
+			device_provider(sampling_ctx, device_name, crate::IOMode::Output, options)?;
+
+		let shared = Arc::new(Shared::new(sampling_ctx));
+		let data_producer = Arc::new(Mutex::new(data_producer));
+		let spawner = {
+			let shared = shared.clone();
+			move |device, config, events| {
+				let data_producer = data_producer.clone();
+				spawn_stream_daemon(
+					sampling_ctx,
+					Arc::new(CpalDevice::new(device, config, options)),
+					options,
+					shared.clone(),
+					Box::new(move |chunk| {
+						let (sampling_ctx, output) = chunk.into_raw();
+						// Only contended while the stream is being replaced,
+						// in which case the new one starts with silence.
+						let produced = data_producer.try_with_lock_mut(|data_producer| {
+							data_producer(InterleavedAudioBuffer::new(sampling_ctx, &mut *output));
+						});
+						if produced.is_none() {
+							output.fill(0.);
+						}
+					}),
+					None,
+					Some(events),
+				)
 			}
-		});
+		};
+
+		let (events, receiver) = mpsc::channel();
+		let stream_daemon = Arc::new(Mutex::new(spawner(device, config, events.clone())));
+		let reconnector = Reconnector::new(
+			ReconnectorConfig {
+				policy,
+				sampling_ctx,
+				device_name: device_name.map(ToOwned::to_owned),
+				mode: crate::IOMode::Output,
+				options,
+			},
+			stream_daemon.clone(),
+			Box::new(spawner),
+			events,
+			receiver,
+			on_event,
+		);
 
-		Ok(Self {
+		Ok(Self::with_backend(
 			sampling_ctx,
 			shared,
-			stream_daemon,
-		})
+			Backend::Device {
+				reconnector: Some(reconnector),
+				stream_daemon,
+			},
+		))
+	}
+
+	/// Build and start an output stream on a custom [`AudioDevice`], e.g. a [`crate::backend::MockDevice`]
+	/// to test what is played without audio hardware.
+	///
+	/// Returns once the device has started the stream, or has failed to, see [`Self::state`].
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		data_producer: Box<DataProducer>,
+		on_error: Option<Box<OnErrorCallback>>,
+	) -> Self {
+		let shared = Arc::new(Shared::new(sampling_ctx));
+		let (events, receiver) = mpsc::channel();
+		let stream_daemon = spawn_stream_daemon(
+			sampling_ctx,
+			Arc::new(device),
+			StreamOptions::default(),
+			shared.clone(),
+			data_producer,
+			on_error,
+			Some(events),
+		);
+		// Either `Started` or `Failed`, the errors reported later are dropped with the receiver.
+		let _ = receiver.recv();
+
+		Self::with_backend(
+			sampling_ctx,
+			shared,
+			Backend::Device {
+				reconnector: None,
+				stream_daemon: Arc::new(Mutex::new(stream_daemon)),
+			},
+		)
+	}
+
+	/// Build a stream that isn't connected to any device: the output of `data_producer`
+	/// is generated, faster than real time, only when requested with [`Self::render`]
+	/// or [`Self::render_to_wav_file`], e.g. to export or test generated audio.
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx, data_producer: Box<DataProducer>) -> Self {
+		Self::with_backend(
+			sampling_ctx,
+			Arc::new(Shared::new(sampling_ctx)),
+			Backend::Offline(Mutex::new(data_producer)),
+		)
+	}
+
+	fn with_backend(sampling_ctx: SamplingCtx, shared: Arc<Shared>, backend: Backend) -> Self {
+		Self {
+			sampling_ctx,
+			shared,
+			limiter_config: Mutex::new(None),
+			capture: Mutex::new(None),
+			backend,
+		}
 	}
 
 	#[must_use]
 	pub fn state(&self) -> AudioStreamSamplingState {
-		match self.stream_daemon.state() {
+		let Backend::Device { stream_daemon, .. } = &self.backend else {
+			return AudioStreamSamplingState::Sampling;
+		};
+		match stream_daemon.with_lock(ResourceDaemon::state) {
 			resource_daemon::DaemonState::Holding => AudioStreamSamplingState::Sampling,
 			resource_daemon::DaemonState::Quitting(reason)
 			| resource_daemon::DaemonState::Quit(reason) => {
@@ -114,6 +361,170 @@ impl OutputStream {
 		}
 	}
 
+	/// Whether the stream has been built with [`Self::new_offline`].
+	#[must_use]
+	pub fn is_offline(&self) -> bool {
+		matches!(self.backend, Backend::Offline(_))
+	}
+
+	/// Generate the next `n_of_frames` of an offline stream, see [`Self::new_offline`].
+	///
+	/// # Panics
+	/// - if the stream is not offline.
+	/// - if the mutex guarding the data producer is poisoned.
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		let mut output = Vec::with_capacity(self.sampling_ctx.frames_to_samples(n_of_frames));
+		let Ok(()) = self.render_chunks(n_of_frames, |chunk| {
+			output.extend_from_slice(chunk);
+			Ok::<_, Infallible>(())
+		});
+		InterleavedAudioBuffer::new(self.sampling_ctx, output)
+	}
+
+	/// Generate the next `n_of_frames` of an offline stream, writing them to a WAV file
+	/// (32-bit float samples), see [`Self::new_offline`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the stream is not offline.
+	/// - if the mutex guarding the data producer is poisoned.
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		let mut writer = WavWriter::new(BufWriter::new(File::create(path)?), self.sampling_ctx)?;
+		self.render_chunks(n_of_frames, |chunk| writer.write(chunk))?;
+		writer.finalize()
+	}
+
+	/// Generate the next frames of an offline stream into `output`, e.g. to use it
+	/// as a source of a [`super::Mixer`], see [`Self::new_offline`].
+	///
+	/// # Panics
+	/// - if the stream is not offline.
+	/// - if `output` has a different number of channels than the stream.
+	/// - if the mutex guarding the data producer is poisoned.
+	pub fn render_into(&self, mut output: InterleavedAudioBuffer<&mut [f32]>) {
+		assert_eq!(
+			output.n_ch(),
+			self.n_ch(),
+			"buffer with incompatible number of channels received"
+		);
+		self.render_chunk(output.raw_buffer_mut());
+	}
+
+	fn render_chunks<E>(
+		&self,
+		n_of_frames: NOfFrames,
+		mut sink: impl FnMut(&[f32]) -> Result<(), E>,
+	) -> Result<(), E> {
+		let mut chunk = vec![0.; self.sampling_ctx.frames_to_samples(OFFLINE_CHUNK_LEN)];
+		let mut remaining = n_of_frames;
+		while remaining > NOfFrames(0) {
+			let chunk_len = remaining.min(OFFLINE_CHUNK_LEN);
+			let chunk = &mut chunk[..self.sampling_ctx.frames_to_samples(chunk_len)];
+			chunk.fill(0.);
+			self.render_chunk(chunk);
+			sink(chunk)?;
+			remaining -= chunk_len;
+		}
+		Ok(())
+	}
+
+	fn render_chunk(&self, chunk: &mut [f32]) {
+		let Backend::Offline(data_producer) = &self.backend else {
+			panic!("only offline streams can be rendered");
+		};
+		#[cfg(feature = "audit")]
+		let _audit = crate::audit::CallbackGuard::enter();
+		let start = Instant::now();
+		data_producer.with_lock_mut(|data_producer| {
+			data_producer(InterleavedAudioBuffer::new(self.sampling_ctx, &mut *chunk));
+		});
+		self.shared.processing.with_lock_mut(|processing| {
+			processing.update(&self.shared.controls);
+			processing.process(self.sampling_ctx, chunk);
+		});
+		self.shared.state.with_lock_mut(|state| {
+			state.stats.record_callback(
+				self.sampling_ctx.samples_to_frames(chunk.len()),
+				start.elapsed(),
+			);
+		});
+	}
+
+	/// Replace the processors applied, in order, to the output of the data producer
+	/// after the volume and the channel gains, e.g. to insert an EQ or a limiter
+	/// without changing the data producer.
+	///
+	/// Processors with their own state can be wrapped in a closure,
+	/// e.g. `Box::new(move |chunk| limiter.process(chunk))`.
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.shared.controls.processors.send(processors);
+	}
+
+	/// Keep the peaks of the output under a ceiling with a [`Limiter`], applied after
+	/// the volume, the channel gains and the processors, or remove it with `None`.
+	///
+	/// The limiter delays the output by its look-ahead.
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		let limiter = config.map(|config| Limiter::new(self.sampling_ctx, config));
+		self.limiter_config.with_lock_mut(|limiter_config| {
+			*limiter_config = config;
+			self.shared.controls.limiter.send(limiter);
+		});
+	}
+
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.limiter_config
+			.with_lock(|limiter_config| *limiter_config)
+	}
+
+	/// Report when the frames are played into `clock`, or stop with `None`, see [`StreamClock`].
+	/// The frames are counted from the start of the stream, across reconnections.
+	/// Offline streams don't report.
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.shared.controls.clock.send(clock);
+	}
+
+	/// Mirror everything sent to the device, after the gains, the processors and the limiter, to a WAV file (32-bit float samples),
+	/// e.g. to check what has actually been played while debugging.
+	///
+	/// The file is written by a worker thread, with at most `queue_len` chunks waiting
+	/// to be written: if the writer can't keep up, the chunks that don't fit are dropped
+	/// instead of blocking the playback. A capture that was already running is stopped first.
+	///
+	/// The file is finalized by [`Self::stop_capture`] or when the stream is dropped.
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed, see [`Self::stop_capture`].
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		let (capture, finisher) =
+			WavFileSink::new_detached(self.sampling_ctx, path.as_ref(), queue_len)?;
+		self.capture
+			.with_lock_mut(|current| {
+				self.shared.controls.capture.send(Some(capture));
+				current.replace(finisher)
+			})
+			.map_or(Ok(()), WavFileFinisher::finish)
+	}
+
+	/// Stop mirroring the output to a file, see [`Self::start_capture`], and wait for the file
+	/// to be finalized. Does nothing if no capture is running.
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.capture
+			.with_lock_mut(|current| {
+				self.shared.controls.capture.send(None);
+				current.take()
+			})
+			.map_or(Ok(()), WavFileFinisher::finish)
+	}
+
 	#[must_use]
 	pub fn sampling_ctx(&self) -> SamplingCtx {
 		self.sampling_ctx
@@ -132,6 +543,366 @@ impl OutputStream {
 	#[must_use]
 	pub fn avg_output_delay(&self) -> Duration {
 		self.shared
-			.with_lock(|shared| shared.output_delay_moving_avg.avg())
+			.state
+			.with_lock(|state| state.output_delay_moving_avg.avg())
+	}
+
+	/// A snapshot of the callback timings, buffer sizes and errors of the stream, see [`StreamStats`].
+	///
+	/// For offline streams, every chunk generated by the render methods counts as a callback.
+	///
+	/// The callback never waits for this snapshot: if it finds it in progress, it publishes
+	/// its stats with the next chunk.
+	#[must_use]
+	pub fn stats(&self) -> StreamStats {
+		self.shared
+			.state
+			.with_lock(|state| state.stats.snapshot(state.output_delay_moving_avg.avg()))
+	}
+
+	/// The gain applied to all the channels, on top of the ones set by [`Self::set_channel_gains`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.shared.gain_targets.volume()
+	}
+
+	/// Set the gain applied to all the channels (1.0 by default), e.g. 0.5 for about -6 dB.
+	///
+	/// The change is ramped over a few milliseconds, to avoid clicks. It never waits for,
+	/// nor holds up, the audio callback.
+	pub fn set_volume(&self, volume: f32) {
+		self.shared.gain_targets.set_volume(volume);
+	}
+
+	/// The gain applied to each channel, on top of [`Self::volume`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.shared.gain_targets.channel_gains()
+	}
+
+	/// Set the gain applied to each channel (1.0 by default), e.g. to balance a stereo signal.
+	///
+	/// The change is ramped over a few milliseconds, to avoid clicks. Like [`Self::set_volume`],
+	/// it never waits for, nor holds up, the audio callback.
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		assert_eq!(
+			gains.len(),
+			self.n_ch(),
+			"the number of gains must match the number of channels"
+		);
+		self.shared.gain_targets.set_channel_gains(gains);
+	}
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)] // REASON: private helper shared by the constructors
+fn spawn_stream_daemon(
+	sampling_ctx: SamplingCtx,
+	device: Arc<dyn AudioDevice>,
+	// Only used to promote the callback thread.
+	#[cfg_attr(not(feature = "realtime"), allow(unused_variables))] options: StreamOptions,
+	shared: Arc<Shared>,
+	mut data_producer: Box<DataProducer>,
+	mut on_error: Option<Box<OnErrorCallback>>,
+	events: Option<Sender<StreamEvent>>,
+) -> StreamDaemon {
+	ResourceDaemon::new(move |quit_signal| {
+		let error_reporter = Arc::new(Mutex::new(ErrorReporter::new(
+			quit_signal,
+			on_error.take(),
+			events.clone(),
+		)));
+		let fill = {
+			let shared = shared.clone();
+			let error_reporter = error_reporter.clone();
+			#[cfg(feature = "realtime")]
+			let mut realtime_promotion = RealtimePromotion::new(sampling_ctx, options);
+			// Not yet published to the shared state, see below.
+			let mut pending_stats = StatsCollector::default();
+
+			move |output: &mut [f32], timestamp: OutputStreamTimestamp| {
+				let callback_start = Instant::now();
+				#[cfg(feature = "realtime")]
+				realtime_promotion.promote_current_thread();
+				#[cfg(feature = "tracing")]
+				let _span = tracing::trace_span!("output_callback", samples = output.len()).entered();
+				#[cfg(feature = "audit")]
+				let _audit = crate::audit::CallbackGuard::enter();
+
+				if !output.len().is_multiple_of(sampling_ctx.n_ch()) {
+					output.fill(0.);
+					pending_stats.record_error();
+					shared.state.try_with_lock_mut(|state| {
+						state.stats.merge(std::mem::take(&mut pending_stats));
+					});
+					// Only contended while the error callback is reporting, in which case
+					// the stream is stopping anyway.
+					error_reporter.try_with_lock_mut(|reporter| {
+						reporter.report(AudioStreamError::FormatChanged);
+					});
+					return;
+				}
+
+				let wrapped = InterleavedAudioBuffer::new(sampling_ctx, &mut *output);
+				let output_buffer_frames = wrapped.n_of_frames();
+
+				data_producer(wrapped);
+
+				// Only contended while the stream is being replaced, in which case
+				// the new one starts with silence.
+				let processed = shared.processing.try_with_lock_mut(|processing| {
+					processing.update(&shared.controls);
+					processing.process(sampling_ctx, output);
+					if let Some(clock) = &processing.clock {
+						clock.report(
+							IOMode::Output,
+							processing.played_frames,
+							timestamp.playback,
+							sampling_ctx.sample_rate(),
+						);
+					}
+					processing.played_frames += output_buffer_frames;
+				});
+				if processed.is_none() {
+					output.fill(0.);
+				}
+
+				pending_stats.record_callback(output_buffer_frames, callback_start.elapsed());
+				// The lock is also taken by the getters, from other threads: rather than
+				// waiting for them, the delay skips this chunk, and the stats are published
+				// with the next one.
+				shared.state.try_with_lock_mut(|state| {
+					state.output_delay_moving_avg.push(
+						timestamp
+							.playback
+							.duration_since(&timestamp.callback)
+							.unwrap_or(Duration::ZERO)
+							+ sampling_ctx.frames_to_duration(output_buffer_frames),
+					);
+					state.stats.merge(std::mem::take(&mut pending_stats));
+				});
+			}
+		};
+		let on_stream_error = {
+			let mut pending_stats = StatsCollector::default();
+			move |err: AudioStreamError| {
+				pending_stats.record_error();
+				shared.state.try_with_lock_mut(|state| {
+					state.stats.merge(std::mem::take(&mut pending_stats));
+				});
+				error_reporter.try_with_lock_mut(|reporter| reporter.report(err));
+			}
+		};
+
+		device
+			.build_output_stream(sampling_ctx, Box::new(fill), Box::new(on_stream_error))
+			.inspect(|_| {
+				#[cfg(feature = "tracing")]
+				tracing::info!(device = %device.name(), %sampling_ctx, "output stream started");
+				if let Some(events) = &events {
+					let _ = events.send(StreamEvent::Started);
+				}
+			})
+			.inspect_err(|err| {
+				#[cfg(feature = "tracing")]
+				tracing::error!(error = %err, %sampling_ctx, "unable to start the output stream");
+				if let Some(events) = &events {
+					let _ = events.send(StreamEvent::Failed(err.clone()));
+				}
+			})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{backend::MockDevice, SampleRate};
+
+	use super::*;
+
+	#[allow(clippy::cast_precision_loss)]
+	fn counter_stream(sampling_ctx: SamplingCtx) -> OutputStream {
+		let mut counter = 0;
+		OutputStream::new_offline(
+			sampling_ctx,
+			Box::new(move |mut chunk| {
+				for mut frame in &mut chunk {
+					frame.samples_mut().fill(counter as f32);
+					counter += 1;
+				}
+			}),
+		)
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp, clippy::cast_precision_loss)]
+	fn offline_rendering() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let stream = counter_stream(sampling_ctx);
+		assert!(stream.is_offline());
+		assert_eq!(stream.state(), AudioStreamSamplingState::Sampling);
+
+		let first = stream.render(NOfFrames(1000));
+		let second = stream.render(NOfFrames(10));
+		assert_eq!(first.n_of_frames(), NOfFrames(1000));
+		for (i, frame) in first.iter().chain(second.iter()).enumerate() {
+			assert_eq!(frame.samples(), [i as f32; 2]);
+		}
+
+		let path = std::env::temp_dir().join("output_stream_offline_rendering.wav");
+		stream.render_to_wav_file(&path, NOfFrames(600)).unwrap();
+		let len = std::fs::metadata(&path).unwrap().len();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(len, 44 + 600 * 2 * 4);
+	}
+
+	#[test]
+	fn offline_chunks_are_counted_in_the_stats() {
+		let stream = counter_stream(SamplingCtx::new(SampleRate(48000), 2));
+		assert_eq!(stream.stats(), StreamStats::default());
+
+		let _ = stream.render(NOfFrames(1100));
+		let stats = stream.stats();
+		assert_eq!(stats.callbacks, 3);
+		assert_eq!(stats.min_buffer_size, Some(NOfFrames(76)));
+		assert_eq!(stats.max_buffer_size, Some(OFFLINE_CHUNK_LEN));
+		assert_eq!(stats.last_buffer_size, Some(NOfFrames(76)));
+		assert!(stats.max_callback_duration >= stats.avg_callback_duration);
+		assert_eq!(stats.errors, 0);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp, clippy::cast_precision_loss)]
+	fn processors_are_applied_in_order() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let stream = counter_stream(sampling_ctx);
+		stream.set_processors(vec![
+			Box::new(|chunk| chunk.raw_buffer_mut().iter_mut().for_each(|s| *s += 1.)),
+			Box::new(|chunk| chunk.raw_buffer_mut().iter_mut().for_each(|s| *s *= 2.)),
+		]);
+		for (i, frame) in stream.render(NOfFrames(10)).iter().enumerate() {
+			assert_eq!(frame.samples(), [(i as f32 + 1.) * 2.; 2]);
+		}
+
+		stream.set_processors(vec![]);
+		assert_eq!(stream.render(NOfFrames(1)).raw_buffer(), &[10.; 2]);
+	}
+
+	#[test]
+	fn limiter_is_applied_after_the_gains() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let stream = OutputStream::new_offline(
+			sampling_ctx,
+			Box::new(|mut chunk| chunk.raw_buffer_mut().fill(0.5)),
+		);
+		stream.set_volume(4.);
+		let config = LimiterConfig {
+			ceiling_dB: -6.,
+			..Default::default()
+		};
+		stream.set_limiter(Some(config));
+		assert_eq!(stream.limiter(), Some(config));
+
+		let output = stream.render(NOfFrames(48000));
+		let ceiling = crate::metering::dB_to_amplitude(-6.);
+		assert!(output.raw_buffer().iter().all(|s| *s <= ceiling));
+		assert!(output.raw_buffer()[2 * 47000..]
+			.iter()
+			.all(|s| (s - ceiling).abs() < 1e-3));
+
+		stream.set_limiter(None);
+		assert!(stream
+			.render(NOfFrames(1))
+			.raw_buffer()
+			.iter()
+			.all(|s| *s > 1.));
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn capture_mirrors_the_output() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+		let stream = counter_stream(sampling_ctx);
+		stream.set_volume(0.5);
+		let _ = stream.render(NOfFrames(48000));
+
+		let path = std::env::temp_dir().join("output_stream_capture.wav");
+		stream.start_capture(&path, 1024).unwrap();
+		let rendered = stream.render(NOfFrames(1000));
+		stream.stop_capture().unwrap();
+		let _ = stream.render(NOfFrames(10));
+		stream.stop_capture().unwrap();
+
+		let bytes = std::fs::read(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		let captured: Vec<f32> = bytes[44..]
+			.chunks_exact(4)
+			.map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+			.collect();
+		// The capture comes after the gains.
+		assert_eq!(&captured, rendered.raw_buffer());
+		assert_eq!(captured[2..4], [48001. * 0.5; 2]);
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp, clippy::cast_precision_loss)]
+	fn playback_on_a_mock_device() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(1000), 2);
+		let device = MockDevice::new("mock", sampling_ctx);
+		let mut counter = 0;
+		let stream = OutputStream::new_on_device(
+			device.clone(),
+			sampling_ctx,
+			Box::new(move |mut chunk| {
+				for mut frame in &mut chunk {
+					frame.samples_mut().fill(counter as f32);
+					counter += 1;
+				}
+			}),
+			None,
+		);
+		assert_eq!(stream.state(), AudioStreamSamplingState::Sampling);
+		assert_eq!(device.n_of_streams(IOMode::Output), 1);
+
+		stream.set_processors(vec![Box::new(|chunk| {
+			chunk.raw_buffer_mut().iter_mut().for_each(|s| *s += 1.);
+		})]);
+		device.process(NOfFrames(10));
+		device.process(NOfFrames(6));
+		let output = device.take_output();
+		assert_eq!(output.n_of_frames(), NOfFrames(16));
+		for (i, frame) in output.iter().enumerate() {
+			assert_eq!(frame.samples(), [i as f32 + 1.; 2]);
+		}
+		let stats = stream.stats();
+		assert_eq!(stats.callbacks, 2);
+		assert_eq!(stats.last_buffer_size, Some(NOfFrames(6)));
+
+		device.fail(&AudioStreamError::DeviceNotAvailable);
+		assert_eq!(
+			stream.state(),
+			AudioStreamSamplingState::Stopped(AudioStreamError::DeviceNotAvailable)
+		);
+		assert_eq!(stream.stats().errors, 1);
+		drop(stream);
+		assert_eq!(device.n_of_streams(IOMode::Output), 0);
+	}
+
+	#[test]
+	fn unsupported_configurations_of_a_mock_device() {
+		let device = MockDevice::new("mock", SamplingCtx::new(SampleRate(1000), 2));
+		let stream = OutputStream::new_on_device(
+			device.clone(),
+			SamplingCtx::new(SampleRate(1000), 1),
+			Box::new(|_| ()),
+			None,
+		);
+		assert!(matches!(
+			stream.state(),
+			AudioStreamSamplingState::Stopped(AudioStreamError::BuildFailed { device_name, .. })
+				if device_name == "mock"
+		));
+		assert_eq!(device.n_of_streams(IOMode::Output), 0);
 	}
 }
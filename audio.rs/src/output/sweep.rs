@@ -0,0 +1,46 @@
+use std::{f64::consts::TAU, time::Duration};
+
+use crate::{SampleRate, SamplingCtx};
+
+/// Generate an exponential sine sweep (ESS), i.e. a sine wave whose frequency grows exponentially
+/// from `start_frequency` to `end_frequency` over the specified `duration`.
+///
+/// Playing this signal and recording the response of a system (e.g. a speaker in a room) makes it possible to
+/// compute its impulse response, see [`crate::analysis::ImpulseResponse::from_sweep`].
+///
+/// # Panics
+/// - if `start_frequency` is not positive, or if it's not lower than `end_frequency`.
+/// - if `end_frequency` is above the Nyquist frequency.
+#[must_use]
+pub fn exponential_sine_sweep(
+	sample_rate: SampleRate,
+	start_frequency: f32,
+	end_frequency: f32,
+	duration: Duration,
+) -> Vec<f32> {
+	assert!(
+		start_frequency > 0. && start_frequency < end_frequency,
+		"the frequency range must be increasing and positive"
+	);
+	#[allow(clippy::cast_precision_loss)]
+	let nyquist_frequency = sample_rate.0 as f32 / 2.;
+	assert!(
+		end_frequency <= nyquist_frequency,
+		"end_frequency must not exceed the Nyquist frequency ({nyquist_frequency}Hz)"
+	);
+
+	let n_of_samples = SamplingCtx::new(sample_rate, 1)
+		.duration_to_frames(duration)
+		.0;
+	let duration = duration.as_secs_f64();
+	let log_ratio = (f64::from(end_frequency) / f64::from(start_frequency)).ln();
+	let scale = TAU * f64::from(start_frequency) * duration / log_ratio;
+
+	(0..n_of_samples)
+		.map(|i| {
+			#[allow(clippy::cast_precision_loss)]
+			let t = i as f64 / sample_rate.0 as f64;
+			(scale * ((t * log_ratio / duration).exp() - 1.)).sin() as f32
+		})
+		.collect()
+}
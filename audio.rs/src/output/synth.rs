@@ -0,0 +1,701 @@
+#![allow(clippy::cast_precision_loss)]
+
+use std::{
+	convert::Infallible,
+	io,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	backend::AudioDevice, buffers::InterleavedAudioBuffer, AudioStreamBuilderError,
+	AudioStreamSamplingState, NOfFrames, OnReconnectEventCallback, ReconnectPolicy, SampleRate,
+	SamplingCtx, StreamClock, StreamOptions,
+};
+
+#[cfg(feature = "midi")]
+use crate::midi::{MidiEvent, CC_ALL_NOTES_OFF, CC_ALL_SOUND_OFF, CC_VOLUME};
+
+use super::{
+	DataProducer, Envelope, EnvelopeStage, LimiterConfig, OutputProcessor, OutputStream, Waveform,
+};
+
+/// The default maximum number of notes played at the same time by a [`Synth`].
+pub const DEFAULT_POLYPHONY: usize = 16;
+
+/// The frequency of a MIDI note, in the twelve-tone equal temperament with A4 (69) at 440 Hz.
+#[must_use]
+pub fn midi_note_to_frequency(midi_note: u8) -> f32 {
+	440. * 2f32.powf((f32::from(midi_note) - 69.) / 12.)
+}
+
+#[derive(Debug, Clone)]
+struct Voice {
+	midi_note: u8,
+	/// The fraction of the cycle covered by a single frame.
+	dt: f32,
+	/// The position in the current cycle, from 0 to 1.
+	t: f32,
+	amplitude: f32,
+	envelope: Envelope,
+	/// When the note started, used to pick the voice to steal.
+	started_at: u64,
+}
+
+impl Voice {
+	fn next_value(&mut self, waveform: Waveform) -> f32 {
+		let value = waveform.value(self.t, self.dt) * self.amplitude * self.envelope.next_gain();
+		self.t = (self.t + self.dt).fract();
+		value
+	}
+}
+
+struct SynthState {
+	sample_rate: SampleRate,
+	voices: Vec<Voice>,
+	max_voices: usize,
+	waveform: Waveform,
+	envelope: Envelope,
+	/// The number of notes started so far.
+	n_of_notes: u64,
+}
+
+impl SynthState {
+	fn fill(&mut self, chunk: &mut InterleavedAudioBuffer<&mut [f32]>) {
+		for mut frame in chunk.iter_mut() {
+			let sample = self
+				.voices
+				.iter_mut()
+				.map(|voice| voice.next_value(self.waveform))
+				.sum::<f32>();
+			frame.samples_mut().fill(sample);
+		}
+		self.voices.retain(|voice| voice.envelope.is_active());
+	}
+
+	fn note_on(&mut self, midi_note: u8, velocity: u8) {
+		let amplitude = f32::from(velocity.min(127)) / 127.;
+		let started_at = self.n_of_notes;
+		self.n_of_notes += 1;
+
+		let idx = if let Some(idx) = self
+			.voices
+			.iter()
+			.position(|voice| voice.midi_note == midi_note)
+		{
+			Some(idx)
+		} else if self.voices.len() < self.max_voices {
+			self.voices.push(Voice {
+				midi_note,
+				dt: 0.,
+				t: 0.,
+				amplitude,
+				envelope: self.envelope.clone(),
+				started_at,
+			});
+			Some(self.voices.len() - 1)
+		} else {
+			self.voice_to_steal()
+		};
+
+		if let Some(voice) = idx.map(|idx| &mut self.voices[idx]) {
+			// The envelope of a retriggered or stolen voice restarts from its current gain,
+			// avoiding clicks.
+			voice.midi_note = midi_note;
+			voice.dt = (midi_note_to_frequency(midi_note) / self.sample_rate.0 as f32).min(0.5);
+			voice.amplitude = amplitude;
+			voice.started_at = started_at;
+			voice.envelope.note_on();
+		}
+	}
+
+	fn note_off(&mut self, midi_note: u8) {
+		for voice in &mut self.voices {
+			if voice.midi_note == midi_note {
+				voice.envelope.note_off();
+			}
+		}
+	}
+
+	/// The quietest of the released voices or, if all the notes are being held, the oldest one.
+	fn voice_to_steal(&self) -> Option<usize> {
+		let released = self
+			.voices
+			.iter()
+			.enumerate()
+			.filter(|(_, voice)| voice.envelope.stage() == EnvelopeStage::Release)
+			.min_by(|(_, a), (_, b)| a.envelope.gain().total_cmp(&b.envelope.gain()));
+		released
+			.or_else(|| {
+				self.voices
+					.iter()
+					.enumerate()
+					.min_by_key(|(_, voice)| voice.started_at)
+			})
+			.map(|(idx, _)| idx)
+	}
+}
+
+/// A polyphonic synthesizer, which plays each note with its own voice, shaped by an [`Envelope`],
+/// mixing all the voices into a single output stream.
+///
+/// When a note is started while all the voices are busy, the quietest released voice
+/// (or the oldest one, if all the notes are being held) is stolen.
+///
+/// Note: all the channels carry the same signal. The voices are summed without any scaling,
+/// so the volume (see [`Self::set_volume`]) should be lowered to avoid clipping when playing
+/// many notes at once.
+pub struct Synth {
+	shared: Arc<Mutex<SynthState>>,
+	base_stream: OutputStream,
+}
+
+impl Synth {
+	/// Build and start an output stream, which plays silence until a note is started.
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		envelope: Envelope,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			device_name,
+			envelope,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start an output stream, see [`StreamOptions`]
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		envelope: Envelope,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, envelope, |data_producer| {
+			OutputStream::new_with_options(sampling_ctx, device_name, data_producer, None, options)
+		})
+	}
+
+	/// Build and start an output stream that reconnects following the given [`ReconnectPolicy`],
+	/// picking up where it was, see [`OutputStream::new_with_reconnect`].
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_reconnect(
+		sampling_ctx: SamplingCtx,
+		device_name: Option<&str>,
+		envelope: Envelope,
+		policy: ReconnectPolicy,
+		on_event: Option<Box<OnReconnectEventCallback>>,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::build(sampling_ctx, envelope, |data_producer| {
+			OutputStream::new_with_reconnect(
+				sampling_ctx,
+				device_name,
+				data_producer,
+				policy,
+				on_event,
+				options,
+			)
+		})
+	}
+
+	/// Build an offline synth, which isn't connected to any device and generates its output
+	/// only when requested, see [`OutputStream::new_offline`].
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	#[must_use]
+	pub fn new_offline(sampling_ctx: SamplingCtx, envelope: Envelope) -> Self {
+		let Ok(synth) = Self::build(sampling_ctx, envelope, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_offline(sampling_ctx, data_producer))
+		});
+		synth
+	}
+
+	/// Build a synth that plays on `device`, see [`OutputStream::new_on_device`].
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	#[must_use]
+	pub fn new_on_device(
+		device: impl AudioDevice,
+		sampling_ctx: SamplingCtx,
+		envelope: Envelope,
+	) -> Self {
+		let Ok(synth) = Self::build(sampling_ctx, envelope, |data_producer| {
+			Ok::<_, Infallible>(OutputStream::new_on_device(
+				device,
+				sampling_ctx,
+				data_producer,
+				None,
+			))
+		});
+		synth
+	}
+
+	fn build<E>(
+		sampling_ctx: SamplingCtx,
+		envelope: Envelope,
+		base_stream: impl FnOnce(Box<DataProducer>) -> Result<OutputStream, E>,
+	) -> Result<Self, E> {
+		assert_eq!(
+			envelope.sample_rate(),
+			sampling_ctx.sample_rate(),
+			"envelope with incompatible sample rate received"
+		);
+		let shared = Arc::new(Mutex::new(SynthState {
+			sample_rate: sampling_ctx.sample_rate(),
+			voices: Vec::with_capacity(DEFAULT_POLYPHONY),
+			max_voices: DEFAULT_POLYPHONY,
+			waveform: Waveform::default(),
+			envelope,
+			n_of_notes: 0,
+		}));
+
+		let base_stream = base_stream(Box::new({
+			let shared = shared.clone();
+			move |mut chunk| {
+				// Only contended while the notes are being changed, in which case
+				// the chunk is skipped.
+				if shared
+					.try_with_lock_mut(|shared| shared.fill(&mut chunk))
+					.is_none()
+				{
+					chunk.raw_buffer_mut().fill(0.);
+				}
+			}
+		}))?;
+		Ok(Self {
+			shared,
+			base_stream,
+		})
+	}
+
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		self.base_stream.state()
+	}
+
+	/// Start playing a note, with a velocity from 1 to 127, where 0 is equivalent to
+	/// [`Self::note_off`] as in the MIDI protocol.
+	///
+	/// If the note is already being played, its voice is retriggered.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_on(&mut self, midi_note: u8, velocity: u8) {
+		if velocity == 0 {
+			self.note_off(midi_note);
+			return;
+		}
+		self.shared
+			.with_lock_mut(|shared| shared.note_on(midi_note, velocity));
+	}
+
+	/// Start the release of a note.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn note_off(&mut self, midi_note: u8) {
+		self.shared
+			.with_lock_mut(|shared| shared.note_off(midi_note));
+	}
+
+	/// Start the release of all the notes being played.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn all_notes_off(&mut self) {
+		self.shared.with_lock_mut(|shared| {
+			for voice in &mut shared.voices {
+				voice.envelope.note_off();
+			}
+		});
+	}
+
+	/// React to an event received from a `MidiInput`, regardless of its channel:
+	/// notes are started and released, [`CC_VOLUME`] sets the volume and
+	/// both [`CC_ALL_NOTES_OFF`] and [`CC_ALL_SOUND_OFF`] release all the notes.
+	/// Other controllers are ignored.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[cfg(feature = "midi")]
+	pub fn apply_midi_event(&mut self, event: MidiEvent) {
+		match event {
+			MidiEvent::NoteOn { note, velocity, .. } => self.note_on(note, velocity),
+			MidiEvent::NoteOff { note, .. } => self.note_off(note),
+			MidiEvent::ControlChange {
+				controller: CC_VOLUME,
+				value,
+				..
+			} => self.set_volume(f32::from(value) / 127.),
+			MidiEvent::ControlChange {
+				controller: CC_ALL_NOTES_OFF | CC_ALL_SOUND_OFF,
+				..
+			} => self.all_notes_off(),
+			MidiEvent::ControlChange { .. } => {}
+		}
+	}
+
+	/// The number of voices currently producing sound, including the released ones.
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn active_voices(&self) -> usize {
+		self.shared.with_lock(|shared| shared.voices.len())
+	}
+
+	/// Set the maximum number of notes played at the same time ([`DEFAULT_POLYPHONY`] by default).
+	/// The voices in excess are stopped immediately, starting from the oldest ones.
+	///
+	/// # Panics
+	/// - if `max_voices` is 0.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_polyphony(&mut self, max_voices: usize) {
+		assert!(max_voices > 0, "at least one voice is required");
+		self.shared.with_lock_mut(|shared| {
+			shared.max_voices = max_voices;
+			if shared.voices.len() > max_voices {
+				shared.voices.sort_by_key(|voice| voice.started_at);
+				let excess = shared.voices.len() - max_voices;
+				shared.voices.drain(..excess);
+			}
+		});
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn polyphony(&self) -> usize {
+		self.shared.with_lock(|shared| shared.max_voices)
+	}
+
+	/// Change the shape of the wave generated by the voices ([`Waveform::Sine`] by default).
+	///
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_waveform(&mut self, waveform: Waveform) {
+		self.shared
+			.with_lock_mut(|shared| shared.waveform = waveform);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn waveform(&self) -> Waveform {
+		self.shared.with_lock(|shared| shared.waveform)
+	}
+
+	/// Set the envelope of the next notes. The notes being played keep their envelope.
+	///
+	/// # Panics
+	/// - if the sample rate of the envelope is different from the one of the stream.
+	/// - if the mutex guarding the internal state is poisoned.
+	pub fn set_envelope(&mut self, envelope: Envelope) {
+		assert_eq!(
+			envelope.sample_rate(),
+			self.sample_rate(),
+			"envelope with incompatible sample rate received"
+		);
+		self.shared
+			.with_lock_mut(|shared| shared.envelope = envelope);
+	}
+
+	/// # Panics
+	/// - if the mutex guarding the internal state is poisoned.
+	#[must_use]
+	pub fn envelope(&self) -> Envelope {
+		self.shared.with_lock(|shared| shared.envelope.clone())
+	}
+
+	/// See [`OutputStream::render`].
+	///
+	/// # Panics
+	/// - if the synth is not offline, see [`Self::new_offline`].
+	#[must_use]
+	pub fn render(&self, n_of_frames: NOfFrames) -> InterleavedAudioBuffer<Vec<f32>> {
+		self.base_stream.render(n_of_frames)
+	}
+
+	/// See [`OutputStream::render_into`].
+	///
+	/// # Panics
+	/// - if the synth is not offline, see [`Self::new_offline`].
+	/// - if `output` has a different number of channels than the synth.
+	pub fn render_into(&self, output: InterleavedAudioBuffer<&mut [f32]>) {
+		self.base_stream.render_into(output);
+	}
+
+	/// See [`OutputStream::render_to_wav_file`].
+	///
+	/// # Errors
+	/// - if the file can't be created or written.
+	///
+	/// # Panics
+	/// - if the synth is not offline, see [`Self::new_offline`].
+	pub fn render_to_wav_file(&self, path: &Path, n_of_frames: NOfFrames) -> io::Result<()> {
+		self.base_stream.render_to_wav_file(path, n_of_frames)
+	}
+
+	/// See [`OutputStream::start_capture`].
+	///
+	/// # Errors
+	/// - if the file can't be created.
+	/// - if the capture that was already running failed.
+	pub fn start_capture(&self, path: impl AsRef<Path>, queue_len: usize) -> io::Result<()> {
+		self.base_stream.start_capture(path, queue_len)
+	}
+
+	/// See [`OutputStream::stop_capture`].
+	///
+	/// # Errors
+	/// The I/O error that stopped the writer, if any.
+	pub fn stop_capture(&self) -> io::Result<()> {
+		self.base_stream.stop_capture()
+	}
+
+	/// See [`OutputStream::set_clock`].
+	pub fn set_clock(&self, clock: Option<StreamClock>) {
+		self.base_stream.set_clock(clock);
+	}
+
+	/// See [`OutputStream::set_processors`].
+	pub fn set_processors(&self, processors: Vec<Box<OutputProcessor>>) {
+		self.base_stream.set_processors(processors);
+	}
+
+	/// See [`OutputStream::set_limiter`].
+	pub fn set_limiter(&self, config: Option<LimiterConfig>) {
+		self.base_stream.set_limiter(config);
+	}
+
+	/// See [`OutputStream::limiter`].
+	#[must_use]
+	pub fn limiter(&self) -> Option<LimiterConfig> {
+		self.base_stream.limiter()
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.base_stream.sampling_ctx()
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.base_stream.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.base_stream.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.base_stream.avg_output_delay()
+	}
+
+	/// See [`OutputStream::volume`].
+	#[must_use]
+	pub fn volume(&self) -> f32 {
+		self.base_stream.volume()
+	}
+
+	/// See [`OutputStream::set_volume`].
+	pub fn set_volume(&self, volume: f32) {
+		self.base_stream.set_volume(volume);
+	}
+
+	/// See [`OutputStream::channel_gains`].
+	#[must_use]
+	pub fn channel_gains(&self) -> Vec<f32> {
+		self.base_stream.channel_gains()
+	}
+
+	/// See [`OutputStream::set_channel_gains`].
+	///
+	/// # Panics
+	/// - if the number of gains is different from the number of channels.
+	pub fn set_channel_gains(&self, gains: &[f32]) {
+		self.base_stream.set_channel_gains(gains);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread::sleep;
+
+	use super::*;
+
+	fn synth_state(max_voices: usize) -> SynthState {
+		let sample_rate = SampleRate(1000);
+		SynthState {
+			sample_rate,
+			voices: vec![],
+			max_voices,
+			waveform: Waveform::Sine,
+			// 2 frames of attack and release.
+			envelope: Envelope::new(
+				sample_rate,
+				Duration::from_millis(2),
+				Duration::ZERO,
+				1.,
+				Duration::from_millis(2),
+			),
+			n_of_notes: 0,
+		}
+	}
+
+	fn fill(state: &mut SynthState, n_of_frames: usize) -> Vec<f32> {
+		let mut output = vec![0.; n_of_frames];
+		state.fill(&mut InterleavedAudioBuffer::new(
+			SamplingCtx::new(state.sample_rate, 1),
+			&mut output[..],
+		));
+		output
+	}
+
+	#[test]
+	fn midi_notes() {
+		assert!((midi_note_to_frequency(69) - 440.).abs() < 1e-3);
+		assert!((midi_note_to_frequency(81) - 880.).abs() < 1e-3);
+		assert!((midi_note_to_frequency(60) - 261.626).abs() < 1e-3);
+	}
+
+	#[test]
+	#[cfg(feature = "midi")]
+	#[allow(clippy::float_cmp)] // REASON: 127 / 127 is exact
+	fn midi_events() {
+		let sample_rate = SampleRate(1000);
+		let mut synth = Synth::new_offline(
+			SamplingCtx::new(sample_rate, 1),
+			Envelope::new(
+				sample_rate,
+				Duration::from_millis(2),
+				Duration::ZERO,
+				1.,
+				Duration::from_millis(2),
+			),
+		);
+		synth.apply_midi_event(MidiEvent::NoteOn {
+			channel: 0,
+			note: 60,
+			velocity: 100,
+		});
+		synth.apply_midi_event(MidiEvent::NoteOn {
+			channel: 3,
+			note: 64,
+			velocity: 100,
+		});
+		assert_eq!(synth.active_voices(), 2);
+
+		synth.apply_midi_event(MidiEvent::ControlChange {
+			channel: 0,
+			controller: CC_VOLUME,
+			value: 127,
+		});
+		assert_eq!(synth.volume(), 1.);
+
+		synth.apply_midi_event(MidiEvent::NoteOff {
+			channel: 0,
+			note: 60,
+			velocity: 0,
+		});
+		let _ = synth.render(NOfFrames(4));
+		assert_eq!(synth.active_voices(), 1);
+
+		synth.apply_midi_event(MidiEvent::ControlChange {
+			channel: 0,
+			controller: CC_ALL_NOTES_OFF,
+			value: 0,
+		});
+		let _ = synth.render(NOfFrames(4));
+		assert_eq!(synth.active_voices(), 0);
+	}
+
+	#[test]
+	fn voices_are_released() {
+		let mut state = synth_state(4);
+		state.note_on(60, 127);
+		state.note_on(64, 127);
+		state.note_on(60, 127);
+		assert_eq!(state.voices.len(), 2);
+
+		let output = fill(&mut state, 4);
+		// Both voices start from the peak of their cycle.
+		assert!((output[0] - 1.).abs() < 1e-6, "{output:?}");
+
+		state.note_off(60);
+		fill(&mut state, 2);
+		assert_eq!(state.voices.len(), 1);
+		assert_eq!(state.voices[0].midi_note, 64);
+	}
+
+	#[test]
+	fn voices_are_stolen() {
+		let mut state = synth_state(2);
+		state.note_on(60, 127);
+		state.note_on(62, 127);
+		fill(&mut state, 2);
+
+		// The oldest held note is stolen.
+		state.note_on(64, 127);
+		let notes: Vec<_> = state.voices.iter().map(|voice| voice.midi_note).collect();
+		assert_eq!(notes, [64, 62]);
+
+		// A released note is preferred.
+		fill(&mut state, 2);
+		state.note_off(64);
+		state.note_on(65, 127);
+		let notes: Vec<_> = state.voices.iter().map(|voice| voice.midi_note).collect();
+		assert_eq!(notes, [65, 62]);
+	}
+
+	#[test]
+	#[ignore = "manually listen to a C major chord"]
+	fn test_manual() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(44100), 1);
+		let mut synth = Synth::new(
+			sampling_ctx,
+			None,
+			Envelope::new(
+				sampling_ctx.sample_rate(),
+				Duration::from_millis(10),
+				Duration::from_millis(200),
+				0.6,
+				Duration::from_millis(500),
+			),
+		)
+		.unwrap();
+		synth.set_volume(0.2);
+		synth.set_waveform(Waveform::Sawtooth);
+		for midi_note in [60, 64, 67] {
+			synth.note_on(midi_note, 100);
+			sleep(Duration::from_millis(300));
+		}
+		sleep(Duration::from_secs(1));
+		synth.all_notes_off();
+		sleep(Duration::from_secs(1));
+		assert_eq!(synth.active_voices(), 0);
+	}
+}
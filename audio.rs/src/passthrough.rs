@@ -0,0 +1,501 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use crate::{
+	callback_capacity, input::InputStream, output::OutputStream, AudioStreamBuilderError,
+	AudioStreamSamplingState, DriftCompensator, DriftEstimator, NOfFrames, SampleRate, SamplingCtx,
+	StreamOptions,
+};
+
+/// How long the streams must run before the drift between them is estimated.
+const DRIFT_ESTIMATION_SPAN: Duration = Duration::from_secs(10);
+/// The correction added when the FIFO is empty (or subtracted when it's full),
+/// to steer it back to its target level.
+const LEVEL_CORRECTION_PPM: f64 = 200.;
+/// The largest correction applied, well above the drift between real devices.
+const MAX_CORRECTION_PPM: f64 = 1000.;
+
+/// Plays the signal of an input device on an output device, e.g. to monitor a microphone
+/// or to measure the round-trip latency of an audio interface.
+///
+/// The two streams are connected by a lock-free FIFO of a given capacity. The output starts
+/// (and, after an underrun, resumes) reading from it only once it's half full, which trades
+/// some latency for robustness against the jitter of the callbacks.
+///
+/// The clocks of two devices always run at slightly different rates, which would eventually
+/// empty or overflow the FIFO. The drift between them is estimated from the number of frames
+/// each stream has transferred, and the input signal is resampled by a few parts per million
+/// to compensate for it, see [`Self::set_drift_compensation`].
+pub struct Passthrough {
+	sampling_ctx: SamplingCtx,
+	shared: Arc<PassthroughState>,
+	input_stream: InputStream,
+	output_stream: OutputStream,
+}
+
+impl Passthrough {
+	/// Build and start an input and an output stream connected by a FIFO of `fifo_len` frames.
+	///
+	/// # Panics
+	/// - if `fifo_len` is less than 2.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new(
+		sampling_ctx: SamplingCtx,
+		input_device_name: Option<&str>,
+		output_device_name: Option<&str>,
+		fifo_len: NOfFrames,
+	) -> Result<Self, AudioStreamBuilderError> {
+		Self::new_with_options(
+			sampling_ctx,
+			input_device_name,
+			output_device_name,
+			fifo_len,
+			StreamOptions::default(),
+		)
+	}
+
+	/// Build and start an input and an output stream connected by a FIFO of `fifo_len` frames,
+	/// see [`StreamOptions`]
+	///
+	/// # Panics
+	/// - if `fifo_len` is less than 2.
+	///
+	/// # Errors
+	/// [`AudioStreamBuilderError`]
+	pub fn new_with_options(
+		sampling_ctx: SamplingCtx,
+		input_device_name: Option<&str>,
+		output_device_name: Option<&str>,
+		fifo_len: NOfFrames,
+		options: StreamOptions,
+	) -> Result<Self, AudioStreamBuilderError> {
+		assert!(fifo_len >= NOfFrames(2), "fifo_len must be at least 2");
+
+		let shared = Arc::new(PassthroughState::new(sampling_ctx, fifo_len));
+
+		// The output is built first, so that it's ready to consume the input as soon as it arrives.
+		let output_stream = OutputStream::new_with_options(
+			sampling_ctx,
+			output_device_name,
+			Box::new({
+				let shared = shared.clone();
+				move |mut output| shared.read(output.raw_buffer_mut())
+			}),
+			None,
+			options,
+		)?;
+
+		let input_stream = InputStream::new_with_options(
+			sampling_ctx,
+			input_device_name,
+			Box::new({
+				let shared = shared.clone();
+				let mut drift =
+					DriftState::new(sampling_ctx, callback_capacity(options.buffer_size));
+				move |chunk, _| shared.write_compensated(chunk.raw_buffer(), &mut drift)
+			}),
+			None,
+			options,
+		)?;
+
+		Ok(Self {
+			sampling_ctx,
+			shared,
+			input_stream,
+			output_stream,
+		})
+	}
+
+	/// The state of the passthrough, i.e. the error that stopped one of the streams, if any.
+	#[must_use]
+	pub fn state(&self) -> AudioStreamSamplingState {
+		match self.input_stream.state() {
+			AudioStreamSamplingState::Sampling => self.output_stream.state(),
+			stopped @ AudioStreamSamplingState::Stopped(_) => stopped,
+		}
+	}
+
+	/// An estimate of the time it takes for the signal to go from the input to the output device,
+	/// i.e. the sum of the average input delay, the time spent in the FIFO and the average output delay.
+	#[must_use]
+	pub fn latency(&self) -> Duration {
+		self.input_stream.avg_input_delay()
+			+ self.sampling_ctx.frames_to_duration(self.buffered_frames())
+			+ self.output_stream.avg_output_delay()
+	}
+
+	/// The number of frames currently in the FIFO.
+	#[must_use]
+	pub fn buffered_frames(&self) -> NOfFrames {
+		self.sampling_ctx.samples_to_frames(self.shared.fifo.len())
+	}
+
+	/// The number of times the output didn't find enough frames in the FIFO, and played silence instead.
+	#[must_use]
+	pub fn underruns(&self) -> usize {
+		self.shared.underruns.load(Ordering::Relaxed)
+	}
+
+	/// The number of times the input found the FIFO full, and dropped part of its signal.
+	#[must_use]
+	pub fn overruns(&self) -> usize {
+		self.shared.overruns.load(Ordering::Relaxed)
+	}
+
+	/// How much faster the output device runs compared to the input one, in parts per million,
+	/// or `None` until the streams have run for a few seconds.
+	#[must_use]
+	pub fn skew_ppm(&self) -> Option<f64> {
+		self.shared.skew_ppm()
+	}
+
+	/// Enable or disable the compensation of the drift between the two devices, enabled by default.
+	pub fn set_drift_compensation(&self, enabled: bool) {
+		self.shared
+			.drift_compensation
+			.store(enabled, Ordering::Relaxed);
+	}
+
+	#[must_use]
+	pub fn drift_compensation(&self) -> bool {
+		self.shared.drift_compensation.load(Ordering::Relaxed)
+	}
+
+	#[must_use]
+	pub fn sampling_ctx(&self) -> SamplingCtx {
+		self.sampling_ctx
+	}
+
+	#[must_use]
+	pub fn sample_rate(&self) -> SampleRate {
+		self.sampling_ctx.sample_rate()
+	}
+
+	#[must_use]
+	pub fn n_ch(&self) -> usize {
+		self.sampling_ctx.n_ch()
+	}
+
+	#[must_use]
+	pub fn avg_input_delay(&self) -> Duration {
+		self.input_stream.avg_input_delay()
+	}
+
+	#[must_use]
+	pub fn avg_output_delay(&self) -> Duration {
+		self.output_stream.avg_output_delay()
+	}
+}
+
+struct PassthroughState {
+	sampling_ctx: SamplingCtx,
+	fifo: SampleFifo,
+	/// The number of samples the FIFO must contain before the output starts reading from it.
+	prime_len: usize,
+	primed: AtomicBool,
+	underruns: AtomicUsize,
+	overruns: AtomicUsize,
+	/// The total number of frames requested by the output.
+	output_frames: AtomicUsize,
+	/// The bits of the estimated skew, NaN if not available yet.
+	skew_ppm: AtomicU64,
+	drift_compensation: AtomicBool,
+}
+
+impl PassthroughState {
+	fn new(sampling_ctx: SamplingCtx, fifo_len: NOfFrames) -> Self {
+		Self {
+			sampling_ctx,
+			fifo: SampleFifo::new(sampling_ctx.frames_to_samples(fifo_len)),
+			prime_len: sampling_ctx.frames_to_samples(fifo_len / 2),
+			primed: AtomicBool::new(false),
+			underruns: AtomicUsize::new(0),
+			overruns: AtomicUsize::new(0),
+			output_frames: AtomicUsize::new(0),
+			skew_ppm: AtomicU64::new(f64::NAN.to_bits()),
+			drift_compensation: AtomicBool::new(true),
+		}
+	}
+
+	fn skew_ppm(&self) -> Option<f64> {
+		Some(f64::from_bits(self.skew_ppm.load(Ordering::Relaxed))).filter(|skew| !skew.is_nan())
+	}
+
+	/// Update the drift estimate and write `data`, resampled to compensate for the drift if enabled.
+	fn write_compensated(&self, data: &[f32], drift: &mut DriftState) {
+		drift.input_frames += self.sampling_ctx.samples_to_frames(data.len());
+		drift.estimator.update(
+			drift.input_frames,
+			NOfFrames(self.output_frames.load(Ordering::Relaxed)),
+		);
+		let skew_ppm = drift.estimator.skew_ppm();
+		if let Some(skew_ppm) = skew_ppm {
+			self.skew_ppm.store(skew_ppm.to_bits(), Ordering::Relaxed);
+		}
+
+		let correction_ppm = match skew_ppm {
+			Some(skew_ppm) if self.drift_compensation.load(Ordering::Relaxed) => {
+				// The FIFO level also drifted before the estimate was available.
+				#[allow(clippy::cast_precision_loss)]
+				let level_error = (self.prime_len as f64 - self.fifo.len() as f64) / self.prime_len as f64;
+				(skew_ppm + LEVEL_CORRECTION_PPM * level_error)
+					.clamp(-MAX_CORRECTION_PPM, MAX_CORRECTION_PPM)
+			}
+			_ => 0.,
+		};
+		drift.compensator.set_skew_ppm(correction_ppm);
+		// Always resampled, so that enabling or disabling the compensation doesn't cause discontinuities.
+		drift.resampled.clear();
+		drift.compensator.process_into(data, &mut drift.resampled);
+		self.write(&drift.resampled);
+	}
+
+	fn write(&self, data: &[f32]) {
+		if self.fifo.push(data) < data.len() {
+			self.overruns.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	fn read(&self, output: &mut [f32]) {
+		self.output_frames.fetch_add(
+			self.sampling_ctx.samples_to_frames(output.len()).0,
+			Ordering::Relaxed,
+		);
+		if !self.primed.load(Ordering::Relaxed) {
+			if self.fifo.len() < self.prime_len {
+				output.fill(0.);
+				return;
+			}
+			self.primed.store(true, Ordering::Relaxed);
+		}
+
+		let popped = self.fifo.pop(output);
+		if popped < output.len() {
+			output[popped..].fill(0.);
+			self.underruns.fetch_add(1, Ordering::Relaxed);
+			self.primed.store(false, Ordering::Relaxed);
+		}
+	}
+}
+
+/// The state owned by the input callback to compensate for the drift between the two devices.
+struct DriftState {
+	estimator: DriftEstimator,
+	compensator: DriftCompensator,
+	/// The total number of frames captured by the input.
+	input_frames: NOfFrames,
+	resampled: Vec<f32>,
+}
+
+impl DriftState {
+	fn new(sampling_ctx: SamplingCtx, capacity: NOfFrames) -> Self {
+		let mut compensator = DriftCompensator::new(sampling_ctx);
+		compensator.set_skew_ppm(MAX_CORRECTION_PPM);
+		let resampled = Vec::with_capacity(
+			compensator.max_output_len(sampling_ctx.frames_to_samples(capacity)),
+		);
+		compensator.set_skew_ppm(0.);
+		Self {
+			estimator: DriftEstimator::new(sampling_ctx.duration_to_frames(DRIFT_ESTIMATION_SPAN)),
+			compensator,
+			input_frames: NOfFrames(0),
+			resampled,
+		}
+	}
+}
+
+/// A lock-free single-producer single-consumer FIFO of samples.
+struct SampleFifo {
+	samples: Box<[AtomicU32]>,
+	/// The total number of samples pushed.
+	written: AtomicUsize,
+	/// The total number of samples popped.
+	read: AtomicUsize,
+}
+
+impl SampleFifo {
+	fn new(capacity: usize) -> Self {
+		Self {
+			samples: (0..capacity)
+				.map(|_| AtomicU32::new(0f32.to_bits()))
+				.collect(),
+			written: AtomicUsize::new(0),
+			read: AtomicUsize::new(0),
+		}
+	}
+
+	fn len(&self) -> usize {
+		// Loaded first, so that it can't be greater than `written`.
+		let read = self.read.load(Ordering::Acquire);
+		self.written.load(Ordering::Acquire) - read
+	}
+
+	/// Append as many samples of `data` as possible, returning how many have been pushed.
+	///
+	/// Must only be called by one thread at a time.
+	fn push(&self, data: &[f32]) -> usize {
+		let capacity = self.samples.len();
+		let written = self.written.load(Ordering::Relaxed);
+		let free = capacity - (written - self.read.load(Ordering::Acquire));
+		let n = data.len().min(free);
+		for (i, sample) in data[..n].iter().enumerate() {
+			self.samples[(written + i) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+		}
+		self.written.store(written + n, Ordering::Release);
+		n
+	}
+
+	/// Fill `out` with as many samples as possible, returning how many have been popped.
+	///
+	/// Must only be called by one thread at a time.
+	fn pop(&self, out: &mut [f32]) -> usize {
+		let capacity = self.samples.len();
+		let read = self.read.load(Ordering::Relaxed);
+		let available = self.written.load(Ordering::Acquire) - read;
+		let n = out.len().min(available);
+		for (i, sample) in out[..n].iter_mut().enumerate() {
+			*sample = f32::from_bits(self.samples[(read + i) % capacity].load(Ordering::Relaxed));
+		}
+		self.read.store(read + n, Ordering::Release);
+		n
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn fifo_wraps_around() {
+		let fifo = SampleFifo::new(4);
+		assert_eq!(fifo.push(&[1., 2., 3.]), 3);
+		let mut out = [0.; 2];
+		assert_eq!(fifo.pop(&mut out), 2);
+		assert_eq!(out, [1., 2.]);
+		assert_eq!(fifo.push(&[4., 5., 6., 7.]), 3);
+		assert_eq!(fifo.len(), 4);
+		let mut out = [0.; 5];
+		assert_eq!(fifo.pop(&mut out), 4);
+		assert_eq!(out, [3., 4., 5., 6., 0.]);
+	}
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn fifo_preserves_the_order_across_threads() {
+		let fifo = Arc::new(SampleFifo::new(64));
+		let producer = thread::spawn({
+			let fifo = fifo.clone();
+			move || {
+				let signal: Vec<f32> = (0..100_000).map(|i| i as f32).collect();
+				let mut i = 0;
+				while i < signal.len() {
+					i += fifo.push(&signal[i..(i + 7).min(signal.len())]);
+				}
+			}
+		});
+
+		let mut expected = 0;
+		let mut out = [0.; 5];
+		while expected < 100_000 {
+			let n = fifo.pop(&mut out);
+			for sample in &out[..n] {
+				assert!((sample - expected as f32).abs() < f32::EPSILON);
+				expected += 1;
+			}
+		}
+		producer.join().unwrap();
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)]
+	fn output_waits_for_the_fifo_to_be_half_full() {
+		let state = PassthroughState::new(SamplingCtx::new(SampleRate(48000), 1), NOfFrames(8));
+		let mut output = [1.; 3];
+
+		state.write(&[1., 2., 3.]);
+		state.read(&mut output);
+		assert_eq!(output, [0.; 3]);
+
+		state.write(&[4.]);
+		state.read(&mut output);
+		assert_eq!(output, [1., 2., 3.]);
+
+		state.read(&mut output);
+		assert_eq!(output, [4., 0., 0.]);
+		assert_eq!(state.underruns.load(Ordering::Relaxed), 1);
+
+		state.write(&[0.; 10]);
+		assert_eq!(state.overruns.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	#[allow(
+		clippy::cast_possible_truncation,
+		clippy::cast_precision_loss,
+		clippy::cast_sign_loss
+	)]
+	fn drift_is_compensated() {
+		let sampling_ctx = SamplingCtx::new(SampleRate(48000), 1);
+		let run = |drift_compensation: bool| {
+			let state = PassthroughState::new(sampling_ctx, NOfFrames(4096));
+			state
+				.drift_compensation
+				.store(drift_compensation, Ordering::Relaxed);
+			let mut drift = DriftState::new(sampling_ctx, NOfFrames(4096));
+			// The input runs 500 ppm faster than the output, in chunks of a different size.
+			let input_rate = 48000. * (1. + 500e-6);
+			let input = [0.5; 441];
+			let mut input_frames = 0;
+			let mut output = [0.; 480];
+			for callback in 0..20_000 {
+				let elapsed = f64::from(callback) * 480. / 48000.;
+				while f64::from(input_frames + 441) <= elapsed * input_rate {
+					state.write_compensated(&input, &mut drift);
+					input_frames += 441;
+				}
+				state.read(&mut output);
+			}
+			state
+		};
+
+		let state = run(false);
+		assert!(state.overruns.load(Ordering::Relaxed) > 0);
+
+		let state = run(true);
+		let skew = state.skew_ppm().unwrap();
+		assert!((skew + 500.).abs() < 5., "{skew}");
+		assert_eq!(state.overruns.load(Ordering::Relaxed), 0);
+		assert_eq!(state.underruns.load(Ordering::Relaxed), 0);
+	}
+
+	#[test]
+	#[ignore = "manually listen to the default input device on the default output device"]
+	fn test_manual() {
+		let passthrough = Passthrough::new(
+			SamplingCtx::new(SampleRate(48000), 1),
+			None,
+			None,
+			NOfFrames(2048),
+		)
+		.unwrap();
+		thread::sleep(Duration::from_secs(5));
+		assert_eq!(passthrough.state(), AudioStreamSamplingState::Sampling);
+		println!(
+			"latency: {:?}, underruns: {}, overruns: {}, skew: {:?} ppm",
+			passthrough.latency(),
+			passthrough.underruns(),
+			passthrough.overruns(),
+			passthrough.skew_ppm()
+		);
+	}
+}
@@ -0,0 +1,281 @@
+use std::{
+	sync::{
+		mpsc::{Receiver, RecvTimeoutError, Sender},
+		Arc, Mutex,
+	},
+	thread::{self, JoinHandle},
+	time::Duration,
+};
+
+use cpal::{traits::DeviceTrait, Device, SupportedStreamConfig};
+use mutex_ext::LockExt;
+use resource_daemon::{QuitSignal, ResourceDaemon};
+
+use crate::{
+	backend::DeviceStream, device_provider, input::OnErrorCallback, AudioStreamBuilderError,
+	AudioStreamError, HostError, IOMode, SamplingCtx, StreamOptions,
+};
+
+/// Controls how a stream tries to recover after an error (e.g. when a USB interface gets disconnected).
+///
+/// Attempts are spaced by an exponential backoff, starting from `initial_backoff`
+/// and doubling after each failure up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReconnectPolicy {
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+	/// The number of consecutive failed attempts after which the stream
+	/// gives up and stays stopped. `None` means "retry forever".
+	pub max_attempts: Option<usize>,
+	/// Whether to try the default device when the original one is not available.
+	pub fallback_to_default: bool,
+	/// Whether to rebuild the stream also when it has been stopped by an error that is not
+	/// [`AudioStreamError::is_recoverable`] (e.g. the host refusing to restart it).
+	pub retry_fatal: bool,
+}
+
+impl Default for ReconnectPolicy {
+	fn default() -> Self {
+		Self {
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(5),
+			max_attempts: None,
+			fallback_to_default: true,
+			retry_fatal: false,
+		}
+	}
+}
+
+impl ReconnectPolicy {
+	/// The time to wait before the given attempt (starting from 1).
+	#[must_use]
+	pub fn backoff(&self, attempt: usize) -> Duration {
+		let exponent = attempt.saturating_sub(1).min(31) as u32;
+		self.initial_backoff
+			.saturating_mul(1 << exponent)
+			.min(self.max_backoff)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconnectEvent {
+	/// The stream stopped because of the given error.
+	Disconnected(AudioStreamError),
+	/// A new attempt (starting from 1) is about to be made.
+	Reconnecting { attempt: usize },
+	/// The stream is running again on the named device.
+	Reconnected { device_name: Option<String> },
+	/// `max_attempts` has been reached, or the stream has been stopped by an error that is not
+	/// recoverable (see [`ReconnectPolicy::retry_fatal`]), the stream will stay stopped.
+	GaveUp,
+}
+
+pub type OnReconnectEventCallback = dyn FnMut(ReconnectEvent) + Send + 'static;
+
+pub(crate) enum StreamEvent {
+	Started,
+	Failed(AudioStreamError),
+	Stop,
+}
+
+pub(crate) type StreamDaemon = ResourceDaemon<Box<dyn DeviceStream>, AudioStreamError>;
+
+/// Reports the error that stops a stream, which can be detected both by its data
+/// and by its error callback.
+pub(crate) struct ErrorReporter {
+	quit_signal: QuitSignal<AudioStreamError>,
+	on_error: Option<Box<OnErrorCallback>>,
+	events: Option<Sender<StreamEvent>>,
+}
+
+impl ErrorReporter {
+	pub(crate) fn new(
+		quit_signal: QuitSignal<AudioStreamError>,
+		on_error: Option<Box<OnErrorCallback>>,
+		events: Option<Sender<StreamEvent>>,
+	) -> Self {
+		Self {
+			quit_signal,
+			on_error,
+			events,
+		}
+	}
+
+	pub(crate) fn report(&mut self, err: AudioStreamError) {
+		#[cfg(feature = "tracing")]
+		tracing::warn!(error = %err, recoverable = err.is_recoverable(), "stream stopped");
+		self.quit_signal.dispatch(err.clone());
+		// Only the first error is reported, as the stream is going to be dropped anyway.
+		if let Some(on_error) = self.on_error.take() {
+			on_error(&err);
+		}
+		if let Some(events) = self.events.take() {
+			let _ = events.send(StreamEvent::Failed(err));
+		}
+	}
+}
+
+pub(crate) type DaemonSpawner =
+	dyn Fn(Device, SupportedStreamConfig, Sender<StreamEvent>) -> StreamDaemon + Send + 'static;
+
+/// Watches the events of a stream and replaces its daemon, following a [`ReconnectPolicy`],
+/// every time it fails.
+pub(crate) struct Reconnector {
+	events: Sender<StreamEvent>,
+	thread_handle: Option<JoinHandle<()>>,
+}
+
+pub(crate) struct ReconnectorConfig {
+	pub policy: ReconnectPolicy,
+	pub sampling_ctx: SamplingCtx,
+	pub device_name: Option<String>,
+	pub mode: IOMode,
+	pub options: StreamOptions,
+}
+
+impl Reconnector {
+	/// Start supervising `daemon`. The spawner must notify `events` when a stream
+	/// starts or fails (`events` and `receiver` are the two ends of the same channel).
+	pub(crate) fn new(
+		config: ReconnectorConfig,
+		daemon: Arc<Mutex<StreamDaemon>>,
+		spawner: Box<DaemonSpawner>,
+		events: Sender<StreamEvent>,
+		receiver: Receiver<StreamEvent>,
+		mut on_event: Option<Box<OnReconnectEventCallback>>,
+	) -> Self {
+		let thread_handle = thread::spawn({
+			let events = events.clone();
+			move || {
+				let mut notify = |event: ReconnectEvent| {
+					#[cfg(feature = "tracing")]
+					tracing::info!(?event, "reconnection");
+					if let Some(on_event) = on_event.as_mut() {
+						on_event(event);
+					}
+				};
+				let mut attempt = 0;
+				let mut device_name = None;
+
+				while let Ok(event) = receiver.recv() {
+					match event {
+						StreamEvent::Stop => return,
+						StreamEvent::Started => {
+							if attempt > 0 {
+								attempt = 0;
+								notify(ReconnectEvent::Reconnected {
+									device_name: device_name.take(),
+								});
+							}
+						}
+						StreamEvent::Failed(err) => {
+							// Only the error that stopped a running stream decides whether to retry,
+							// the ones of the following attempts just count against `max_attempts`.
+							let fatal =
+								attempt == 0 && !err.is_recoverable() && !config.policy.retry_fatal;
+							if attempt == 0 {
+								notify(ReconnectEvent::Disconnected(err));
+							}
+							attempt += 1;
+							if fatal
+								|| config
+									.policy
+									.max_attempts
+									.is_some_and(|max_attempts| attempt > max_attempts)
+							{
+								notify(ReconnectEvent::GaveUp);
+								return;
+							}
+							notify(ReconnectEvent::Reconnecting { attempt });
+
+							match receiver.recv_timeout(config.policy.backoff(attempt)) {
+								Ok(StreamEvent::Stop) | Err(RecvTimeoutError::Disconnected) => {
+									return
+								}
+								Ok(_) | Err(RecvTimeoutError::Timeout) => (),
+							}
+
+							match provide_device(&config) {
+								Ok((device, stream_config)) => {
+									device_name = device.name().ok();
+									let new_daemon = spawner(device, stream_config, events.clone());
+									// Dropping the previous daemon releases its (broken) stream.
+									let previous = daemon.with_lock_mut(|daemon| {
+										std::mem::replace(daemon, new_daemon)
+									});
+									drop(previous);
+								}
+								Err(err) => {
+									// Counts as a failed attempt and schedules the next one.
+									let _ = events.send(StreamEvent::Failed(match err {
+										AudioStreamBuilderError::NoDeviceFound { .. } => {
+											AudioStreamError::DeviceNotAvailable
+										}
+										_ => AudioStreamError::BuildFailed {
+											device_name: config
+												.device_name
+												.clone()
+												.unwrap_or_default(),
+											source: HostError::new(err),
+										},
+									}));
+								}
+							}
+						}
+					}
+				}
+			}
+		});
+
+		Self {
+			events,
+			thread_handle: Some(thread_handle),
+		}
+	}
+}
+
+fn provide_device(
+	config: &ReconnectorConfig,
+) -> Result<(Device, SupportedStreamConfig), AudioStreamBuilderError> {
+	device_provider(
+		config.sampling_ctx,
+		config.device_name.as_deref(),
+		config.mode,
+		config.options,
+	)
+	.or_else(|err| {
+		if config.policy.fallback_to_default && config.device_name.is_some() {
+			device_provider(config.sampling_ctx, None, config.mode, config.options)
+		} else {
+			Err(err)
+		}
+	})
+}
+
+impl Drop for Reconnector {
+	fn drop(&mut self) {
+		let _ = self.events.send(StreamEvent::Stop);
+		if let Some(thread_handle) = self.thread_handle.take() {
+			let _ = thread_handle.join();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exponential_backoff() {
+		let policy = ReconnectPolicy {
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(1),
+			..Default::default()
+		};
+		assert_eq!(policy.backoff(1), Duration::from_millis(100));
+		assert_eq!(policy.backoff(2), Duration::from_millis(200));
+		assert_eq!(policy.backoff(4), Duration::from_millis(800));
+		assert_eq!(policy.backoff(5), Duration::from_secs(1));
+		assert_eq!(policy.backoff(1000), Duration::from_secs(1));
+	}
+}
@@ -0,0 +1,248 @@
+use std::{
+	sync::{Arc, Mutex},
+	thread::sleep,
+	time::{Duration, Instant},
+};
+
+use mutex_ext::LockExt;
+
+use crate::{
+	analysis::estimate_delay,
+	input::InputStream,
+	output::{exponential_sine_sweep, OutputStream},
+	AudioStreamBuilderError, AudioStreamError, AudioStreamSamplingState, ChannelSelection,
+	NOfFrames, SampleRate, SamplingCtx, StreamOptions,
+};
+
+/// Silence played before the chirp, to let both streams settle.
+const WARM_UP: Duration = Duration::from_millis(300);
+const CHIRP_DURATION: Duration = Duration::from_millis(200);
+/// The longest round trip that can be measured.
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+/// The minimum correlation between the chirp and the captured signal for the measurement to be valid.
+const MIN_CORRELATION: f32 = 0.1;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RoundTripError {
+	#[error(transparent)]
+	Build(#[from] AudioStreamBuilderError),
+	#[error("a stream stopped during the measurement")]
+	Stopped(AudioStreamError),
+	#[error("the chirp was not detected in the captured signal")]
+	NotDetected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundTripLatency {
+	/// The measured time between the chirp being passed to the output stream and
+	/// the same signal being captured by the input stream.
+	pub latency: Duration,
+	/// The sum of the average input and output delays reported by the streams,
+	/// which doesn't include the delays that are not known to the audio host
+	/// (e.g. the converters, or the distance between a speaker and a microphone).
+	pub reported_latency: Duration,
+	/// How well the captured signal matches the chirp, from 0 to 1, see
+	/// [`crate::analysis::DelayEstimate::correlation`].
+	pub correlation: f32,
+}
+
+/// Measure the end-to-end latency between an output and an input device, by playing a chirp and
+/// looking for it in the captured signal.
+///
+/// The output and input must be connected, either electrically (loopback cable) or acoustically
+/// (speaker and microphone). The measurement blocks for about 2 seconds.
+///
+/// The input is mixed down to mono, while the chirp is played on all the channels of the output.
+///
+/// # Errors
+/// [`RoundTripError`]
+pub fn measure_round_trip_latency(
+	sampling_ctx: SamplingCtx,
+	output_device_name: Option<&str>,
+	input_device_name: Option<&str>,
+	options: StreamOptions,
+) -> Result<RoundTripLatency, RoundTripError> {
+	let sample_rate = sampling_ctx.sample_rate();
+	#[allow(clippy::cast_precision_loss)]
+	let end_frequency = (sample_rate.0 as f32 / 2. * 0.9).min(10_000.);
+	let chirp: Vec<f32> = exponential_sine_sweep(sample_rate, 100., end_frequency, CHIRP_DURATION)
+		.into_iter()
+		.map(|sample| sample * 0.5)
+		.collect();
+
+	let mono_ctx = SamplingCtx::new(sample_rate, 1);
+	let warm_up_frames = mono_ctx.duration_to_frames(WARM_UP);
+	let shared = Arc::new(Mutex::new(RoundTripState::default()));
+
+	let input_stream = InputStream::new_with_options(
+		mono_ctx,
+		input_device_name,
+		Box::new({
+			let shared = shared.clone();
+			move |chunk, _| {
+				let now = Instant::now();
+				shared.with_lock_mut(|state| {
+					state.captured.extend_from_slice(chunk.raw_buffer());
+					state.chunks.push((now, NOfFrames(state.captured.len())));
+				});
+			}
+		}),
+		None,
+		StreamOptions {
+			channels: ChannelSelection::Mixdown,
+			..options
+		},
+	)?;
+
+	let output_stream = OutputStream::new_with_options(
+		sampling_ctx,
+		output_device_name,
+		Box::new({
+			let shared = shared.clone();
+			let chirp = chirp.clone();
+			let mut written_frames = NOfFrames(0);
+			move |mut chunk| {
+				let now = Instant::now();
+				let n_of_frames = chunk.n_of_frames();
+				for (i, mut frame) in chunk.iter_mut().enumerate() {
+					let chirp_idx = (written_frames + NOfFrames(i))
+						.0
+						.checked_sub(warm_up_frames.0);
+					frame.samples_mut().fill(
+						chirp_idx
+							.and_then(|idx| chirp.get(idx))
+							.copied()
+							.unwrap_or(0.),
+					);
+					if chirp_idx == Some(0) {
+						shared.with_lock_mut(|state| {
+							state.chirp_start =
+								Some(now + mono_ctx.frames_to_duration(NOfFrames(i)));
+						});
+					}
+				}
+				written_frames += n_of_frames;
+			}
+		}),
+		None,
+		options,
+	)?;
+
+	sleep(WARM_UP + CHIRP_DURATION + MAX_LATENCY);
+
+	for state in [input_stream.state(), output_stream.state()] {
+		if let AudioStreamSamplingState::Stopped(err) = state {
+			return Err(RoundTripError::Stopped(err));
+		}
+	}
+	let reported_latency = input_stream.avg_input_delay() + output_stream.avg_output_delay();
+	drop(output_stream);
+	drop(input_stream);
+
+	let state = shared.with_lock_mut(std::mem::take);
+	let (latency, correlation) = state
+		.latency(&chirp, sample_rate)
+		.ok_or(RoundTripError::NotDetected)?;
+
+	Ok(RoundTripLatency {
+		latency,
+		reported_latency,
+		correlation,
+	})
+}
+
+#[derive(Debug, Default)]
+struct RoundTripState {
+	/// When the first frame of the chirp would be played, if the output had no latency.
+	chirp_start: Option<Instant>,
+	captured: Vec<f32>,
+	/// When each chunk was received, with the number of frames captured up to its end.
+	chunks: Vec<(Instant, NOfFrames)>,
+}
+
+impl RoundTripState {
+	fn latency(&self, chirp: &[f32], sample_rate: SampleRate) -> Option<(Duration, f32)> {
+		let chirp_start = self.chirp_start?;
+		let estimate = estimate_delay(
+			chirp,
+			&self.captured,
+			NOfFrames(self.captured.len()),
+			sample_rate,
+		);
+		if estimate.correlation() < MIN_CORRELATION || estimate.lag() < 0 {
+			return None;
+		}
+
+		// When the first frame of the chirp was captured, estimated from the time its chunk was received.
+		let chirp_frame = estimate.n_of_frames();
+		let &(received_at, chunk_end) = self
+			.chunks
+			.iter()
+			.find(|(_, chunk_end)| *chunk_end > chirp_frame)?;
+		let captured_at = received_at.checked_sub(
+			SamplingCtx::new(sample_rate, 1).frames_to_duration(chunk_end - chirp_frame),
+		)?;
+
+		Some((
+			captured_at.saturating_duration_since(chirp_start),
+			estimate.correlation(),
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn latency_from_the_captured_signal() {
+		let sample_rate = SampleRate(8000);
+		let chirp = exponential_sine_sweep(sample_rate, 100., 3000., CHIRP_DURATION);
+
+		// The chirp shows up 1000 frames into the capture, which was received in chunks of 500 frames.
+		let mut captured = vec![0.; 1000];
+		captured.extend(chirp.iter().map(|sample| sample * 0.3));
+		captured.resize(4000, 0.);
+
+		let chirp_start = Instant::now();
+		let first_chunk = chirp_start + Duration::from_millis(100);
+		let chunks = (1..=8)
+			.map(|i| {
+				(
+					first_chunk + Duration::from_micros(62_500 * (i - 1)),
+					NOfFrames(500 * i as usize),
+				)
+			})
+			.collect();
+
+		let state = RoundTripState {
+			chirp_start: Some(chirp_start),
+			captured,
+			chunks,
+		};
+		let (latency, correlation) = state.latency(&chirp, sample_rate).unwrap();
+		// The chirp starts in the third chunk, received 225ms after the chirp start, 500 frames (62.5ms) before its end.
+		assert_eq!(latency, Duration::from_micros(225_000 - 62_500));
+		assert!(correlation > 0.9, "{correlation}");
+
+		let silent = RoundTripState {
+			chirp_start: Some(chirp_start),
+			captured: vec![0.; 4000],
+			chunks: vec![(first_chunk, NOfFrames(4000))],
+		};
+		assert_eq!(silent.latency(&chirp, sample_rate), None);
+	}
+
+	#[test]
+	#[ignore = "manually measure the latency between the default output and input devices"]
+	fn test_manual() {
+		let latency = measure_round_trip_latency(
+			SamplingCtx::new(SampleRate(48000), 2),
+			None,
+			None,
+			StreamOptions::default(),
+		)
+		.unwrap();
+		println!("{latency:?}");
+	}
+}
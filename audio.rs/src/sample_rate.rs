@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
 use derive_more::derive::{
 	Add, AddAssign, Div, DivAssign, Mul, MulAssign, Rem, RemAssign, Sub, SubAssign,
@@ -25,8 +25,39 @@ use derive_more::derive::{
 	Rem,
 	RemAssign,
 )]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SampleRate(pub usize);
 
+impl SampleRate {
+	/// The sample rate of audio CDs.
+	pub const HZ_44_100: Self = Self(44_100);
+	/// The standard sample rate of digital video and of most audio interfaces.
+	pub const HZ_48_000: Self = Self(48_000);
+	pub const HZ_96_000: Self = Self(96_000);
+
+	/// The duration of a single frame.
+	///
+	/// # Panics
+	/// - if the sample rate is 0.
+	#[must_use]
+	pub fn period(self) -> Duration {
+		assert!(self.0 > 0, "the period of a 0Hz sample rate is infinite");
+		#[allow(clippy::cast_precision_loss)] // REASON: sample rates are far below 2^52
+		Duration::from_secs_f64(1. / self.0 as f64)
+	}
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum SampleRateConversionError {
+	#[error("{0} is not a valid sample rate, which must be a non-negative integer")]
+	NotANaturalNumber(f32),
+	#[error("{0} can't be represented exactly as an f32")]
+	TooLarge(SampleRate),
+}
+
+/// The largest integer up to which every integer can be represented exactly as an f32.
+const MAX_EXACT_F32: usize = 1 << f32::MANTISSA_DIGITS;
+
 impl Display for SampleRate {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		Display::fmt(&format!("{}Hz", self.0), f)
@@ -44,3 +75,61 @@ impl From<SampleRate> for usize {
 		value.0
 	}
 }
+
+impl TryFrom<f32> for SampleRate {
+	type Error = SampleRateConversionError;
+
+	fn try_from(value: f32) -> Result<Self, Self::Error> {
+		#[allow(clippy::cast_precision_loss)] // REASON: MAX_EXACT_F32 is exactly representable
+		if value.is_finite() && value >= 0. && value.fract() == 0. && value <= MAX_EXACT_F32 as f32
+		{
+			#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+			// REASON: checked above
+			Ok(Self(value as usize))
+		} else {
+			Err(SampleRateConversionError::NotANaturalNumber(value))
+		}
+	}
+}
+
+impl TryFrom<SampleRate> for f32 {
+	type Error = SampleRateConversionError;
+
+	fn try_from(value: SampleRate) -> Result<Self, Self::Error> {
+		if value.0 <= MAX_EXACT_F32 {
+			#[allow(clippy::cast_precision_loss)] // REASON: checked above
+			Ok(value.0 as f32)
+		} else {
+			Err(SampleRateConversionError::TooLarge(value))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn period() {
+		assert_eq!(SampleRate::HZ_48_000.period(), Duration::from_nanos(20_833));
+		assert_eq!(SampleRate(1000).period(), Duration::from_millis(1));
+		assert_eq!(SampleRate::HZ_44_100.to_string(), "44100Hz");
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)] // REASON: the conversions are exact
+	fn f32_conversions() {
+		assert_eq!(SampleRate::try_from(96_000.), Ok(SampleRate::HZ_96_000));
+		assert_eq!(f32::try_from(SampleRate::HZ_44_100), Ok(44_100.));
+		assert!(SampleRate::try_from(-1.).is_err());
+		assert!(SampleRate::try_from(44_100.5).is_err());
+		assert!(SampleRate::try_from(f32::NAN).is_err());
+		assert!(SampleRate::try_from(f32::INFINITY).is_err());
+		assert_eq!(
+			f32::try_from(SampleRate(MAX_EXACT_F32 + 1)),
+			Err(SampleRateConversionError::TooLarge(SampleRate(
+				MAX_EXACT_F32 + 1
+			)))
+		);
+	}
+}
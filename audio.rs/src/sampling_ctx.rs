@@ -1,17 +1,52 @@
-use std::time::Duration;
+use std::{fmt::Display, time::Duration};
 
-use crate::{NOfFrames, SampleRate};
+use crate::{NOfFrames, SampleRate, TimedFrames};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(try_from = "SamplingCtxFields")
+)]
 pub struct SamplingCtx {
 	sample_rate: SampleRate,
 	n_ch: usize,
 }
 
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingCtxError {
+	#[error("the sample rate must be greater than 0")]
+	ZeroSampleRate,
+	#[error("the number of channels must be greater than 0")]
+	ZeroChannels,
+}
+
 impl SamplingCtx {
+	/// # Panics
+	/// - if `sample_rate` or `n_ch` is 0, see [`Self::try_new`].
 	#[must_use]
 	pub const fn new(sample_rate: SampleRate, n_ch: usize) -> Self {
-		Self { sample_rate, n_ch }
+		match Self::try_new(sample_rate, n_ch) {
+			Ok(sampling_ctx) => sampling_ctx,
+			Err(SamplingCtxError::ZeroSampleRate) => {
+				panic!("the sample rate must be greater than 0")
+			}
+			Err(SamplingCtxError::ZeroChannels) => {
+				panic!("the number of channels must be greater than 0")
+			}
+		}
+	}
+
+	/// # Errors
+	/// [`SamplingCtxError`] if `sample_rate` or `n_ch` is 0.
+	pub const fn try_new(sample_rate: SampleRate, n_ch: usize) -> Result<Self, SamplingCtxError> {
+		if sample_rate.0 == 0 {
+			Err(SamplingCtxError::ZeroSampleRate)
+		} else if n_ch == 0 {
+			Err(SamplingCtxError::ZeroChannels)
+		} else {
+			Ok(Self { sample_rate, n_ch })
+		}
 	}
 
 	/// The number of samples (or rather, frames) per second
@@ -56,6 +91,58 @@ impl SamplingCtx {
 	pub const fn frames_to_duration(&self, n_of_frames: NOfFrames) -> Duration {
 		Duration::from_micros((n_of_frames.0 * 1_000_000 / self.sample_rate.0) as u64)
 	}
+
+	/// Tag `n_of_frames` with the sample rate, so that it can't be mixed with frames
+	/// counted at other rates, see [`TimedFrames`].
+	#[must_use]
+	pub const fn timed_frames(&self, n_of_frames: NOfFrames) -> TimedFrames {
+		TimedFrames::new(n_of_frames, self.sample_rate)
+	}
+
+	/// The frame `duration` after `frame`, e.g. to schedule an event relative to the current position.
+	///
+	/// Note: will convert to microseconds to approximate the number of frames
+	#[must_use]
+	pub const fn frames_after(&self, frame: NOfFrames, duration: Duration) -> NOfFrames {
+		NOfFrames(frame.0 + self.duration_to_frames(duration).0)
+	}
+
+	/// The frame `duration` before `frame`, or `None` if it would precede the first one.
+	///
+	/// Note: will convert to microseconds to approximate the number of frames
+	#[must_use]
+	pub const fn frames_before(&self, frame: NOfFrames, duration: Duration) -> Option<NOfFrames> {
+		match frame.0.checked_sub(self.duration_to_frames(duration).0) {
+			Some(frame) => Some(NOfFrames(frame)),
+			None => None,
+		}
+	}
+}
+
+/// Formats e.g. as "48kHz 2ch" or "44.1kHz 1ch".
+impl Display for SamplingCtx {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		#[allow(clippy::cast_precision_loss)] // REASON: sample rates are far below 2^52
+		let khz = self.sample_rate.0 as f64 / 1000.;
+		write!(f, "{khz}kHz {}ch", self.n_ch)
+	}
+}
+
+/// The serialized form of [`SamplingCtx`], validated when deserializing.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SamplingCtxFields {
+	sample_rate: SampleRate,
+	n_ch: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SamplingCtxFields> for SamplingCtx {
+	type Error = SamplingCtxError;
+
+	fn try_from(fields: SamplingCtxFields) -> Result<Self, Self::Error> {
+		Self::try_new(fields.sample_rate, fields.n_ch)
+	}
 }
 
 #[cfg(test)]
@@ -99,4 +186,47 @@ mod tests {
 			Duration::from_secs(2)
 		);
 	}
+
+	#[test]
+	fn frames_relative_to_a_duration() {
+		let sampling_ctx = SamplingCtx::new(48000.into(), 2);
+		assert_eq!(
+			sampling_ctx.frames_after(NOfFrames(100), Duration::from_millis(10)),
+			NOfFrames(580)
+		);
+		assert_eq!(
+			sampling_ctx.frames_before(NOfFrames(580), Duration::from_millis(10)),
+			Some(NOfFrames(100))
+		);
+		assert_eq!(
+			sampling_ctx.frames_before(NOfFrames(100), Duration::from_millis(10)),
+			None
+		);
+	}
+
+	#[test]
+	fn validation_and_display() {
+		assert_eq!(
+			SamplingCtx::try_new(SampleRate(0), 2),
+			Err(SamplingCtxError::ZeroSampleRate)
+		);
+		assert_eq!(
+			SamplingCtx::try_new(SampleRate::HZ_48_000, 0),
+			Err(SamplingCtxError::ZeroChannels)
+		);
+		assert_eq!(
+			SamplingCtx::new(SampleRate::HZ_48_000, 2).to_string(),
+			"48kHz 2ch"
+		);
+		assert_eq!(
+			SamplingCtx::new(SampleRate::HZ_44_100, 1).to_string(),
+			"44.1kHz 1ch"
+		);
+	}
+
+	#[test]
+	#[should_panic = "the number of channels must be greater than 0"]
+	fn zero_channels() {
+		let _ = SamplingCtx::new(SampleRate::HZ_48_000, 0);
+	}
 }
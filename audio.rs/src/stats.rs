@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use crate::NOfFrames;
+
+/// A snapshot of the health of a stream, e.g. to display it or to pick a larger buffer size
+/// when the callbacks get close to their deadline.
+///
+/// See `InputStream::stats` and `OutputStream::stats`. Streams built with `new_with_reconnect`
+/// keep counting across reconnections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StreamStats {
+	/// The number of callbacks run since the stream was built.
+	pub callbacks: usize,
+	/// The average time spent in a callback, including the user callback (`on_data`
+	/// or the data producer).
+	pub avg_callback_duration: Duration,
+	pub max_callback_duration: Duration,
+	/// The number of frames exchanged with the device at the latest callback,
+	/// `None` before the first one.
+	pub last_buffer_size: Option<NOfFrames>,
+	/// The smallest number of frames exchanged in a callback, which can be lower than
+	/// the requested buffer size on hosts that only take it as a hint.
+	pub min_buffer_size: Option<NOfFrames>,
+	pub max_buffer_size: Option<NOfFrames>,
+	/// The moving average of the delay between the device and the callbacks,
+	/// same as `InputStream::avg_input_delay` and `OutputStream::avg_output_delay`.
+	pub avg_delay: Duration,
+	/// The number of errors reported while sampling, e.g. by the audio host.
+	pub errors: usize,
+}
+
+/// Accumulates the [`StreamStats`] from the audio callbacks.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StatsCollector {
+	callbacks: usize,
+	total_callback_duration: Duration,
+	max_callback_duration: Duration,
+	last_buffer_size: Option<NOfFrames>,
+	min_buffer_size: Option<NOfFrames>,
+	max_buffer_size: Option<NOfFrames>,
+	errors: usize,
+}
+
+impl StatsCollector {
+	pub(crate) fn record_callback(&mut self, buffer_size: NOfFrames, duration: Duration) {
+		self.callbacks += 1;
+		self.total_callback_duration += duration;
+		self.max_callback_duration = self.max_callback_duration.max(duration);
+		self.last_buffer_size = Some(buffer_size);
+		self.min_buffer_size = Some(
+			self.min_buffer_size
+				.map_or(buffer_size, |min| min.min(buffer_size)),
+		);
+		self.max_buffer_size = Some(
+			self.max_buffer_size
+				.map_or(buffer_size, |max| max.max(buffer_size)),
+		);
+	}
+
+	pub(crate) fn record_error(&mut self) {
+		self.errors += 1;
+	}
+
+	/// Add what has been recorded by `other` after what has been recorded by `self`,
+	/// e.g. to publish the stats collected while the shared collector was locked.
+	pub(crate) fn merge(&mut self, other: Self) {
+		self.callbacks += other.callbacks;
+		self.total_callback_duration += other.total_callback_duration;
+		self.max_callback_duration = self.max_callback_duration.max(other.max_callback_duration);
+		self.last_buffer_size = other.last_buffer_size.or(self.last_buffer_size);
+		self.min_buffer_size = match (self.min_buffer_size, other.min_buffer_size) {
+			(Some(min), Some(other_min)) => Some(min.min(other_min)),
+			(min, other_min) => min.or(other_min),
+		};
+		self.max_buffer_size = self.max_buffer_size.max(other.max_buffer_size);
+		self.errors += other.errors;
+	}
+
+	pub(crate) fn snapshot(&self, avg_delay: Duration) -> StreamStats {
+		StreamStats {
+			callbacks: self.callbacks,
+			avg_callback_duration: u32::try_from(self.callbacks)
+				.ok()
+				.filter(|&callbacks| callbacks > 0)
+				.map_or(Duration::ZERO, |callbacks| {
+					self.total_callback_duration / callbacks
+				}),
+			max_callback_duration: self.max_callback_duration,
+			last_buffer_size: self.last_buffer_size,
+			min_buffer_size: self.min_buffer_size,
+			max_buffer_size: self.max_buffer_size,
+			avg_delay,
+			errors: self.errors,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn snapshot() {
+		let mut collector = StatsCollector::default();
+		assert_eq!(collector.snapshot(Duration::ZERO), StreamStats::default());
+
+		collector.record_callback(NOfFrames(512), Duration::from_micros(100));
+		collector.record_callback(NOfFrames(256), Duration::from_micros(300));
+		collector.record_callback(NOfFrames(480), Duration::from_micros(200));
+		collector.record_error();
+		assert_eq!(
+			collector.snapshot(Duration::from_millis(10)),
+			StreamStats {
+				callbacks: 3,
+				avg_callback_duration: Duration::from_micros(200),
+				max_callback_duration: Duration::from_micros(300),
+				last_buffer_size: Some(NOfFrames(480)),
+				min_buffer_size: Some(NOfFrames(256)),
+				max_buffer_size: Some(NOfFrames(512)),
+				avg_delay: Duration::from_millis(10),
+				errors: 1,
+			}
+		);
+	}
+
+	#[test]
+	fn merge() {
+		let mut collector = StatsCollector::default();
+		collector.record_callback(NOfFrames(512), Duration::from_micros(100));
+
+		let mut pending = StatsCollector::default();
+		pending.record_callback(NOfFrames(256), Duration::from_micros(300));
+		pending.record_error();
+		collector.merge(pending);
+		collector.merge(StatsCollector::default());
+
+		let stats = collector.snapshot(Duration::ZERO);
+		assert_eq!(stats.callbacks, 2);
+		assert_eq!(stats.avg_callback_duration, Duration::from_micros(200));
+		assert_eq!(stats.max_callback_duration, Duration::from_micros(300));
+		assert_eq!(stats.last_buffer_size, Some(NOfFrames(256)));
+		assert_eq!(stats.min_buffer_size, Some(NOfFrames(256)));
+		assert_eq!(stats.max_buffer_size, Some(NOfFrames(512)));
+		assert_eq!(stats.errors, 1);
+	}
+}
@@ -0,0 +1,246 @@
+use std::{
+	cmp::Ordering,
+	fmt::Display,
+	ops::{Add, Sub},
+	time::Duration,
+};
+
+use crate::{NOfFrames, SampleRate};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A number of frames together with the sample rate they are counted at.
+///
+/// Unlike plain [`NOfFrames`], frame counts at different sample rates can't be mixed by accident:
+/// adding, subtracting or comparing them fails (see [`SampleRateMismatch`]) until one of them is
+/// explicitly converted with [`Self::to_rate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimedFrames {
+	frames: NOfFrames,
+	sample_rate: SampleRate,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("frames counted at {left} can't be combined with frames counted at {right}")]
+pub struct SampleRateMismatch {
+	pub left: SampleRate,
+	pub right: SampleRate,
+}
+
+impl TimedFrames {
+	#[must_use]
+	pub const fn new(frames: NOfFrames, sample_rate: SampleRate) -> Self {
+		Self {
+			frames,
+			sample_rate,
+		}
+	}
+
+	/// The frames spanning `duration`, rounded to the nearest one.
+	///
+	/// # Panics
+	/// - if the result doesn't fit in a `usize`.
+	#[must_use]
+	pub fn from_duration(duration: Duration, sample_rate: SampleRate) -> Self {
+		Self::new(
+			NOfFrames(rounded_ratio(
+				duration.as_nanos() * sample_rate.0 as u128,
+				NANOS_PER_SEC,
+			)),
+			sample_rate,
+		)
+	}
+
+	#[must_use]
+	pub const fn frames(&self) -> NOfFrames {
+		self.frames
+	}
+
+	#[must_use]
+	pub const fn sample_rate(&self) -> SampleRate {
+		self.sample_rate
+	}
+
+	/// The time spanned by the frames, rounded to the nearest nanosecond.
+	///
+	/// # Panics
+	/// - if the sample rate is 0.
+	#[must_use]
+	pub fn duration(&self) -> Duration {
+		assert!(
+			self.sample_rate.0 > 0,
+			"the sample rate must be greater than 0"
+		);
+		let nanos = rounded_ratio(
+			self.frames.0 as u128 * NANOS_PER_SEC,
+			self.sample_rate.0 as u128,
+		);
+		Duration::from_nanos(nanos as u64)
+	}
+
+	/// The same span of time counted at another sample rate, rounded to the nearest frame.
+	///
+	/// # Panics
+	/// - if the current sample rate is 0.
+	#[must_use]
+	pub fn to_rate(self, sample_rate: SampleRate) -> Self {
+		assert!(
+			self.sample_rate.0 > 0,
+			"the sample rate must be greater than 0"
+		);
+		Self::new(
+			NOfFrames(rounded_ratio(
+				self.frames.0 as u128 * sample_rate.0 as u128,
+				self.sample_rate.0 as u128,
+			)),
+			sample_rate,
+		)
+	}
+
+	/// # Errors
+	/// [`SampleRateMismatch`] if `other` is counted at a different sample rate.
+	pub fn checked_add(self, other: Self) -> Result<Self, SampleRateMismatch> {
+		self.check_rate(other)?;
+		Ok(Self::new(self.frames + other.frames, self.sample_rate))
+	}
+
+	/// Subtract `other`, returning `Ok(None)` if it's longer than `self`.
+	///
+	/// # Errors
+	/// [`SampleRateMismatch`] if `other` is counted at a different sample rate.
+	pub fn checked_sub(self, other: Self) -> Result<Option<Self>, SampleRateMismatch> {
+		self.check_rate(other)?;
+		Ok(self
+			.frames
+			.0
+			.checked_sub(other.frames.0)
+			.map(|frames| Self::new(NOfFrames(frames), self.sample_rate)))
+	}
+
+	/// # Errors
+	/// [`SampleRateMismatch`] if `other` is counted at a different sample rate.
+	pub fn try_cmp(&self, other: &Self) -> Result<Ordering, SampleRateMismatch> {
+		self.check_rate(*other)?;
+		Ok(self.frames.cmp(&other.frames))
+	}
+
+	fn check_rate(self, other: Self) -> Result<(), SampleRateMismatch> {
+		if self.sample_rate == other.sample_rate {
+			Ok(())
+		} else {
+			Err(SampleRateMismatch {
+				left: self.sample_rate,
+				right: other.sample_rate,
+			})
+		}
+	}
+}
+
+/// Frame counts at different sample rates are not comparable, see [`TimedFrames::try_cmp`].
+impl PartialOrd for TimedFrames {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		self.try_cmp(other).ok()
+	}
+}
+
+impl Add for TimedFrames {
+	type Output = Self;
+
+	/// # Panics
+	/// - if the operands are counted at different sample rates, see [`TimedFrames::checked_add`].
+	fn add(self, rhs: Self) -> Self {
+		self.checked_add(rhs).unwrap_or_else(|err| panic!("{err}"))
+	}
+}
+
+impl Sub for TimedFrames {
+	type Output = Self;
+
+	/// # Panics
+	/// - if the operands are counted at different sample rates, or if `rhs` is longer than `self`,
+	///   see [`TimedFrames::checked_sub`].
+	fn sub(self, rhs: Self) -> Self {
+		self.checked_sub(rhs)
+			.unwrap_or_else(|err| panic!("{err}"))
+			.expect("attempt to subtract with overflow")
+	}
+}
+
+impl Display for TimedFrames {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} frames at {}", self.frames, self.sample_rate)
+	}
+}
+
+/// `numerator / denominator`, rounded to the nearest integer.
+fn rounded_ratio(numerator: u128, denominator: u128) -> usize {
+	usize::try_from((numerator + denominator / 2) / denominator)
+		.expect("the number of frames doesn't fit in a usize")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn arithmetic_at_the_same_rate() {
+		let a = TimedFrames::new(NOfFrames(480), SampleRate::HZ_48_000);
+		let b = TimedFrames::new(NOfFrames(120), SampleRate::HZ_48_000);
+		assert_eq!(
+			a + b,
+			TimedFrames::new(NOfFrames(600), SampleRate::HZ_48_000)
+		);
+		assert_eq!(
+			a - b,
+			TimedFrames::new(NOfFrames(360), SampleRate::HZ_48_000)
+		);
+		assert_eq!(b.checked_sub(a), Ok(None));
+		assert!(b < a);
+		assert_eq!(a.duration(), Duration::from_millis(10));
+		assert_eq!(a.to_string(), "480 frames at 48000Hz");
+	}
+
+	#[test]
+	fn different_rates_are_not_mixed() {
+		let a = TimedFrames::new(NOfFrames(480), SampleRate::HZ_48_000);
+		let b = TimedFrames::new(NOfFrames(441), SampleRate::HZ_44_100);
+		let mismatch = SampleRateMismatch {
+			left: SampleRate::HZ_48_000,
+			right: SampleRate::HZ_44_100,
+		};
+		assert_eq!(a.checked_add(b), Err(mismatch));
+		assert_eq!(a.checked_sub(b), Err(mismatch));
+		assert_eq!(a.try_cmp(&b), Err(mismatch));
+		assert_eq!(a.partial_cmp(&b), None);
+		assert_ne!(a, b);
+
+		// Both span 10ms.
+		assert_eq!(b.to_rate(SampleRate::HZ_48_000), a);
+		assert_eq!(a.try_cmp(&b.to_rate(a.sample_rate())), Ok(Ordering::Equal));
+	}
+
+	#[test]
+	#[should_panic = "can't be combined"]
+	fn adding_different_rates_panics() {
+		let _ = TimedFrames::new(NOfFrames(1), SampleRate::HZ_48_000)
+			+ TimedFrames::new(NOfFrames(1), SampleRate::HZ_96_000);
+	}
+
+	#[test]
+	fn conversions_round_to_the_nearest_frame() {
+		assert_eq!(
+			TimedFrames::from_duration(Duration::from_micros(10), SampleRate::HZ_44_100).frames(),
+			NOfFrames(0)
+		);
+		assert_eq!(
+			TimedFrames::from_duration(Duration::from_micros(20), SampleRate::HZ_44_100).frames(),
+			NOfFrames(1)
+		);
+		assert_eq!(
+			TimedFrames::new(NOfFrames(3), SampleRate::HZ_96_000)
+				.to_rate(SampleRate::HZ_44_100)
+				.frames(),
+			NOfFrames(1)
+		);
+	}
+}
@@ -0,0 +1,168 @@
+//! The audit allocator replaces the allocator of the whole binary, so it's only installed here.
+
+use std::{
+	alloc::System,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex, PoisonError,
+	},
+	thread::sleep,
+	time::Duration,
+};
+
+use audio::{
+	analysis::Harmonic,
+	audit::{
+		callback_audit_stats, reset_callback_audit_stats, set_audit_mode, AuditAllocator, AuditMode,
+	},
+	buffers::InterleavedAudioBuffer,
+	output::{
+		AudioPlayer, Envelope, Metronome, Mixer, OnPlaybackEnd, Oscillator, OutputStream,
+		QueuedPlayer, Synth, Waveform,
+	},
+	NOfFrames, SampleRate, SamplingCtx,
+};
+use rustfft::num_complex::Complex32;
+
+#[global_allocator]
+static ALLOCATOR: AuditAllocator = AuditAllocator::new(System);
+
+/// The counters are global, so the tests take turns.
+static AUDIT: Mutex<()> = Mutex::new(());
+
+/// Run `render`, asserting that the callbacks it ran neither allocated nor waited for a lock.
+fn assert_realtime_safe(name: &str, render: impl FnOnce()) {
+	reset_callback_audit_stats();
+	render();
+	let stats = callback_audit_stats();
+	assert!(stats.callbacks > 0, "{name}: no callbacks");
+	assert_eq!(stats.allocations, 0, "{name}: allocations");
+	assert_eq!(stats.flagged_callbacks, 0, "{name}: flagged callbacks");
+}
+
+#[test]
+fn allocations_inside_the_callbacks_are_counted() {
+	let _audit = AUDIT.lock().unwrap_or_else(PoisonError::into_inner);
+	set_audit_mode(AuditMode::Count);
+	let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+
+	let stream = OutputStream::new_offline(
+		sampling_ctx,
+		Box::new(|mut chunk| chunk.raw_buffer_mut().fill(0.5)),
+	);
+	stream.set_volume(0.5);
+	stream.set_channel_gains(&[1., 0.5]);
+	stream.set_processors(vec![Box::new(|chunk| {
+		chunk.raw_buffer_mut().iter_mut().for_each(|s| *s *= 2.);
+	})]);
+	reset_callback_audit_stats();
+	let _ = stream.render(NOfFrames(2048));
+	let stats = callback_audit_stats();
+	assert_eq!(stats.callbacks, 4);
+	assert_eq!(stats.allocations, 0);
+	assert_eq!(stats.flagged_callbacks, 0);
+
+	let stream = OutputStream::new_offline(
+		sampling_ctx,
+		Box::new(|mut chunk| {
+			let scratch = std::hint::black_box(vec![0.5; chunk.raw_buffer().len()]);
+			chunk.raw_buffer_mut().copy_from_slice(&scratch);
+		}),
+	);
+	reset_callback_audit_stats();
+	let _ = stream.render(NOfFrames(1024));
+	let stats = callback_audit_stats();
+	assert_eq!(stats.callbacks, 2);
+	// The allocation and deallocation of the scratch buffer.
+	assert_eq!(stats.allocations, 4);
+	assert_eq!(stats.flagged_callbacks, 2);
+}
+
+#[test]
+fn built_in_streams_are_realtime_safe() {
+	let _audit = AUDIT.lock().unwrap_or_else(PoisonError::into_inner);
+	set_audit_mode(AuditMode::Count);
+	let sampling_ctx = SamplingCtx::new(SampleRate(48000), 2);
+	let signal = |n_of_frames| {
+		InterleavedAudioBuffer::new(
+			sampling_ctx,
+			vec![0.5; sampling_ctx.frames_to_samples(NOfFrames(n_of_frames))],
+		)
+	};
+
+	// Signals that end, are queued and are crossfaded, whose callbacks are called
+	// and whose buffers are dropped outside of the callbacks.
+	let ended = Arc::new(AtomicUsize::new(0));
+	let on_end = || -> Option<Box<OnPlaybackEnd>> {
+		let ended = ended.clone();
+		Some(Box::new(move |_| {
+			ended.fetch_add(1, Ordering::Relaxed);
+		}))
+	};
+	let mut player = AudioPlayer::new_offline(sampling_ctx);
+	let _first = player.play_handle(signal(1000), on_end());
+	let _second = player.enqueue(signal(100), on_end());
+	let _third = player.enqueue(signal(700), on_end());
+	assert_realtime_safe("player", || {
+		let _ = player.render(NOfFrames(1024));
+	});
+	player.set_signal(signal(2000));
+	assert_realtime_safe("player", || {
+		let _ = player.render(NOfFrames(4096));
+	});
+	while ended.load(Ordering::Relaxed) < 3 {
+		sleep(Duration::from_millis(1));
+	}
+
+	let player = QueuedPlayer::new_offline(sampling_ctx);
+	for n_of_frames in [100, 1000, 10] {
+		player.enqueue(signal(n_of_frames));
+	}
+	assert_realtime_safe("queued player", || {
+		let _ = player.render(NOfFrames(2048));
+	});
+	assert_eq!(player.queue_len(), 0);
+
+	let mut oscillator = Oscillator::new_offline(sampling_ctx);
+	oscillator.set_harmonics(vec![Harmonic::new(Complex32::ONE, 440.)]);
+	oscillator.glide_to_harmonics(
+		vec![Harmonic::new(Complex32::ONE, 880.)],
+		Duration::from_millis(20),
+	);
+	assert_realtime_safe("oscillator", || {
+		let _ = oscillator.render(NOfFrames(2048));
+	});
+	assert!(!oscillator.is_gliding());
+	oscillator.set_waveform(Waveform::Square);
+	assert_realtime_safe("oscillator", || {
+		let _ = oscillator.render(NOfFrames(2048));
+	});
+
+	let mixer = Mixer::new_offline(sampling_ctx);
+	mixer.add_source(Box::new(|mut chunk| chunk.raw_buffer_mut().fill(0.1)));
+	let scheduled = mixer.schedule(signal(100), NOfFrames(200));
+	assert_realtime_safe("mixer", || {
+		let _ = mixer.render(NOfFrames(2048));
+	});
+	assert!(!mixer.has_source(scheduled));
+
+	let ms = Duration::from_millis;
+	let mut synth = Synth::new_offline(
+		sampling_ctx,
+		Envelope::new(sampling_ctx.sample_rate(), ms(5), ms(5), 0.5, ms(5)),
+	);
+	synth.note_on(60, 100);
+	synth.note_on(64, 100);
+	assert_realtime_safe("synth", || {
+		let _ = synth.render(NOfFrames(1024));
+	});
+	synth.note_off(60);
+	assert_realtime_safe("synth", || {
+		let _ = synth.render(NOfFrames(2048));
+	});
+
+	let metronome = Metronome::new_offline(sampling_ctx, 480., 4);
+	assert_realtime_safe("metronome", || {
+		let _ = metronome.render(NOfFrames(24000));
+	});
+}